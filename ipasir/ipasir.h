@@ -0,0 +1,52 @@
+/*
+ * IPASIR: 標準的なインクリメンタル SAT ソルバーの C インターフェース
+ *
+ * src/ipasir.rs の関数に対応する．target/{debug,release}/libtrial_sat.{a,so} とリンクして使う
+ * (静的ライブラリの場合は -lpthread -ldl -lm も必要)．
+ * リテラルは DIMACS 形式の整数(変数 v の正リテラルが v，負リテラルが -v)で表す．
+ * 0 (ipasir_add の節の終わりを除く)や INT32_MIN をリテラルとして渡すとプロセスを中断する．
+ */
+#ifndef TRIAL_SAT_IPASIR_H
+#define TRIAL_SAT_IPASIR_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* ソルバーの名前とバージョン */
+const char *ipasir_signature(void);
+
+/* ソルバーを作成する(ipasir_release で解放する) */
+void *ipasir_init(void);
+
+/* ソルバーを解放する */
+void ipasir_release(void *solver);
+
+/* 節にリテラルを追加する(0 で節を確定する) */
+void ipasir_add(void *solver, int32_t lit_or_zero);
+
+/* 次の ipasir_solve でのみ有効な仮定を追加する */
+void ipasir_assume(void *solver, int32_t lit);
+
+/* 充足可能であれば 10，充足不可能であれば 20，中断された場合は 0 を返す */
+int ipasir_solve(void *solver);
+
+/* 直前の ipasir_solve が 10 を返したとき，lit が真なら lit，偽なら -lit を返す(ソルバーが知らない変数であれば 0) */
+int32_t ipasir_val(void *solver, int32_t lit);
+
+/* 直前の ipasir_solve が 20 を返したとき，仮定 lit が充足不可能となった原因に含まれていれば 1 を返す */
+int ipasir_failed(void *solver, int32_t lit);
+
+/* 探索中に terminate(data) を繰り返し呼び，0 以外が返されたら探索を中断する(terminate が NULL なら解除) */
+void ipasir_set_terminate(void *solver, void *data, int (*terminate)(void *data));
+
+/* 長さが max_length 以下の学習節が得られるたびに，0 で終わるリテラルの列として learn(data, clause) を呼ぶ */
+void ipasir_set_learn(void *solver, void *data, int max_length, void (*learn)(void *data, int32_t *clause));
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,209 @@
+/*
+ * IPASIR インターフェースの動作確認
+ *
+ * make ipasir-test でライブラリとともにビルドして実行する．
+ */
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "ipasir.h"
+
+#define CHECK(condition)                                                                \
+    do {                                                                                \
+        if (!(condition)) {                                                             \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #condition); \
+            exit(1);                                                                    \
+        }                                                                               \
+    } while (0)
+
+static void add_clause(void *solver, const int32_t *literals) {
+    for (; *literals != 0; literals++) {
+        ipasir_add(solver, *literals);
+    }
+    ipasir_add(solver, 0);
+}
+
+static int learnt_count = 0;
+
+static void learn(void *data, int32_t *clause) {
+    int *max_length = data;
+    int length = 0;
+    for (; clause[length] != 0; length++) {
+    }
+    CHECK(length <= *max_length);
+    learnt_count++;
+}
+
+static int terminate_now(void *data) {
+    (void)data;
+    return 1;
+}
+
+/* 鳩の巣原理: n+1 羽の鳩を n 個の巣に入れる(変数 p*n+h+1 は鳩 p が巣 h に入る) */
+static void add_pigeonhole(void *solver, int n) {
+    for (int p = 0; p <= n; p++) {
+        for (int h = 0; h < n; h++) {
+            ipasir_add(solver, p * n + h + 1);
+        }
+        ipasir_add(solver, 0);
+    }
+    for (int h = 0; h < n; h++) {
+        for (int p = 0; p <= n; p++) {
+            for (int q = p + 1; q <= n; q++) {
+                ipasir_add(solver, -(p * n + h + 1));
+                ipasir_add(solver, -(q * n + h + 1));
+                ipasir_add(solver, 0);
+            }
+        }
+    }
+}
+
+/* 線形合同法による乱数 */
+static uint32_t random_state = 12345;
+
+static int32_t random_below(int32_t n) {
+    random_state = random_state * 1103515245u + 12345u;
+    return (int32_t)((random_state >> 16) % (uint32_t)n);
+}
+
+static int32_t random_literal(int32_t n) {
+    int32_t variable = random_below(n) + 1;
+    return random_below(2) ? variable : -variable;
+}
+
+#define N 8
+#define MAX_CLAUSES 64
+
+/* values(ビット集合)で lit が真か */
+static int is_true(uint32_t values, int32_t lit) {
+    int32_t variable = lit > 0 ? lit : -lit;
+    return ((values >> (variable - 1)) & 1) == (lit > 0);
+}
+
+/* 3 リテラルの節と仮定をすべて充足する割り当てがあるかを全探索で調べる */
+static int is_satisfiable(int32_t clauses[][3], int m, const int32_t *assumptions, int k) {
+    for (uint32_t values = 0; values < (1u << N); values++) {
+        int ok = 1;
+        for (int c = 0; c < m && ok; c++) {
+            ok = is_true(values, clauses[c][0]) || is_true(values, clauses[c][1]) || is_true(values, clauses[c][2]);
+        }
+        for (int a = 0; a < k && ok; a++) {
+            ok = is_true(values, assumptions[a]);
+        }
+        if (ok) {
+            return 1;
+        }
+    }
+    return 0;
+}
+
+/* 節を追加しながら仮定を変えて解き，結果を全探索と比べる */
+static void check_against_brute_force(void) {
+    for (int round = 0; round < 20; round++) {
+        void *solver = ipasir_init();
+        int32_t clauses[MAX_CLAUSES][3];
+        int m = 0;
+        while (m < MAX_CLAUSES) {
+            for (int j = 0; j < 3; j++) {
+                clauses[m][j] = random_literal(N);
+                ipasir_add(solver, clauses[m][j]);
+            }
+            ipasir_add(solver, 0);
+            m++;
+            int32_t assumptions[3];
+            int k = random_below(4);
+            for (int a = 0; a < k; a++) {
+                assumptions[a] = random_literal(N);
+                ipasir_assume(solver, assumptions[a]);
+            }
+            int result = ipasir_solve(solver);
+            if (is_satisfiable(clauses, m, assumptions, k)) {
+                CHECK(result == 10);
+                /* 解は節と仮定をすべて充足する(0 はどちらの値でもよい) */
+                for (int c = 0; c < m; c++) {
+                    int satisfied = 0;
+                    for (int j = 0; j < 3; j++) {
+                        int32_t value = ipasir_val(solver, clauses[c][j]);
+                        satisfied |= value == clauses[c][j] || value == 0;
+                    }
+                    CHECK(satisfied);
+                }
+                for (int a = 0; a < k; a++) {
+                    int32_t value = ipasir_val(solver, assumptions[a]);
+                    CHECK(value == assumptions[a] || value == 0);
+                }
+            } else {
+                CHECK(result == 20);
+                /* 原因の仮定だけでも充足不可能 */
+                int32_t failed[3];
+                int f = 0;
+                for (int a = 0; a < k; a++) {
+                    if (ipasir_failed(solver, assumptions[a])) {
+                        failed[f++] = assumptions[a];
+                    }
+                }
+                CHECK(!is_satisfiable(clauses, m, failed, f));
+                if (!is_satisfiable(clauses, m, NULL, 0)) {
+                    break;
+                }
+            }
+        }
+        ipasir_release(solver);
+    }
+}
+
+int main(void) {
+    printf("signature: %s\n", ipasir_signature());
+
+    /* 充足可能な問題と解の値 */
+    void *solver = ipasir_init();
+    add_clause(solver, (int32_t[]){1, 2, 0});
+    add_clause(solver, (int32_t[]){-1, 2, 0});
+    add_clause(solver, (int32_t[]){-2, 3, 0});
+    CHECK(ipasir_solve(solver) == 10);
+    CHECK(ipasir_val(solver, 2) == 2);
+    CHECK(ipasir_val(solver, -2) == 2);
+    CHECK(ipasir_val(solver, 3) == 3);
+    CHECK(ipasir_val(solver, 1) == 1 || ipasir_val(solver, 1) == -1);
+
+    /* 仮定による充足不可能性と原因の仮定 */
+    ipasir_assume(solver, -3);
+    ipasir_assume(solver, 1);
+    CHECK(ipasir_solve(solver) == 20);
+    CHECK(ipasir_failed(solver, -3) == 1);
+
+    /* 仮定は 1 回の求解でのみ有効 */
+    ipasir_assume(solver, 1);
+    CHECK(ipasir_solve(solver) == 10);
+    CHECK(ipasir_val(solver, 1) == 1);
+    CHECK(ipasir_failed(solver, 1) == 0);
+
+    /* 節の追加による充足不可能性 */
+    add_clause(solver, (int32_t[]){-3, 0});
+    CHECK(ipasir_solve(solver) == 20);
+    ipasir_release(solver);
+
+    /* 学習節の通知 */
+    solver = ipasir_init();
+    int max_length = 3;
+    ipasir_set_learn(solver, &max_length, max_length, learn);
+    add_pigeonhole(solver, 6);
+    CHECK(ipasir_solve(solver) == 20);
+    CHECK(learnt_count > 0);
+    ipasir_release(solver);
+
+    /* 中断 */
+    solver = ipasir_init();
+    ipasir_set_terminate(solver, NULL, terminate_now);
+    add_pigeonhole(solver, 8);
+    CHECK(ipasir_solve(solver) == 0);
+    ipasir_set_terminate(solver, NULL, NULL);
+    add_clause(solver, (int32_t[]){1, 0});
+    ipasir_release(solver);
+
+    check_against_brute_force();
+
+    printf("learnt_count=%d\n", learnt_count);
+    printf("ok\n");
+    return 0;
+}
@@ -44,6 +44,14 @@ where
         self.vec.first()
     }
 
+    pub fn last(&self) -> Option<&ValueT> {
+        self.vec.last()
+    }
+
+    pub fn as_slice(&self) -> &[ValueT] {
+        self.vec.as_slice()
+    }
+
     pub fn iter(&self) -> Iter<ValueT> {
         self.vec.iter()
     }
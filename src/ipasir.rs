@@ -0,0 +1,288 @@
+//! IPASIR (標準的なインクリメンタル SAT ソルバーの C インターフェース) の実装
+//!
+//! 関数の仕様は ipasir/ipasir.h を参照．リテラルは DIMACS 形式の整数(変数 v の正リテラルが v，負リテラルが -v)で表す．
+
+use std::ffi::{c_char, c_int, c_void};
+
+use crate::finite_collections::Array;
+use crate::sat::{Literal, SATSolver, SATSolverResult, VariableSize};
+
+/// ipasir_init が返すソルバーの状態
+struct IpasirSolver {
+    solver: SATSolver,
+    // ipasir_add で追加中の節
+    clause: Array<VariableSize, Literal>,
+    // 次の ipasir_solve での仮定
+    assumptions: Vec<Literal>,
+    // 直前の ipasir_solve で得られた解
+    solution: Option<Array<VariableSize, bool>>,
+    // 直前の ipasir_solve で充足不可能となった原因の仮定
+    failed_assumptions: Vec<Literal>,
+}
+
+fn to_literal(lit: i32) -> Literal {
+    // -i32::MIN は i32 で表せないので変数にならない(extern "C" 関数内の panic はプロセスを中断する)
+    assert!(lit != 0 && lit != i32::MIN, "invalid literal: {}", lit);
+    Literal { sign: lit > 0, index: lit.unsigned_abs() - 1 }
+}
+
+fn to_dimacs(literal: &Literal) -> i32 {
+    let variable = (literal.index + 1) as i32;
+    if literal.sign {
+        variable
+    } else {
+        -variable
+    }
+}
+
+unsafe fn as_solver<'a>(solver: *mut c_void) -> &'a mut IpasirSolver {
+    debug_assert!(!solver.is_null());
+    &mut *(solver as *mut IpasirSolver)
+}
+
+#[no_mangle]
+pub extern "C" fn ipasir_signature() -> *const c_char {
+    concat!("trial_sat-", env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+#[no_mangle]
+pub extern "C" fn ipasir_init() -> *mut c_void {
+    let solver = Box::new(IpasirSolver {
+        solver: SATSolver::new(),
+        clause: Array::default(),
+        assumptions: Vec::new(),
+        solution: None,
+        failed_assumptions: Vec::new(),
+    });
+    Box::into_raw(solver) as *mut c_void
+}
+
+/// # Safety
+///
+/// solver は ipasir_init が返したもので，まだ解放されていないこと．
+#[no_mangle]
+pub unsafe extern "C" fn ipasir_release(solver: *mut c_void) {
+    drop(Box::from_raw(solver as *mut IpasirSolver));
+}
+
+/// # Safety
+///
+/// solver は ipasir_init が返したもので，まだ解放されていないこと．
+#[no_mangle]
+pub unsafe extern "C" fn ipasir_add(solver: *mut c_void, lit_or_zero: i32) {
+    let solver = as_solver(solver);
+    solver.solution = None;
+    if lit_or_zero == 0 {
+        // SATSolver の節では変数が重複してはならないので，重複するリテラルを取り除き，恒真な節は追加しない
+        solver.clause.sort_by_cached_key(|literal| (literal.index, literal.sign));
+        let mut clause: Array<VariableSize, Literal> = Array::default();
+        let mut is_tautology = false;
+        for literal in solver.clause.iter() {
+            match clause.last().copied() {
+                Some(last) if last.index == literal.index => is_tautology |= last.sign != literal.sign,
+                _ => clause.push(*literal),
+            }
+        }
+        if !is_tautology {
+            solver.solver.add_clause(&clause);
+        }
+        solver.clause.clear();
+    } else {
+        solver.clause.push(to_literal(lit_or_zero));
+    }
+}
+
+/// # Safety
+///
+/// solver は ipasir_init が返したもので，まだ解放されていないこと．
+#[no_mangle]
+pub unsafe extern "C" fn ipasir_assume(solver: *mut c_void, lit: i32) {
+    let solver = as_solver(solver);
+    solver.assumptions.push(to_literal(lit));
+}
+
+/// 充足可能であれば 10，充足不可能であれば 20，中断された場合は 0 を返す
+///
+/// # Safety
+///
+/// solver は ipasir_init が返したもので，まだ解放されていないこと．
+#[no_mangle]
+pub unsafe extern "C" fn ipasir_solve(solver: *mut c_void) -> c_int {
+    let solver = as_solver(solver);
+    let result = solver.solver.solve_with_assumptions(&solver.assumptions);
+    // 仮定は 1 回の求解でのみ有効
+    solver.assumptions.clear();
+    solver.failed_assumptions.clear();
+    match result {
+        SATSolverResult::Satisfiable { solution } => {
+            solver.solution = Some(solution);
+            10
+        }
+        SATSolverResult::Unsatisfiable => {
+            solver.solution = None;
+            solver.failed_assumptions.extend(solver.solver.failed_assumptions().iter().copied());
+            20
+        }
+        SATSolverResult::Unknown => {
+            solver.solution = None;
+            0
+        }
+    }
+}
+
+/// 直前の ipasir_solve で得られた解で lit が真なら lit，偽なら -lit を返す(ソルバーが知らない変数であれば 0)
+///
+/// # Safety
+///
+/// solver は ipasir_init が返したもので，まだ解放されていないこと．
+#[no_mangle]
+pub unsafe extern "C" fn ipasir_val(solver: *mut c_void, lit: i32) -> i32 {
+    let solver = as_solver(solver);
+    let literal = to_literal(lit);
+    let Some(solution) = &solver.solution else {
+        return 0;
+    };
+    if literal.index >= solution.len() {
+        return 0;
+    }
+    if solution[literal.index] == literal.sign {
+        lit
+    } else {
+        -lit
+    }
+}
+
+/// 仮定 lit が直前の ipasir_solve で充足不可能となった原因に含まれていれば 1，そうでなければ 0 を返す
+///
+/// # Safety
+///
+/// solver は ipasir_init が返したもので，まだ解放されていないこと．
+#[no_mangle]
+pub unsafe extern "C" fn ipasir_failed(solver: *mut c_void, lit: i32) -> c_int {
+    let solver = as_solver(solver);
+    let literal = to_literal(lit);
+    solver.failed_assumptions.contains(&literal) as c_int
+}
+
+/// 探索中に terminate(data) を繰り返し呼び，0 以外が返されたら探索を中断する(terminate が NULL なら解除)
+///
+/// # Safety
+///
+/// solver は ipasir_init が返したもので，まだ解放されていないこと．terminate は ipasir_solve の間 data とともに呼べること．
+#[no_mangle]
+pub unsafe extern "C" fn ipasir_set_terminate(
+    solver: *mut c_void,
+    data: *mut c_void,
+    terminate: Option<unsafe extern "C" fn(data: *mut c_void) -> c_int>,
+) {
+    let solver = as_solver(solver);
+    let callback = terminate.map(|terminate| Box::new(move || terminate(data) != 0) as Box<dyn FnMut() -> bool>);
+    solver.solver.set_terminate_callback(callback);
+}
+
+/// 長さが max_length 以下の学習節が得られるたびに，0 で終わるリテラルの列として learn(data, clause) を呼ぶ(learn が NULL なら解除)
+///
+/// # Safety
+///
+/// solver は ipasir_init が返したもので，まだ解放されていないこと．learn は ipasir_solve の間 data とともに呼べること．
+#[no_mangle]
+pub unsafe extern "C" fn ipasir_set_learn(
+    solver: *mut c_void,
+    data: *mut c_void,
+    max_length: c_int,
+    learn: Option<unsafe extern "C" fn(data: *mut c_void, clause: *mut i32)>,
+) {
+    let solver = as_solver(solver);
+    let callback = learn.map(|learn| {
+        let mut buffer: Vec<i32> = Vec::new();
+        Box::new(move |clause: &Array<VariableSize, Literal>| {
+            buffer.clear();
+            buffer.extend(clause.iter().map(to_dimacs));
+            buffer.push(0);
+            learn(data, buffer.as_mut_ptr());
+        }) as Box<dyn FnMut(&Array<VariableSize, Literal>)>
+    });
+    solver.solver.set_learn_callback(max_length.max(0) as VariableSize, callback);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sat::test_helpers::Random;
+
+    /// 節と仮定(DIMACS 形式)をすべて充足する割り当てがあるかを全探索で調べる
+    fn is_satisfiable(n: u32, clauses: &[Vec<i32>], assumptions: &[i32]) -> bool {
+        let is_true = |values: u32, lit: i32| (values >> (lit.unsigned_abs() - 1) & 1 != 0) == (lit > 0);
+        (0..1u32 << n).any(|values| {
+            clauses.iter().all(|clause| clause.iter().any(|lit| is_true(values, *lit)))
+                && assumptions.iter().all(|lit| is_true(values, *lit))
+        })
+    }
+
+    #[test]
+    fn incremental_solving_matches_brute_force() {
+        let mut random = Random::new(1);
+        let n = 10;
+        for _ in 0..20 {
+            let solver = ipasir_init();
+            let mut clauses: Vec<Vec<i32>> = Vec::new();
+            // 節を少しずつ追加しながら，そのたびに仮定を変えて解く(節には同じ変数が重複することもある)
+            for _ in 0..10 {
+                for _ in 0..5 {
+                    let clause: Vec<i32> = (0..3)
+                        .map(|_| (random.next_below(n) + 1) as i32 * if random.next_bool() { 1 } else { -1 })
+                        .collect();
+                    for lit in clause.iter() {
+                        unsafe { ipasir_add(solver, *lit) };
+                    }
+                    unsafe { ipasir_add(solver, 0) };
+                    clauses.push(clause);
+                }
+                for _ in 0..3 {
+                    let assumptions: Vec<i32> = (0..random.next_below(4))
+                        .map(|_| (random.next_below(n) + 1) as i32 * if random.next_bool() { 1 } else { -1 })
+                        .collect();
+                    for lit in assumptions.iter() {
+                        unsafe { ipasir_assume(solver, *lit) };
+                    }
+                    match unsafe { ipasir_solve(solver) } {
+                        10 => {
+                            assert!(is_satisfiable(n, &clauses, &assumptions));
+                            // ipasir_val で読み出した解は節と仮定をすべて充足する(0 はどちらの値でもよい)
+                            let is_true = |lit: i32| {
+                                let value = unsafe { ipasir_val(solver, lit) };
+                                value == lit || value == 0
+                            };
+                            // ソルバーが知らない変数(まだ現れていないか，恒真な節にしか現れていない変数)の値は 0
+                            assert!((1..=n as i32).all(|variable| {
+                                let value = unsafe { ipasir_val(solver, variable) };
+                                (value == variable || value == -variable || value == 0)
+                                    && unsafe { ipasir_val(solver, -variable) } == value
+                            }));
+                            assert!(clauses.iter().all(|clause| clause.iter().any(|lit| is_true(*lit))));
+                            assert!(assumptions.iter().all(|lit| is_true(*lit)));
+                        }
+                        20 => {
+                            assert!(!is_satisfiable(n, &clauses, &assumptions));
+                            // 原因の仮定だけでも充足不可能
+                            let failed: Vec<i32> = assumptions
+                                .iter()
+                                .copied()
+                                .filter(|lit| unsafe { ipasir_failed(solver, *lit) } == 1)
+                                .collect();
+                            assert!(!is_satisfiable(n, &clauses, &failed));
+                        }
+                        result => panic!("unexpected result {}", result),
+                    }
+                }
+            }
+            unsafe { ipasir_release(solver) };
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid literal: -2147483648")]
+    fn int_min_is_rejected() {
+        to_literal(i32::MIN);
+    }
+}
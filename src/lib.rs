@@ -0,0 +1,3 @@
+pub mod finite_collections;
+pub mod ipasir;
+pub mod sat;
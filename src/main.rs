@@ -1,7 +1,11 @@
-mod finite_collections;
-mod sat;
-
-use sat::{SATSolver, SATSolverResult};
+use num::Zero;
+use trial_sat::finite_collections::Array;
+use trial_sat::sat;
+use trial_sat::sat::{
+    ConflictLearning, CubeGenerator, DecisionHeuristicKind, Literal, MUSResult, MaxSATAlgorithm, MaxSATResult,
+    PBConstraintHandling, PBSolverResult, PropagationOrder, PseudoBooleanEncoding, SATProblem, SATSolver,
+    SATSolverOptions, SATSolverResult, VariableSize,
+};
 
 // fn print_sat_problem(problem: &SATProblem) {
 //     for clause in problem.clauses.iter() {
@@ -18,40 +22,712 @@ use sat::{SATSolver, SATSolverResult};
 //     }
 // }
 
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("usage: trial_sat [options] < input.cnf");
+    eprintln!("       trial_sat --cube DEPTH [options] < input.cnf > output.icnf");
+    eprintln!("       trial_sat --conquer [options] < input.icnf");
+    eprintln!("       trial_sat --enumerate N(0: all) [--project V1,V2,...] [options] < input.cnf");
+    eprintln!("       trial_sat --count [options] < input.cnf");
+    eprintln!("       trial_sat --backbone [--project V1,V2,...] [options] < input.cnf");
+    eprintln!("       trial_sat --prime-implicant [options] < input.cnf");
+    eprintln!("       trial_sat --minimal-model [--project V1,V2,...] [options] < input.cnf");
+    eprintln!("       trial_sat --approx-count [--epsilon F] [--delta F] [options] < input.cnf");
+    eprintln!("       trial_sat --sample N [--epsilon F(> 1.71)] [options] < input.cnf");
+    eprintln!("       trial_sat pb [--pb-encoding native|bdd|adder] [options] < input.opb");
+    eprintln!("       trial_sat maxsat [--maxsat-algorithm oll|linear] [options] < input.wcnf");
+    eprintln!("       trial_sat mus [options] < input.(g)cnf > mus.cnf");
+    eprintln!("       trial_sat mcs [options] < input.(g)cnf");
+    eprintln!("options:");
+    eprintln!("  --decision-heuristic activity|vmtf|lrb|chb");
+    eprintln!("  --propagation-order fifo|score|lbd|length");
+    eprintln!("  --conflict-learning single|best-lbd|best-length|all");
+    eprintln!("  --max-analyzed-conflicts N");
+    eprintln!("  --local-search-flips N");
+    eprintln!("  --threads N (only without a mode)");
+    eprintln!("  --seed N");
+    eprintln!("  --random-decision-frequency F");
+    eprintln!("  --shuffle-initial-activity");
+    eprintln!("  --random-initial-phase");
+    eprintln!("  --random-tie-breaking");
+    eprintln!("  --detect-xors");
+    std::process::exit(1);
+}
+
+enum Mode {
+    Solve,
+    /// 先読みによってキューブを生成して iCNF 形式で出力する
+    Cube {
+        depth: u32,
+    },
+    /// iCNF 形式の各キューブを仮定として解く
+    Conquer,
+    /// 解を列挙して出力する(limit が None ならすべての解)
+    Enumerate {
+        limit: Option<usize>,
+    },
+    /// モデル数を数える("c p show" があれば射影モデル数)
+    Count,
+    /// すべての解で真になるリテラルを出力する
+    Backbone,
+    /// 解を素項(すべての節を充足する極小な部分割り当て)に縮めて出力する
+    PrimeImplicant,
+    /// 射影する変数について真の変数の集合が極小な解を出力する
+    MinimalModel,
+    /// ApproxMC で射影モデル数を近似する
+    ApproximateCount,
+    /// UniGen で射影した解をほぼ一様に count 個選んで出力する
+    Sample {
+        count: usize,
+    },
+    /// OPB 形式の擬似ブール最適化問題を解く
+    PB {
+        handling: PBConstraintHandling,
+    },
+    /// WCNF 形式の MaxSAT 問題を解く
+    MaxSAT {
+        algorithm: MaxSATAlgorithm,
+    },
+    /// (G)CNF 形式の充足不能な問題から MUS を求めて DIMACS 形式で出力する
+    Mus,
+    /// (G)CNF 形式の問題の MCS(グループ 0 以外のグループの集合)をすべて出力する
+    Mcs,
+}
+
+struct Arguments {
+    options: SATSolverOptions,
+    threads: usize,
+    mode: Mode,
+    detect_xors: bool,
+    // 解を列挙するときに射影する変数(None ならすべての変数)
+    projection: Option<Vec<VariableSize>>,
+    // 近似の許容誤差(None なら近似数え上げでは 0.8，サンプリングでは 16)と失敗確率
+    epsilon: Option<f64>,
+    delta: f64,
+}
+
+fn parse_arguments() -> Arguments {
+    let mut options = SATSolverOptions::default();
+    let mut threads = 1;
+    let mut mode = Mode::Solve;
+    let mut detect_xors = false;
+    let mut projection = None;
+    let mut epsilon = None;
+    let mut delta = 0.2;
+    let mut arguments = std::env::args().skip(1).peekable();
+    match arguments.peek().map(|argument| argument.as_str()) {
+        Some("pb") => {
+            arguments.next();
+            mode = Mode::PB { handling: PBConstraintHandling::Native };
+        }
+        Some("maxsat") => {
+            arguments.next();
+            mode = Mode::MaxSAT { algorithm: MaxSATAlgorithm::OLL };
+        }
+        Some("mus") => {
+            arguments.next();
+            mode = Mode::Mus;
+        }
+        Some("mcs") => {
+            arguments.next();
+            mode = Mode::Mcs;
+        }
+        _ => {}
+    }
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--decision-heuristic" => {
+                let value =
+                    arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --decision-heuristic"));
+                options.decision_heuristic = match value.as_str() {
+                    "activity" => DecisionHeuristicKind::Activity,
+                    "vmtf" => DecisionHeuristicKind::Vmtf,
+                    "lrb" => DecisionHeuristicKind::Lrb,
+                    "chb" => DecisionHeuristicKind::Chb,
+                    _ => exit_with_usage(&format!("unknown decision heuristic: {}", value)),
+                };
+            }
+            "--propagation-order" => {
+                let value = arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --propagation-order"));
+                options.propagation_order = match value.as_str() {
+                    "fifo" => PropagationOrder::FIFO,
+                    "score" => PropagationOrder::Score,
+                    "lbd" => PropagationOrder::LBD,
+                    "length" => PropagationOrder::Length,
+                    _ => exit_with_usage(&format!("unknown propagation order: {}", value)),
+                };
+            }
+            "--conflict-learning" => {
+                let value = arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --conflict-learning"));
+                options.conflict_learning = match value.as_str() {
+                    "single" => ConflictLearning::Single,
+                    "best-lbd" => ConflictLearning::BestLBD,
+                    "best-length" => ConflictLearning::BestLength,
+                    "all" => ConflictLearning::All,
+                    _ => exit_with_usage(&format!("unknown conflict learning: {}", value)),
+                };
+            }
+            "--max-analyzed-conflicts" => {
+                let value =
+                    arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --max-analyzed-conflicts"));
+                options.max_analyzed_conflicts = match value.parse() {
+                    Ok(n) if n >= 1 => n,
+                    _ => exit_with_usage(&format!("invalid number of conflicts: {}", value)),
+                };
+            }
+            "--local-search-flips" => {
+                let value =
+                    arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --local-search-flips"));
+                options.local_search_flips = match value.parse() {
+                    Ok(n) => n,
+                    _ => exit_with_usage(&format!("invalid number of flips: {}", value)),
+                };
+            }
+            "--threads" => {
+                let value = arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --threads"));
+                threads = match value.parse() {
+                    Ok(n) if n >= 1 => n,
+                    _ => exit_with_usage(&format!("invalid number of threads: {}", value)),
+                };
+            }
+            "--seed" => {
+                let value = arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --seed"));
+                options.seed = match value.parse() {
+                    Ok(n) => n,
+                    _ => exit_with_usage(&format!("invalid seed: {}", value)),
+                };
+            }
+            "--random-decision-frequency" => {
+                let value =
+                    arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --random-decision-frequency"));
+                options.random_decision_frequency = match value.parse() {
+                    Ok(f) if (0.0..=1.0).contains(&f) => f,
+                    _ => exit_with_usage(&format!("invalid frequency: {}", value)),
+                };
+            }
+            "--shuffle-initial-activity" => {
+                options.shuffle_initial_activity = true;
+            }
+            "--random-initial-phase" => {
+                options.randomize_initial_phase = true;
+            }
+            "--random-tie-breaking" => {
+                options.random_tie_breaking = true;
+            }
+            "--detect-xors" => {
+                detect_xors = true;
+            }
+            "--cube" => {
+                let value = arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --cube"));
+                mode = match value.parse() {
+                    Ok(depth) => Mode::Cube { depth },
+                    _ => exit_with_usage(&format!("invalid depth: {}", value)),
+                };
+            }
+            "--conquer" => {
+                mode = Mode::Conquer;
+            }
+            "--enumerate" => {
+                let value = arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --enumerate"));
+                mode = match value.parse() {
+                    Ok(0) => Mode::Enumerate { limit: None },
+                    Ok(limit) => Mode::Enumerate { limit: Some(limit) },
+                    _ => exit_with_usage(&format!("invalid number of models: {}", value)),
+                };
+            }
+            "--count" => {
+                mode = Mode::Count;
+            }
+            "--backbone" => {
+                mode = Mode::Backbone;
+            }
+            "--prime-implicant" => {
+                mode = Mode::PrimeImplicant;
+            }
+            "--minimal-model" => {
+                mode = Mode::MinimalModel;
+            }
+            "--approx-count" => {
+                mode = Mode::ApproximateCount;
+            }
+            "--sample" => {
+                let value = arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --sample"));
+                mode = match value.parse() {
+                    Ok(count) => Mode::Sample { count },
+                    _ => exit_with_usage(&format!("invalid number of samples: {}", value)),
+                };
+            }
+            "--epsilon" => {
+                let value = arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --epsilon"));
+                epsilon = match value.parse::<f64>() {
+                    Ok(value) if value > 0.0 => Some(value),
+                    _ => exit_with_usage(&format!("invalid epsilon: {}", value)),
+                };
+            }
+            "--delta" => {
+                let value = arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --delta"));
+                delta = match value.parse::<f64>() {
+                    Ok(value) if 0.0 < value && value < 1.0 => value,
+                    _ => exit_with_usage(&format!("invalid delta: {}", value)),
+                };
+            }
+            "--project" => {
+                let value = arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --project"));
+                let variables: Result<Vec<VariableSize>, _> = value
+                    .split(',')
+                    .filter(|field| !field.is_empty())
+                    .map(|field| field.parse::<VariableSize>())
+                    .collect();
+                projection = match variables {
+                    Ok(variables) if variables.iter().all(|variable| *variable >= 1) => {
+                        Some(variables.iter().map(|variable| variable - 1).collect())
+                    }
+                    _ => exit_with_usage(&format!("invalid variables: {}", value)),
+                };
+            }
+            "--pb-encoding" if matches!(mode, Mode::PB { .. }) => {
+                let value = arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --pb-encoding"));
+                mode = Mode::PB {
+                    handling: match value.as_str() {
+                        "native" => PBConstraintHandling::Native,
+                        "bdd" => PBConstraintHandling::Encoding(PseudoBooleanEncoding::BDD),
+                        "adder" => PBConstraintHandling::Encoding(PseudoBooleanEncoding::Adder),
+                        _ => exit_with_usage(&format!("unknown pb encoding: {}", value)),
+                    },
+                };
+            }
+            "--maxsat-algorithm" if matches!(mode, Mode::MaxSAT { .. }) => {
+                let value = arguments.next().unwrap_or_else(|| exit_with_usage("missing value of --maxsat-algorithm"));
+                mode = Mode::MaxSAT {
+                    algorithm: match value.as_str() {
+                        "oll" => MaxSATAlgorithm::OLL,
+                        "linear" => MaxSATAlgorithm::Linear,
+                        _ => exit_with_usage(&format!("unknown maxsat algorithm: {}", value)),
+                    },
+                };
+            }
+            _ => exit_with_usage(&format!("unknown argument: {}", argument)),
+        }
+    }
+    // 並列に解くのは充足可能性の判定のみ
+    if threads > 1 && !matches!(mode, Mode::Solve) {
+        exit_with_usage("--threads is supported only when solving a CNF without a mode");
+    }
+    Arguments { options, threads, mode, detect_xors, projection, epsilon, delta }
+}
+
+/// OPB 形式の問題を解いて PB competition の形式で出力する
+fn solve_pb(options: &SATSolverOptions, handling: PBConstraintHandling) {
+    let problem = sat::read_opb(std::io::BufReader::new(std::io::stdin()));
+    let result = sat::solve_pb(&problem, options, handling, |value| println!("o {}", value));
+    let solution = match &result {
+        PBSolverResult::Optimum { value, solution } => {
+            println!("s {}", if value.is_some() { "OPTIMUM FOUND" } else { "SATISFIABLE" });
+            solution
+        }
+        PBSolverResult::Satisfiable { solution, .. } => {
+            println!("s SATISFIABLE");
+            solution
+        }
+        PBSolverResult::Unsatisfiable => {
+            println!("s UNSATISFIABLE");
+            return;
+        }
+        PBSolverResult::Unknown => {
+            println!("s UNKNOWN");
+            return;
+        }
+    };
+    // チェック
+    for constraint in problem.constraints.iter() {
+        let sum: i64 = constraint
+            .terms
+            .iter()
+            .filter(|(_, literal)| solution[literal.index] == literal.sign)
+            .map(|(weight, _)| *weight)
+            .sum();
+        let is_satisfied = match constraint.relation {
+            sat::PBRelation::GreaterEqual => sum >= constraint.bound,
+            sat::PBRelation::Equal => sum == constraint.bound,
+            sat::PBRelation::LessEqual => sum <= constraint.bound,
+        };
+        if !is_satisfied {
+            eprintln!("BAGUTTERU!");
+            return;
+        }
+    }
+    for (variable_index, literals) in problem.products.iter() {
+        if solution[*variable_index] != literals.iter().all(|literal| solution[literal.index] == literal.sign) {
+            eprintln!("BAGUTTERU!");
+            return;
+        }
+    }
+    let values: Vec<String> = (0..problem.number_of_variables)
+        .map(|variable_index| format!("{}x{}", if solution[variable_index] { "" } else { "-" }, variable_index + 1))
+        .collect();
+    println!("v {}", values.join(" "));
+}
+
+/// WCNF 形式の問題を解いて MaxSAT Evaluation の形式で出力する
+fn solve_maxsat(options: &SATSolverOptions, algorithm: MaxSATAlgorithm) {
+    let problem = sat::read_wcnf(std::io::BufReader::new(std::io::stdin()));
+    let (result, statistics) = sat::solve_maxsat(&problem, options, algorithm, |cost| println!("o {}", cost));
+    eprintln!("core_count={} totalizer_count={}", statistics.core_count, statistics.totalizer_count);
+    let (cost, solution) = match &result {
+        MaxSATResult::Optimum { cost, solution } => {
+            println!("s OPTIMUM FOUND");
+            (*cost, solution)
+        }
+        MaxSATResult::Satisfiable { cost, solution } => {
+            println!("s SATISFIABLE");
+            (*cost, solution)
+        }
+        MaxSATResult::Unsatisfiable => {
+            println!("s UNSATISFIABLE");
+            return;
+        }
+        MaxSATResult::Unknown => {
+            println!("s UNKNOWN");
+            return;
+        }
+    };
+    // チェック
+    let is_satisfied =
+        |clause: &Array<VariableSize, Literal>| clause.iter().any(|literal| solution[literal.index] == literal.sign);
+    if !problem.hard_clauses.iter().all(is_satisfied)
+        || problem
+            .soft_clauses
+            .iter()
+            .filter(|(_, clause)| !is_satisfied(clause))
+            .map(|(weight, _)| *weight)
+            .sum::<u64>()
+            != cost
+    {
+        eprintln!("BAGUTTERU!");
+        return;
+    }
+    let values: String = (0..problem.number_of_variables)
+        .map(|variable_index| if solution[variable_index] { '1' } else { '0' })
+        .collect();
+    println!("v {}", values);
+}
+
+/// solution が問題のすべての節と XOR 制約を充足するか
+fn is_solution(problem: &SATProblem, solution: &Array<VariableSize, bool>) -> bool {
+    problem.clauses.iter().all(|clause| clause.iter().any(|literal| solution[literal.index] == literal.sign))
+        && problem.xors.iter().all(|(xor_variables, parity)| {
+            xor_variables.iter().fold(false, |value, variable_index| value ^ solution[*variable_index]) == *parity
+        })
+}
+
+/// (G)CNF 形式の問題から MUS を求め，ハード節と MUS のグループの節を DIMACS 形式で出力する
+fn extract_mus(options: &SATSolverOptions) {
+    let problem = sat::read_gcnf(std::io::BufReader::new(std::io::stdin()));
+    let groups = match sat::extract_mus(&problem, options) {
+        MUSResult::Unsatisfiable { groups, solve_count, rotation_count } => {
+            eprintln!("solve_count={} rotation_count={}", solve_count, rotation_count);
+            groups
+        }
+        MUSResult::Satisfiable => {
+            println!("s SATISFIABLE");
+            return;
+        }
+        MUSResult::Unknown => {
+            println!("s UNKNOWN");
+            return;
+        }
+    };
+    let mut is_selected = vec![false; problem.number_of_groups as usize];
+    is_selected[0] = true;
+    for group in groups.iter() {
+        is_selected[*group as usize] = true;
+    }
+    let clauses: Vec<&Array<VariableSize, Literal>> =
+        problem.clauses.iter().filter(|(group, _)| is_selected[*group as usize]).map(|(_, clause)| clause).collect();
+    let group_names: Vec<String> = groups.iter().map(|group| group.to_string()).collect();
+    println!("c mus_groups={}", group_names.join(" "));
+    println!("p cnf {} {}", problem.number_of_variables, clauses.len());
+    for clause in clauses {
+        let literals: Vec<String> = clause
+            .iter()
+            .map(|literal| format!("{}{}", if literal.sign { "" } else { "-" }, literal.index + 1))
+            .collect();
+        println!("{} 0", literals.join(" "));
+    }
+}
+
+/// (G)CNF 形式の問題の MCS を 1 行に 1 つずつ "v グループ ... 0" の形式で出力する
+fn enumerate_mcses(options: &SATSolverOptions) {
+    let problem = sat::read_gcnf(std::io::BufReader::new(std::io::stdin()));
+    let mut enumerator = sat::MCSEnumerator::new(&problem, options);
+    let mut mcs_count = 0;
+    for mcs in enumerator.by_ref() {
+        let groups: Vec<String> = mcs.iter().map(|group| group.to_string()).collect();
+        println!("v {} 0", groups.join(" "));
+        mcs_count += 1;
+    }
+    eprintln!("solve_count={}", enumerator.solve_count);
+    println!("c mcs_count={}", mcs_count);
+}
+
 fn main() {
-    let problem = sat::read_cnf(std::io::BufReader::new(std::io::stdin()));
+    let arguments = parse_arguments();
+    if let Mode::Mcs = arguments.mode {
+        enumerate_mcses(&arguments.options);
+        return;
+    }
+    if let Mode::Mus = arguments.mode {
+        extract_mus(&arguments.options);
+        return;
+    }
+    if let Mode::PB { handling } = arguments.mode {
+        solve_pb(&arguments.options, handling);
+        return;
+    }
+    if let Mode::MaxSAT { algorithm } = arguments.mode {
+        solve_maxsat(&arguments.options, algorithm);
+        return;
+    }
+    let (problem, cubes) = match arguments.mode {
+        Mode::Conquer => sat::read_icnf(std::io::BufReader::new(std::io::stdin())),
+        _ => (sat::read_cnf(std::io::BufReader::new(std::io::stdin())), Default::default()),
+    };
+    let problem = if arguments.detect_xors { sat::detect_xors(&problem) } else { problem };
     // print_sat_problem(&problem);
 
-    let mut solver = SATSolver::new();
-    for clause in problem.clauses.iter() {
-        solver.add_clause(clause);
+    if let Mode::Cube { depth } = arguments.mode {
+        let mut solver = SATSolver::with_options(arguments.options);
+        solver.add_problem(&problem);
+        let mut cube_generator = CubeGenerator::new(depth);
+        let cubes = cube_generator.generate(&mut solver);
+        eprintln!(
+            "cube_count={} refuted_count={} failed_literal_count={}",
+            cubes.len(),
+            cube_generator.refuted_count(),
+            cube_generator.failed_literal_count()
+        );
+        sat::write_icnf(&mut std::io::BufWriter::new(std::io::stdout()), &problem, &cubes).unwrap();
+        return;
+    }
+
+    if let Mode::Count = arguments.mode {
+        let (count, statistics) = sat::count_models(&problem, &arguments.options);
+        eprintln!(
+            "decision_count={} conflict_count={} cache_hit_count={} cache_size={}",
+            statistics.decision_count, statistics.conflict_count, statistics.cache_hit_count, statistics.cache_size
+        );
+        println!("s {}", if count.is_zero() { "UNSATISFIABLE" } else { "SATISFIABLE" });
+        println!("c s type {}", if problem.projection.is_some() { "pmc" } else { "mc" });
+        println!("c s exact arb int {}", count);
+        return;
     }
-    let result = solver.solve();
-    let summary = solver.summary();
-    eprintln!(
-        "check_count={}, skip_by_cached_count={}, skip_by_another_count={}, propagation_count={}, conflict_count={} restart_count={}",
-        summary.0, summary.1, summary.2, summary.3, summary.4, summary.5
-    );
+
+    // 射影する変数は引数で指定されたもの，入力の "c p show" で指定されたもの，すべての変数の順に選ぶ
+    let projection = arguments
+        .projection
+        .clone()
+        .or_else(|| problem.projection.as_ref().map(|projection| projection.iter().copied().collect()))
+        .unwrap_or_else(|| (0..problem.number_of_variables).collect::<Vec<VariableSize>>());
+
+    if let Mode::Backbone = arguments.mode {
+        let mut solver = SATSolver::with_options(arguments.options);
+        solver.add_problem(&problem);
+        let Some((backbone, solve_count)) = solver.backbone(&projection) else {
+            println!("s UNSATISFIABLE");
+            return;
+        };
+        eprintln!("backbone_size={} solve_count={}", backbone.len(), solve_count);
+        println!("s SATISFIABLE");
+        let literals: Vec<String> = backbone
+            .iter()
+            .map(|literal| format!("{}{}", if literal.sign { "" } else { "-" }, literal.index + 1))
+            .collect();
+        println!("v {} 0", literals.join(" "));
+        return;
+    }
+
+    if let Mode::PrimeImplicant = arguments.mode {
+        let mut solver = SATSolver::with_options(arguments.options);
+        solver.add_problem(&problem);
+        let SATSolverResult::Satisfiable { solution } = solver.solve() else {
+            println!("s UNSATISFIABLE");
+            return;
+        };
+        let implicant = sat::prime_implicant(&problem, &solution);
+        // チェック(外した変数を反対の値にしても解になる)
+        let mut flipped_solution = solution.clone();
+        for variable_index in 0..problem.number_of_variables {
+            if implicant[variable_index].is_none() {
+                flipped_solution[variable_index] = !flipped_solution[variable_index];
+            }
+        }
+        if !is_solution(&problem, &solution) || !is_solution(&problem, &flipped_solution) {
+            eprintln!("BAGUTTERU!");
+            return;
+        }
+        println!("s SATISFIABLE");
+        let literals: Vec<String> = (0..problem.number_of_variables)
+            .filter_map(|variable_index| {
+                implicant[variable_index].map(|value| format!("{}{}", if value { "" } else { "-" }, variable_index + 1))
+            })
+            .collect();
+        println!("v {} 0", literals.join(" "));
+        return;
+    }
+
+    if let Mode::MinimalModel = arguments.mode {
+        let mut solver = SATSolver::with_options(arguments.options);
+        solver.add_problem(&problem);
+        let Some((solution, solve_count)) = sat::minimal_model(&mut solver, &projection) else {
+            println!("s UNSATISFIABLE");
+            return;
+        };
+        eprintln!("solve_count={}", solve_count);
+        if !is_solution(&problem, &solution) {
+            eprintln!("BAGUTTERU!");
+            return;
+        }
+        println!("s SATISFIABLE");
+        let literals: Vec<String> = (0..problem.number_of_variables)
+            .map(|variable_index| format!("{}{}", if solution[variable_index] { "" } else { "-" }, variable_index + 1))
+            .collect();
+        println!("v {} 0", literals.join(" "));
+        return;
+    }
+
+    if let Mode::ApproximateCount = arguments.mode {
+        let seed = arguments.options.seed;
+        let mut solver = SATSolver::with_options(arguments.options);
+        solver.add_problem(&problem);
+        let count =
+            sat::approximate_count(&mut solver, &projection, arguments.epsilon.unwrap_or(0.8), arguments.delta, seed);
+        println!("s {}", if count.is_zero() { "UNSATISFIABLE" } else { "SATISFIABLE" });
+        println!("c s type {}", if projection.len() < problem.number_of_variables as usize { "pmc" } else { "mc" });
+        println!("c s approx arb int {}", count);
+        return;
+    }
+
+    if let Mode::Sample { count } = arguments.mode {
+        let epsilon = arguments.epsilon.unwrap_or(16.0);
+        if epsilon <= 1.71 {
+            exit_with_usage(&format!("epsilon of sampling must be greater than 1.71: {}", epsilon));
+        }
+        let seed = arguments.options.seed;
+        let mut solver = SATSolver::with_options(arguments.options);
+        solver.add_problem(&problem);
+        let mut sampler = sat::UniformSampler::new(&mut solver, &projection, epsilon, seed);
+        eprintln!(
+            "kappa={:.3} pivot={} low_threshold={} high_threshold={} hash_length={}",
+            sampler.kappa, sampler.pivot, sampler.low_threshold, sampler.high_threshold, sampler.hash_length
+        );
+        // 適当な大きさのセルが見つからずに失敗することがあるので，続けて失敗できる回数に上限を設ける
+        let max_consecutive_failure_count = 100;
+        let mut sample_count = 0;
+        let mut consecutive_failure_count = 0;
+        let mut is_unsatisfiable = false;
+        while sample_count < count {
+            let Some(solution) = sampler.sample(&mut solver) else {
+                if sampler.failure_count == 0 {
+                    // 解がない
+                    is_unsatisfiable = true;
+                    break;
+                }
+                consecutive_failure_count += 1;
+                if consecutive_failure_count >= max_consecutive_failure_count {
+                    eprintln!("sampling failed {} times in a row", consecutive_failure_count);
+                    break;
+                }
+                continue;
+            };
+            consecutive_failure_count = 0;
+            if !is_solution(&problem, &solution) {
+                eprintln!("BAGUTTERU!");
+                return;
+            }
+            let literals: Vec<String> = projection
+                .iter()
+                .map(|variable_index| {
+                    format!("{}{}", if solution[*variable_index] { "" } else { "-" }, variable_index + 1)
+                })
+                .collect();
+            println!("v {} 0", literals.join(" "));
+            sample_count += 1;
+        }
+        eprintln!("sample_count={} failure_count={}", sample_count, sampler.failure_count);
+        println!("s {}", if is_unsatisfiable { "UNSATISFIABLE" } else { "SATISFIABLE" });
+        return;
+    }
+
+    if let Mode::Enumerate { limit } = arguments.mode {
+        let mut solver = SATSolver::with_options(arguments.options);
+        solver.add_problem(&problem);
+        let mut is_correct = true;
+        let model_count = solver.enumerate_models(&projection, limit, |solution| {
+            is_correct &= is_solution(&problem, solution);
+            let literals: Vec<String> = projection
+                .iter()
+                .map(|variable_index| {
+                    format!("{}{}", if solution[*variable_index] { "" } else { "-" }, variable_index + 1)
+                })
+                .collect();
+            println!("v {} 0", literals.join(" "));
+        });
+        if !is_correct {
+            eprintln!("BAGUTTERU!");
+            return;
+        }
+        println!("c model_count={}", model_count);
+        println!("{}", if model_count > 0 { "SATISFIABLE" } else { "UNSATISFIABLE" });
+        return;
+    }
+
+    let result = if let Mode::Conquer = arguments.mode {
+        let mut solver = SATSolver::with_options(arguments.options);
+        solver.add_problem(&problem);
+        sat::conquer(&mut solver, &cubes)
+    } else if arguments.threads > 1 {
+        sat::solve_portfolio(&problem, &arguments.options, arguments.threads)
+    } else {
+        let mut solver = SATSolver::with_options(arguments.options);
+        solver.add_problem(&problem);
+        let result = solver.solve();
+        let summary = solver.summary();
+        eprintln!(
+            "check_count={}, skip_by_cached_count={}, skip_by_another_count={}, propagation_count={}, conflict_count={} restart_count={}",
+            summary.0, summary.1, summary.2, summary.3, summary.4, summary.5
+        );
+        let (propagation_order, propagation_statistics) = solver.propagation_summary();
+        eprintln!(
+            "propagation_order={:?}, tentative_assignment_count={}, reason_replacement_count={}, conflict_detection_count={}, max_queue_length={}, queue_comparison_count={}, conflict_comparison_count={}",
+            propagation_order,
+            propagation_statistics.tentative_assignment_count,
+            propagation_statistics.reason_replacement_count,
+            propagation_statistics.conflict_detection_count,
+            propagation_statistics.max_queue_length,
+            propagation_statistics.queue_comparison_count,
+            propagation_statistics.conflict_comparison_count
+        );
+        let xor_summary = solver.xor_summary();
+        eprintln!(
+            "xor_row_count={}, xor_propagation_count={}, xor_elimination_count={}",
+            xor_summary.0, xor_summary.1, xor_summary.2
+        );
+        result
+    };
     match result {
         SATSolverResult::Satisfiable { solution } => {
             // チェック
-            for clause in problem.clauses.iter() {
-                let mut is_satisfied = false;
-                for literal in clause.iter() {
-                    if solution[literal.index] == literal.sign {
-                        is_satisfied = true;
-                        break;
-                    }
-                }
-                if !is_satisfied {
-                    eprintln!("BAGUTTERU!");
-                    return;
-                }
+            if !is_solution(&problem, &solution) {
+                eprintln!("BAGUTTERU!");
+                return;
             }
             println!("SATISFIABLE");
         }
         SATSolverResult::Unsatisfiable => {
             println!("UNSATISFIABLE");
         }
+        SATSolverResult::Unknown => {
+            println!("UNKNOWN");
+        }
     }
 }
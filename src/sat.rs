@@ -1,16 +1,58 @@
 mod analyze;
 mod calculate_lbd;
+mod cardinality_theory;
 mod clause_theory;
+mod cube;
+mod decision_heuristic;
+mod detect_xor;
+mod encoding;
+mod hashing;
+mod local_search;
+mod maxsat;
+mod mcs;
+mod minimize;
+mod model_counter;
+mod mus;
+mod pb;
+mod portfolio;
+mod random;
 mod read_cnf;
+mod read_opb;
 mod simplify;
 mod solver;
+#[cfg(test)]
+pub(crate) mod test_helpers;
+mod theory;
 // mod tentative_assigned_variable_queue;
 mod types;
 // mod unassigned_variable_queue;
+mod user_propagator;
 // mod variable_manager;
 mod variables;
+mod xor_theory;
 
-pub use read_cnf::read_cnf;
+pub use cube::{conquer, write_icnf, CubeGenerator};
+pub use decision_heuristic::DecisionHeuristicKind;
+pub use detect_xor::detect_xors;
+pub use encoding::{
+    add_at_least_k, add_at_most_k, add_at_most_one, add_exactly_one, add_pseudo_boolean_at_least,
+    add_pseudo_boolean_at_most, AtMostOneEncoding, CardinalityEncoding, PseudoBooleanEncoding, Totalizer,
+};
+pub use hashing::{approximate_count, UniformSampler};
+pub use maxsat::{solve_maxsat, MaxSATAlgorithm, MaxSATResult, MaxSATStatistics};
+pub use mcs::MCSEnumerator;
+pub use minimize::{minimal_model, prime_implicant};
+pub use model_counter::{count_models, ModelCountingStatistics};
+pub use mus::{extract_mus, MUSResult};
+pub use pb::{solve_pb, PBConstraintHandling, PBSolverResult};
+pub use portfolio::solve_portfolio;
+pub use read_cnf::{read_cnf, read_gcnf, read_icnf, read_wcnf, GroupedProblem, MaxSATProblem, SATProblem};
+pub use read_opb::{read_opb, PBConstraint, PBProblem, PBRelation};
 
+pub use solver::ConflictLearning;
 pub use solver::SATSolver;
+pub use solver::SATSolverOptions;
 pub use solver::SATSolverResult;
+pub use types::{Literal, VariableSize};
+pub use user_propagator::{UserPropagation, UserPropagator};
+pub use variables::PropagationOrder;
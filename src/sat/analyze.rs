@@ -1,7 +1,7 @@
-use crate::finite_collections::{Array, Comparator, FiniteHeapedMap};
+use crate::finite_collections::{Array, Comparator, FiniteHeapedMap, Set};
 
-use super::clause_theory::ClauseTheory;
 use super::simplify::Simplify;
+use super::theory::Theories;
 use super::types::{Literal, Reason, VariableSize};
 use super::variables::{VariableState, Variables};
 
@@ -18,7 +18,8 @@ struct AnalyzerBufferComparator {}
 impl Comparator<VariableSize, AnalyzerBufferValue> for AnalyzerBufferComparator {
     #[inline(always)]
     fn compare(lhs: &(u32, AnalyzerBufferValue), rhs: &(u32, AnalyzerBufferValue)) -> std::cmp::Ordering {
-        rhs.1.assignment_level.cmp(&lhs.1.assignment_level)
+        // NOTE: 時系列バックトラックを行うと割当レベルの順序と決定レベルの順序が一致するとは限らないので，決定レベルを優先して比較
+        (rhs.1.decision_level, rhs.1.assignment_level).cmp(&(lhs.1.decision_level, lhs.1.assignment_level))
     }
 }
 
@@ -27,17 +28,23 @@ pub struct Analyze {
     simplify: Simplify,
     literals: Array<VariableSize, Literal>,
     analyzer_buffer: FiniteHeapedMap<VariableSize, AnalyzerBufferValue, AnalyzerBufferComparator>,
+    seen_variables: Set<VariableSize>,
+    variable_stack: Array<VariableSize, VariableSize>,
 }
 
 impl Analyze {
+    /// 矛盾を解析して学習節を求める
+    ///
+    /// (矛盾の決定レベル, 学習節によって伝播が可能になる決定レベル, 学習節) を返す．
+    /// 時系列バックトラックを行っている場合には，矛盾の決定レベルは現在の決定レベルより小さいことがある．
     #[inline(never)]
     pub fn analyze(
         &mut self,
         conflicting_variable_index: VariableSize,
         reasons: [Reason; 2],
         variables: &mut Variables,
-        theory: &mut ClauseTheory,
-    ) -> (VariableSize, Array<VariableSize, Literal>) {
+        theories: &Theories,
+    ) -> (VariableSize, VariableSize, Array<VariableSize, Literal>) {
         self.analyzer_buffer.clear();
         if self.analyzer_buffer.capacity() < variables.dimension() {
             self.analyzer_buffer.reserve(variables.dimension() - self.analyzer_buffer.capacity());
@@ -46,14 +53,47 @@ impl Analyze {
         for (reason, value) in reasons.iter().zip([false, true]) {
             // 矛盾が生じている変数のアクティビティを増大
             variables.increase_activity(conflicting_variable_index);
-            self.resolve(conflicting_variable_index, value, *reason, variables, theory);
+            self.resolve(conflicting_variable_index, value, *reason, variables, theories);
         }
+        self.learn(variables, theories)
+    }
+
+    /// すべてのリテラルに偽が割り当てられている節(外部の伝播器が返した矛盾)を解析して学習節を求める
+    ///
+    /// 返り値は analyze と同じ．
+    #[inline(never)]
+    pub fn analyze_clause(
+        &mut self,
+        conflicting_clause: &Array<VariableSize, Literal>,
+        variables: &mut Variables,
+        theories: &Theories,
+    ) -> (VariableSize, VariableSize, Array<VariableSize, Literal>) {
+        debug_assert!(conflicting_clause.iter().all(|l| variables.get(l.index).is_value_assigned(!l.sign)));
+        self.analyzer_buffer.clear();
+        if self.analyzer_buffer.capacity() < variables.dimension() {
+            self.analyzer_buffer.reserve(variables.dimension() - self.analyzer_buffer.capacity());
+        }
+        self.literals.clone_from(conflicting_clause);
+        self.merge_literals(variables);
+        self.learn(variables, theories)
+    }
+
+    /// analyzer_buffer の節をバックジャンプ可能になるまで融合して学習節を求める
+    fn learn(
+        &mut self,
+        variables: &mut Variables,
+        theories: &Theories,
+    ) -> (VariableSize, VariableSize, Array<VariableSize, Literal>) {
+        if self.analyzer_buffer.is_empty() {
+            // 節融合の結果が空になった場合には空の学習節を返す(Unsatisifiable)
+            return (0, 0, Array::default());
+        }
+        // 矛盾の決定レベル(節融合の結果に含まれるリテラルの決定レベルの最大値)
+        let conflict_decision_level = self.analyzer_buffer.first_key_value().unwrap().1.decision_level;
+        debug_assert!(conflict_decision_level <= variables.current_decision_level());
         // バックジャンプ可能な節が獲られるまで融合を繰り返す
         loop {
-            if self.analyzer_buffer.len() == 0 {
-                // 節融合の結果が空になった場合には空の学習節を返す(Unsatisifiable)
-                return (0, Array::default());
-            }
+            debug_assert!(!self.analyzer_buffer.is_empty());
             {
                 // バックジャンプ可能かを判定
                 // 二分ヒープの先頭 3 つの decision_level を取得
@@ -62,11 +102,11 @@ impl Analyze {
                 let first_three_decision_levels =
                     Vec::from_iter(self.analyzer_buffer.iter().take(3).map(|item| item.1.decision_level));
                 assert!(first_three_decision_levels.len() >= 1);
-                // 最も大きい決定レベルは現在の決定レベルと同じであるはず
-                assert!(first_three_decision_levels[0] == variables.current_decision_level());
+                // 最も大きい決定レベルは矛盾の決定レベルと同じであるはず
+                assert!(first_three_decision_levels[0] == conflict_decision_level);
                 // 2 番目に大きい決定レベルを取得
                 let second_largest_decision_level = first_three_decision_levels[1..].iter().max().unwrap_or(&0);
-                if *second_largest_decision_level < variables.current_decision_level() {
+                if *second_largest_decision_level < conflict_decision_level {
                     // 2 番目に大きい決定レベルが現在の決定レベル未満であればバックジャンプ可能なので，現在の節を学習節として返す
                     let mut learnt_clause = Array::default();
                     learnt_clause.reserve(self.analyzer_buffer.len());
@@ -74,12 +114,12 @@ impl Analyze {
                         learnt_clause.push(Literal { index: *variable_index, sign: buffer_value.sign });
                     }
                     // simplify
-                    self.simplify.simplify(&mut learnt_clause, variables, theory);
+                    self.simplify.simplify(&mut learnt_clause, variables, theories);
                     // 学習節に含まれる変数のアクティビティを増大
                     for literal in learnt_clause.iter() {
                         variables.increase_activity(literal.index);
                     }
-                    return (*second_largest_decision_level, learnt_clause);
+                    return (conflict_decision_level, *second_largest_decision_level, learnt_clause);
                 }
             }
             // 最大割り当てレベル(節融合による消去対象)の変数を選択
@@ -89,10 +129,103 @@ impl Analyze {
             // 消去対象の変数のアクティビティを増大
             variables.increase_activity(variable_index);
             // 節融合
-            self.resolve(variable_index, value, reason, variables, theory);
+            self.resolve(variable_index, value, reason, variables, theories);
         }
     }
 
+    /// 仮定 failed_literal が偽になった原因となった仮定を求める
+    ///
+    /// failed_literal の割り当ての理由を決定変数まで遡り，failed_literal と遡って得られた決定変数のリテラル(仮定)を failed_assumptions に格納する．
+    /// 仮定はすべての自由な決定変数より先に割り当てられるため，遡って得られる決定変数はすべて仮定である．
+    #[inline(never)]
+    pub fn analyze_final(
+        &mut self,
+        failed_literal: Literal,
+        variables: &Variables,
+        theories: &Theories,
+        failed_assumptions: &mut Array<VariableSize, Literal>,
+    ) {
+        failed_assumptions.clear();
+        failed_assumptions.push(failed_literal);
+        self.seen_variables.clear();
+        if self.seen_variables.capacity() < variables.dimension() {
+            self.seen_variables.reserve(variables.dimension() - self.seen_variables.capacity());
+        }
+        self.variable_stack.clear();
+        self.seen_variables.insert(failed_literal.index);
+        self.variable_stack.push(failed_literal.index);
+        while let Some(variable_index) = self.variable_stack.pop() {
+            let VariableState::Assigned { assigned_value, decision_level, reason, .. } = *variables.get(variable_index)
+            else {
+                unreachable!();
+            };
+            if decision_level == 0 {
+                continue;
+            }
+            match reason {
+                Reason::Decision => {
+                    failed_assumptions.push(Literal { index: variable_index, sign: assigned_value });
+                }
+                Reason::Propagation { .. } | Reason::Theory { .. } | Reason::External { .. } => {
+                    theories.explain(variable_index, assigned_value, reason, variables, &mut self.literals);
+                    for literal in self.literals.iter() {
+                        if literal.index != variable_index && !self.seen_variables.contains_key(literal.index) {
+                            self.seen_variables.insert(literal.index);
+                            self.variable_stack.push(literal.index);
+                        }
+                    }
+                }
+            }
+        }
+        self.seen_variables.clear();
+    }
+
+    /// literals の割り当てを含意する決定変数のリテラルを decisions に格納する(決定レベル 0 の割り当ては除く)
+    pub fn collect_decisions(
+        &mut self,
+        literals: &[Literal],
+        variables: &Variables,
+        theories: &Theories,
+        decisions: &mut Array<VariableSize, Literal>,
+    ) {
+        decisions.clear();
+        self.seen_variables.clear();
+        if self.seen_variables.capacity() < variables.dimension() {
+            self.seen_variables.reserve(variables.dimension() - self.seen_variables.capacity());
+        }
+        self.variable_stack.clear();
+        for literal in literals.iter() {
+            if !self.seen_variables.contains_key(literal.index) {
+                self.seen_variables.insert(literal.index);
+                self.variable_stack.push(literal.index);
+            }
+        }
+        while let Some(variable_index) = self.variable_stack.pop() {
+            let VariableState::Assigned { assigned_value, decision_level, reason, .. } = *variables.get(variable_index)
+            else {
+                unreachable!();
+            };
+            if decision_level == 0 {
+                continue;
+            }
+            match reason {
+                Reason::Decision => {
+                    decisions.push(Literal { index: variable_index, sign: assigned_value });
+                }
+                Reason::Propagation { .. } | Reason::Theory { .. } | Reason::External { .. } => {
+                    theories.explain(variable_index, assigned_value, reason, variables, &mut self.literals);
+                    for literal in self.literals.iter() {
+                        if literal.index != variable_index && !self.seen_variables.contains_key(literal.index) {
+                            self.seen_variables.insert(literal.index);
+                            self.variable_stack.push(literal.index);
+                        }
+                    }
+                }
+            }
+        }
+        self.seen_variables.clear();
+    }
+
     #[inline(never)]
     fn resolve(
         &mut self,
@@ -100,12 +233,16 @@ impl Analyze {
         value: bool,
         reason: Reason,
         variables: &Variables,
-        theory: &mut ClauseTheory,
+        theories: &Theories,
     ) {
         // 割り当てを説明する節を取得
-        self.literals.clear();
-        theory.explain(variable_index, value, reason, &mut self.literals);
-        // 節を analyzer_buffer に融合
+        theories.explain(variable_index, value, reason, variables, &mut self.literals);
+        self.merge_literals(variables);
+    }
+
+    /// literals の節を analyzer_buffer に融合する
+    #[inline(always)]
+    fn merge_literals(&mut self, variables: &Variables) {
         for literal in self.literals.iter() {
             if self.analyzer_buffer.contains_key(literal.index) {
                 if self.analyzer_buffer.get(literal.index).unwrap().sign == !literal.sign {
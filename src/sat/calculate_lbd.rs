@@ -12,8 +12,9 @@ impl CalculateLBD {
     #[inline(never)]
     pub fn calculate(&mut self, literals: &Array<VariableSize, Literal>, variables: &Variables) -> VariableSize {
         self.decision_level_set.clear();
-        if self.decision_level_set.capacity() < variables.dimension() {
-            self.decision_level_set.reserve(variables.dimension() - self.decision_level_set.capacity());
+        // 決定レベルは変数の数まで取り得る
+        if self.decision_level_set.capacity() < variables.dimension() + 1 {
+            self.decision_level_set.reserve(variables.dimension() + 1 - self.decision_level_set.capacity());
         }
         let mut lbd: VariableSize = 0;
         for literal in literals.iter() {
@@ -0,0 +1,375 @@
+use crate::finite_collections::{Array, Set};
+
+use super::theory::Theory;
+use super::types::{ConstraintSize, Literal, Reason, VariableSize};
+use super::variables::{VariableState, Variables};
+
+#[derive(Clone, Copy)]
+struct WatchedBy {
+    constraint_index: ConstraintSize,
+    watching_position: VariableSize,
+}
+
+/// literals のうち真であるものが高々 bound 個
+///
+/// 先頭の literals.len()-bound+1 個のリテラルを監視する．
+struct CardinalityConstraint {
+    literals: Array<VariableSize, Literal>,
+    bound: VariableSize,
+}
+
+impl CardinalityConstraint {
+    /// 監視するリテラルの数
+    #[inline(always)]
+    fn number_of_watched_literals(&self) -> VariableSize {
+        self.literals.len() - self.bound + 1
+    }
+}
+
+/// 基数制約 sum(literals) <= k を伝播する
+///
+/// 真が割り当てられていない n-k+1 個のリテラルを監視し，監視しているリテラルに真が割り当てられたら，
+/// 監視していないリテラルから真が割り当てられていないものを探して監視を移す．
+/// 見つからなければ監視していない k-1 個のリテラルと合わせて k 個のリテラルが真なので，残りの監視リテラルに偽を伝播する．
+/// (k 個以上のリテラルを監視しなければ，それらがすべて真になっても気付けないので，監視するリテラルはこれより減らせない．
+/// 真が割り当てられるたびに調べるのは監視していない k-1 個のリテラルだけなので，k が小さければ伝播の手間は小さい．)
+/// 伝播した時点で真のリテラルは理由の k 個だけで，その後に真が割り当てられるリテラルはないので，
+/// 理由節は Analyze から要求されたときに制約の現在の割り当てから生成する．
+#[derive(Default)]
+pub struct CardinalityTheory {
+    theory_index: ConstraintSize,
+    watched_infos: Array<VariableSize, [Array<ConstraintSize, WatchedBy>; 2]>,
+    constraints: Array<ConstraintSize, CardinalityConstraint>,
+    decision_level_set: Set<VariableSize>,
+    propagation_count: usize,
+}
+
+impl CardinalityTheory {
+    /// Reason::Theory の theory_index を theory_index とする
+    pub fn new(theory_index: ConstraintSize) -> Self {
+        CardinalityTheory { theory_index, ..Default::default() }
+    }
+
+    /// literals のうち真であるものが高々 bound 個という制約を追加する(決定レベル 0 で呼ぶ)
+    ///
+    /// literals の変数は互いに異なり，割り当てられていないこと．1 <= bound < literals.len() であること．
+    pub fn add_at_most(&mut self, literals: Array<VariableSize, Literal>, bound: VariableSize) {
+        debug_assert!(1 <= bound && bound < literals.len());
+        let constraint_index = self.constraints.len();
+        let constraint = CardinalityConstraint { literals, bound };
+        for position in 0..constraint.number_of_watched_literals() {
+            let literal = constraint.literals[position];
+            self.watched_infos[literal.index][literal.sign as usize]
+                .push(WatchedBy { constraint_index, watching_position: position });
+        }
+        self.constraints.push(constraint);
+    }
+
+    fn propagate_constraint(
+        &mut self,
+        constraint_index: ConstraintSize,
+        watching_position: VariableSize,
+        variables: &mut Variables,
+    ) {
+        let constraint = &self.constraints[constraint_index];
+        if self.decision_level_set.capacity() < variables.dimension() + 1 {
+            self.decision_level_set.reserve(variables.dimension() + 1 - self.decision_level_set.capacity());
+        }
+        // 理由節は伝播するリテラルの否定と真が割り当てられているリテラルの否定からなる
+        let mut lbd = 0;
+        let mut clause_length = 1;
+        let mut propagation_decision_level = 0;
+        for literal in constraint.literals.iter() {
+            if let VariableState::Assigned { assigned_value, decision_level, .. } = *variables.get(literal.index) {
+                if assigned_value == literal.sign {
+                    clause_length += 1;
+                    propagation_decision_level = propagation_decision_level.max(decision_level);
+                    if decision_level != 0 && !self.decision_level_set.contains_key(decision_level) {
+                        self.decision_level_set.insert(decision_level);
+                        lbd += 1;
+                    }
+                }
+            }
+        }
+        self.decision_level_set.clear();
+        debug_assert!(clause_length == constraint.bound + 1);
+        for position in 0..constraint.number_of_watched_literals() {
+            let literal = constraint.literals[position];
+            if position == watching_position || variables.get(literal.index).is_value_assigned(!literal.sign) {
+                continue;
+            }
+            // 監視リテラルに真が割り当てられていれば，割り当てられた時点で代わりを見つけているか伝播しているはず
+            debug_assert!(!variables.get(literal.index).is_assigned());
+            self.propagation_count += 1;
+            variables.tentatively_assign(
+                literal.index,
+                !literal.sign,
+                Reason::Theory {
+                    theory_index: self.theory_index,
+                    explanation_index: constraint_index,
+                    lbd,
+                    clause_length,
+                    decision_level: propagation_decision_level,
+                },
+            );
+        }
+    }
+
+    /// 制約の数と基数制約による伝播の回数を返す
+    pub fn summary(&self) -> (usize, usize) {
+        (self.constraints.len() as usize, self.propagation_count)
+    }
+}
+
+impl Theory for CardinalityTheory {
+    fn expand(&mut self, additional: VariableSize) {
+        self.watched_infos.resize_with(self.watched_infos.len() + additional, || [Array::default(), Array::default()]);
+    }
+
+    #[inline(never)]
+    fn propagate(&mut self, assigned_variable_index: VariableSize, variables: &mut Variables) {
+        let VariableState::Assigned { assigned_value, .. } = *variables.get(assigned_variable_index) else {
+            unreachable!();
+        };
+        let mut k: ConstraintSize = 0;
+        'loop_watching_constraint: while k < self.watched_infos[assigned_variable_index][assigned_value as usize].len()
+        {
+            let WatchedBy { constraint_index, watching_position } =
+                self.watched_infos[assigned_variable_index][assigned_value as usize][k];
+            let constraint = &mut self.constraints[constraint_index];
+            debug_assert!(constraint.literals[watching_position].index == assigned_variable_index);
+            // 監視対象ではないリテラルから真が割り当てられていないものを探す
+            for l in constraint.number_of_watched_literals()..constraint.literals.len() {
+                let literal = constraint.literals[l];
+                if !variables.get(literal.index).is_value_assigned(literal.sign) {
+                    self.watched_infos[assigned_variable_index][assigned_value as usize].swap_remove(k);
+                    self.watched_infos[literal.index][literal.sign as usize]
+                        .push(WatchedBy { constraint_index, watching_position });
+                    constraint.literals.swap(watching_position, l);
+                    continue 'loop_watching_constraint;
+                }
+            }
+            // 見つからなければ残りの監視リテラルはすべて偽でなければならない
+            self.propagate_constraint(constraint_index, watching_position, variables);
+            k += 1;
+        }
+    }
+
+    /// 割り当ての理由となる節を生成する
+    fn explain(
+        &self,
+        variable_index: VariableSize,
+        value: bool,
+        reason: Reason,
+        variables: &Variables,
+        clause: &mut Array<VariableSize, Literal>,
+    ) {
+        let Reason::Theory { explanation_index: constraint_index, .. } = reason else {
+            unreachable!();
+        };
+        let constraint = &self.constraints[constraint_index];
+        debug_assert!(constraint.literals.iter().any(|l| l.index == variable_index && l.sign != value));
+        clause.clear();
+        clause.push(Literal { index: variable_index, sign: value });
+        for literal in constraint.literals.iter() {
+            if literal.index != variable_index && variables.get(literal.index).is_value_assigned(literal.sign) {
+                clause.push(!*literal);
+            }
+        }
+    }
+
+    /// 割り当てがすべての基数制約を充足するか
+    fn is_satisfied_by(&mut self, assignment: &Array<VariableSize, bool>) -> bool {
+        self.constraints.iter().all(|constraint| {
+            constraint.literals.iter().filter(|literal| assignment[literal.index] == literal.sign).count()
+                <= constraint.bound as usize
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::super::decision_heuristic::{create_decision_heuristic, DecisionHeuristicKind};
+    use super::super::solver::{SATSolver, SATSolverOptions, SATSolverResult};
+    use super::super::test_helpers::{is_model, is_true, random_problem, Random};
+    use super::super::variables::{PropagationOrder, VariableRandomization};
+    use super::*;
+
+    /// 変数の部分集合を符号を乱択して選んだリテラル列(長さは 3 以上)
+    fn random_literals(random: &mut Random, n: VariableSize) -> Array<VariableSize, Literal> {
+        let mut literals = Array::default();
+        while literals.len() < 3 {
+            literals.clear();
+            for variable_index in 0..n {
+                if random.next_bool() {
+                    literals.push(Literal { index: variable_index, sign: random.next_bool() });
+                }
+            }
+        }
+        literals
+    }
+
+    fn count_true(literals: &Array<VariableSize, Literal>, values: u32) -> VariableSize {
+        literals.iter().filter(|literal| is_true(literal, values)).count() as VariableSize
+    }
+
+    /// 理由節が伝播したリテラルと先に偽が割り当てられたリテラルからなり，制約から導かれることを全探索で確かめる
+    fn check_explanation(
+        theory: &CardinalityTheory,
+        variables: &Variables,
+        n: VariableSize,
+        variable_index: VariableSize,
+        value: bool,
+        reason: Reason,
+        assignment_level: VariableSize,
+    ) {
+        let mut clause = Array::default();
+        theory.explain(variable_index, value, reason, variables, &mut clause);
+        assert!(clause.iter().any(|literal| *literal == Literal { index: variable_index, sign: value }));
+        for literal in clause.iter().filter(|literal| literal.index != variable_index) {
+            let VariableState::Assigned { assigned_value, assignment_level: other_assignment_level, .. } =
+                *variables.get(literal.index)
+            else {
+                panic!("the explanation has an unassigned literal");
+            };
+            assert_eq!(assigned_value, !literal.sign);
+            assert!(other_assignment_level < assignment_level);
+        }
+        let Reason::Theory { explanation_index, .. } = reason else {
+            unreachable!();
+        };
+        let constraint = &theory.constraints[explanation_index];
+        for values in 0..1u32 << n {
+            if count_true(&constraint.literals, values) <= constraint.bound {
+                assert!(clause.iter().any(|literal| is_true(literal, values)));
+            }
+        }
+    }
+
+    #[test]
+    fn propagation_and_explanations_match_brute_force() {
+        let mut random = Random::new(1);
+        let n = 8;
+        let mut conflict_count = 0;
+        for _ in 0..300 {
+            let mut variables = Variables::new(
+                create_decision_heuristic(DecisionHeuristicKind::Activity),
+                PropagationOrder::Score,
+                VariableRandomization::default(),
+            );
+            variables.redimension(n, &mut random);
+            let mut theory = CardinalityTheory::new(0);
+            theory.expand(n);
+            let mut constraints = Vec::new();
+            for _ in 0..3 {
+                let literals = random_literals(&mut random, n);
+                let bound = 1 + random.next_below(literals.len() - 1);
+                theory.add_at_most(literals.clone(), bound);
+                constraints.push((literals, bound));
+            }
+            'loop_decision: while let Some(variable_index) =
+                (0..n).find(|variable_index| !variables.get(*variable_index).is_assigned())
+            {
+                variables.tentatively_assign(variable_index, random.next_bool(), Reason::Decision);
+                while variables.number_of_tentative_assigned_variables() != 0
+                    || variables.number_of_conflicting_variables() != 0
+                {
+                    if variables.number_of_conflicting_variables() != 0 {
+                        // 矛盾している変数の 2 つの理由はどちらも割り当てから導かれる
+                        let (variable_index, variable_state) = variables.first_conflicting_variable().unwrap();
+                        let VariableState::Conflicting { reasons, .. } = *variable_state else {
+                            unreachable!();
+                        };
+                        for (reason, value) in reasons.iter().zip([false, true]) {
+                            if let Reason::Theory { .. } = reason {
+                                let level = variables.current_assignment_level() + 1;
+                                check_explanation(&theory, &variables, n, variable_index, value, *reason, level);
+                            }
+                        }
+                        conflict_count += 1;
+                        break 'loop_decision;
+                    }
+                    let (variable_index, _) = variables.first_tentatively_assigned_variable().unwrap();
+                    variables.assign(variable_index);
+                    theory.propagate(variable_index, &mut variables);
+                }
+                // 伝播し尽くしていれば，真のリテラルが k 個の制約の残りのリテラルはすべて偽
+                for (literals, bound) in constraints.iter() {
+                    let true_count = literals
+                        .iter()
+                        .filter(|literal| variables.get(literal.index).is_value_assigned(literal.sign))
+                        .count() as VariableSize;
+                    assert!(true_count <= *bound);
+                    if true_count == *bound {
+                        assert!(literals.iter().all(|literal| variables.get(literal.index).is_assigned()));
+                    }
+                }
+                for variable_index in 0..n {
+                    if let VariableState::Assigned { assigned_value, assignment_level, reason, .. } =
+                        *variables.get(variable_index)
+                    {
+                        if let Reason::Theory { .. } = reason {
+                            check_explanation(
+                                &theory,
+                                &variables,
+                                n,
+                                variable_index,
+                                assigned_value,
+                                reason,
+                                assignment_level,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        assert!(conflict_count != 0);
+    }
+
+    #[test]
+    fn solver_with_cardinality_constraints_matches_brute_force() {
+        let mut random = Random::new(2);
+        let n = 10;
+        for k in 0..40 {
+            let problem = random_problem(&mut random, n, k % 20);
+            let mut constraints = Vec::new();
+            for _ in 0..3 {
+                let literals = random_literals(&mut random, n);
+                let bound = random.next_below(literals.len() + 1);
+                constraints.push((literals, bound));
+            }
+            let expected: HashSet<u32> = (0..1u32 << n)
+                .filter(|values| {
+                    is_model(&problem, *values)
+                        && constraints.iter().all(|(literals, bound)| count_true(literals, *values) <= *bound)
+                })
+                .collect();
+            for chronological_backtracking_threshold in [100, 0] {
+                let mut solver = SATSolver::with_options(SATSolverOptions {
+                    chronological_backtracking_threshold,
+                    ..SATSolverOptions::default()
+                });
+                solver.add_problem(&problem);
+                for (literals, bound) in constraints.iter() {
+                    solver.add_at_most(literals.as_slice(), *bound);
+                }
+                let to_values = |solution: &Array<VariableSize, bool>| {
+                    (0..n).filter(|i| solution[*i]).fold(0u32, |values, i| values | 1 << i)
+                };
+                match solver.solve() {
+                    SATSolverResult::Satisfiable { solution } => assert!(expected.contains(&to_values(&solution))),
+                    SATSolverResult::Unsatisfiable => assert!(expected.is_empty()),
+                    SATSolverResult::Unknown => unreachable!(),
+                }
+                let mut models = HashSet::new();
+                let all_variables: Vec<VariableSize> = (0..n).collect();
+                solver.enumerate_models(&all_variables, None, |solution| {
+                    assert!(models.insert(to_values(solution)));
+                });
+                assert_eq!(models, expected);
+            }
+        }
+    }
+}
@@ -2,9 +2,18 @@ use crate::finite_collections::Array;
 use average::{AverageTrait, ExponentialMovingAverage, MovingAverage};
 
 use super::calculate_lbd::CalculateLBD;
+use super::solver::SATSolverOptions;
+use super::theory::Theory;
 use super::types::{ConstraintSize, Literal, Reason, VariableSize};
 use super::variables::{VariableState, Variables};
 
+/// LBD の長期平均の時定数
+pub const LBD_AVERAGING_TIME_CONSTANT: usize = 100000;
+/// LBD の短期平均の時定数
+pub const CURRENT_LBD_AVERAGING_TIME_CONSTANT: usize = 100;
+/// 節のアクティビティの時定数
+pub const CLAUSE_ACTIVITY_TIME_CONSTANT: usize = 1000;
+
 #[derive(Clone, Copy)]
 struct WatchedBy {
     clause_index: ConstraintSize,
@@ -12,11 +21,23 @@ struct WatchedBy {
     cached_another_literal: Option<Literal>,
 }
 
+/// 学習節の階層
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ClauseTier {
+    /// LBD が十分小さく，削除されることのない節
+    Core,
+    /// しばらく使用されなければ Local に降格される節
+    Mid,
+    /// アクティビティに基づいて削除される節
+    Local,
+}
+
 struct Clause {
     literals: Array<VariableSize, Literal>,
     // TODO 節の状態は「非学習節・学習節・削除された学習節」のいずれかなので，以下は enum にした方がきれい
     is_learnt: bool,
     lbd: VariableSize,
+    tier: ClauseTier,
     is_deleted: bool,
     generated_time: usize,
     last_used_time: usize,
@@ -32,46 +53,77 @@ pub struct ClauseTheory {
     time: usize,
     lbd_average: ExponentialMovingAverage<f64>,
     current_lbd_average: MovingAverage<f64>,
+    core_lbd_threshold: VariableSize,
+    mid_lbd_threshold: VariableSize,
+    mid_tier_unused_limit: usize,
+    reduction_interval: usize,
+    reduction_interval_increment: usize,
     last_reduction_time: usize,
+    last_restart_time: usize,
+    next_reduction_time: usize,
     check_count: usize,
     skip_by_cached_count: usize,
     skip_by_another_count: usize,
     propagation_count: usize,
     clause_reduction_count: usize,
+    promotion_count: usize,
+    demotion_count: usize,
 }
 
 impl ClauseTheory {
-    pub fn new(
-        lbd_averaging_time_constant: usize,
-        current_lbd_averaging_time_constant: usize,
-        clause_activity_time_constant: usize,
-    ) -> Self {
+    /// options の学習節の階層と削除の設定で作る(時定数は既定値)
+    pub fn new(options: &SATSolverOptions) -> Self {
+        assert!(options.core_lbd_threshold <= options.mid_lbd_threshold);
+        assert!(options.reduction_interval > 0);
         ClauseTheory {
-            activity_time_constant: clause_activity_time_constant as f64,
+            activity_time_constant: CLAUSE_ACTIVITY_TIME_CONSTANT as f64,
             activity_increase_value: 1.0,
             watched_infos: Array::default(),
             clause_infos: Array::default(),
             calculate_lbd: CalculateLBD::default(),
             time: 0,
-            lbd_average: ExponentialMovingAverage::new(lbd_averaging_time_constant),
-            current_lbd_average: MovingAverage::new(current_lbd_averaging_time_constant),
-            last_reduction_time: 0,
+            lbd_average: ExponentialMovingAverage::new(LBD_AVERAGING_TIME_CONSTANT),
+            current_lbd_average: MovingAverage::new(CURRENT_LBD_AVERAGING_TIME_CONSTANT),
+            core_lbd_threshold: options.core_lbd_threshold,
+            mid_lbd_threshold: options.mid_lbd_threshold,
+            mid_tier_unused_limit: options.mid_tier_unused_limit,
+            reduction_interval: options.reduction_interval,
+            reduction_interval_increment: options.reduction_interval_increment,
+            // 直前の削除以降に生成された節は削除しないが，初回の削除では前半に生成された節を削除の候補とする
+            last_reduction_time: options.reduction_interval / 2,
+            last_restart_time: 0,
+            next_reduction_time: options.reduction_interval,
             check_count: 0,
             skip_by_cached_count: 0,
             skip_by_another_count: 0,
             propagation_count: 0,
             clause_reduction_count: 0,
+            promotion_count: 0,
+            demotion_count: 0,
         }
     }
 
-    pub fn expand(&mut self, additional: VariableSize) {
-        self.watched_infos.resize_with(self.watched_infos.len() + additional, || [Array::default(), Array::default()]);
+    pub fn add_clause(&mut self, literals: Array<VariableSize, Literal>, is_learnt: bool, variables: &mut Variables) {
+        self.insert_clause(literals, is_learnt, None, variables);
+    }
+
+    /// 他のソルバーから共有された学習節を追加する
+    ///
+    /// 割り当てから LBD を計算できないことがあるため，共有元での LBD をそのまま使用する．
+    pub fn add_imported_clause(
+        &mut self,
+        literals: Array<VariableSize, Literal>,
+        lbd: VariableSize,
+        variables: &mut Variables,
+    ) {
+        self.insert_clause(literals, true, Some(lbd.max(1)), variables);
     }
 
-    pub fn add_clause(
+    fn insert_clause(
         &mut self,
         mut literals: Array<VariableSize, Literal>,
         is_learnt: bool,
+        given_lbd: Option<VariableSize>,
         variables: &mut Variables,
     ) {
         // TODO: あとで対応(すべてのリテラルに偽が割り当てられているケースはひとまず考えない)
@@ -93,28 +145,34 @@ impl ClauseTheory {
                         lbd: lbd,
                         clause_length: 1,
                         assignment_level_at_propagated: variables.current_assignment_level(),
+                        decision_level: 0,
                     },
                 );
             }
         } else {
-            lbd = if is_learnt { self.calculate_lbd.calculate(&literals, variables) } else { literals.len() };
+            lbd = match given_lbd {
+                Some(given_lbd) => given_lbd,
+                None if is_learnt => self.calculate_lbd.calculate(&literals, variables),
+                None => literals.len(),
+            };
 
             /* 割当の状態に応じてリテラルをソート
              * 1. 真が割り当てられている -> 未割り当て -> 偽が割り当てられているの順
              * 2. 真が割り当てられているリテラル同士では割当レベルの昇順
-             *    偽が割り当てられているリテラル同士では割当レベルの降順
+             *    偽が割り当てられているリテラル同士では決定レベルの降順・割当レベルの降順
+             *    (時系列バックトラックを行うと割当レベルの順序と決定レベルの順序が一致するとは限らない)
              */
             literals.sort_by_cached_key(|l| match variables.get(l.index) {
-                VariableState::Assigned { assigned_value, assignment_level, .. } => {
+                VariableState::Assigned { assigned_value, decision_level, assignment_level, .. } => {
                     if *assigned_value == l.sign {
-                        (0, *assignment_level)
+                        (0, 0, *assignment_level)
                     } else {
-                        (2, VariableSize::MAX - *assignment_level)
+                        (2, VariableSize::MAX - *decision_level, VariableSize::MAX - *assignment_level)
                     }
                 }
-                VariableState::Unassigned { .. } => (1, 0),
-                VariableState::TentativelyAssigned { .. } => (1, 0),
-                VariableState::Conflicting { .. } => (1, 0),
+                VariableState::Unassigned { .. } => (1, 0, 0),
+                VariableState::TentativelyAssigned { .. } => (1, 0, 0),
+                VariableState::Conflicting { .. } => (1, 0, 0),
             });
             // 少なくとも先頭要素に偽が割り当てられていることはないはず
             debug_assert!(!variables.get(literals[0].index).is_value_assigned(!literals[0].sign));
@@ -130,22 +188,31 @@ impl ClauseTheory {
             if variables.get(literals[1].index).is_value_assigned(!literals[1].sign) {
                 // 末尾の監視リテラルに偽が割り当てられている場合には未割り当ての監視リテラルに真を割り当て
                 if !variables.get(literals[0].index).is_assigned() {
+                    // 末尾の監視リテラルの決定レベルが伝播されるリテラルの決定レベル
+                    let VariableState::Assigned { decision_level: propagation_decision_level, .. } =
+                        *variables.get(literals[1].index)
+                    else {
+                        unreachable!();
+                    };
                     //
                     let mut lbd_upper = lbd;
-                    if variables.current_decision_level() != 0 {
+                    if propagation_decision_level != 0 {
                         for literal in literals.iter() {
                             if let VariableState::Assigned { decision_level, reason, .. } = variables.get(literal.index)
                             {
-                                if *decision_level == variables.current_decision_level() {
-                                    if let Reason::Propagation { lbd: u, .. } = reason {
+                                if *decision_level == propagation_decision_level {
+                                    if let Reason::Propagation { lbd: u, .. }
+                                    | Reason::Theory { lbd: u, .. }
+                                    | Reason::External { lbd: u, .. } = reason
+                                    {
                                         debug_assert!(*u >= 1);
-                                        debug_assert!(*u <= variables.current_decision_level());
+                                        debug_assert!(*u <= propagation_decision_level);
                                         lbd_upper += u - 1;
                                     }
                                 }
                             }
                         }
-                        lbd_upper = lbd_upper.min(variables.current_decision_level());
+                        lbd_upper = lbd_upper.min(propagation_decision_level);
                     }
                     variables.tentatively_assign(
                         literals[0].index,
@@ -155,6 +222,7 @@ impl ClauseTheory {
                             lbd: lbd_upper,
                             clause_length: literals.len(),
                             assignment_level_at_propagated: variables.current_assignment_level(),
+                            decision_level: propagation_decision_level,
                         },
                     );
                 }
@@ -162,15 +230,17 @@ impl ClauseTheory {
         }
         //
         debug_assert!(literals.len() <= 1 || lbd >= 1);
-        if is_learnt {
+        if is_learnt && given_lbd.is_none() {
             self.lbd_average.add(lbd as f64);
             self.current_lbd_average.add(lbd as f64);
         }
         // 節を追加
+        let tier = Self::calculate_tier(lbd, self.core_lbd_threshold, self.mid_lbd_threshold);
         self.clause_infos.push(Clause {
             literals: literals,
             is_learnt: is_learnt,
             lbd: lbd,
+            tier,
             is_deleted: false,
             generated_time: self.time,
             last_used_time: self.time,
@@ -178,7 +248,85 @@ impl ClauseTheory {
         });
     }
 
-    pub fn propagate(&mut self, assigned_variable_index: VariableSize, variables: &mut Variables) {
+    /// variable_indices のいずれかの変数を含む節を(学習節も含めて)削除する
+    ///
+    /// 決定レベル 0 で呼ぶこと．削除する節は決定レベル 0 の割り当ての理由になっていてはならない．
+    pub fn remove_clauses_with_variables(&mut self, variable_indices: &[VariableSize], variables: &Variables) {
+        debug_assert!(variables.current_decision_level() == 0);
+        for clause_index in 0..self.clause_infos.len() {
+            let clause = &self.clause_infos[clause_index];
+            if !clause.is_deleted && clause.literals.iter().any(|literal| variable_indices.contains(&literal.index)) {
+                debug_assert!(!self.is_locked(clause_index, variables));
+                let clause = &mut self.clause_infos[clause_index];
+                clause.is_deleted = true;
+                clause.literals.clear();
+                clause.literals.shrink_to_fit();
+            }
+        }
+        self.remove_deleted_watches();
+    }
+
+    /// 削除された節の監視を削除
+    fn remove_deleted_watches(&mut self) {
+        for variable_index in 0..self.watched_infos.len() {
+            for value in [0, 1] {
+                let list = &mut self.watched_infos[variable_index][value];
+                let mut k: ConstraintSize = 0;
+                while k < list.len() {
+                    if self.clause_infos[list[k].clause_index].is_deleted {
+                        list.swap_remove(k);
+                    } else {
+                        k += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// 節が現在の割り当ての理由になっているか
+    fn is_locked(&self, clause_index: ConstraintSize, variables: &Variables) -> bool {
+        // 伝播されたリテラルは必ず監視リテラル(先頭の 2 つ)のいずれか
+        self.clause_infos[clause_index].literals.iter().take(2).any(|literal| match variables.get(literal.index) {
+            VariableState::Assigned { reason: Reason::Propagation { clause_index: i, .. }, .. } => *i == clause_index,
+            VariableState::TentativelyAssigned { reason: Reason::Propagation { clause_index: i, .. }, .. } => {
+                *i == clause_index
+            }
+            _ => false,
+        })
+    }
+
+    fn calculate_tier(
+        lbd: VariableSize,
+        core_lbd_threshold: VariableSize,
+        mid_lbd_threshold: VariableSize,
+    ) -> ClauseTier {
+        if lbd <= core_lbd_threshold {
+            ClauseTier::Core
+        } else if lbd <= mid_lbd_threshold {
+            ClauseTier::Mid
+        } else {
+            ClauseTier::Local
+        }
+    }
+
+    /// 削除されていない非学習節を列挙する
+    ///
+    /// 決定レベル 0 で充足されて削除された節は含まない(リテラルが空になっているため)．
+    pub fn original_clauses(&self) -> impl Iterator<Item = &Array<VariableSize, Literal>> {
+        self.clause_infos.iter().filter(|clause| !clause.is_learnt && !clause.is_deleted).map(|clause| &clause.literals)
+    }
+
+    pub fn summary(&self) -> (usize, usize, usize, usize) {
+        (self.check_count, self.skip_by_cached_count, self.skip_by_another_count, self.propagation_count)
+    }
+}
+
+impl Theory for ClauseTheory {
+    fn expand(&mut self, additional: VariableSize) {
+        self.watched_infos.resize_with(self.watched_infos.len() + additional, || [Array::default(), Array::default()]);
+    }
+
+    fn propagate(&mut self, assigned_variable_index: VariableSize, variables: &mut Variables) {
         let VariableState::Assigned { assigned_value, .. } = *variables.get(assigned_variable_index) else {
             unreachable!();
         };
@@ -234,23 +382,39 @@ impl ClauseTheory {
                     let lbd = self.calculate_lbd.calculate(&clause.literals, variables);
                     if clause.is_learnt && lbd < clause.lbd {
                         clause.lbd = lbd;
+                        // LBD が改善したら上位の階層に昇格
+                        let tier = Self::calculate_tier(lbd, self.core_lbd_threshold, self.mid_lbd_threshold);
+                        if tier != clause.tier && (tier == ClauseTier::Core || clause.tier == ClauseTier::Local) {
+                            clause.tier = tier;
+                            self.promotion_count += 1;
+                        }
+                    }
+                    // 伝播されるリテラルの決定レベルは他のリテラルの決定レベルの最大値
+                    let mut propagation_decision_level = 0;
+                    for literal in clause.literals.iter() {
+                        if let VariableState::Assigned { decision_level, .. } = variables.get(literal.index) {
+                            propagation_decision_level = propagation_decision_level.max(*decision_level);
+                        }
                     }
                     //
                     let mut lbd_upper = lbd;
-                    if variables.current_decision_level() != 0 {
+                    if propagation_decision_level != 0 {
                         for literal in clause.literals.iter() {
                             if let VariableState::Assigned { decision_level, reason, .. } = variables.get(literal.index)
                             {
-                                if *decision_level == variables.current_decision_level() {
-                                    if let Reason::Propagation { lbd: u, .. } = reason {
+                                if *decision_level == propagation_decision_level {
+                                    if let Reason::Propagation { lbd: u, .. }
+                                    | Reason::Theory { lbd: u, .. }
+                                    | Reason::External { lbd: u, .. } = reason
+                                    {
                                         debug_assert!(*u >= 1);
-                                        debug_assert!(*u <= variables.current_decision_level());
+                                        debug_assert!(*u <= propagation_decision_level);
                                         lbd_upper += u - 1;
                                     }
                                 }
                             }
                         }
-                        lbd_upper = lbd_upper.min(variables.current_decision_level());
+                        lbd_upper = lbd_upper.min(propagation_decision_level);
                     }
                     // もう一方の監視リテラルに真を割り当て
                     variables.tentatively_assign(
@@ -261,6 +425,7 @@ impl ClauseTheory {
                             lbd: lbd_upper,
                             clause_length: clause.literals.len(),
                             assignment_level_at_propagated: variables.current_assignment_level(),
+                            decision_level: propagation_decision_level,
                         },
                     );
                 }
@@ -269,11 +434,12 @@ impl ClauseTheory {
         }
     }
 
-    pub fn explain(
+    fn explain(
         &self,
         variable_index: VariableSize,
         value: bool,
         reason: Reason,
+        _variables: &Variables,
         clause: &mut Array<VariableSize, Literal>,
     ) {
         assert!(matches!(reason, Reason::Propagation { .. }));
@@ -284,12 +450,15 @@ impl ClauseTheory {
         clause.clone_from(&self.clause_infos[clause_index].literals);
     }
 
-    pub fn increase_activity(&mut self, clause_index: ConstraintSize) {
+    fn increase_activity(&mut self, reason: Reason) {
+        let Reason::Propagation { clause_index, .. } = reason else {
+            unreachable!();
+        };
         self.clause_infos[clause_index].activity += self.activity_increase_value;
         self.clause_infos[clause_index].last_used_time = self.time;
     }
 
-    pub fn advance_time(&mut self) {
+    fn advance_time(&mut self) {
         self.time += 1;
         self.activity_increase_value /= 1.0 - 1.0 / self.activity_time_constant;
         if self.activity_increase_value > 1e4 {
@@ -302,105 +471,292 @@ impl ClauseTheory {
         }
     }
 
-    pub fn is_request_restart(&self) -> bool {
-        let lbd_is_too_large = self.current_lbd_average.value() * self.current_lbd_average.count() as f64
-            / self.current_lbd_average.time_constant() as f64
-            > self.lbd_average.value();
-        let restart_interval_is_too_long =
-            self.time > self.last_reduction_time + 2 * (5000 + 100 * self.clause_reduction_count);
-        lbd_is_too_large || restart_interval_is_too_long
+    fn is_request_reduction(&self) -> bool {
+        self.time >= self.next_reduction_time
     }
 
-    pub fn restart(&mut self, variables: &Variables) {
-        assert!(variables.current_decision_level() == 0);
-        eprintln!(
-            "pldb_average={} current_pldb_average={}",
-            self.lbd_average.value(),
-            self.current_lbd_average.value()
-        );
-        self.current_lbd_average.clear();
-
-        if self.clause_reduction_count == 0
-            || self.time > self.last_reduction_time + 5000 + 100 * self.clause_reduction_count
-        {
-            // 決定レベル 0 で充足されている節を削除
-            for clause in self.clause_infos.iter_mut().filter(|c| !c.is_deleted) {
-                let satisfied = clause.literals.iter().any(|l| variables.get(l.index).is_value_assigned(l.sign));
-                if satisfied {
-                    clause.is_deleted = true;
-                    clause.literals.clear();
-                    clause.literals.shrink_to_fit();
-                } else {
-                    // fix されている変数を節から削除(2 つ目のリテラルまでは監視対象かもしれないのでひとまず触らない)
-                    let mut k = 2;
-                    while k < clause.literals.len() {
-                        if variables.get(clause.literals[k].index).is_value_assigned(!clause.literals[k].sign) {
-                            clause.literals.swap_remove(k);
-                        } else {
-                            k += 1;
-                        }
-                    }
-                }
-            }
+    fn remove_constraints_with_variables(&mut self, variable_indices: &[VariableSize], variables: &Variables) {
+        self.remove_clauses_with_variables(variable_indices, variables);
+    }
 
-            // 削除対象の候補を列挙
-            let mut clause_priority_order = Vec::from_iter((0..self.clause_infos.len()).filter(|i| {
-                self.clause_infos[*i].is_learnt
-                    && !self.clause_infos[*i].is_deleted
-                    && self.clause_infos[*i].lbd >= 3
-                    && (self.clause_infos[*i].lbd >= 6
-                        || self.clause_infos[*i].last_used_time < self.last_reduction_time)
-                    && self.clause_infos[*i].generated_time < self.last_reduction_time
-            }));
-            // 削除の優先度の高い順にソート
-            clause_priority_order.sort_unstable_by(|l, r| {
-                let lhs = self.clause_infos[*l].activity;
-                let rhs = self.clause_infos[*r].activity;
-                lhs.partial_cmp(&rhs).unwrap()
-            });
-            // 1/2 削除
-            for clause_index in clause_priority_order.iter().take(clause_priority_order.len() / 2) {
-                let clause = &mut self.clause_infos[*clause_index];
+    fn reduce(&mut self, variables: &Variables) {
+        // NOTE: リスタートとは独立に行うので，決定レベル 0 とは限らない
+        // 決定レベル 0 で充足されている節を削除
+        for clause in self.clause_infos.iter_mut().filter(|c| !c.is_deleted) {
+            let satisfied = clause.literals.iter().any(|l| variables.get(l.index).is_value_fixed(l.sign));
+            if satisfied {
                 clause.is_deleted = true;
                 clause.literals.clear();
                 clause.literals.shrink_to_fit();
-            }
-            // 削除された節の監視を削除
-            for variable_index in 0..self.watched_infos.len() {
-                for value in [0, 1] {
-                    let list = &mut self.watched_infos[variable_index][value];
-                    let mut k: ConstraintSize = 0;
-                    while k < list.len() {
-                        if self.clause_infos[list[k].clause_index].is_deleted {
-                            list.swap_remove(k);
-                        } else {
-                            k += 1;
-                        }
+            } else {
+                // fix されている変数を節から削除(2 つ目のリテラルまでは監視対象かもしれないのでひとまず触らない)
+                let mut k = 2;
+                while k < clause.literals.len() {
+                    if variables.get(clause.literals[k].index).is_value_fixed(!clause.literals[k].sign) {
+                        clause.literals.swap_remove(k);
+                    } else {
+                        k += 1;
                     }
                 }
             }
+        }
+
+        // しばらく使用されていない Mid 階層の節を Local 階層に降格
+        for clause in self.clause_infos.iter_mut() {
+            if clause.is_learnt
+                && !clause.is_deleted
+                && clause.tier == ClauseTier::Mid
+                && clause.last_used_time + self.mid_tier_unused_limit < self.time
             {
-                // デバッグ出力
-                let mut number_of_learnt_clauses = 0;
-                let mut lbd_ammount = 0;
-                for clause in self.clause_infos.iter() {
-                    if clause.is_learnt && !clause.is_deleted {
-                        number_of_learnt_clauses += 1;
-                        lbd_ammount += clause.lbd;
-                    }
+                clause.tier = ClauseTier::Local;
+                self.demotion_count += 1;
+            }
+        }
+
+        // 削除対象の候補(Local 階層の節)を列挙
+        let mut clause_priority_order = Vec::from_iter((0..self.clause_infos.len()).filter(|i| {
+            self.clause_infos[*i].is_learnt
+                && !self.clause_infos[*i].is_deleted
+                && self.clause_infos[*i].tier == ClauseTier::Local
+                && self.clause_infos[*i].generated_time < self.last_reduction_time
+                && !self.is_locked(*i, variables)
+        }));
+        // 削除の優先度の高い順にソート
+        clause_priority_order.sort_unstable_by(|l, r| {
+            let lhs = self.clause_infos[*l].activity;
+            let rhs = self.clause_infos[*r].activity;
+            lhs.partial_cmp(&rhs).unwrap()
+        });
+        // 1/2 削除
+        for clause_index in clause_priority_order.iter().take(clause_priority_order.len() / 2) {
+            let clause = &mut self.clause_infos[*clause_index];
+            clause.is_deleted = true;
+            clause.literals.clear();
+            clause.literals.shrink_to_fit();
+        }
+        self.remove_deleted_watches();
+        if self.clause_infos.iter().any(|clause| clause.is_learnt && !clause.is_deleted) {
+            // デバッグ出力
+            let mut number_of_learnt_clauses = [0; 3];
+            let mut lbd_ammount = 0;
+            for clause in self.clause_infos.iter() {
+                if clause.is_learnt && !clause.is_deleted {
+                    number_of_learnt_clauses[clause.tier as usize] += 1;
+                    lbd_ammount += clause.lbd;
                 }
-                eprintln!(
-                    "reduce learnt clauses {} pldb_average={}",
-                    number_of_learnt_clauses,
-                    lbd_ammount as f64 / number_of_learnt_clauses as f64
-                );
             }
-            self.clause_reduction_count += 1;
-            self.last_reduction_time = self.time;
+            eprintln!(
+                "reduce learnt clauses core={} mid={} local={} pldb_average={} promotion_count={} demotion_count={}",
+                number_of_learnt_clauses[0],
+                number_of_learnt_clauses[1],
+                number_of_learnt_clauses[2],
+                lbd_ammount as f64 / number_of_learnt_clauses.iter().sum::<usize>() as f64,
+                self.promotion_count,
+                self.demotion_count,
+            );
         }
+        self.clause_reduction_count += 1;
+        self.last_reduction_time = self.time;
+        self.next_reduction_time =
+            self.time + self.reduction_interval + self.reduction_interval_increment * self.clause_reduction_count;
     }
 
-    pub fn summary(&self) -> (usize, usize, usize, usize) {
-        (self.check_count, self.skip_by_cached_count, self.skip_by_another_count, self.propagation_count)
+    fn is_request_restart(&self) -> bool {
+        let lbd_is_too_large = self.current_lbd_average.count() != 0
+            && self.current_lbd_average.value() * self.current_lbd_average.count() as f64
+                / self.current_lbd_average.time_constant() as f64
+                > self.lbd_average.value();
+        // 一定期間リスタートしていなければ強制的にリスタート
+        let restart_interval_is_too_long = self.time
+            > self.last_restart_time
+                + 2 * (self.reduction_interval + self.reduction_interval_increment * self.clause_reduction_count);
+        lbd_is_too_large || restart_interval_is_too_long
+    }
+
+    fn restart(&mut self) {
+        if self.current_lbd_average.count() != 0 {
+            eprintln!(
+                "pldb_average={} current_pldb_average={}",
+                self.lbd_average.value(),
+                self.current_lbd_average.value()
+            );
+        }
+        self.current_lbd_average.clear();
+        self.last_restart_time = self.time;
+    }
+
+    /// 割り当てがすべての非学習節を充足するか
+    fn is_satisfied_by(&mut self, assignment: &Array<VariableSize, bool>) -> bool {
+        // NOTE: 決定レベル 0 で充足されて削除された節は検査しないので，assignment は決定レベル 0 の割り当てと一致している必要がある
+        self.original_clauses().all(|literals| literals.iter().any(|literal| assignment[literal.index] == literal.sign))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decision_heuristic::{create_decision_heuristic, DecisionHeuristicKind};
+    use super::super::random::Random;
+    use super::super::variables::{PropagationOrder, VariableRandomization};
+    use super::*;
+
+    fn setup(n: VariableSize, options: &SATSolverOptions) -> (ClauseTheory, Variables) {
+        let mut variables = Variables::new(
+            create_decision_heuristic(DecisionHeuristicKind::Activity),
+            PropagationOrder::Score,
+            VariableRandomization::default(),
+        );
+        let mut theory = ClauseTheory::new(options);
+        variables.redimension(n, &mut Random::new(0));
+        theory.expand(n);
+        (theory, variables)
+    }
+
+    fn clause(literals: &[(VariableSize, bool)]) -> Array<VariableSize, Literal> {
+        let mut clause = Array::default();
+        for &(index, sign) in literals {
+            clause.push(Literal { index, sign });
+        }
+        clause
+    }
+
+    /// index に value を決定変数として割り当てて伝播する
+    fn decide(theory: &mut ClauseTheory, variables: &mut Variables, index: VariableSize, value: bool) {
+        variables.tentatively_assign(index, value, Reason::Decision);
+        propagate(theory, variables);
+    }
+
+    /// 仮割り当てされた変数を割り当てて伝播する
+    fn propagate(theory: &mut ClauseTheory, variables: &mut Variables) {
+        while let Some((variable_index, _)) = variables.first_tentatively_assigned_variable() {
+            variables.assign(variable_index);
+            theory.propagate(variable_index, variables);
+        }
+        assert_eq!(variables.number_of_conflicting_variables(), 0);
+    }
+
+    #[test]
+    fn learnt_clause_is_promoted_when_lbd_improves() {
+        let options = SATSolverOptions { core_lbd_threshold: 2, mid_lbd_threshold: 3, ..SATSolverOptions::default() };
+        let (mut theory, mut variables) = setup(6, &options);
+        // x0 が偽なら x1, x2, x3 も偽
+        for index in 1..4 {
+            theory.add_clause(clause(&[(0, true), (index, false)]), false, &mut variables);
+        }
+        // x1, x2, x3 を別々の決定レベルで偽にしてから学習節を加えると LBD は 3
+        for index in 1..4 {
+            decide(&mut theory, &mut variables, index, false);
+        }
+        theory.add_clause(clause(&[(4, true), (1, true), (2, true), (3, true)]), true, &mut variables);
+        let learnt_clause_index = theory.clause_infos.len() - 1;
+        assert!(theory.clause_infos[learnt_clause_index].tier == ClauseTier::Mid);
+        assert_eq!(theory.clause_infos[learnt_clause_index].lbd, 3);
+        variables.backtrack(0);
+        // x0 を偽にすると x1, x2, x3 が同じ決定レベルで偽になり，学習節の LBD は 1 に改善する
+        decide(&mut theory, &mut variables, 0, false);
+        assert!(variables.get(4).is_value_assigned(true));
+        assert_eq!(theory.clause_infos[learnt_clause_index].lbd, 1);
+        assert!(theory.clause_infos[learnt_clause_index].tier == ClauseTier::Core);
+        assert_eq!(theory.promotion_count, 1);
+    }
+
+    #[test]
+    fn unused_mid_tier_clause_is_demoted() {
+        let options = SATSolverOptions {
+            core_lbd_threshold: 1,
+            mid_lbd_threshold: 6,
+            mid_tier_unused_limit: 10,
+            ..SATSolverOptions::default()
+        };
+        let (mut theory, mut variables) = setup(8, &options);
+        // LBD が 2 の学習節を 2 つ加える
+        decide(&mut theory, &mut variables, 0, false);
+        decide(&mut theory, &mut variables, 1, false);
+        theory.add_clause(clause(&[(2, true), (3, true), (0, true), (1, true)]), true, &mut variables);
+        theory.add_clause(clause(&[(4, true), (5, true), (0, true), (1, true)]), true, &mut variables);
+        let (unused_clause_index, used_clause_index) = (theory.clause_infos.len() - 2, theory.clause_infos.len() - 1);
+        assert!(theory.clause_infos[unused_clause_index].tier == ClauseTier::Mid);
+        assert!(theory.clause_infos[used_clause_index].tier == ClauseTier::Mid);
+        variables.backtrack(0);
+        let reason = Reason::Propagation {
+            clause_index: used_clause_index,
+            lbd: 2,
+            clause_length: 4,
+            assignment_level_at_propagated: 0,
+            decision_level: 0,
+        };
+        // mid_tier_unused_limit の間は使用されていなくても降格されない
+        for _ in 0..options.mid_tier_unused_limit {
+            theory.advance_time();
+            theory.increase_activity(reason);
+        }
+        theory.reduce(&variables);
+        assert!(theory.clause_infos[unused_clause_index].tier == ClauseTier::Mid);
+        // 使用された節は Mid のまま，それ以上使用されていない節は Local に降格される
+        theory.advance_time();
+        theory.increase_activity(reason);
+        theory.reduce(&variables);
+        assert!(theory.clause_infos[unused_clause_index].tier == ClauseTier::Local);
+        assert!(theory.clause_infos[used_clause_index].tier == ClauseTier::Mid);
+        assert_eq!(theory.demotion_count, 1);
+    }
+
+    #[test]
+    fn reduction_keeps_reason_clauses() {
+        let options = SATSolverOptions {
+            core_lbd_threshold: 1,
+            mid_lbd_threshold: 2,
+            reduction_interval: 2,
+            ..SATSolverOptions::default()
+        };
+        let n = 44;
+        let (mut theory, mut variables) = setup(n, &options);
+        for index in 0..4 {
+            decide(&mut theory, &mut variables, index, false);
+        }
+        // 決定レベル 4 で y を伝播する LBD 4 の学習節と，伝播しない LBD 4 の学習節(どちらも Local 階層)
+        let mut reason_clause_indices = Vec::new();
+        for y in 4..24 {
+            reason_clause_indices.push(theory.clause_infos.len());
+            theory.add_clause(clause(&[(y, true), (0, true), (1, true), (2, true), (3, true)]), true, &mut variables);
+        }
+        let mut other_clause_indices = Vec::new();
+        for z in (24..n).step_by(2) {
+            other_clause_indices.push(theory.clause_infos.len());
+            theory.add_clause(
+                clause(&[(z, true), (z + 1, true), (0, true), (1, true), (2, true), (3, true)]),
+                true,
+                &mut variables,
+            );
+        }
+        propagate(&mut theory, &mut variables);
+        assert!(variables.current_decision_level() == 4);
+        // 理由節のアクティビティが最も低くても削除されない
+        for clause_index in reason_clause_indices.iter() {
+            assert!(theory.clause_infos[*clause_index].tier == ClauseTier::Local);
+            theory.clause_infos[*clause_index].activity = 0.0;
+        }
+        theory.reduce(&variables);
+        for clause_index in reason_clause_indices.iter() {
+            assert!(!theory.clause_infos[*clause_index].is_deleted);
+        }
+        let deleted_count = other_clause_indices.iter().filter(|i| theory.clause_infos[**i].is_deleted).count();
+        assert_eq!(deleted_count, other_clause_indices.len() / 2);
+        // 割り当ての理由は削除後も説明できる
+        for y in 4..24 {
+            let VariableState::Assigned { assigned_value, reason, .. } = *variables.get(y) else {
+                panic!();
+            };
+            let mut explanation = Array::default();
+            theory.explain(y, assigned_value, reason, &variables, &mut explanation);
+            assert_eq!(explanation.len(), 5);
+        }
+        // 削除されなかった節は削除後も伝播する
+        let (z, _) = (24..n)
+            .step_by(2)
+            .zip(other_clause_indices.iter())
+            .find(|(_, clause_index)| !theory.clause_infos[**clause_index].is_deleted)
+            .unwrap();
+        decide(&mut theory, &mut variables, z, false);
+        assert!(variables.get(z + 1).is_value_assigned(true));
     }
 }
@@ -0,0 +1,332 @@
+use std::io::Write;
+
+use crate::finite_collections::Array;
+
+use super::read_cnf::SATProblem;
+use super::solver::{SATSolver, SATSolverResult};
+use super::types::{ConstraintSize, Literal, VariableSize};
+
+/// 先読み(lookahead)によるキューブの生成
+///
+/// 未割り当ての変数それぞれについて両方の値を試しに伝播させ，伝播によって割り当てられる変数の数の積が最大の変数で分岐する．
+/// 一方の値で矛盾する変数(failed literal)があれば，もう一方の値をキューブに加えて分岐せずに先読みをやり直す．
+pub struct CubeGenerator {
+    max_depth: VariableSize,
+    cube: Array<VariableSize, Literal>,
+    cubes: Array<ConstraintSize, Array<VariableSize, Literal>>,
+    refuted_count: usize,
+    failed_literal_count: usize,
+}
+
+impl CubeGenerator {
+    pub fn new(max_depth: VariableSize) -> Self {
+        CubeGenerator {
+            max_depth,
+            cube: Array::default(),
+            cubes: Array::default(),
+            refuted_count: 0,
+            failed_literal_count: 0,
+        }
+    }
+
+    /// 分岐の深さが max_depth に達するまで分割したキューブを返す(伝播だけで矛盾したキューブは含まない)
+    #[inline(never)]
+    pub fn generate(&mut self, solver: &mut SATSolver) -> Array<ConstraintSize, Array<VariableSize, Literal>> {
+        self.cube.clear();
+        self.cubes.clear();
+        self.split(solver, 0);
+        std::mem::take(&mut self.cubes)
+    }
+
+    /// これまでに伝播だけで矛盾したキューブの数
+    #[inline(always)]
+    pub fn refuted_count(&self) -> usize {
+        self.refuted_count
+    }
+
+    /// これまでに見つかった failed literal の数
+    #[inline(always)]
+    pub fn failed_literal_count(&self) -> usize {
+        self.failed_literal_count
+    }
+
+    fn split(&mut self, solver: &mut SATSolver, depth: VariableSize) {
+        if !solver.propagate_cube(self.cube.as_slice()) {
+            self.refuted_count += 1;
+            return;
+        }
+        if depth == self.max_depth {
+            self.cubes.push(self.cube.clone());
+            return;
+        }
+        // 先読み
+        let mut best: Option<(u64, VariableSize)> = None;
+        for variable_index in 0..solver.number_of_variables() {
+            if solver.value(variable_index).is_some() {
+                continue;
+            }
+            let positive = solver.probe(Literal { index: variable_index, sign: true });
+            let negative = solver.probe(Literal { index: variable_index, sign: false });
+            match (positive, negative) {
+                (None, None) => {
+                    // どちらの値でも矛盾するならキューブ全体が矛盾
+                    self.refuted_count += 1;
+                    return;
+                }
+                (None, Some(_)) | (Some(_), None) => {
+                    // 矛盾しない方の値をキューブに加えて先読みをやり直す
+                    self.failed_literal_count += 1;
+                    self.cube.push(Literal { index: variable_index, sign: positive.is_some() });
+                    self.split(solver, depth);
+                    self.cube.pop();
+                    return;
+                }
+                (Some(positive), Some(negative)) => {
+                    let score = (positive as u64 + 1) * (negative as u64 + 1);
+                    if best.is_none_or(|(best_score, _)| score > best_score) {
+                        best = Some((score, variable_index));
+                    }
+                }
+            }
+        }
+        let Some((_, variable_index)) = best else {
+            // すべての変数が割り当て済み(キューブが解を含む)
+            self.cubes.push(self.cube.clone());
+            return;
+        };
+        for sign in [true, false] {
+            self.cube.push(Literal { index: variable_index, sign });
+            self.split(solver, depth + 1);
+            self.cube.pop();
+        }
+    }
+}
+
+fn write_literals<W: Write>(writer: &mut W, literals: &[Literal]) -> std::io::Result<()> {
+    for literal in literals.iter() {
+        let number = literal.index as i64 + 1;
+        write!(writer, "{} ", if literal.sign { number } else { -number })?;
+    }
+    writeln!(writer, "0")
+}
+
+/// 問題とキューブを iCNF 形式で書き出す
+pub fn write_icnf<W: Write>(
+    writer: &mut W,
+    problem: &SATProblem,
+    cubes: &Array<ConstraintSize, Array<VariableSize, Literal>>,
+) -> std::io::Result<()> {
+    writeln!(writer, "p inccnf")?;
+    for clause in problem.clauses.iter() {
+        write_literals(writer, clause.as_slice())?;
+    }
+    // iCNF には XOR 節がないので節に展開する(展開に使う変数は問題の変数の後ろに追加する)
+    let mut number_of_variables = problem.number_of_variables;
+    for (xor_variables, parity) in problem.xors.iter() {
+        write_xor_clauses(writer, xor_variables.as_slice(), *parity, &mut number_of_variables)?;
+    }
+    for cube in cubes.iter() {
+        write!(writer, "a ")?;
+        write_literals(writer, cube.as_slice())?;
+    }
+    Ok(())
+}
+
+/// xor_variables の排他的論理和が parity に等しいという制約を節に展開して書き出す
+///
+/// 先頭の 3 変数の排他的論理和を新しい変数(番号は number_of_variables から順に割り当てる)で置き換えながら，
+/// 4 変数以下の XOR 制約ごとにパリティが異なる割り当てを 1 つずつ禁止する節を書き出す．
+fn write_xor_clauses<W: Write>(
+    writer: &mut W,
+    xor_variables: &[VariableSize],
+    parity: bool,
+    number_of_variables: &mut VariableSize,
+) -> std::io::Result<()> {
+    let mut literals: Vec<Literal> =
+        xor_variables.iter().map(|variable_index| Literal { sign: true, index: *variable_index }).collect();
+    while literals.len() > 4 {
+        let sum = Literal { sign: true, index: *number_of_variables };
+        *number_of_variables += 1;
+        let mut chunk: Vec<Literal> = literals.drain(..3).collect();
+        chunk.push(sum);
+        write_parity_clauses(writer, &chunk, false)?;
+        literals.push(sum);
+    }
+    write_parity_clauses(writer, &literals, parity)
+}
+
+/// literals の排他的論理和が parity に等しいという制約を，パリティが異なる割り当てを 1 つずつ禁止する節で書き出す
+fn write_parity_clauses<W: Write>(writer: &mut W, literals: &[Literal], parity: bool) -> std::io::Result<()> {
+    let mut clause = Vec::with_capacity(literals.len());
+    for values in 0..1u32 << literals.len() {
+        if (values.count_ones() & 1 == 1) == parity {
+            continue;
+        }
+        clause.clear();
+        for (i, literal) in literals.iter().enumerate() {
+            clause.push(if values >> i & 1 == 1 { !*literal } else { *literal });
+        }
+        write_literals(writer, &clause)?;
+    }
+    Ok(())
+}
+
+/// 各キューブを仮定として 1 つの SATSolver で順に解く(学習節はキューブ間で引き継がれる)
+///
+/// いずれかのキューブで充足可能であればその解を返し，すべてのキューブで充足不可能であれば充足不可能を返す．
+#[inline(never)]
+pub fn conquer(solver: &mut SATSolver, cubes: &Array<ConstraintSize, Array<VariableSize, Literal>>) -> SATSolverResult {
+    for cube in cubes.iter() {
+        let result = solver.solve_with_assumptions(cube.as_slice());
+        match result {
+            SATSolverResult::Unsatisfiable => {
+                if solver.failed_assumptions().is_empty() {
+                    // 仮定によらず充足不可能
+                    return SATSolverResult::Unsatisfiable;
+                }
+            }
+            _ => return result,
+        }
+    }
+    SATSolverResult::Unsatisfiable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::read_cnf::read_icnf;
+    use super::super::test_helpers::{brute_force, is_solution, is_true, random_problem, Random};
+    use super::*;
+
+    /// values の第 i ビットを変数 i の値とする割り当てがキューブのリテラルをすべて充足するか
+    fn satisfies_cube(cube: &Array<VariableSize, Literal>, values: u32) -> bool {
+        cube.iter().all(|literal| is_true(literal, values))
+    }
+
+    /// 全探索で求めた解(第 i ビットを変数 i の値とする)
+    fn models(problem: &SATProblem) -> Vec<u32> {
+        let projection: Vec<VariableSize> = (0..problem.number_of_variables).collect();
+        brute_force(problem, &projection)
+            .into_iter()
+            .map(|values| values.iter().rev().fold(0, |bits, value| bits << 1 | *value as u32))
+            .collect()
+    }
+
+    #[test]
+    fn cubes_partition_models() {
+        let mut random = Random::new(1);
+        let n = 12;
+        for k in 0..40 {
+            let problem = random_problem(&mut random, n, 30 + k % 30);
+            let models = models(&problem);
+            for depth in 0..4 {
+                let mut solver = SATSolver::new();
+                solver.add_problem(&problem);
+                let cubes = CubeGenerator::new(depth).generate(&mut solver);
+                // 各解はちょうど 1 つのキューブに含まれる
+                let count: usize = cubes
+                    .iter()
+                    .map(|cube| models.iter().filter(|values| satisfies_cube(cube, **values)).count())
+                    .sum();
+                assert_eq!(count, models.len());
+                for values in models.iter() {
+                    assert_eq!(cubes.iter().filter(|cube| satisfies_cube(cube, *values)).count(), 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn failed_literal_is_added_to_cube() {
+        // x0 = false とすると x1 と ¬x1 が伝播されて矛盾する
+        let mut problem = SATProblem { number_of_variables: 4, ..SATProblem::default() };
+        for clause in [[(0, true), (1, true)], [(0, true), (1, false)], [(2, true), (3, true)]] {
+            let mut literals = Array::default();
+            for (index, sign) in clause {
+                literals.push(Literal { index, sign });
+            }
+            problem.clauses.push(literals);
+        }
+        let mut solver = SATSolver::new();
+        solver.add_problem(&problem);
+        let mut cube_generator = CubeGenerator::new(1);
+        let cubes = cube_generator.generate(&mut solver);
+        assert!(cube_generator.failed_literal_count() >= 1);
+        assert_eq!(cubes.len(), 2);
+        for cube in cubes.iter() {
+            assert!(cube.iter().any(|literal| *literal == Literal { index: 0, sign: true }));
+        }
+        let models = models(&problem);
+        let count: usize =
+            cubes.iter().map(|cube| models.iter().filter(|values| satisfies_cube(cube, **values)).count()).sum();
+        assert_eq!(count, models.len());
+    }
+
+    #[test]
+    fn conquer_matches_direct_solve() {
+        let mut random = Random::new(2);
+        let n = 40;
+        for k in 0..30 {
+            let problem = random_problem(&mut random, n, 150 + k * 2);
+            let mut solver = SATSolver::new();
+            solver.add_problem(&problem);
+            let expected = match solver.solve() {
+                SATSolverResult::Satisfiable { .. } => true,
+                SATSolverResult::Unsatisfiable => false,
+                SATSolverResult::Unknown => panic!(),
+            };
+            let mut solver = SATSolver::new();
+            solver.add_problem(&problem);
+            let cubes = CubeGenerator::new(3).generate(&mut solver);
+            let mut solver = SATSolver::new();
+            solver.add_problem(&problem);
+            match conquer(&mut solver, &cubes) {
+                SATSolverResult::Satisfiable { solution } => {
+                    assert!(expected);
+                    assert!(is_solution(&problem, &solution));
+                }
+                SATSolverResult::Unsatisfiable => assert!(!expected),
+                SATSolverResult::Unknown => panic!(),
+            }
+        }
+    }
+
+    #[test]
+    fn write_icnf_expands_xors() {
+        let mut random = Random::new(3);
+        let n = 9;
+        for k in 0..40 {
+            let mut problem = random_problem(&mut random, n, k % 8);
+            for _ in 0..1 + k % 2 {
+                let mut xor_variables = Array::default();
+                for variable_index in 0..n {
+                    if random.next_bool() {
+                        xor_variables.push(variable_index);
+                    }
+                }
+                if xor_variables.is_empty() {
+                    xor_variables.push(random.next_below(n));
+                }
+                problem.xors.push((xor_variables, random.next_bool()));
+            }
+            let mut cubes: Array<ConstraintSize, Array<VariableSize, Literal>> = Array::default();
+            let mut cube = Array::default();
+            cube.push(Literal { index: random.next_below(n), sign: random.next_bool() });
+            cubes.push(cube);
+            let mut icnf = Vec::new();
+            write_icnf(&mut icnf, &problem, &cubes).unwrap();
+            let (mut expanded, read_cubes) = read_icnf(icnf.as_slice());
+            // NOTE: iCNF には変数の数がないので，節に現れない変数も含める
+            expanded.number_of_variables = expanded.number_of_variables.max(n);
+            assert!(expanded.xors.is_empty());
+            assert_eq!(read_cubes.len(), cubes.len());
+            assert!(read_cubes[0].as_slice() == cubes[0].as_slice());
+            // 展開に使った変数を消去すると元の問題と同じ解になる
+            let projection: Vec<VariableSize> = (0..n).collect();
+            let expanded_models = brute_force(&expanded, &projection);
+            assert_eq!(expanded_models, brute_force(&problem, &projection));
+            // 展開に使った変数の値は元の変数の値から一意に定まる
+            let all_projection: Vec<VariableSize> = (0..expanded.number_of_variables).collect();
+            assert_eq!(brute_force(&expanded, &all_projection).len(), expanded_models.len());
+        }
+    }
+}
@@ -0,0 +1,98 @@
+mod activity;
+mod chb;
+mod lrb;
+mod vmtf;
+
+use std::cmp::Ordering;
+
+use crate::finite_collections::{Comparator, FiniteHeapedMap};
+
+use super::random::Random;
+use super::types::VariableSize;
+
+pub use activity::Activity;
+pub use chb::Chb;
+pub use lrb::Lrb;
+pub use vmtf::Vmtf;
+
+/// 決定変数の選択方法
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DecisionHeuristicKind {
+    /// アクティビティ(指数平滑化)の大きい変数を選択
+    Activity,
+    /// 直近に矛盾に関与した変数を選択(variable move-to-front)
+    Vmtf,
+    /// 学習率(learning rate branching)の大きい変数を選択
+    Lrb,
+    /// 矛盾への寄与の履歴(conflict history-based branching)の大きい変数を選択
+    Chb,
+}
+
+/// 決定変数の選択方法
+///
+/// 変数の割り当て状態の変化と矛盾の解析への関与を通知され，それに基づいて次の決定変数を選択する．
+pub trait DecisionHeuristic {
+    /// 変数の次元を拡張する(追加された変数は未割り当て)
+    fn expand(&mut self, additional: VariableSize);
+
+    /// first 以降の変数(expand で追加された変数)の初期の選択順序をランダムに並べ替える
+    fn shuffle(&mut self, first: VariableSize, random: &mut Random);
+
+    /// 矛盾の解析に関与した変数の優先度を増大する
+    fn bump(&mut self, index: VariableSize);
+
+    /// 矛盾の解析が終わるたびに時刻を 1 つ進める
+    fn advance_time(&mut self);
+
+    /// 変数が割り当てられたことを通知する
+    fn assign(&mut self, index: VariableSize);
+
+    /// 変数が未割り当てに戻ったことを通知する(仮割り当ての取り消しを含む)
+    fn unassign(&mut self, index: VariableSize);
+
+    /// 未割り当ての変数のうち最も優先度の高い変数を返す(選択された変数は取り除かない)
+    fn select(&mut self, is_unassigned: &dyn Fn(VariableSize) -> bool) -> Option<VariableSize>;
+
+    /// 未割り当ての変数のうち最も優先度の高い変数を返す(select と異なり内部状態を変更しない)
+    fn peek(&self, is_unassigned: &dyn Fn(VariableSize) -> bool) -> Option<VariableSize>;
+
+    /// 変数の優先度(大きいほど優先して選択される)
+    fn priority(&self, index: VariableSize) -> f64;
+}
+
+pub fn create_decision_heuristic(kind: DecisionHeuristicKind) -> Box<dyn DecisionHeuristic> {
+    match kind {
+        DecisionHeuristicKind::Activity => Box::new(Activity::new(50.0)),
+        DecisionHeuristicKind::Vmtf => Box::new(Vmtf::default()),
+        DecisionHeuristicKind::Lrb => Box::new(Lrb::new(0.4, 0.06, 1e-6)),
+        DecisionHeuristicKind::Chb => Box::new(Chb::new(0.4, 0.06, 1e-6)),
+    }
+}
+
+/// 優先度の降順に並べるための比較
+struct PriorityComparator {}
+
+impl Comparator<VariableSize, f64> for PriorityComparator {
+    #[inline(always)]
+    fn compare(lhs: &(VariableSize, f64), rhs: &(VariableSize, f64)) -> Ordering {
+        rhs.1.partial_cmp(&lhs.1).unwrap()
+    }
+}
+
+/// ヒープから取り除かずに，未割り当ての変数のうち最も優先度の高い変数を返す
+fn peek_heap(
+    unassigned_variables: &FiniteHeapedMap<VariableSize, f64, PriorityComparator>,
+    is_unassigned: &dyn Fn(VariableSize) -> bool,
+) -> Option<VariableSize> {
+    if let Some((index, ..)) = unassigned_variables.first_key_value() {
+        if is_unassigned(*index) {
+            return Some(*index);
+        }
+    }
+    // 割り当て済みの変数が先頭に残っていれば，ヒープ全体から探す
+    unassigned_variables
+        .iter()
+        .filter(|(index, ..)| is_unassigned(*index))
+        .max_by(|lhs, rhs| lhs.1.partial_cmp(&rhs.1).unwrap())
+        .map(|(index, ..)| *index)
+}
@@ -0,0 +1,103 @@
+use crate::finite_collections::{Array, FiniteHeapedMap};
+
+use super::super::random::Random;
+use super::super::types::VariableSize;
+use super::{peek_heap, DecisionHeuristic, PriorityComparator};
+
+/// アクティビティ(矛盾への関与の指数平滑化)の大きい変数を選択する
+pub struct Activity {
+    activity_time_constant: f64,
+    activity_increase_value: f64,
+    activities: Array<VariableSize, f64>,
+    unassigned_variables: FiniteHeapedMap<VariableSize, f64, PriorityComparator>,
+}
+
+impl Activity {
+    pub fn new(activity_time_constant: f64) -> Self {
+        assert!(activity_time_constant.is_finite());
+        assert!(activity_time_constant > 0.0);
+        Activity {
+            activity_time_constant,
+            activity_increase_value: 1.0,
+            activities: Array::default(),
+            unassigned_variables: FiniteHeapedMap::default(),
+        }
+    }
+}
+
+impl DecisionHeuristic for Activity {
+    fn expand(&mut self, additional: VariableSize) {
+        let begin = self.activities.len();
+        self.unassigned_variables.reserve(additional);
+        for index in begin..begin + additional {
+            let initial_activity = 0.0;
+            self.activities.push(initial_activity);
+            self.unassigned_variables.insert(index, initial_activity);
+        }
+    }
+
+    fn shuffle(&mut self, first: VariableSize, random: &mut Random) {
+        // 1 回の bump より十分小さい初期値を与える
+        for index in first..self.activities.len() {
+            self.activities[index] = random.next_f64() * 1e-3;
+            if self.unassigned_variables.contains_key(index) {
+                self.unassigned_variables.insert(index, self.activities[index]);
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn bump(&mut self, index: VariableSize) {
+        self.activities[index] += self.activity_increase_value;
+        if self.unassigned_variables.contains_key(index) {
+            self.unassigned_variables.insert(index, self.activities[index]);
+        }
+        if self.activities[index] > 1e4 {
+            for activity in self.activities.iter_mut() {
+                *activity /= self.activity_increase_value;
+            }
+            self.activity_increase_value = 1.0;
+            // NOTE: activity を定数倍しても順序は変わらないので，ヒープ上の値を置き換えるだけでよい
+            let indices = Vec::from_iter(self.unassigned_variables.iter().map(|(index, ..)| *index));
+            for index in indices {
+                self.unassigned_variables.insert(index, self.activities[index]);
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn advance_time(&mut self) {
+        self.activity_increase_value /= 1.0 - 1.0 / self.activity_time_constant;
+    }
+
+    #[inline(always)]
+    fn assign(&mut self, _index: VariableSize) {
+        // 割り当て済みの変数はヒープに残しておき，選択時に取り除く
+    }
+
+    #[inline(always)]
+    fn unassign(&mut self, index: VariableSize) {
+        if !self.unassigned_variables.contains_key(index) {
+            self.unassigned_variables.insert(index, self.activities[index]);
+        }
+    }
+
+    fn select(&mut self, is_unassigned: &dyn Fn(VariableSize) -> bool) -> Option<VariableSize> {
+        while let Some((index, ..)) = self.unassigned_variables.first_key_value() {
+            if is_unassigned(*index) {
+                return Some(*index);
+            }
+            self.unassigned_variables.pop_first();
+        }
+        None
+    }
+
+    fn peek(&self, is_unassigned: &dyn Fn(VariableSize) -> bool) -> Option<VariableSize> {
+        peek_heap(&self.unassigned_variables, is_unassigned)
+    }
+
+    #[inline(always)]
+    fn priority(&self, index: VariableSize) -> f64 {
+        self.activities[index]
+    }
+}
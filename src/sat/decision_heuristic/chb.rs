@@ -0,0 +1,120 @@
+use crate::finite_collections::{Array, FiniteHeapedMap};
+
+use super::super::random::Random;
+use super::super::types::VariableSize;
+use super::{peek_heap, DecisionHeuristic, PriorityComparator};
+
+/// 矛盾への寄与の履歴の大きい変数を選択する(conflict history-based branching)
+///
+/// 割り当てられた変数には，伝播が矛盾に至った場合には 1.0，至らなかった場合には 0.9 を
+/// 最後に矛盾の解析に関与してからの矛盾の回数で割った値を報酬として与える．
+pub struct Chb {
+    step_size: f64,
+    min_step_size: f64,
+    step_size_decay: f64,
+    conflict_count: usize,
+    scores: Array<VariableSize, f64>,
+    last_conflict_counts: Array<VariableSize, usize>,
+    // 報酬が未確定の(前回の決定または矛盾以降に割り当てられた)変数
+    assigned_variables: Array<VariableSize, VariableSize>,
+    unassigned_variables: FiniteHeapedMap<VariableSize, f64, PriorityComparator>,
+}
+
+impl Chb {
+    pub fn new(step_size: f64, min_step_size: f64, step_size_decay: f64) -> Self {
+        assert!(0.0 < min_step_size && min_step_size <= step_size && step_size <= 1.0);
+        assert!(step_size_decay >= 0.0);
+        Chb {
+            step_size,
+            min_step_size,
+            step_size_decay,
+            conflict_count: 0,
+            scores: Array::default(),
+            last_conflict_counts: Array::default(),
+            assigned_variables: Array::default(),
+            unassigned_variables: FiniteHeapedMap::default(),
+        }
+    }
+
+    fn reward(&mut self, multiplier: f64) {
+        for k in 0..self.assigned_variables.len() {
+            let index = self.assigned_variables[k];
+            let reward = multiplier / (self.conflict_count.saturating_sub(self.last_conflict_counts[index]) + 1) as f64;
+            self.scores[index] = (1.0 - self.step_size) * self.scores[index] + self.step_size * reward;
+            if self.unassigned_variables.contains_key(index) {
+                self.unassigned_variables.insert(index, self.scores[index]);
+            }
+        }
+        self.assigned_variables.clear();
+    }
+}
+
+impl DecisionHeuristic for Chb {
+    fn expand(&mut self, additional: VariableSize) {
+        let begin = self.scores.len();
+        self.unassigned_variables.reserve(additional);
+        for index in begin..begin + additional {
+            self.scores.push(0.0);
+            self.last_conflict_counts.push(0);
+            self.unassigned_variables.insert(index, 0.0);
+        }
+    }
+
+    fn shuffle(&mut self, first: VariableSize, random: &mut Random) {
+        // 報酬の更新 1 回分より十分小さい初期値を与える
+        for index in first..self.scores.len() {
+            self.scores[index] = random.next_f64() * 1e-6;
+            if self.unassigned_variables.contains_key(index) {
+                self.unassigned_variables.insert(index, self.scores[index]);
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn bump(&mut self, index: VariableSize) {
+        // 解析中の矛盾は advance_time で数えられる
+        self.last_conflict_counts[index] = self.conflict_count + 1;
+    }
+
+    #[inline(always)]
+    fn advance_time(&mut self) {
+        self.conflict_count += 1;
+        // 矛盾に至った伝播で割り当てられた変数に報酬を与える
+        self.reward(1.0);
+        self.step_size = (self.step_size - self.step_size_decay).max(self.min_step_size);
+    }
+
+    #[inline(always)]
+    fn assign(&mut self, index: VariableSize) {
+        self.assigned_variables.push(index);
+    }
+
+    #[inline(always)]
+    fn unassign(&mut self, index: VariableSize) {
+        if !self.unassigned_variables.contains_key(index) {
+            self.unassigned_variables.insert(index, self.scores[index]);
+        }
+    }
+
+    fn select(&mut self, is_unassigned: &dyn Fn(VariableSize) -> bool) -> Option<VariableSize> {
+        // 矛盾に至らなかった伝播で割り当てられた変数に報酬を与える
+        self.reward(0.9);
+        while let Some((index, ..)) = self.unassigned_variables.first_key_value() {
+            if is_unassigned(*index) {
+                return Some(*index);
+            }
+            self.unassigned_variables.pop_first();
+        }
+        None
+    }
+
+    fn peek(&self, is_unassigned: &dyn Fn(VariableSize) -> bool) -> Option<VariableSize> {
+        // NOTE: select で与える報酬は反映しない
+        peek_heap(&self.unassigned_variables, is_unassigned)
+    }
+
+    #[inline(always)]
+    fn priority(&self, index: VariableSize) -> f64 {
+        self.scores[index]
+    }
+}
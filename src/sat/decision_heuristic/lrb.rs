@@ -0,0 +1,112 @@
+use crate::finite_collections::{Array, FiniteHeapedMap};
+
+use super::super::random::Random;
+use super::super::types::VariableSize;
+use super::{peek_heap, DecisionHeuristic, PriorityComparator};
+
+/// 学習率(割り当てられていた間に矛盾の解析に関与した割合の指数平滑化)の大きい変数を選択する(learning rate branching)
+pub struct Lrb {
+    step_size: f64,
+    min_step_size: f64,
+    step_size_decay: f64,
+    conflict_count: usize,
+    learning_rates: Array<VariableSize, f64>,
+    assigned_times: Array<VariableSize, usize>,
+    participation_counts: Array<VariableSize, usize>,
+    is_assigned: Array<VariableSize, bool>,
+    unassigned_variables: FiniteHeapedMap<VariableSize, f64, PriorityComparator>,
+}
+
+impl Lrb {
+    pub fn new(step_size: f64, min_step_size: f64, step_size_decay: f64) -> Self {
+        assert!(0.0 < min_step_size && min_step_size <= step_size && step_size <= 1.0);
+        assert!(step_size_decay >= 0.0);
+        Lrb {
+            step_size,
+            min_step_size,
+            step_size_decay,
+            conflict_count: 0,
+            learning_rates: Array::default(),
+            assigned_times: Array::default(),
+            participation_counts: Array::default(),
+            is_assigned: Array::default(),
+            unassigned_variables: FiniteHeapedMap::default(),
+        }
+    }
+}
+
+impl DecisionHeuristic for Lrb {
+    fn expand(&mut self, additional: VariableSize) {
+        let begin = self.learning_rates.len();
+        self.unassigned_variables.reserve(additional);
+        for index in begin..begin + additional {
+            self.learning_rates.push(0.0);
+            self.assigned_times.push(0);
+            self.participation_counts.push(0);
+            self.is_assigned.push(false);
+            self.unassigned_variables.insert(index, 0.0);
+        }
+    }
+
+    fn shuffle(&mut self, first: VariableSize, random: &mut Random) {
+        // 学習率の更新 1 回分より十分小さい初期値を与える
+        for index in first..self.learning_rates.len() {
+            self.learning_rates[index] = random.next_f64() * 1e-6;
+            if self.unassigned_variables.contains_key(index) {
+                self.unassigned_variables.insert(index, self.learning_rates[index]);
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn bump(&mut self, index: VariableSize) {
+        self.participation_counts[index] += 1;
+    }
+
+    #[inline(always)]
+    fn advance_time(&mut self) {
+        self.conflict_count += 1;
+        self.step_size = (self.step_size - self.step_size_decay).max(self.min_step_size);
+    }
+
+    #[inline(always)]
+    fn assign(&mut self, index: VariableSize) {
+        self.is_assigned[index] = true;
+        self.assigned_times[index] = self.conflict_count;
+        self.participation_counts[index] = 0;
+    }
+
+    #[inline(always)]
+    fn unassign(&mut self, index: VariableSize) {
+        if self.is_assigned[index] {
+            self.is_assigned[index] = false;
+            // 割り当てられていた間の矛盾の回数に対する，矛盾の解析に関与した回数の割合を報酬とする
+            let interval = self.conflict_count - self.assigned_times[index];
+            if interval > 0 {
+                let reward = self.participation_counts[index] as f64 / interval as f64;
+                self.learning_rates[index] =
+                    (1.0 - self.step_size) * self.learning_rates[index] + self.step_size * reward;
+            }
+        }
+        self.unassigned_variables.insert(index, self.learning_rates[index]);
+    }
+
+    fn select(&mut self, is_unassigned: &dyn Fn(VariableSize) -> bool) -> Option<VariableSize> {
+        while let Some((index, ..)) = self.unassigned_variables.first_key_value() {
+            if is_unassigned(*index) {
+                return Some(*index);
+            }
+            self.unassigned_variables.pop_first();
+        }
+        None
+    }
+
+    fn peek(&self, is_unassigned: &dyn Fn(VariableSize) -> bool) -> Option<VariableSize> {
+        peek_heap(&self.unassigned_variables, is_unassigned)
+    }
+
+    #[inline(always)]
+    fn priority(&self, index: VariableSize) -> f64 {
+        self.learning_rates[index]
+    }
+}
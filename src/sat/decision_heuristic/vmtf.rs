@@ -0,0 +1,190 @@
+use crate::finite_collections::Array;
+
+use super::super::random::Random;
+use super::super::types::VariableSize;
+use super::DecisionHeuristic;
+
+/// 直近に矛盾の解析に関与した変数を選択する(variable move-to-front)
+///
+/// 変数を矛盾の解析に関与した時刻の順に双方向連結リストで管理し，末尾(最も新しい変数)から順に未割り当ての変数を探す．
+/// 1 回の矛盾の解析に関与した変数は，解析の終了時に元の順序を保ったまま末尾に移動する．
+pub struct Vmtf {
+    time: u64,
+    timestamps: Array<VariableSize, u64>,
+    previous: Array<VariableSize, VariableSize>,
+    next: Array<VariableSize, VariableSize>,
+    first: VariableSize,
+    last: VariableSize,
+    // NOTE: search より新しい変数はすべて割り当て済み
+    search: VariableSize,
+    // 解析中の矛盾に関与した変数(重複を含む)
+    bumped_variables: Array<VariableSize, VariableSize>,
+}
+
+impl Default for Vmtf {
+    fn default() -> Self {
+        Vmtf {
+            time: 0,
+            timestamps: Array::default(),
+            previous: Array::default(),
+            next: Array::default(),
+            first: Self::NULL_INDEX,
+            last: Self::NULL_INDEX,
+            search: Self::NULL_INDEX,
+            bumped_variables: Array::default(),
+        }
+    }
+}
+
+impl Vmtf {
+    const NULL_INDEX: VariableSize = VariableSize::MAX;
+
+    #[inline(always)]
+    fn unlink(&mut self, index: VariableSize) {
+        let previous = self.previous[index];
+        let next = self.next[index];
+        if previous != Self::NULL_INDEX {
+            self.next[previous] = next;
+        } else {
+            self.first = next;
+        }
+        if next != Self::NULL_INDEX {
+            self.previous[next] = previous;
+        } else {
+            self.last = previous;
+        }
+        if self.search == index {
+            self.search = previous;
+        }
+    }
+
+    #[inline(always)]
+    fn push_back(&mut self, index: VariableSize) {
+        self.previous[index] = self.last;
+        self.next[index] = Self::NULL_INDEX;
+        if self.last != Self::NULL_INDEX {
+            self.next[self.last] = index;
+        } else {
+            self.first = index;
+        }
+        self.last = index;
+        self.time += 1;
+        self.timestamps[index] = self.time;
+    }
+}
+
+impl DecisionHeuristic for Vmtf {
+    fn expand(&mut self, additional: VariableSize) {
+        let begin = self.timestamps.len();
+        for index in begin..begin + additional {
+            self.timestamps.push(0);
+            self.previous.push(Self::NULL_INDEX);
+            self.next.push(Self::NULL_INDEX);
+            self.push_back(index);
+        }
+        self.search = self.last;
+    }
+
+    fn shuffle(&mut self, first: VariableSize, random: &mut Random) {
+        // 追加された変数をランダムな順序で末尾に移動
+        let mut indices = Vec::from_iter(first..self.timestamps.len());
+        for k in (1..indices.len()).rev() {
+            indices.swap(k, random.next_below(k as u32 + 1) as usize);
+        }
+        for index in indices {
+            self.unlink(index);
+            self.push_back(index);
+        }
+        self.search = self.last;
+    }
+
+    #[inline(always)]
+    fn bump(&mut self, index: VariableSize) {
+        self.bumped_variables.push(index);
+    }
+
+    fn advance_time(&mut self) {
+        if self.bumped_variables.is_empty() {
+            return;
+        }
+        // 関与した変数を以前の時刻の昇順に末尾に移動する(解析の順序で移動すると関与した変数同士の順序が崩れる)
+        let timestamps = &self.timestamps;
+        self.bumped_variables.sort_by_cached_key(|index| timestamps[*index]);
+        let mut last_bumped = Self::NULL_INDEX;
+        for k in 0..self.bumped_variables.len() {
+            let index = self.bumped_variables[k];
+            if index == last_bumped {
+                continue;
+            }
+            last_bumped = index;
+            self.unlink(index);
+            self.push_back(index);
+        }
+        self.bumped_variables.clear();
+        // NOTE: 移動した変数が未割り当てかどうかは分からないので，探索の開始位置を末尾に戻す
+        self.search = self.last;
+    }
+
+    #[inline(always)]
+    fn assign(&mut self, _index: VariableSize) {}
+
+    #[inline(always)]
+    fn unassign(&mut self, index: VariableSize) {
+        if self.search == Self::NULL_INDEX || self.timestamps[index] > self.timestamps[self.search] {
+            self.search = index;
+        }
+    }
+
+    fn select(&mut self, is_unassigned: &dyn Fn(VariableSize) -> bool) -> Option<VariableSize> {
+        let mut index = self.search;
+        while index != Self::NULL_INDEX && !is_unassigned(index) {
+            index = self.previous[index];
+        }
+        self.search = index;
+        if index != Self::NULL_INDEX {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    fn peek(&self, is_unassigned: &dyn Fn(VariableSize) -> bool) -> Option<VariableSize> {
+        let mut index = self.search;
+        while index != Self::NULL_INDEX && !is_unassigned(index) {
+            index = self.previous[index];
+        }
+        if index != Self::NULL_INDEX {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    #[inline(always)]
+    fn priority(&self, index: VariableSize) -> f64 {
+        self.timestamps[index] as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bumped_variables_keep_previous_order() {
+        let mut vmtf = Vmtf::default();
+        vmtf.expand(5);
+        // 解析の順序(重複を含む)に関係なく，以前の順序を保ったまま末尾に移動する
+        for index in [3, 1, 3, 0] {
+            vmtf.bump(index);
+        }
+        vmtf.advance_time();
+        assert!(vmtf.priority(2) < vmtf.priority(4));
+        assert!(vmtf.priority(4) < vmtf.priority(0));
+        assert!(vmtf.priority(0) < vmtf.priority(1));
+        assert!(vmtf.priority(1) < vmtf.priority(3));
+        assert_eq!(vmtf.select(&|_| true), Some(3));
+        assert_eq!(vmtf.select(&|index| index != 3), Some(1));
+        assert_eq!(vmtf.select(&|index| index == 4 || index == 2), Some(4));
+    }
+}
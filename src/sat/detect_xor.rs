@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use crate::finite_collections::Array;
+
+use super::read_cnf::SATProblem;
+use super::types::{ConstraintSize, VariableSize};
+
+/// XOR 制約として検出する節の最大の長さ(長さ k の XOR 制約は 2^(k-1) 個の節からなる)
+const MAX_XOR_LENGTH: usize = 6;
+
+/// 同じ変数の集合からなる節の組
+#[derive(Default)]
+struct ClauseGroup {
+    // 節の符号(i 番目のリテラルが正なら i ビット目が 1)と節の番号
+    clauses: HashMap<u32, ConstraintSize>,
+}
+
+/// CNF に符号化された XOR 制約を検出し，その節を XOR 制約に置き換えた問題を返す
+///
+/// 変数の集合が同じで，リテラルの符号のパリティが等しいすべての組み合わせの節がそろっていれば XOR 制約とみなす．
+#[inline(never)]
+pub fn detect_xors(problem: &SATProblem) -> SATProblem {
+    let mut groups: HashMap<Vec<VariableSize>, ClauseGroup> = HashMap::new();
+    for (clause_index, clause) in problem.clauses.iter().enumerate() {
+        let clause_index = clause_index as ConstraintSize;
+        if clause.len() < 3 || clause.len() as usize > MAX_XOR_LENGTH {
+            continue;
+        }
+        let mut literals: Vec<_> = clause.iter().copied().collect();
+        literals.sort_by_key(|literal| literal.index);
+        if literals.windows(2).any(|pair| pair[0].index == pair[1].index) {
+            continue;
+        }
+        let signs = literals.iter().enumerate().fold(0u32, |signs, (i, literal)| signs | (literal.sign as u32) << i);
+        groups
+            .entry(literals.iter().map(|literal| literal.index).collect())
+            .or_default()
+            .clauses
+            .entry(signs)
+            .or_insert(clause_index);
+    }
+
+    // 出力を決定的にするために変数の集合の順に調べる
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    groups.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    let mut is_removed: Array<ConstraintSize, bool> = Array::default();
+    is_removed.resize(problem.clauses.len(), false);
+    let mut detected = SATProblem {
+        number_of_variables: problem.number_of_variables,
+        projection: problem.projection.clone(),
+        ..SATProblem::default()
+    };
+    for (xor_variables, parity) in problem.xors.iter() {
+        detected.xors.push((xor_variables.clone(), *parity));
+    }
+    let mut detected_count = 0;
+    for (variable_indices, group) in groups.iter() {
+        let length = variable_indices.len();
+        if group.clauses.len() < 1 << (length - 1) {
+            continue;
+        }
+        for parity in [false, true] {
+            // 節は符号 s の否定の割り当てを禁止するので，パリティが parity でない割り当てをすべて禁止する節を探す
+            let required_parity = (!parity as u32 ^ length as u32) & 1;
+            let required_signs = (0..1u32 << length).filter(|signs| signs.count_ones() & 1 == required_parity);
+            if !required_signs.clone().all(|signs| group.clauses.contains_key(&signs)) {
+                continue;
+            }
+            for signs in required_signs {
+                is_removed[group.clauses[&signs]] = true;
+            }
+            let mut xor_variables = Array::default();
+            for variable_index in variable_indices.iter() {
+                xor_variables.push(*variable_index);
+            }
+            detected.xors.push((xor_variables, parity));
+            detected_count += 1;
+        }
+    }
+
+    for (clause_index, clause) in problem.clauses.iter().enumerate() {
+        if !is_removed[clause_index as ConstraintSize] {
+            detected.clauses.push(clause.clone());
+        }
+    }
+    eprintln!(
+        "detected_xor_count={} removed_clause_count={}",
+        detected_count,
+        problem.clauses.len() - detected.clauses.len()
+    );
+    detected
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::super::solver::{SATSolver, SATSolverResult};
+    use super::super::test_helpers::{brute_force, random_clause, Random};
+    use super::super::types::Literal;
+    use super::*;
+
+    /// XOR 制約をパリティの異なる割り当てを 1 つずつ禁止する節に符号化した問題を作る
+    fn random_problem_with_encoded_xors(
+        random: &mut Random,
+        n: VariableSize,
+    ) -> (SATProblem, HashSet<Vec<VariableSize>>) {
+        let mut problem = SATProblem { number_of_variables: n, ..SATProblem::default() };
+        let mut encoded = HashSet::new();
+        for _ in 0..3 {
+            let mut xor_variables: Vec<VariableSize> = (0..n).filter(|_| random.next_below(3) == 0).collect();
+            xor_variables.truncate(MAX_XOR_LENGTH);
+            if xor_variables.len() < 3 || !encoded.insert(xor_variables.clone()) {
+                continue;
+            }
+            let parity = random.next_bool();
+            for values in 0..1u32 << xor_variables.len() {
+                if (values.count_ones() & 1 == 1) == parity {
+                    continue;
+                }
+                let mut clause = Array::default();
+                for (i, variable_index) in xor_variables.iter().enumerate() {
+                    clause.push(Literal { sign: values >> i & 1 == 0, index: *variable_index });
+                }
+                problem.clauses.push(clause);
+            }
+        }
+        for _ in 0..10 {
+            problem.clauses.push(random_clause(random, n));
+        }
+        (problem, encoded)
+    }
+
+    #[test]
+    fn detected_xors_are_solved_natively() {
+        let mut random = Random::new(1);
+        for _ in 0..30 {
+            let (problem, encoded) = random_problem_with_encoded_xors(&mut random, 10);
+            let detected = detect_xors(&problem);
+            // 符号化した XOR 制約はすべて検出され，解は変わらない
+            let detected_variables: HashSet<Vec<VariableSize>> =
+                detected.xors.iter().map(|(xor_variables, _)| xor_variables.iter().copied().collect()).collect();
+            assert!(encoded.is_subset(&detected_variables));
+            let projection: Vec<VariableSize> = (0..problem.number_of_variables).collect();
+            let models = brute_force(&problem, &projection);
+            assert_eq!(brute_force(&detected, &projection), models);
+            // XOR 制約を Gauss-Jordan 消去で伝播しても解は同じ
+            let mut solver = SATSolver::new();
+            solver.add_problem(&detected);
+            let mut enumerated = HashSet::new();
+            solver.enumerate_models(&projection, None, |solution| {
+                enumerated
+                    .insert(projection.iter().map(|variable_index| solution[*variable_index]).collect::<Vec<_>>());
+            });
+            assert_eq!(enumerated, models);
+            assert_eq!(matches!(solver.solve(), SATSolverResult::Satisfiable { .. }), !models.is_empty());
+        }
+    }
+}
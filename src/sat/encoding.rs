@@ -0,0 +1,653 @@
+use std::collections::HashMap;
+
+use crate::finite_collections::Array;
+
+use super::solver::SATSolver;
+use super::types::{Literal, VariableSize};
+
+/// at-most-one 制約の符号化
+#[derive(Clone, Copy, Debug)]
+pub enum AtMostOneEncoding {
+    /// すべての 2 つ組を禁止する(補助変数なし，O(n^2) 節)
+    Pairwise,
+    /// 逐次カウンタ(Sinz)
+    SequentialCounter,
+    /// 3 つずつの組に指揮変数を置いて再帰的に符号化する(Klieber & Kwon)
+    Commander,
+    /// 変数を格子状に並べて行と列の at-most-one に分解する(Chen)
+    Product,
+}
+
+/// at-most-k / at-least-k 制約の符号化
+#[derive(Clone, Copy, Debug)]
+pub enum CardinalityEncoding {
+    /// 二分木の各節点で部分和を単進数で表す(k+1 で打ち切る)
+    Totalizer,
+    /// 上位 k+1 個だけを残す奇偶マージによる整列回路(Asín et al.)
+    CardinalityNetwork,
+}
+
+/// 擬似ブール制約の符号化
+#[derive(Clone, Copy, Debug)]
+pub enum PseudoBooleanEncoding {
+    /// 残りの上限で共有した二分決定図
+    BDD,
+    /// 全加算器による二進数の和と定数との比較
+    Adder,
+}
+
+/// 補助変数を 1 つ追加してその正リテラルを返す
+fn new_literal(solver: &mut SATSolver) -> Literal {
+    solver.expand_variables(1);
+    Literal { sign: true, index: solver.number_of_variables() - 1 }
+}
+
+/// 節を追加する(同じ変数が重複する節は整理する)
+fn add_clause(solver: &mut SATSolver, literals: &[Literal]) {
+    let mut clause: Array<VariableSize, Literal> = Array::default();
+    for literal in literals.iter() {
+        if clause.iter().any(|other| *other == !*literal) {
+            // 恒真
+            return;
+        }
+        if !clause.iter().any(|other| other == literal) {
+            clause.push(*literal);
+        }
+    }
+    solver.add_clause(&clause);
+}
+
+/// literals のうち高々 1 つが真
+#[inline(never)]
+pub fn add_at_most_one(solver: &mut SATSolver, literals: &[Literal], encoding: AtMostOneEncoding) {
+    match encoding {
+        AtMostOneEncoding::Pairwise => add_pairwise(solver, literals),
+        AtMostOneEncoding::SequentialCounter => add_sequential_counter(solver, literals),
+        AtMostOneEncoding::Commander => add_commander(solver, literals),
+        AtMostOneEncoding::Product => add_product(solver, literals),
+    }
+}
+
+/// literals のうちちょうど 1 つが真
+#[inline(never)]
+pub fn add_exactly_one(solver: &mut SATSolver, literals: &[Literal], encoding: AtMostOneEncoding) {
+    add_clause(solver, literals);
+    add_at_most_one(solver, literals, encoding);
+}
+
+fn add_pairwise(solver: &mut SATSolver, literals: &[Literal]) {
+    for i in 0..literals.len() {
+        for j in i + 1..literals.len() {
+            add_clause(solver, &[!literals[i], !literals[j]]);
+        }
+    }
+}
+
+fn add_sequential_counter(solver: &mut SATSolver, literals: &[Literal]) {
+    let n = literals.len();
+    if n <= 1 {
+        return;
+    }
+    // s_i: literals[0..=i] のいずれかが真
+    let counters: Vec<Literal> = (0..n - 1).map(|_| new_literal(solver)).collect();
+    add_clause(solver, &[!literals[0], counters[0]]);
+    for i in 1..n - 1 {
+        add_clause(solver, &[!literals[i], counters[i]]);
+        add_clause(solver, &[!counters[i - 1], counters[i]]);
+        add_clause(solver, &[!literals[i], !counters[i - 1]]);
+    }
+    add_clause(solver, &[!literals[n - 1], !counters[n - 2]]);
+}
+
+fn add_commander(solver: &mut SATSolver, literals: &[Literal]) {
+    const GROUP_SIZE: usize = 3;
+    if literals.len() <= 2 * GROUP_SIZE {
+        add_pairwise(solver, literals);
+        return;
+    }
+    let mut commanders = Vec::new();
+    for group in literals.chunks(GROUP_SIZE) {
+        let commander = new_literal(solver);
+        add_pairwise(solver, group);
+        // 組の変数が真なら指揮変数も真，指揮変数が真なら組のいずれかが真
+        let mut clause = vec![!commander];
+        for literal in group.iter() {
+            add_clause(solver, &[!*literal, commander]);
+            clause.push(*literal);
+        }
+        add_clause(solver, &clause);
+        commanders.push(commander);
+    }
+    add_commander(solver, &commanders);
+}
+
+fn add_product(solver: &mut SATSolver, literals: &[Literal]) {
+    let n = literals.len();
+    if n <= 4 {
+        add_pairwise(solver, literals);
+        return;
+    }
+    let p = (n as f64).sqrt().ceil() as usize;
+    let q = n.div_ceil(p);
+    let rows: Vec<Literal> = (0..q).map(|_| new_literal(solver)).collect();
+    let columns: Vec<Literal> = (0..p).map(|_| new_literal(solver)).collect();
+    for (k, literal) in literals.iter().enumerate() {
+        add_clause(solver, &[!*literal, rows[k / p]]);
+        add_clause(solver, &[!*literal, columns[k % p]]);
+    }
+    add_product(solver, &rows);
+    add_product(solver, &columns);
+}
+
+/// literals のうち真であるものが高々 k 個
+#[inline(never)]
+pub fn add_at_most_k(solver: &mut SATSolver, literals: &[Literal], k: VariableSize, encoding: CardinalityEncoding) {
+    let k = k as usize;
+    if k >= literals.len() {
+        return;
+    }
+    if k == 0 {
+        for literal in literals.iter() {
+            add_clause(solver, &[!*literal]);
+        }
+        return;
+    }
+    let bound = match encoding {
+        CardinalityEncoding::Totalizer => {
+            Totalizer::new(solver, literals, k as VariableSize).at_most(k as VariableSize)
+        }
+        CardinalityEncoding::CardinalityNetwork => {
+            let outputs = cardinality_network(solver, literals, k + 1);
+            outputs[k].map(|output| !output)
+        }
+    };
+    if let Some(literal) = bound {
+        add_clause(solver, &[literal]);
+    }
+}
+
+/// literals のうち真であるものが少なくとも k 個
+#[inline(never)]
+pub fn add_at_least_k(solver: &mut SATSolver, literals: &[Literal], k: VariableSize, encoding: CardinalityEncoding) {
+    if k as usize > literals.len() {
+        add_clause(solver, &[]);
+        return;
+    }
+    let negations: Vec<Literal> = literals.iter().map(|literal| !*literal).collect();
+    add_at_most_k(solver, &negations, literals.len() as VariableSize - k, encoding);
+}
+
+/// 真であるリテラルの個数の単進数表現(totalizer)
+///
+/// outputs[j] は少なくとも j+1 個のリテラルが真であれば真になる．上限を仮定として与えれば同じ符号化を使い回して上限を変えられる．
+pub struct Totalizer {
+    outputs: Array<VariableSize, Literal>,
+    max_bound: VariableSize,
+}
+
+impl Totalizer {
+    /// 上限 max_bound までの at-most-k 制約に使える totalizer を作る
+    #[inline(never)]
+    pub fn new(solver: &mut SATSolver, literals: &[Literal], max_bound: VariableSize) -> Self {
+        let limit = literals.len().min(max_bound as usize + 1);
+        let mut outputs = Array::default();
+        if limit > 0 {
+            for output in Self::build(solver, literals, limit).iter() {
+                outputs.push(*output);
+            }
+        }
+        Totalizer { outputs, max_bound }
+    }
+
+    fn build(solver: &mut SATSolver, literals: &[Literal], limit: usize) -> Vec<Literal> {
+        if literals.len() == 1 {
+            return vec![literals[0]];
+        }
+        let (left, right) = literals.split_at(literals.len() / 2);
+        let left = Self::build(solver, left, limit);
+        let right = Self::build(solver, right, limit);
+        let m = (left.len() + right.len()).min(limit);
+        let outputs: Vec<Literal> = (0..m).map(|_| new_literal(solver)).collect();
+        // 左で i 個以上，右で j 個以上が真なら i+j 個以上が真
+        for i in 0..=left.len() {
+            for j in 0..=right.len() {
+                if i + j == 0 || i + j > m {
+                    continue;
+                }
+                let mut clause = Vec::with_capacity(3);
+                if i > 0 {
+                    clause.push(!left[i - 1]);
+                }
+                if j > 0 {
+                    clause.push(!right[j - 1]);
+                }
+                clause.push(outputs[i + j - 1]);
+                add_clause(solver, &clause);
+            }
+        }
+        outputs
+    }
+
+    /// 真であるリテラルが高々 k 個であることを表すリテラル(常に成り立つ場合は None)
+    pub fn at_most(&self, k: VariableSize) -> Option<Literal> {
+        assert!(k <= self.max_bound);
+        if k < self.outputs.len() {
+            Some(!self.outputs[k])
+        } else {
+            None
+        }
+    }
+}
+
+/// 比較器(None は定数の偽)．大きい方と小さい方の順に返す
+///
+/// at-most-k に必要な入力から出力への向きの節だけを追加する．
+fn comparator(solver: &mut SATSolver, a: Option<Literal>, b: Option<Literal>) -> (Option<Literal>, Option<Literal>) {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let maximum = new_literal(solver);
+            let minimum = new_literal(solver);
+            add_clause(solver, &[!a, maximum]);
+            add_clause(solver, &[!b, maximum]);
+            add_clause(solver, &[!a, !b, minimum]);
+            (Some(maximum), Some(minimum))
+        }
+        (Some(literal), None) | (None, Some(literal)) => (Some(literal), None),
+        (None, None) => (None, None),
+    }
+}
+
+/// 降順に整列済みの同じ長さ(2 のべき)の 2 つの列を奇偶マージする
+fn merge(solver: &mut SATSolver, a: &[Option<Literal>], b: &[Option<Literal>]) -> Vec<Option<Literal>> {
+    debug_assert!(a.len() == b.len());
+    if a.len() == 1 {
+        let (maximum, minimum) = comparator(solver, a[0], b[0]);
+        return vec![maximum, minimum];
+    }
+    let even_a: Vec<_> = a.iter().step_by(2).copied().collect();
+    let even_b: Vec<_> = b.iter().step_by(2).copied().collect();
+    let odd_a: Vec<_> = a.iter().skip(1).step_by(2).copied().collect();
+    let odd_b: Vec<_> = b.iter().skip(1).step_by(2).copied().collect();
+    let even = merge(solver, &even_a, &even_b);
+    let odd = merge(solver, &odd_a, &odd_b);
+    let n = even.len();
+    let mut outputs = Vec::with_capacity(2 * n);
+    outputs.push(even[0]);
+    for i in 0..n - 1 {
+        let (maximum, minimum) = comparator(solver, odd[i], even[i + 1]);
+        outputs.push(maximum);
+        outputs.push(minimum);
+    }
+    outputs.push(odd[n - 1]);
+    outputs
+}
+
+/// 長さ 2 のべきの列を降順に整列する
+fn sort(solver: &mut SATSolver, inputs: &[Option<Literal>]) -> Vec<Option<Literal>> {
+    if inputs.len() == 1 {
+        return inputs.to_vec();
+    }
+    let (left, right) = inputs.split_at(inputs.len() / 2);
+    let left = sort(solver, left);
+    let right = sort(solver, right);
+    merge(solver, &left, &right)
+}
+
+/// 真であるリテラルが多い順の上位 m 個の出力を返す(m 個に満たない分は None で埋める)
+fn cardinality_network(solver: &mut SATSolver, literals: &[Literal], m: usize) -> Vec<Option<Literal>> {
+    let width = m.next_power_of_two();
+    let mut inputs: Vec<Option<Literal>> = literals.iter().map(|literal| Some(*literal)).collect();
+    inputs.resize(inputs.len().div_ceil(width) * width, None);
+    // width 個ずつ整列し，順にマージして上位 width 個だけを残す
+    let mut outputs = sort(solver, &inputs[..width]);
+    for block in inputs[width..].chunks(width) {
+        let sorted = sort(solver, block);
+        outputs = merge(solver, &outputs, &sorted);
+        outputs.truncate(width);
+    }
+    outputs.truncate(m);
+    outputs
+}
+
+/// 重み付きの和 Σ weight * literal が bound 以下
+///
+/// 負の重みはリテラルを否定して正の重みにする．
+#[inline(never)]
+pub fn add_pseudo_boolean_at_most(
+    solver: &mut SATSolver,
+    terms: &[(i64, Literal)],
+    bound: i64,
+    encoding: PseudoBooleanEncoding,
+) {
+    let terms = terms.iter().map(|(weight, literal)| (*weight as i128, *literal));
+    add_linear_at_most(solver, terms, bound as i128, encoding);
+}
+
+/// 重み付きの和 Σ weight * literal が bound 以上
+#[inline(never)]
+pub fn add_pseudo_boolean_at_least(
+    solver: &mut SATSolver,
+    terms: &[(i64, Literal)],
+    bound: i64,
+    encoding: PseudoBooleanEncoding,
+) {
+    let terms = terms.iter().map(|(weight, literal)| (-(*weight as i128), *literal));
+    add_linear_at_most(solver, terms, -(bound as i128), encoding);
+}
+
+/// 重み付きの和 Σ weight * literal が bound 以下(重みの絶対値は 2^63 以下)
+///
+/// NOTE: 負の重みを移項した bound や重みの和は i64, u64 の範囲を超えうるので，i128, u128 で計算する
+fn add_linear_at_most<I>(solver: &mut SATSolver, terms: I, bound: i128, encoding: PseudoBooleanEncoding)
+where
+    I: Iterator<Item = (i128, Literal)>,
+{
+    let mut bound = bound;
+    let mut normalized_terms = Vec::new();
+    for (weight, literal) in terms {
+        debug_assert!(weight.unsigned_abs() <= 1 << 63);
+        if weight > 0 {
+            normalized_terms.push((weight as u64, literal));
+        } else if weight < 0 {
+            // w * l = w + (-w) * !l
+            bound -= weight;
+            normalized_terms.push((weight.unsigned_abs() as u64, !literal));
+        }
+    }
+    if bound < 0 {
+        add_clause(solver, &[]);
+        return;
+    }
+    let bound = bound as u128;
+    if normalized_terms.iter().map(|(weight, _)| *weight as u128).sum::<u128>() <= bound {
+        return;
+    }
+    match encoding {
+        PseudoBooleanEncoding::BDD => add_bdd(solver, normalized_terms, bound),
+        PseudoBooleanEncoding::Adder => add_adder(solver, &normalized_terms, bound),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BDDNode {
+    True,
+    False,
+    Literal(Literal),
+}
+
+struct BDDBuilder {
+    terms: Vec<(u64, Literal)>,
+    // suffix_sums[i]: terms[i..] の重みの和
+    suffix_sums: Vec<u128>,
+    nodes: HashMap<(usize, u128), BDDNode>,
+}
+
+impl BDDBuilder {
+    /// terms[i..] の和が bound 以下であることを表す節点(節点のリテラルが真なら制約が成り立つ)
+    fn build(&mut self, solver: &mut SATSolver, i: usize, bound: u128) -> BDDNode {
+        if self.suffix_sums[i] <= bound {
+            return BDDNode::True;
+        }
+        if let Some(node) = self.nodes.get(&(i, bound)) {
+            return *node;
+        }
+        let (weight, literal) = self.terms[i];
+        let weight = weight as u128;
+        let high = if weight > bound { BDDNode::False } else { self.build(solver, i + 1, bound - weight) };
+        let low = self.build(solver, i + 1, bound);
+        let node = if high == low {
+            low
+        } else {
+            let node = new_literal(solver);
+            // 上限が単調なので low が偽なら high も偽(high == low)
+            if let BDDNode::Literal(low) = low {
+                add_clause(solver, &[!node, low]);
+            }
+            match high {
+                BDDNode::False => add_clause(solver, &[!node, !literal]),
+                BDDNode::Literal(high) => add_clause(solver, &[!node, !literal, high]),
+                BDDNode::True => unreachable!(),
+            }
+            BDDNode::Literal(node)
+        };
+        self.nodes.insert((i, bound), node);
+        node
+    }
+}
+
+fn add_bdd(solver: &mut SATSolver, terms: Vec<(u64, Literal)>, bound: u128) {
+    let mut terms = terms;
+    // 重みの大きい順に並べると節点が共有されやすい
+    terms.sort_by_key(|term| std::cmp::Reverse(term.0));
+    let mut suffix_sums = vec![0; terms.len() + 1];
+    for i in (0..terms.len()).rev() {
+        suffix_sums[i] = suffix_sums[i + 1] + terms[i].0 as u128;
+    }
+    let mut builder = BDDBuilder { terms, suffix_sums, nodes: HashMap::new() };
+    match builder.build(solver, 0, bound) {
+        BDDNode::True => {}
+        BDDNode::False => add_clause(solver, &[]),
+        BDDNode::Literal(root) => add_clause(solver, &[root]),
+    }
+}
+
+/// 全加算器(carry が None なら半加算器)．和と桁上がりを返す
+fn full_adder(solver: &mut SATSolver, a: Literal, b: Literal, carry: Option<Literal>) -> (Literal, Literal) {
+    let sum = new_literal(solver);
+    let carry_out = new_literal(solver);
+    let inputs: Vec<Literal> = [Some(a), Some(b), carry].iter().flatten().copied().collect();
+    // sum = a xor b (xor carry)
+    for signs in 0..1u32 << inputs.len() {
+        let mut clause: Vec<Literal> = Vec::with_capacity(inputs.len() + 1);
+        let mut parity = false;
+        for (k, input) in inputs.iter().enumerate() {
+            let value = (signs >> k) & 1 != 0;
+            parity ^= value;
+            clause.push(if value { !*input } else { *input });
+        }
+        clause.push(if parity { sum } else { !sum });
+        add_clause(solver, &clause);
+    }
+    // carry_out = (a, b, carry) の多数決(carry が None なら a and b)
+    if inputs.len() == 3 {
+        for (x, y) in [(a, b), (a, inputs[2]), (b, inputs[2])] {
+            add_clause(solver, &[!x, !y, carry_out]);
+            add_clause(solver, &[x, y, !carry_out]);
+        }
+    } else {
+        add_clause(solver, &[!a, !b, carry_out]);
+        add_clause(solver, &[a, !carry_out]);
+        add_clause(solver, &[b, !carry_out]);
+    }
+    (sum, carry_out)
+}
+
+fn add_adder(solver: &mut SATSolver, terms: &[(u64, Literal)], bound: u128) {
+    // 各桁に加えるビットを集めて全加算器で畳み込む
+    let mut buckets: Vec<Vec<Literal>> = Vec::new();
+    for (weight, literal) in terms.iter() {
+        for bit in 0..64 {
+            if (weight >> bit) & 1 != 0 {
+                if buckets.len() <= bit {
+                    buckets.resize(bit + 1, Vec::new());
+                }
+                buckets[bit].push(*literal);
+            }
+        }
+    }
+    let mut sum_bits: Vec<Option<Literal>> = Vec::new();
+    let mut bit = 0;
+    while bit < buckets.len() {
+        while buckets[bit].len() >= 2 {
+            let a = buckets[bit].pop().unwrap();
+            let b = buckets[bit].pop().unwrap();
+            let c = buckets[bit].pop();
+            let (sum, carry) = full_adder(solver, a, b, c);
+            buckets[bit].push(sum);
+            if buckets.len() <= bit + 1 {
+                buckets.push(Vec::new());
+            }
+            buckets[bit + 1].push(carry);
+        }
+        sum_bits.push(buckets[bit].pop());
+        bit += 1;
+    }
+    // 和が bound を超えるのは，bound のビットが 0 の桁で和のビットが 1 であり，それより上の bound のビットが 1 の桁で和のビットもすべて 1 の場合
+    // NOTE: 和の桁数は bound の桁数を超えうるので，シフトがあふれる上位の桁は 0 とする
+    let bound_bit = |i: usize| bound.checked_shr(i as u32).is_some_and(|shifted| shifted & 1 != 0);
+    let width = sum_bits.len().max(128 - bound.leading_zeros() as usize);
+    for i in 0..width {
+        if bound_bit(i) {
+            continue;
+        }
+        let Some(Some(sum_bit)) = sum_bits.get(i) else {
+            continue;
+        };
+        let mut clause = vec![!*sum_bit];
+        let mut is_satisfied = false;
+        for j in i + 1..width {
+            if !bound_bit(j) {
+                continue;
+            }
+            match sum_bits.get(j) {
+                Some(Some(higher_bit)) => clause.push(!*higher_bit),
+                _ => {
+                    // 和のビットが常に 0 なので和は bound を超えない
+                    is_satisfied = true;
+                    break;
+                }
+            }
+        }
+        if !is_satisfied {
+            add_clause(solver, &clause);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sat::SATSolverResult;
+
+    /// n 変数のすべての割り当てについて，符号化した制約が成り立つことと仮定の下で充足可能であることが一致するか検査する
+    ///
+    /// 否定を含む場合も検査するため，奇数番目の変数は否定したリテラルを制約に渡す．
+    fn check<E, C>(n: VariableSize, encode: E, constraint: C)
+    where
+        E: Fn(&mut SATSolver, &[Literal]),
+        C: Fn(&[bool]) -> bool,
+    {
+        let mut solver = SATSolver::new();
+        solver.expand_variables(n);
+        let literals: Vec<Literal> =
+            (0..n).map(|variable_index| Literal { sign: variable_index % 2 == 0, index: variable_index }).collect();
+        encode(&mut solver, &literals);
+        for values in 0..1u32 << n {
+            let assumptions: Vec<Literal> = (0..n)
+                .map(|variable_index| Literal { sign: values >> variable_index & 1 != 0, index: variable_index })
+                .collect();
+            let literal_values: Vec<bool> =
+                literals.iter().map(|literal| (values >> literal.index & 1 != 0) == literal.sign).collect();
+            let result = solver.solve_with_assumptions(&assumptions);
+            assert_eq!(
+                matches!(result, SATSolverResult::Satisfiable { .. }),
+                constraint(&literal_values),
+                "values={:b}",
+                values
+            );
+        }
+    }
+
+    fn count(values: &[bool]) -> usize {
+        values.iter().filter(|value| **value).count()
+    }
+
+    #[test]
+    fn at_most_one_and_exactly_one() {
+        for encoding in [
+            AtMostOneEncoding::Pairwise,
+            AtMostOneEncoding::SequentialCounter,
+            AtMostOneEncoding::Commander,
+            AtMostOneEncoding::Product,
+        ] {
+            for n in 1..=7 {
+                check(n, |solver, literals| add_at_most_one(solver, literals, encoding), |values| count(values) <= 1);
+                check(n, |solver, literals| add_exactly_one(solver, literals, encoding), |values| count(values) == 1);
+            }
+        }
+    }
+
+    #[test]
+    fn at_most_k_and_at_least_k() {
+        for encoding in [CardinalityEncoding::Totalizer, CardinalityEncoding::CardinalityNetwork] {
+            for n in 1..=6 {
+                for k in 0..=n + 1 {
+                    check(
+                        n,
+                        |solver, literals| add_at_most_k(solver, literals, k, encoding),
+                        |values| count(values) <= k as usize,
+                    );
+                    check(
+                        n,
+                        |solver, literals| add_at_least_k(solver, literals, k, encoding),
+                        |values| count(values) >= k as usize,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn totalizer_bound_by_assumption() {
+        let n = 6;
+        let mut solver = SATSolver::new();
+        solver.expand_variables(n);
+        let literals: Vec<Literal> =
+            (0..n).map(|variable_index| Literal { sign: true, index: variable_index }).collect();
+        let totalizer = Totalizer::new(&mut solver, &literals, 4);
+        for k in 0..=4 {
+            for values in 0..1u32 << n {
+                let mut assumptions: Vec<Literal> = (0..n)
+                    .map(|variable_index| Literal { sign: values >> variable_index & 1 != 0, index: variable_index })
+                    .collect();
+                assumptions.extend(totalizer.at_most(k));
+                let result = solver.solve_with_assumptions(&assumptions);
+                assert_eq!(matches!(result, SATSolverResult::Satisfiable { .. }), values.count_ones() <= k);
+            }
+        }
+    }
+
+    #[test]
+    fn pseudo_boolean() {
+        let weight_sets: [&[i64]; 4] =
+            [&[1, 2, 3, 4, 5], &[3, -2, 7, -5, 1, 4], &[i64::MAX, i64::MAX, 1, -1], &[i64::MIN, i64::MIN, 3, i64::MAX]];
+        for encoding in [PseudoBooleanEncoding::BDD, PseudoBooleanEncoding::Adder] {
+            for weights in weight_sets {
+                let sum = |values: &[bool]| -> i128 {
+                    weights
+                        .iter()
+                        .zip(values.iter())
+                        .filter(|(_, value)| **value)
+                        .map(|(weight, _)| *weight as i128)
+                        .sum()
+                };
+                for bound in [i64::MIN, -7, -1, 0, 1, 6, 11, i64::MAX] {
+                    let n = weights.len() as VariableSize;
+                    let terms = |literals: &[Literal]| -> Vec<(i64, Literal)> {
+                        weights.iter().copied().zip(literals.iter().copied()).collect()
+                    };
+                    check(
+                        n,
+                        |solver, literals| add_pseudo_boolean_at_most(solver, &terms(literals), bound, encoding),
+                        |values| sum(values) <= bound as i128,
+                    );
+                    check(
+                        n,
+                        |solver, literals| add_pseudo_boolean_at_least(solver, &terms(literals), bound, encoding),
+                        |values| sum(values) >= bound as i128,
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use num::{BigUint, ToPrimitive};
+
+use crate::finite_collections::Array;
+
+use super::random::Random;
+use super::solver::SATSolver;
+use super::types::{Literal, VariableSize};
+
+/// 射影する変数上のランダムな XOR 制約の列(ハッシュ関数)
+///
+/// 各 XOR 制約には新しい変数 s, t を加えて ¬s, ¬t を仮定したときだけ働くようにする．
+/// s, t は他の制約に現れないので(どちらか一方だけでは，他の変数が決定レベル 0 で固定されると固定されてしまう)，
+/// 決定レベル 0 で割り当てられることはなく，s, t を含まない学習節は他の制約だけから導かれる．
+/// 使い終わったら s, t を含む制約を取り除き，s, t は次の XOR 制約のために確保しておく．
+#[derive(Default)]
+struct Hash {
+    // 先頭から 2m 個で m 個の XOR 制約を有効にする仮定
+    assumptions: Vec<Literal>,
+}
+
+impl Hash {
+    /// XOR 制約を 1 つ追加する
+    ///
+    /// 各変数を確率 1/2 で含め，パリティも一様に選ぶ．
+    fn push_random_xor(&mut self, solver: &mut SATSolver, sampling_set: &[VariableSize], random: &mut Random) {
+        let mut variable_indices: Vec<VariableSize> =
+            sampling_set.iter().filter(|_| random.next_bool()).copied().collect();
+        let parity = random.next_bool();
+        solver.expand_variables(2);
+        let number_of_variables = solver.number_of_variables();
+        for selector in number_of_variables - 2..number_of_variables {
+            variable_indices.push(selector);
+            self.assumptions.push(Literal { sign: false, index: selector });
+        }
+        solver.add_xor(&variable_indices, parity);
+    }
+
+    fn len(&self) -> usize {
+        self.assumptions.len() / 2
+    }
+
+    /// 先頭から m 個の XOR 制約を有効にする仮定
+    fn assumptions(&self, m: usize) -> &[Literal] {
+        &self.assumptions[..2 * m]
+    }
+
+    /// すべての XOR 制約を取り除く(XOR 制約のために加えた変数は末尾に並んでいる)
+    fn remove(self, solver: &mut SATSolver) {
+        let count = self.assumptions.len() as VariableSize;
+        let first_variable_index = solver.number_of_variables() - count;
+        debug_assert!((first_variable_index..).zip(self.assumptions.iter()).all(|(i, literal)| literal.index == i));
+        solver.remove_last_variables(count);
+    }
+}
+
+/// assumptions の下で射影した解を高々 limit 個列挙する
+fn bounded_solutions(
+    solver: &mut SATSolver,
+    assumptions: &[Literal],
+    sampling_set: &[VariableSize],
+    limit: usize,
+) -> Vec<Array<VariableSize, bool>> {
+    let mut solutions = Vec::new();
+    solver.enumerate_models_with_assumptions(assumptions, sampling_set, Some(limit), |solution| {
+        solutions.push(solution.clone())
+    });
+    solutions
+}
+
+/// ApproxMC の閾値 1 + 9.84 (1 + ε/(1+ε)) (1 + 1/ε)^2
+fn approximate_count_threshold(epsilon: f64) -> usize {
+    (1.0 + 9.84 * (1.0 + epsilon / (1.0 + epsilon)) * (1.0 + 1.0 / epsilon).powi(2)).ceil() as usize
+}
+
+/// sampling_set に射影した解の数を ApproxMC の方法で近似する
+///
+/// 確率 1 - delta 以上で，真の値の 1/(1+epsilon) 倍以上 (1+epsilon) 倍以下の値を返す．
+/// ランダムな XOR 制約を m 個加えたセルの解の数が閾値未満となる最小の m を二分探索で求め，(セルの解の数)・2^m を推定値とする．
+/// これを ceil(17 log2(3/delta)) 回繰り返した中央値を返す．
+#[inline(never)]
+pub fn approximate_count(
+    solver: &mut SATSolver,
+    sampling_set: &[VariableSize],
+    epsilon: f64,
+    delta: f64,
+    seed: u64,
+) -> BigUint {
+    debug_assert!(epsilon > 0.0 && 0.0 < delta && delta < 1.0);
+    let threshold = approximate_count_threshold(epsilon);
+    let count = solver.enumerate_models(sampling_set, Some(threshold), |_| {});
+    if count < threshold {
+        // 解が少なければ数え上げた値が正確な値
+        return BigUint::from(count);
+    }
+    let iteration_count = (17.0 * (3.0 / delta).log2()).ceil() as usize;
+    let mut random = Random::new(seed);
+    let mut estimates = Vec::with_capacity(iteration_count);
+    for _ in 0..iteration_count {
+        // XOR 制約は二分探索で必要になった分だけ追加し，先頭の m 個を有効にしたものを m 個目のハッシュとする
+        let mut hash = Hash::default();
+        let mut cell_counts: HashMap<usize, usize> = HashMap::new();
+        let mut cell_count = |m: usize, hash: &mut Hash, solver: &mut SATSolver| -> usize {
+            while hash.len() < m {
+                hash.push_random_xor(solver, sampling_set, &mut random);
+            }
+            *cell_counts.entry(m).or_insert_with(|| {
+                solver.enumerate_models_with_assumptions(hash.assumptions(m), sampling_set, Some(threshold), |_| {})
+            })
+        };
+        // cell_count(lower) >= threshold かつ cell_count(upper) < threshold (upper が射影する変数の数の場合は例外)
+        let mut lower = 0;
+        let mut upper = sampling_set.len();
+        while upper - lower > 1 {
+            let middle = (lower + upper) / 2;
+            if cell_count(middle, &mut hash, solver) < threshold {
+                upper = middle;
+            } else {
+                lower = middle;
+            }
+        }
+        let count = cell_count(upper, &mut hash, solver);
+        estimates.push(BigUint::from(count) << upper);
+        hash.remove(solver);
+    }
+    estimates.sort();
+    estimates.swap_remove(estimates.len() / 2)
+}
+
+/// UniGen による射影した解のほぼ一様なサンプラ
+///
+/// 各解が確率 1/(1+epsilon)/N 以上 (1+epsilon)/N 以下で選ばれる(N は射影した解の数)．
+/// ApproxMC で求めた解の数の近似値から XOR 制約の個数 q を決め，q-3 個から q 個までの XOR 制約で分割したセルのうち
+/// 解の数が適当な範囲に入った最初のセルから一様に 1 つ選ぶ．
+pub struct UniformSampler {
+    random: Random,
+    sampling_set: Vec<VariableSize>,
+    // 返す解の変数の数
+    number_of_variables: VariableSize,
+    /// epsilon から定めた κ
+    pub kappa: f64,
+    /// セルの解の数の目安
+    pub pivot: f64,
+    /// 解を選ぶセルの解の数の下限
+    pub low_threshold: usize,
+    /// 解を選ぶセルの解の数の上限
+    pub high_threshold: usize,
+    // 解が high_threshold 個以下ならすべての解
+    small_solutions: Option<Vec<Array<VariableSize, bool>>>,
+    /// XOR 制約の個数 q(解が high_threshold 個以下なら 0)
+    pub hash_length: usize,
+    pub failure_count: usize,
+}
+
+impl UniformSampler {
+    /// epsilon は 1.71 より大きいこと
+    #[inline(never)]
+    pub fn new(solver: &mut SATSolver, sampling_set: &[VariableSize], epsilon: f64, seed: u64) -> Self {
+        debug_assert!(epsilon > 1.71);
+        // epsilon = (1+κ)(2.23 + 0.48/(1-κ)^2) - 1 を満たす κ ∈ (0, 1) を二分法で求める
+        let mut kappa_lower = 0.0;
+        let mut kappa_upper = 1.0;
+        for _ in 0..64 {
+            let kappa: f64 = (kappa_lower + kappa_upper) / 2.0;
+            if (1.0 + kappa) * (2.23 + 0.48 / (1.0 - kappa).powi(2)) - 1.0 < epsilon {
+                kappa_lower = kappa;
+            } else {
+                kappa_upper = kappa;
+            }
+        }
+        let kappa = kappa_lower;
+        let pivot = (4.03 * (1.0 + 1.0 / kappa).powi(2)).ceil();
+        let high_threshold = (1.0 + (1.0 + kappa) * pivot) as usize;
+        let low_threshold = (pivot / (1.0 + kappa)) as usize;
+        let number_of_variables = solver.number_of_variables();
+        let mut random = Random::new(seed);
+        let solutions = bounded_solutions(solver, &[], sampling_set, high_threshold + 1);
+        let (small_solutions, hash_length) = if solutions.len() <= high_threshold {
+            (Some(solutions), 0)
+        } else {
+            let count = approximate_count(solver, sampling_set, 0.8, 0.8, random.next_u64());
+            // q = ceil(log2 C + log2 1.8 - log2 pivot)
+            let log2_count = match count.to_f64() {
+                Some(count) if count.is_finite() => count.log2(),
+                _ => count.bits() as f64,
+            };
+            (None, (log2_count + 1.8f64.log2() - pivot.log2()).ceil().max(0.0) as usize)
+        };
+        UniformSampler {
+            random,
+            sampling_set: sampling_set.to_vec(),
+            number_of_variables,
+            kappa,
+            pivot,
+            low_threshold,
+            high_threshold,
+            small_solutions,
+            hash_length,
+            failure_count: 0,
+        }
+    }
+
+    /// 解を 1 つ選ぶ(解がない場合や，適当な大きさのセルが見つからなかった場合は None)
+    #[inline(never)]
+    pub fn sample(&mut self, solver: &mut SATSolver) -> Option<Array<VariableSize, bool>> {
+        if let Some(solutions) = &self.small_solutions {
+            if solutions.is_empty() {
+                return None;
+            }
+            return Some(solutions[self.random.next_below(solutions.len() as u32) as usize].clone());
+        }
+        let mut hash = Hash::default();
+        for length in self.hash_length.saturating_sub(3)..=self.hash_length {
+            while hash.len() < length {
+                hash.push_random_xor(solver, &self.sampling_set, &mut self.random);
+            }
+            let solutions =
+                bounded_solutions(solver, hash.assumptions(length), &self.sampling_set, self.high_threshold + 1);
+            if self.low_threshold <= solutions.len() && solutions.len() <= self.high_threshold {
+                hash.remove(solver);
+                let mut solution = solutions[self.random.next_below(solutions.len() as u32) as usize].clone();
+                // 解は呼び出し側が追加した変数の数に揃える
+                solution.resize(self.number_of_variables, false);
+                return Some(solution);
+            }
+        }
+        hash.remove(solver);
+        self.failure_count += 1;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::super::model_counter::count_models;
+    use super::super::solver::SATSolverOptions;
+    use super::super::test_helpers::{is_solution, random_problem};
+    use super::*;
+
+    #[test]
+    fn approximate_count_is_within_tolerance() {
+        let mut random = Random::new(1);
+        let epsilon = 0.8;
+        for k in 0..8 {
+            let mut problem = random_problem(&mut random, 14, 10 + k);
+            let mut projection = Array::default();
+            for variable_index in 0..12 {
+                projection.push(variable_index);
+            }
+            problem.projection = Some(projection.clone());
+            let (count, _) = count_models(&problem, &SATSolverOptions::default());
+            let mut solver = SATSolver::with_options(SATSolverOptions::default());
+            solver.add_problem(&problem);
+            let estimate = approximate_count(&mut solver, projection.as_slice(), epsilon, 0.2, k as u64);
+            let (count, estimate) = (count.to_f64().unwrap(), estimate.to_f64().unwrap());
+            assert!(
+                count / (1.0 + epsilon) <= estimate && estimate <= count * (1.0 + epsilon),
+                "{} {}",
+                count,
+                estimate
+            );
+            // XOR 制約と選択用の変数は取り除かれている
+            assert_eq!(solver.number_of_variables(), problem.number_of_variables);
+            assert_eq!(solver.enumerate_models(projection.as_slice(), None, |_| {}) as f64, count);
+        }
+    }
+
+    #[test]
+    fn sampled_solutions_cover_all_projected_models() {
+        let mut random = Random::new(2);
+        let projection: Vec<VariableSize> = (0..8).collect();
+        for k in 0..4 {
+            // 射影した解が多く，XOR 制約でセルに分割される問題を選ぶ
+            let (problem, models) = loop {
+                let problem = random_problem(&mut random, 10, 6);
+                let mut solver = SATSolver::with_options(SATSolverOptions::default());
+                solver.add_problem(&problem);
+                let mut models = HashSet::new();
+                solver.enumerate_models(&projection, None, |solution| {
+                    models
+                        .insert(projection.iter().map(|variable_index| solution[*variable_index]).collect::<Vec<_>>());
+                });
+                if 60 <= models.len() && models.len() <= 120 {
+                    break (problem, models);
+                }
+            };
+            let mut solver = SATSolver::with_options(SATSolverOptions::default());
+            solver.add_problem(&problem);
+            let mut sampler = UniformSampler::new(&mut solver, &projection, 16.0, k);
+            assert!(sampler.small_solutions.is_none());
+            let mut sampled_models = HashSet::new();
+            for _ in 0..4000 {
+                if sampled_models.len() == models.len() {
+                    break;
+                }
+                let Some(solution) = sampler.sample(&mut solver) else {
+                    continue;
+                };
+                assert_eq!(solution.len(), problem.number_of_variables);
+                assert!(is_solution(&problem, &solution));
+                sampled_models.insert(projection.iter().map(|variable_index| solution[*variable_index]).collect());
+            }
+            assert_eq!(sampled_models, models);
+            assert_eq!(solver.number_of_variables(), problem.number_of_variables);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use crate::finite_collections::Array;
+
+use super::clause_theory::ClauseTheory;
+use super::random::Random;
+use super::types::{ConstraintSize, Literal, VariableSize};
+use super::variables::{VariableState, Variables};
+
+/// 確率的局所探索(probSAT)
+///
+/// 非学習節のみを対象に，充足されていない節をランダムに 1 つ選び，その節に含まれる変数を
+/// 反転したときに偽になる節の数(break)が少ないほど高い確率で反転する．
+/// 決定レベル 0 で割り当てられている変数は固定し，それにより充足されている節は対象外とする．
+pub struct LocalSearch {
+    random: Random,
+    break_probabilities: Array<VariableSize, f64>,
+    // 対象の節(リテラルは literals[clause_starts[c]..clause_starts[c + 1]])
+    literals: Array<ConstraintSize, Literal>,
+    clause_starts: Array<ConstraintSize, ConstraintSize>,
+    // リテラルを含む節の一覧(occurrences[変数][符号])
+    occurrences: Array<VariableSize, [Array<ConstraintSize, ConstraintSize>; 2]>,
+    assignment: Array<VariableSize, bool>,
+    best_assignment: Array<VariableSize, bool>,
+    // 節に含まれる真のリテラルの数
+    true_counts: Array<ConstraintSize, VariableSize>,
+    // 充足されていない節の一覧と，その中での各節の位置
+    unsatisfied_clauses: Array<ConstraintSize, ConstraintSize>,
+    unsatisfied_positions: Array<ConstraintSize, ConstraintSize>,
+    candidate_probabilities: Array<VariableSize, f64>,
+    flip_count: usize,
+}
+
+impl LocalSearch {
+    const NULL_POSITION: ConstraintSize = ConstraintSize::MAX;
+
+    /// break の値ごとの反転確率の重み cb^(-break) を前もって計算しておく上限
+    const MAX_TABULATED_BREAK: VariableSize = 64;
+
+    pub fn new(seed: u64, cb: f64) -> Self {
+        let mut break_probabilities = Array::default();
+        for b in 0..=Self::MAX_TABULATED_BREAK {
+            break_probabilities.push(cb.powi(-(b as i32)));
+        }
+        LocalSearch {
+            random: Random::new(seed),
+            break_probabilities,
+            literals: Array::default(),
+            clause_starts: Array::default(),
+            occurrences: Array::default(),
+            assignment: Array::default(),
+            best_assignment: Array::default(),
+            true_counts: Array::default(),
+            unsatisfied_clauses: Array::default(),
+            unsatisfied_positions: Array::default(),
+            candidate_probabilities: Array::default(),
+            flip_count: 0,
+        }
+    }
+
+    /// 最良の割り当て(充足されていない節の数が最小の割り当て)
+    #[inline(always)]
+    pub fn best_assignment(&self) -> &Array<VariableSize, bool> {
+        &self.best_assignment
+    }
+
+    /// これまでに反転した回数の合計
+    #[inline(always)]
+    pub fn flip_count(&self) -> usize {
+        self.flip_count
+    }
+
+    /// 保存されている位相を初期値として最大 max_flips 回の反転を行う
+    ///
+    /// 探索後に充足されていない節の数の最小値を返す(0 であれば best_assignment は非学習節をすべて充足する)．
+    /// 決定レベル 0 の割り当てだけで偽になる節が存在する場合には None を返す．
+    #[inline(never)]
+    pub fn search(&mut self, theory: &ClauseTheory, variables: &Variables, max_flips: usize) -> Option<ConstraintSize> {
+        if !self.initialize(theory, variables) {
+            return None;
+        }
+        let mut best_unsatisfied_count = self.unsatisfied_clauses.len();
+        self.best_assignment.clone_from(&self.assignment);
+        for _ in 0..max_flips {
+            if self.unsatisfied_clauses.is_empty() {
+                break;
+            }
+            // 充足されていない節をランダムに選択
+            let clause_index = self.unsatisfied_clauses[self.random.next_below(self.unsatisfied_clauses.len())];
+            // 節に含まれる変数の break に応じて反転する変数を選択
+            let (start, end) = (self.clause_starts[clause_index], self.clause_starts[clause_index + 1]);
+            self.candidate_probabilities.clear();
+            let mut sum = 0.0;
+            for k in start..end {
+                let break_count = self.break_count(self.literals[k].index);
+                let probability = self.break_probabilities[break_count.min(Self::MAX_TABULATED_BREAK)];
+                sum += probability;
+                self.candidate_probabilities.push(probability);
+            }
+            let mut threshold = self.random.next_f64() * sum;
+            let mut selected = end - 1;
+            for k in start..end {
+                threshold -= self.candidate_probabilities[k - start];
+                if threshold < 0.0 {
+                    selected = k;
+                    break;
+                }
+            }
+            self.flip(self.literals[selected].index);
+            self.flip_count += 1;
+            if self.unsatisfied_clauses.len() < best_unsatisfied_count {
+                best_unsatisfied_count = self.unsatisfied_clauses.len();
+                self.best_assignment.clone_from(&self.assignment);
+            }
+        }
+        Some(best_unsatisfied_count)
+    }
+
+    #[inline(never)]
+    fn initialize(&mut self, theory: &ClauseTheory, variables: &Variables) -> bool {
+        // 初期割り当ては保存されている位相(割り当て済みであればその値)
+        self.assignment.clear();
+        for variable_index in 0..variables.dimension() {
+            self.assignment.push(match variables.get(variable_index) {
+                VariableState::Assigned { assigned_value, .. } => *assigned_value,
+                VariableState::TentativelyAssigned { tentatively_assigned_value, .. } => *tentatively_assigned_value,
+                VariableState::Conflicting { last_assigned_value, .. } => *last_assigned_value,
+                VariableState::Unassigned { last_assigned_value } => *last_assigned_value,
+            });
+        }
+        for occurrence in self.occurrences.iter_mut() {
+            occurrence[0].clear();
+            occurrence[1].clear();
+        }
+        self.occurrences.resize_with(variables.dimension(), || [Array::default(), Array::default()]);
+        // 決定レベル 0 で充足されている節を除き，決定レベル 0 で偽のリテラルを取り除いて節を登録
+        self.literals.clear();
+        self.clause_starts.clear();
+        self.clause_starts.push(0);
+        for clause in theory.original_clauses() {
+            if clause.iter().any(|literal| variables.get(literal.index).is_value_fixed(literal.sign)) {
+                continue;
+            }
+            let clause_index = self.clause_starts.len() - 1;
+            for literal in clause.iter() {
+                if !variables.get(literal.index).is_value_fixed(!literal.sign) {
+                    self.literals.push(*literal);
+                    self.occurrences[literal.index][literal.sign as usize].push(clause_index);
+                }
+            }
+            if self.literals.len() == self.clause_starts[clause_index] {
+                return false;
+            }
+            self.clause_starts.push(self.literals.len());
+        }
+        // 真のリテラルの数と充足されていない節を計算
+        let number_of_clauses = self.clause_starts.len() - 1;
+        self.true_counts.clear();
+        self.unsatisfied_clauses.clear();
+        self.unsatisfied_positions.clear();
+        for clause_index in 0..number_of_clauses {
+            let mut true_count = 0;
+            for k in self.clause_starts[clause_index]..self.clause_starts[clause_index + 1] {
+                let literal = self.literals[k];
+                if self.assignment[literal.index] == literal.sign {
+                    true_count += 1;
+                }
+            }
+            self.true_counts.push(true_count);
+            self.unsatisfied_positions.push(Self::NULL_POSITION);
+            if true_count == 0 {
+                self.unsatisfied_positions[clause_index] = self.unsatisfied_clauses.len();
+                self.unsatisfied_clauses.push(clause_index);
+            }
+        }
+        true
+    }
+
+    /// 変数を反転したときに偽になる節の数
+    #[inline(always)]
+    fn break_count(&self, variable_index: VariableSize) -> VariableSize {
+        let value = self.assignment[variable_index];
+        let mut break_count = 0;
+        for clause_index in self.occurrences[variable_index][value as usize].iter() {
+            if self.true_counts[*clause_index] == 1 {
+                break_count += 1;
+            }
+        }
+        break_count
+    }
+
+    #[inline(always)]
+    fn flip(&mut self, variable_index: VariableSize) {
+        let value = self.assignment[variable_index];
+        self.assignment[variable_index] = !value;
+        // 真になったリテラルを含む節
+        for k in 0..self.occurrences[variable_index][!value as usize].len() {
+            let clause_index = self.occurrences[variable_index][!value as usize][k];
+            self.true_counts[clause_index] += 1;
+            if self.true_counts[clause_index] == 1 {
+                // 充足されていない節の一覧から削除
+                let position = self.unsatisfied_positions[clause_index];
+                self.unsatisfied_clauses.swap_remove(position);
+                if position < self.unsatisfied_clauses.len() {
+                    self.unsatisfied_positions[self.unsatisfied_clauses[position]] = position;
+                }
+                self.unsatisfied_positions[clause_index] = Self::NULL_POSITION;
+            }
+        }
+        // 偽になったリテラルを含む節
+        for k in 0..self.occurrences[variable_index][value as usize].len() {
+            let clause_index = self.occurrences[variable_index][value as usize][k];
+            self.true_counts[clause_index] -= 1;
+            if self.true_counts[clause_index] == 0 {
+                // 充足されていない節の一覧に追加
+                self.unsatisfied_positions[clause_index] = self.unsatisfied_clauses.len();
+                self.unsatisfied_clauses.push(clause_index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decision_heuristic::{create_decision_heuristic, DecisionHeuristicKind};
+    use super::super::solver::SATSolverOptions;
+    use super::super::theory::Theory;
+    use super::super::variables::{PropagationOrder, VariableRandomization};
+    use super::*;
+
+    fn clause(literals: &[(VariableSize, bool)]) -> Array<VariableSize, Literal> {
+        let mut clause = Array::default();
+        for &(index, sign) in literals {
+            clause.push(Literal { index, sign });
+        }
+        clause
+    }
+
+    #[test]
+    fn search_after_reduction_finds_model() {
+        let mut variables = Variables::new(
+            create_decision_heuristic(DecisionHeuristicKind::Activity),
+            PropagationOrder::Score,
+            VariableRandomization::default(),
+        );
+        let mut theory = ClauseTheory::new(&SATSolverOptions::default());
+        variables.redimension(4, &mut Random::new(0));
+        theory.expand(4);
+        // x0 は決定レベル 0 で真に固定され，x0 を含む節は削除される
+        theory.add_clause(clause(&[(0, true)]), false, &mut variables);
+        variables.assign(0);
+        theory.add_clause(clause(&[(0, true), (1, false)]), false, &mut variables);
+        theory.add_clause(clause(&[(0, true), (2, true), (3, true)]), false, &mut variables);
+        theory.add_clause(clause(&[(1, true), (2, true)]), false, &mut variables);
+        theory.add_clause(clause(&[(2, false), (3, false)]), false, &mut variables);
+        theory.add_clause(clause(&[(1, false), (3, true)]), false, &mut variables);
+        theory.reduce(&variables);
+        assert_eq!(theory.original_clauses().count(), 3);
+
+        let mut local_search = LocalSearch::new(0, 2.5);
+        assert_eq!(local_search.search(&theory, &variables, 1000), Some(0));
+        let assignment = local_search.best_assignment();
+        assert!(assignment[0]);
+        assert!(theory.is_satisfied_by(assignment));
+    }
+}
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+
+use crate::finite_collections::Array;
+
+use super::encoding::{add_pseudo_boolean_at_most, PseudoBooleanEncoding, Totalizer};
+use super::read_cnf::MaxSATProblem;
+use super::solver::{SATSolver, SATSolverOptions, SATSolverResult};
+use super::types::{Literal, VariableSize};
+
+/// MaxSAT の探索方法
+#[derive(Clone, Copy, Debug)]
+pub enum MaxSATAlgorithm {
+    /// 充足不能コアを緩和して下界を上げていく(OLL / RC2)
+    OLL,
+    /// 解が見つかるたびにコストの上限を下げていく(linear SAT-UNSAT)
+    Linear,
+}
+
+pub enum MaxSATResult {
+    /// 最小コストとそれを達成する解
+    Optimum {
+        cost: u64,
+        solution: Array<VariableSize, bool>,
+    },
+    /// 最適性を示す前に打ち切られた
+    Satisfiable {
+        cost: u64,
+        solution: Array<VariableSize, bool>,
+    },
+    Unsatisfiable,
+    Unknown,
+}
+
+/// MaxSAT の探索の統計情報
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MaxSATStatistics {
+    /// 見つけた充足不能コアの数(OLL)
+    pub core_count: usize,
+    /// コアの緩和に使った totalizer の数(OLL)
+    pub totalizer_count: usize,
+}
+
+/// 解で充足されないソフト節の重みの和
+fn evaluate(problem: &MaxSATProblem, solution: &Array<VariableSize, bool>) -> u64 {
+    problem
+        .soft_clauses
+        .iter()
+        .filter(|(_, clause)| !clause.iter().any(|literal| solution[literal.index] == literal.sign))
+        .map(|(weight, _)| *weight)
+        .sum()
+}
+
+/// ハード節を追加し，各ソフト節を充足しないときに真になるリテラル(緩和リテラル)と重みの組を返す
+///
+/// 単位節のソフト節はそのリテラルの否定を，それ以外は節に追加した新しい変数を緩和リテラルとする．
+/// 同じ緩和リテラルの重みはまとめ，空のソフト節の重みは常に支払うコストとして返す．
+fn relax(solver: &mut SATSolver, problem: &MaxSATProblem) -> (Vec<(Literal, u64)>, u64) {
+    solver.expand_variables(problem.number_of_variables);
+    for clause in problem.hard_clauses.iter() {
+        solver.add_clause(clause);
+    }
+    let mut relaxations: Vec<(Literal, u64)> = Vec::new();
+    let mut relaxation_positions: HashMap<(VariableSize, bool), usize> = HashMap::new();
+    let mut base_cost = 0;
+    for (weight, clause) in problem.soft_clauses.iter() {
+        let relaxation = match clause.len() {
+            0 => {
+                base_cost += *weight;
+                continue;
+            }
+            1 => !clause[0],
+            _ => {
+                solver.expand_variables(1);
+                let relaxation = Literal { sign: true, index: solver.number_of_variables() - 1 };
+                let mut relaxed_clause = clause.clone();
+                relaxed_clause.push(relaxation);
+                solver.add_clause(&relaxed_clause);
+                relaxation
+            }
+        };
+        let position = *relaxation_positions.entry((relaxation.index, relaxation.sign)).or_insert_with(|| {
+            relaxations.push((relaxation, 0));
+            relaxations.len() - 1
+        });
+        relaxations[position].1 += *weight;
+    }
+    (relaxations, base_cost)
+}
+
+/// 重み付き MaxSAT 問題を解く
+///
+/// 解が見つかるたびに on_improvement にそのコストを通知する．結果とともに探索の統計情報を返す．
+/// ソフト節の重みの和は i64::MAX 以下であること(コストは u64 で，擬似ブール制約の係数は i64 で桁あふれしない)．
+#[inline(never)]
+pub fn solve_maxsat<F>(
+    problem: &MaxSATProblem,
+    options: &SATSolverOptions,
+    algorithm: MaxSATAlgorithm,
+    mut on_improvement: F,
+) -> (MaxSATResult, MaxSATStatistics)
+where
+    F: FnMut(u64),
+{
+    assert!(
+        problem
+            .soft_clauses
+            .iter()
+            .try_fold(0u64, |sum, (weight, _)| sum.checked_add(*weight))
+            .is_some_and(|sum| sum <= i64::MAX as u64),
+        "sum of soft clause weights exceeds {}",
+        i64::MAX
+    );
+    let mut solver = SATSolver::with_options(options.clone());
+    let (relaxations, base_cost) = relax(&mut solver, problem);
+    let mut statistics = MaxSATStatistics::default();
+    let result = match algorithm {
+        MaxSATAlgorithm::OLL => {
+            solve_oll(&mut solver, problem, relaxations, base_cost, &mut on_improvement, &mut statistics)
+        }
+        MaxSATAlgorithm::Linear => solve_linear(&mut solver, problem, relaxations, base_cost, &mut on_improvement),
+    };
+    (result, statistics)
+}
+
+/// OLL の仮定
+struct Objective {
+    // 仮定とするリテラル(偽になるとコスト weight を支払う)
+    literal: Literal,
+    weight: u64,
+    // 総和の変数であれば，その totalizer と上限
+    sum: Option<(usize, VariableSize)>,
+}
+
+/// 仮定を加える(すでにある仮定であれば重みを加える)
+fn add_objective(
+    objectives: &mut Vec<Objective>,
+    objective_positions: &mut HashMap<(VariableSize, bool), usize>,
+    literal: Literal,
+    weight: u64,
+    sum: Option<(usize, VariableSize)>,
+) {
+    match objective_positions.get(&(literal.index, literal.sign)) {
+        Some(position) => objectives[*position].weight += weight,
+        None => {
+            objective_positions.insert((literal.index, literal.sign), objectives.len());
+            objectives.push(Objective { literal, weight, sum });
+        }
+    }
+}
+
+/// 充足不能コアに含まれる仮定の最小の重みだけ下界を上げ，コアの仮定のうち偽になるものが高々 1 つという仮定を加える
+///
+/// 偽になる仮定の個数は totalizer で数え，上限 k の仮定がコアに含まれたら上限 k+1 の仮定を加える．
+#[inline(never)]
+fn solve_oll(
+    solver: &mut SATSolver,
+    problem: &MaxSATProblem,
+    relaxations: Vec<(Literal, u64)>,
+    base_cost: u64,
+    on_improvement: &mut dyn FnMut(u64),
+    statistics: &mut MaxSATStatistics,
+) -> MaxSATResult {
+    let mut objectives: Vec<Objective> = relaxations
+        .into_iter()
+        .map(|(relaxation, weight)| Objective { literal: !relaxation, weight, sum: None })
+        .collect();
+    let mut objective_positions: HashMap<(VariableSize, bool), usize> = HashMap::new();
+    for (position, objective) in objectives.iter().enumerate() {
+        objective_positions.insert((objective.literal.index, objective.literal.sign), position);
+    }
+    let mut totalizers: Vec<(Totalizer, VariableSize)> = Vec::new();
+    let mut lower_bound = base_cost;
+    loop {
+        let assumptions: Vec<Literal> =
+            objectives.iter().filter(|objective| objective.weight > 0).map(|objective| objective.literal).collect();
+        match solver.solve_with_assumptions(&assumptions) {
+            SATSolverResult::Satisfiable { solution } => {
+                let cost = evaluate(problem, &solution);
+                debug_assert!(cost == lower_bound);
+                on_improvement(cost);
+                return MaxSATResult::Optimum { cost, solution };
+            }
+            SATSolverResult::Unsatisfiable => {
+                let core: Vec<usize> = solver
+                    .failed_assumptions()
+                    .iter()
+                    .map(|literal| objective_positions[&(literal.index, literal.sign)])
+                    .collect();
+                if core.is_empty() {
+                    return MaxSATResult::Unsatisfiable;
+                }
+                statistics.core_count += 1;
+                let min_weight = core.iter().map(|position| objectives[*position].weight).min().unwrap();
+                lower_bound += min_weight;
+                let mut violations = Vec::with_capacity(core.len());
+                for position in core.iter() {
+                    objectives[*position].weight -= min_weight;
+                    violations.push(!objectives[*position].literal);
+                    // 上限 k の総和が k を超えるなら，上限 k+1 の仮定を加える
+                    if let Some((totalizer_index, k)) = objectives[*position].sum {
+                        let (totalizer, length) = &totalizers[totalizer_index];
+                        if k + 1 < *length {
+                            let literal = totalizer.at_most(k + 1).unwrap();
+                            add_objective(
+                                &mut objectives,
+                                &mut objective_positions,
+                                literal,
+                                min_weight,
+                                Some((totalizer_index, k + 1)),
+                            );
+                        }
+                    }
+                }
+                if violations.len() == 1 {
+                    // 仮定がどの解でも偽になる
+                    let mut clause = Array::default();
+                    clause.push(violations[0]);
+                    solver.add_clause(&clause);
+                    continue;
+                }
+                let length = violations.len() as VariableSize;
+                let totalizer = Totalizer::new(solver, &violations, length);
+                let literal = totalizer.at_most(1).unwrap();
+                add_objective(
+                    &mut objectives,
+                    &mut objective_positions,
+                    literal,
+                    min_weight,
+                    Some((totalizers.len(), 1)),
+                );
+                totalizers.push((totalizer, length));
+                statistics.totalizer_count += 1;
+            }
+            SATSolverResult::Unknown => {
+                return MaxSATResult::Unknown;
+            }
+        }
+    }
+}
+
+/// 解が見つかるたびに，緩和リテラルの重み付きの和がそのコスト未満という制約を加える
+///
+/// 重みがすべて等しければ totalizer を一度だけ作り，上限を仮定として与える．そうでなければ制約を BDD で符号化して追加する．
+#[inline(never)]
+fn solve_linear(
+    solver: &mut SATSolver,
+    problem: &MaxSATProblem,
+    relaxations: Vec<(Literal, u64)>,
+    base_cost: u64,
+    on_improvement: &mut dyn FnMut(u64),
+) -> MaxSATResult {
+    let is_unweighted = relaxations.windows(2).all(|pair| pair[0].1 == pair[1].1);
+    let relaxation_literals: Vec<Literal> = relaxations.iter().map(|(relaxation, _)| *relaxation).collect();
+    let totalizer = if is_unweighted {
+        Some(Totalizer::new(solver, &relaxation_literals, relaxation_literals.len() as VariableSize))
+    } else {
+        None
+    };
+    let mut assumptions = Vec::new();
+    let mut best: Option<(u64, Array<VariableSize, bool>)> = None;
+    loop {
+        match solver.solve_with_assumptions(&assumptions) {
+            SATSolverResult::Satisfiable { solution } => {
+                let cost = evaluate(problem, &solution);
+                debug_assert!(best.as_ref().is_none_or(|(best_cost, _)| cost < *best_cost));
+                on_improvement(cost);
+                // 緩和リテラルの重みの和が bound 未満の解を探す
+                let bound = cost - base_cost;
+                if bound == 0 {
+                    return MaxSATResult::Optimum { cost, solution };
+                }
+                best = Some((cost, solution));
+                match &totalizer {
+                    Some(totalizer) => {
+                        let k = (bound - 1) / relaxations[0].1;
+                        assumptions.clear();
+                        assumptions.push(totalizer.at_most(k as VariableSize).unwrap());
+                    }
+                    None => {
+                        let terms: Vec<(i64, Literal)> =
+                            relaxations.iter().map(|(relaxation, weight)| (*weight as i64, *relaxation)).collect();
+                        add_pseudo_boolean_at_most(solver, &terms, bound as i64 - 1, PseudoBooleanEncoding::BDD);
+                    }
+                }
+            }
+            SATSolverResult::Unsatisfiable => {
+                return match best {
+                    Some((cost, solution)) => MaxSATResult::Optimum { cost, solution },
+                    None => MaxSATResult::Unsatisfiable,
+                };
+            }
+            SATSolverResult::Unknown => {
+                return match best {
+                    Some((cost, solution)) => MaxSATResult::Satisfiable { cost, solution },
+                    None => MaxSATResult::Unknown,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::read_cnf::read_wcnf;
+    use super::super::test_helpers::{is_true, random_clause, Random};
+    use super::*;
+
+    #[test]
+    fn solve_maxsat_with_large_weights() {
+        // x1 と x2 のちょうど一方が真で，ソフト節の重みの和は i64::MAX - 1
+        let large = (i64::MAX as u64 - 4) / 2;
+        let input = format!("h 1 2 0\nh -1 -2 0\n{} -1 0\n{} -2 0\n3 1 0\n", large + 1, large);
+        let problem = read_wcnf(input.as_bytes());
+        for algorithm in [MaxSATAlgorithm::OLL, MaxSATAlgorithm::Linear] {
+            let mut costs = Vec::new();
+            let (result, _) = solve_maxsat(&problem, &SATSolverOptions::default(), algorithm, |cost| costs.push(cost));
+            let MaxSATResult::Optimum { cost, solution } = result else {
+                panic!("no optimum with {:?}", algorithm);
+            };
+            assert_eq!(cost, large + 1);
+            assert_eq!(evaluate(&problem, &solution), cost);
+            assert!(solution[0] && !solution[1]);
+            assert_eq!(costs.last(), Some(&cost));
+        }
+    }
+
+    /// 乱数で生成した重み付き MaxSAT 問題(unweighted であればソフト節の重みはすべて 1)
+    fn random_problem(random: &mut Random, n: VariableSize, unweighted: bool) -> MaxSATProblem {
+        let mut problem = MaxSATProblem { number_of_variables: n, ..MaxSATProblem::default() };
+        for _ in 0..random.next_below(55) {
+            problem.hard_clauses.push(random_clause(random, n));
+        }
+        for _ in 0..1 + random.next_below(20) {
+            let weight = if unweighted { 1 } else { 1 + random.next_below(10) as u64 };
+            // 長さ 0 から 3 のソフト節(同じ単位節が複数あれば重みはまとめられる)
+            let mut clause = random_clause(random, n);
+            clause.resize(random.next_below(4), Literal { sign: true, index: 0 });
+            problem.soft_clauses.push((weight, clause));
+        }
+        problem
+    }
+
+    /// 全探索で求めた最小コスト(ハード節を充足する解がなければ None)
+    fn brute_force(problem: &MaxSATProblem) -> Option<u64> {
+        (0..1u32 << problem.number_of_variables)
+            .filter(|values| {
+                problem.hard_clauses.iter().all(|clause| clause.iter().any(|literal| is_true(literal, *values)))
+            })
+            .map(|values| {
+                problem
+                    .soft_clauses
+                    .iter()
+                    .filter(|(_, clause)| !clause.iter().any(|literal| is_true(literal, values)))
+                    .map(|(weight, _)| *weight)
+                    .sum()
+            })
+            .min()
+    }
+
+    #[test]
+    fn solve_maxsat_matches_brute_force() {
+        let mut random = Random::new(1);
+        let n = 10;
+        for k in 0..200 {
+            let problem = random_problem(&mut random, n, k % 2 == 0);
+            let expected = brute_force(&problem);
+            for algorithm in [MaxSATAlgorithm::OLL, MaxSATAlgorithm::Linear] {
+                let mut costs = Vec::new();
+                let (result, _) =
+                    solve_maxsat(&problem, &SATSolverOptions::default(), algorithm, |cost| costs.push(cost));
+                match result {
+                    MaxSATResult::Optimum { cost, solution } => {
+                        assert_eq!(Some(cost), expected, "{:?}", algorithm);
+                        assert!(problem
+                            .hard_clauses
+                            .iter()
+                            .all(|clause| clause.iter().any(|literal| solution[literal.index] == literal.sign)));
+                        assert_eq!(evaluate(&problem, &solution), cost);
+                        // 通知されるコストは狭義単調減少で，最後が最小コスト
+                        assert!(costs.windows(2).all(|pair| pair[0] > pair[1]));
+                        assert_eq!(costs.last(), Some(&cost));
+                    }
+                    MaxSATResult::Unsatisfiable => assert_eq!(expected, None, "{:?}", algorithm),
+                    _ => panic!(),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::finite_collections::Array;
+
+use super::mus::add_grouped_problem;
+use super::read_cnf::GroupedProblem;
+use super::solver::{SATSolver, SATSolverOptions, SATSolverResult};
+use super::types::{ConstraintSize, Literal, VariableSize};
+
+/// 極小修正集合(MCS: 取り除くとハード節と残りのグループが充足可能になる極小なグループの集合)を順に求めるイテレータ
+///
+/// グループ 0 の節はハード節とし，それ以外のグループを取り除く対象とする．
+/// 各 MCS は LinearSearch で求める．解で充足されるグループの集合 S から始め，充足されないグループを 1 つずつ S に加えて
+/// S のグループを仮定して解き，充足可能であれば新しい解で充足されるグループもすべて S に加える(充足不能なら MCS に含める)．
+/// 求めた MCS のグループのいずれかを有効にする節を追加して，同じ MCS とその上位集合が再び求まらないようにする．
+pub struct MCSEnumerator<'a> {
+    problem: &'a GroupedProblem,
+    solver: SATSolver,
+    // グループを有効にする変数(グループ 0 は使わない)
+    selectors: Array<ConstraintSize, VariableSize>,
+    is_finished: bool,
+    pub solve_count: usize,
+}
+
+impl<'a> MCSEnumerator<'a> {
+    pub fn new(problem: &'a GroupedProblem, options: &SATSolverOptions) -> Self {
+        let mut solver = SATSolver::with_options(options.clone());
+        let selectors = add_grouped_problem(&mut solver, problem);
+        MCSEnumerator { problem, solver, selectors, is_finished: false, solve_count: 0 }
+    }
+
+    /// 解で充足されるグループを is_satisfied に加える
+    fn add_satisfied_groups(
+        &self,
+        solution: &Array<VariableSize, bool>,
+        is_satisfied: &mut Array<ConstraintSize, bool>,
+    ) {
+        let mut has_falsified_clause = Array::default();
+        has_falsified_clause.resize(self.problem.number_of_groups, false);
+        for (group, clause) in self.problem.clauses.iter() {
+            if !clause.iter().any(|literal| solution[literal.index] == literal.sign) {
+                has_falsified_clause[*group] = true;
+            }
+        }
+        for group in 1..self.problem.number_of_groups {
+            if !has_falsified_clause[group] {
+                is_satisfied[group] = true;
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for MCSEnumerator<'a> {
+    type Item = Array<ConstraintSize, ConstraintSize>;
+
+    /// 次の MCS を昇順のグループとして返す(すべて求め終えたか，中断された場合は None)
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_finished {
+            return None;
+        }
+        self.solve_count += 1;
+        let SATSolverResult::Satisfiable { solution } = self.solver.solve() else {
+            self.is_finished = true;
+            return None;
+        };
+        let mut is_satisfied = Array::default();
+        is_satisfied.resize(self.problem.number_of_groups, false);
+        self.add_satisfied_groups(&solution, &mut is_satisfied);
+        let mut mcs = Array::default();
+        for group in 1..self.problem.number_of_groups {
+            if is_satisfied[group] {
+                continue;
+            }
+            let mut assumptions: Vec<Literal> = (1..self.problem.number_of_groups)
+                .filter(|group| is_satisfied[*group])
+                .map(|group| Literal { sign: true, index: self.selectors[group] })
+                .collect();
+            assumptions.push(Literal { sign: true, index: self.selectors[group] });
+            self.solve_count += 1;
+            match self.solver.solve_with_assumptions(&assumptions) {
+                SATSolverResult::Satisfiable { solution } => {
+                    self.add_satisfied_groups(&solution, &mut is_satisfied);
+                    debug_assert!(is_satisfied[group]);
+                }
+                SATSolverResult::Unsatisfiable => {
+                    mcs.push(group);
+                }
+                SATSolverResult::Unknown => {
+                    self.is_finished = true;
+                    return None;
+                }
+            }
+        }
+        let mut blocking_clause = Array::default();
+        for group in mcs.iter() {
+            blocking_clause.push(Literal { sign: true, index: self.selectors[*group] });
+        }
+        self.solver.add_clause(&blocking_clause);
+        Some(mcs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::super::test_helpers::{is_true, random_clause, Random};
+    use super::*;
+
+    fn random_grouped_problem(random: &mut Random, n: VariableSize, m: usize, g: ConstraintSize) -> GroupedProblem {
+        let mut problem = GroupedProblem { number_of_variables: n, number_of_groups: g, ..GroupedProblem::default() };
+        for _ in 0..m {
+            let clause = random_clause(random, n);
+            problem.clauses.push((random.next_below(g), clause));
+        }
+        problem
+    }
+
+    /// ハード節と removed (ビット集合) 以外のグループの節がすべて充足可能かを全探索で調べる
+    fn is_satisfiable(problem: &GroupedProblem, removed: u32) -> bool {
+        (0..1u32 << problem.number_of_variables).any(|values| {
+            problem
+                .clauses
+                .iter()
+                .filter(|(group, _)| removed >> group & 1 == 0)
+                .all(|(_, clause)| clause.iter().any(|literal| is_true(literal, values)))
+        })
+    }
+
+    #[test]
+    fn mcs_enumeration_matches_brute_force() {
+        let mut random = Random::new(1);
+        let options = SATSolverOptions::default();
+        for k in 0..40 {
+            let problem = random_grouped_problem(&mut random, 8, 40 + k % 20, 8);
+            // 取り除くと充足可能になり，取り除いたグループのどれを戻しても充足不能になるグループの集合
+            let expected: HashSet<Vec<ConstraintSize>> = (0..1u32 << problem.number_of_groups)
+                .filter(|removed| removed & 1 == 0 && is_satisfiable(&problem, *removed))
+                .filter(|removed| {
+                    (1..problem.number_of_groups)
+                        .filter(|group| removed >> group & 1 != 0)
+                        .all(|group| !is_satisfiable(&problem, removed & !(1 << group)))
+                })
+                .map(|removed| (1..problem.number_of_groups).filter(|group| removed >> group & 1 != 0).collect())
+                .collect();
+            let mut actual = HashSet::new();
+            for mcs in MCSEnumerator::new(&problem, &options) {
+                assert!(actual.insert(mcs.iter().copied().collect::<Vec<_>>()));
+            }
+            assert_eq!(actual, expected);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::finite_collections::Array;
+
+use super::read_cnf::SATProblem;
+use super::solver::{SATSolver, SATSolverResult};
+use super::types::{ConstraintSize, Literal, VariableSize};
+
+/// 解を，問題のすべての節と XOR 制約を充足する極小な部分割り当て(素項)に縮める
+///
+/// 変数を順に調べ，その変数のリテラルで真になっている節のすべてに他の真のリテラルが残っていれば割り当てから外す(None にする)．
+/// XOR 制約の変数はすべて割り当てておかないと XOR 制約を充足しないので外さない．
+#[inline(never)]
+pub fn prime_implicant(
+    problem: &SATProblem,
+    solution: &Array<VariableSize, bool>,
+) -> Array<VariableSize, Option<bool>> {
+    // 節ごとの真のリテラルの変数の数と，変数ごとにそのリテラルが真になる節
+    let mut true_counts: Array<ConstraintSize, VariableSize> = Array::default();
+    let mut occurrences: Array<VariableSize, Array<ConstraintSize, ConstraintSize>> = Array::default();
+    occurrences.resize(problem.number_of_variables, Array::default());
+    for (clause_index, clause) in problem.clauses.iter().enumerate() {
+        let mut true_variables: Vec<VariableSize> = clause
+            .iter()
+            .filter(|literal| solution[literal.index] == literal.sign)
+            .map(|literal| literal.index)
+            .collect();
+        true_variables.sort_unstable();
+        true_variables.dedup();
+        debug_assert!(!true_variables.is_empty());
+        true_counts.push(true_variables.len() as VariableSize);
+        for variable_index in true_variables {
+            occurrences[variable_index].push(clause_index as ConstraintSize);
+        }
+    }
+    let mut is_required: Array<VariableSize, bool> = Array::default();
+    is_required.resize(problem.number_of_variables, false);
+    for (xor_variables, _) in problem.xors.iter() {
+        for variable_index in xor_variables.iter() {
+            is_required[*variable_index] = true;
+        }
+    }
+    let mut implicant = Array::default();
+    for variable_index in 0..problem.number_of_variables {
+        let occurrences = &occurrences[variable_index];
+        if !is_required[variable_index] && occurrences.iter().all(|clause_index| true_counts[*clause_index] >= 2) {
+            for clause_index in occurrences.iter() {
+                true_counts[*clause_index] -= 1;
+            }
+            implicant.push(None);
+        } else {
+            implicant.push(Some(solution[variable_index]));
+        }
+    }
+    implicant
+}
+
+/// variable_indices の変数について，真になる変数の集合が包含関係で極小な解を求める
+///
+/// 解が見つかるたびに，その解で偽の変数を偽に固定する仮定と，真の変数の少なくとも 1 つが偽になる節を加えて解き直し，
+/// 充足不能になった時点の解を返す．節には新しい変数 s を加えて s を仮定したときだけ働くようにし，終了時に ¬s を追加して無効にする．
+/// 解とともに solve を呼んだ回数を返す．充足不能な場合や中断された場合には None を返す．
+#[inline(never)]
+pub fn minimal_model(
+    solver: &mut SATSolver,
+    variable_indices: &[VariableSize],
+) -> Option<(Array<VariableSize, bool>, usize)> {
+    let required_variable_dimension =
+        variable_indices.iter().map(|variable_index| variable_index + 1).max().unwrap_or(0);
+    if required_variable_dimension > solver.number_of_variables() {
+        solver.expand_variables(required_variable_dimension - solver.number_of_variables());
+    }
+    let number_of_variables = solver.number_of_variables();
+    let SATSolverResult::Satisfiable { mut solution } = solver.solve() else {
+        return None;
+    };
+    let mut solve_count = 1;
+    let mut minimal_solution = loop {
+        let true_variables: Vec<VariableSize> =
+            variable_indices.iter().copied().filter(|variable_index| solution[*variable_index]).collect();
+        if true_variables.is_empty() {
+            break solution;
+        }
+        solver.expand_variables(1);
+        let selector = Literal { sign: true, index: solver.number_of_variables() - 1 };
+        let mut clause = Array::default();
+        clause.push(!selector);
+        for variable_index in true_variables.iter() {
+            clause.push(Literal { sign: false, index: *variable_index });
+        }
+        solver.add_clause(&clause);
+        let mut assumptions = vec![selector];
+        for variable_index in variable_indices.iter() {
+            if !solution[*variable_index] {
+                assumptions.push(Literal { sign: false, index: *variable_index });
+            }
+        }
+        solve_count += 1;
+        let result = solver.solve_with_assumptions(&assumptions);
+        let mut clause = Array::default();
+        clause.push(!selector);
+        solver.add_clause(&clause);
+        match result {
+            SATSolverResult::Satisfiable { solution: smaller_solution } => solution = smaller_solution,
+            SATSolverResult::Unsatisfiable => break solution,
+            SATSolverResult::Unknown => return None,
+        }
+    };
+    // 選択用の変数は呼び出し側には見せない
+    minimal_solution.resize(number_of_variables, false);
+    Some((minimal_solution, solve_count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::{is_model, random_problem, Random};
+    use super::*;
+
+    /// 3-SAT の節に長さ 3 以下の XOR 制約を 1 つ加えた問題
+    fn random_problem_with_xor(random: &mut Random, n: VariableSize, m: usize) -> SATProblem {
+        let mut problem = random_problem(random, n, m);
+        let mut xor_variables = Array::default();
+        for variable_index in 0..n {
+            if xor_variables.len() < 3 && random.next_below(4) == 0 {
+                xor_variables.push(variable_index);
+            }
+        }
+        problem.xors.push((xor_variables, random.next_bool()));
+        problem
+    }
+
+    /// 部分割り当てのすべての拡張が解であるか
+    fn is_implicant(problem: &SATProblem, implicant: &[Option<bool>]) -> bool {
+        (0..1u32 << problem.number_of_variables).all(|values| {
+            implicant
+                .iter()
+                .enumerate()
+                .any(|(variable_index, value)| value.is_some_and(|value| (values >> variable_index & 1 != 0) != value))
+                || is_model(problem, values)
+        })
+    }
+
+    #[test]
+    fn prime_implicant_is_minimal() {
+        let mut random = Random::new(1);
+        for k in 0..40 {
+            let problem = random_problem_with_xor(&mut random, 10, 15 + k % 20);
+            for values in (0..1u32 << problem.number_of_variables).filter(|values| is_model(&problem, *values)).take(20)
+            {
+                let mut solution = Array::default();
+                for variable_index in 0..problem.number_of_variables {
+                    solution.push(values >> variable_index & 1 != 0);
+                }
+                let mut implicant: Vec<Option<bool>> = prime_implicant(&problem, &solution).iter().copied().collect();
+                assert_eq!(implicant.len(), problem.number_of_variables as usize);
+                assert!(implicant
+                    .iter()
+                    .zip(solution.iter())
+                    .all(|(value, assigned)| value.is_none_or(|value| value == *assigned)));
+                assert!(is_implicant(&problem, &implicant));
+                // どの変数を外しても解でない拡張が生じる
+                for variable_index in 0..implicant.len() {
+                    let Some(value) = implicant[variable_index] else {
+                        continue;
+                    };
+                    implicant[variable_index] = None;
+                    assert!(!is_implicant(&problem, &implicant));
+                    implicant[variable_index] = Some(value);
+                }
+            }
+        }
+    }
+}
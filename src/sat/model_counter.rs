@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+
+use num::{BigUint, One, Zero};
+
+use crate::finite_collections::Array;
+
+use super::analyze::Analyze;
+use super::clause_theory::ClauseTheory;
+use super::decision_heuristic::create_decision_heuristic;
+use super::random::Random;
+use super::read_cnf::SATProblem;
+use super::solver::SATSolverOptions;
+use super::theory::Theories;
+use super::types::{ConstraintSize, Literal, Reason, VariableSize};
+use super::variables::{VariableRandomization, VariableState, Variables};
+
+/// 連結成分(成分の変数，成分の節)
+type Component = (Vec<VariableSize>, Vec<ConstraintSize>);
+
+/// 連結成分の分解と成分のキャッシュによる(射影)モデル計数器
+///
+/// 割り当ての伝播と矛盾の解析には SATSolver と同じ Variables，Theories，Analyze を使う．
+/// 学習節は伝播にのみ使い，連結成分とキャッシュのキーは元の節だけから求める．
+struct ModelCounter {
+    variables: Variables,
+    theories: Theories,
+    analyze: Analyze,
+    // 元の節と，各変数が現れる元の節の番号
+    clauses: Array<ConstraintSize, Array<VariableSize, Literal>>,
+    occurrences: Array<VariableSize, Array<ConstraintSize, ConstraintSize>>,
+    is_projected: Array<VariableSize, bool>,
+    // 成分(未充足の節を未割り当てのリテラルに制限したもの)ごとのモデル数
+    cache: HashMap<Vec<u32>, BigUint>,
+    // キャッシュに追加した順のキー(モデル数が 0 になった分岐で追加したものを取り消すため)
+    cache_log: Vec<Vec<u32>>,
+    // 成分の探索で使う作業領域
+    visited_variables: Array<VariableSize, bool>,
+    visited_clauses: Array<ConstraintSize, bool>,
+    decision_count: usize,
+    conflict_count: usize,
+    cache_hit_count: usize,
+}
+
+/// 現在の割り当てで伝播していない矛盾が残っている(呼び出し元の決定レベル以下の割り当てが矛盾している)
+struct Conflict;
+
+impl ModelCounter {
+    /// number_of_variables は問題の変数に XOR 制約の分割で加えた変数を含めた数(加えた変数は射影しない)
+    fn new(problem: &SATProblem, number_of_variables: VariableSize, options: &SATSolverOptions) -> Self {
+        let mut variables = Variables::new(
+            create_decision_heuristic(options.decision_heuristic),
+            options.propagation_order,
+            VariableRandomization { initial_phase: false, initial_order: false, tie_breaking: false },
+        );
+        variables.redimension(number_of_variables, &mut Random::new(options.seed));
+        let mut theories = Theories::new(ClauseTheory::new(options));
+        theories.expand(number_of_variables);
+        let mut is_projected = Array::default();
+        match &problem.projection {
+            Some(projection) => {
+                is_projected.resize(number_of_variables, false);
+                for variable_index in projection.iter() {
+                    is_projected[*variable_index] = true;
+                }
+            }
+            None => {
+                is_projected.resize(problem.number_of_variables, true);
+                is_projected.resize(number_of_variables, false);
+            }
+        }
+        let mut visited_variables = Array::default();
+        visited_variables.resize(number_of_variables, false);
+        let mut occurrences = Array::default();
+        occurrences.resize_with(number_of_variables, Array::default);
+        ModelCounter {
+            variables,
+            theories,
+            analyze: Analyze::default(),
+            clauses: Array::default(),
+            occurrences,
+            is_projected,
+            cache: HashMap::new(),
+            cache_log: Vec::new(),
+            visited_variables,
+            visited_clauses: Array::default(),
+            decision_count: 0,
+            conflict_count: 0,
+            cache_hit_count: 0,
+        }
+    }
+
+    /// 元の節を追加して決定レベル 0 で伝播する(矛盾すれば false を返す)
+    fn add_clause(&mut self, literals: &[Literal]) -> bool {
+        let mut clause: Array<VariableSize, Literal> = Array::default();
+        for literal in literals.iter() {
+            if clause.iter().any(|other| *other == !*literal)
+                || self.variables.get(literal.index).is_value_fixed(literal.sign)
+            {
+                // 恒真または充足済み
+                return true;
+            }
+            if !clause.iter().any(|other| other == literal)
+                && !self.variables.get(literal.index).is_value_fixed(!literal.sign)
+            {
+                clause.push(*literal);
+            }
+        }
+        if clause.is_empty() {
+            return false;
+        }
+        let clause_index = self.clauses.len();
+        for literal in clause.iter() {
+            self.occurrences[literal.index].push(clause_index);
+        }
+        self.clauses.push(clause.clone());
+        self.visited_clauses.push(false);
+        self.theories.clause_theory.add_clause(clause, false, &mut self.variables);
+        self.propagate().is_ok()
+    }
+
+    fn propagate(&mut self) -> Result<(), Conflict> {
+        while self.variables.number_of_tentative_assigned_variables() != 0
+            || self.variables.number_of_conflicting_variables() != 0
+        {
+            if self.variables.number_of_conflicting_variables() != 0 {
+                return Err(Conflict);
+            }
+            let (variable_index, _) = self.variables.first_tentatively_assigned_variable().unwrap();
+            self.variables.assign(variable_index);
+            self.theories.propagate(variable_index, &mut self.variables);
+        }
+        Ok(())
+    }
+
+    fn backtrack(&mut self, decision_level: VariableSize) {
+        let reassigned_position = self.variables.backtrack(decision_level);
+        self.theories.backtrack(decision_level, reassigned_position, &mut self.variables);
+    }
+
+    /// 決定レベル base_decision_level より上で生じた矛盾を解析し，base_decision_level に戻って学習節を追加する
+    ///
+    /// 学習節を追加して伝播した結果も矛盾する場合や，矛盾の決定レベルが base_decision_level 以下の場合には Conflict を返す．
+    fn resolve_conflict(&mut self, base_decision_level: VariableSize) -> Result<(), Conflict> {
+        let (variable_index, variable_state) = self.variables.first_conflicting_variable().unwrap();
+        let VariableState::Conflicting { reasons, .. } = *variable_state else {
+            unreachable!();
+        };
+        let (conflict_decision_level, _, learnt_clause) =
+            self.analyze.analyze(variable_index, reasons, &mut self.variables, &self.theories);
+        if conflict_decision_level <= base_decision_level {
+            return Err(Conflict);
+        }
+        self.conflict_count += 1;
+        self.backtrack(base_decision_level);
+        self.theories.clause_theory.add_clause(learnt_clause, true, &mut self.variables);
+        self.variables.advance_time();
+        self.theories.advance_time();
+        if self.theories.is_request_reduction() {
+            self.theories.reduce(&self.variables);
+        }
+        self.propagate()
+    }
+
+    /// 節が現在の割り当てで充足されているか
+    fn is_satisfied(&self, clause_index: ConstraintSize) -> bool {
+        self.clauses[clause_index]
+            .iter()
+            .any(|literal| self.variables.get(literal.index).is_value_assigned(literal.sign))
+    }
+
+    /// variable_indices の未割り当ての変数を未充足の元の節でつながる成分に分け，(成分の変数, 成分の節) の列を返す
+    ///
+    /// どの未充足の節にも現れない変数は成分に含めず，そのうち射影する変数の数を返す．
+    fn split_components(&mut self, variable_indices: &[VariableSize]) -> (Vec<Component>, VariableSize) {
+        let mut components = Vec::new();
+        let mut free_count = 0;
+        for variable_index in variable_indices.iter() {
+            if self.visited_variables[*variable_index] || self.variables.get(*variable_index).is_assigned() {
+                continue;
+            }
+            let mut component_variables = vec![*variable_index];
+            let mut component_clauses = Vec::new();
+            self.visited_variables[*variable_index] = true;
+            let mut k = 0;
+            while k < component_variables.len() {
+                let variable_index = component_variables[k];
+                k += 1;
+                for occurrence in 0..self.occurrences[variable_index].len() {
+                    let clause_index = self.occurrences[variable_index][occurrence];
+                    if self.visited_clauses[clause_index] || self.is_satisfied(clause_index) {
+                        continue;
+                    }
+                    self.visited_clauses[clause_index] = true;
+                    component_clauses.push(clause_index);
+                    for literal in self.clauses[clause_index].iter() {
+                        if !self.visited_variables[literal.index] && !self.variables.get(literal.index).is_assigned() {
+                            self.visited_variables[literal.index] = true;
+                            component_variables.push(literal.index);
+                        }
+                    }
+                }
+            }
+            if component_clauses.is_empty() {
+                if self.is_projected[*variable_index] {
+                    free_count += 1;
+                }
+                continue;
+            }
+            components.push((component_variables, component_clauses));
+        }
+        for (component_variables, component_clauses) in components.iter() {
+            for variable_index in component_variables.iter() {
+                self.visited_variables[*variable_index] = false;
+            }
+            for clause_index in component_clauses.iter() {
+                self.visited_clauses[*clause_index] = false;
+            }
+        }
+        for variable_index in variable_indices.iter() {
+            self.visited_variables[*variable_index] = false;
+        }
+        (components, free_count)
+    }
+
+    /// variable_indices の未割り当ての変数に関する(射影)モデル数を求める
+    ///
+    /// 成分のいずれかのモデル数が 0 であれば，その間にキャッシュに追加した成分を取り消す
+    /// (他の成分が充足不能であると学習節による伝播が成分だけからは導けない割り当てを含みうるため)．
+    fn count_variables(&mut self, variable_indices: &[VariableSize]) -> Result<BigUint, Conflict> {
+        let cache_log_length = self.cache_log.len();
+        let result = self.count_components(variable_indices);
+        if !matches!(&result, Ok(count) if !count.is_zero()) {
+            for key in self.cache_log.drain(cache_log_length..) {
+                self.cache.remove(&key);
+            }
+        }
+        result
+    }
+
+    fn count_components(&mut self, variable_indices: &[VariableSize]) -> Result<BigUint, Conflict> {
+        let number_of_assigned_variables = self.variables.number_of_assigned_variables();
+        let (components, free_count) = self.split_components(variable_indices);
+        let mut count = BigUint::one() << free_count as usize;
+        for (k, (component_variables, component_clauses)) in components.iter().enumerate() {
+            if self.variables.number_of_assigned_variables() != number_of_assigned_variables {
+                // 学習節によって割り当てが増えたら残りの成分を分け直す
+                let rest: Vec<VariableSize> = components[k..]
+                    .iter()
+                    .flat_map(|(component_variables, _)| component_variables.iter().copied())
+                    .collect();
+                return Ok(count * self.count_variables(&rest)?);
+            }
+            let component_count = self.count_component(component_variables, component_clauses)?;
+            if component_count.is_zero() {
+                return Ok(component_count);
+            }
+            count *= component_count;
+        }
+        Ok(count)
+    }
+
+    /// 成分のキャッシュのキー(未充足の節を未割り当てのリテラルに制限して整列したもの)
+    fn component_key(&self, component_clauses: &[ConstraintSize]) -> Vec<u32> {
+        let mut restricted_clauses: Vec<Vec<u32>> = component_clauses
+            .iter()
+            .map(|clause_index| {
+                let mut codes: Vec<u32> = self.clauses[*clause_index]
+                    .iter()
+                    .filter(|literal| !self.variables.get(literal.index).is_assigned())
+                    .map(|literal| literal.index << 1 | literal.sign as u32)
+                    .collect();
+                codes.sort_unstable();
+                codes
+            })
+            .collect();
+        restricted_clauses.sort_unstable();
+        restricted_clauses.dedup();
+        let mut key = Vec::new();
+        for codes in restricted_clauses.iter() {
+            key.extend_from_slice(codes);
+            key.push(u32::MAX);
+        }
+        key
+    }
+
+    /// 成分の変数で分岐してモデル数を求める
+    ///
+    /// 射影する変数を先に選び，射影する変数を含まない成分は充足可能かどうか(0 または 1)だけを求める．
+    #[inline(never)]
+    fn count_component(
+        &mut self,
+        component_variables: &[VariableSize],
+        component_clauses: &[ConstraintSize],
+    ) -> Result<BigUint, Conflict> {
+        let key = self.component_key(component_clauses);
+        if let Some(count) = self.cache.get(&key) {
+            self.cache_hit_count += 1;
+            return Ok(count.clone());
+        }
+        // 射影する変数を優先し，最も短い未充足の節に現れる変数のうち未充足の節に多く現れる変数で分岐する
+        // (長い XOR 制約を分割した節の列のように出現数が同じ変数が多いときに，分割した制約を 1 つずつ割り当て終えるため)
+        let mut occurrence_counts: HashMap<(VariableSize, bool), usize> = HashMap::new();
+        let mut shortest_lengths: HashMap<VariableSize, usize> = HashMap::new();
+        for clause_index in component_clauses.iter() {
+            let length = self.clauses[*clause_index]
+                .iter()
+                .filter(|literal| !self.variables.get(literal.index).is_assigned())
+                .count();
+            for literal in self.clauses[*clause_index].iter() {
+                if !self.variables.get(literal.index).is_assigned() {
+                    *occurrence_counts.entry((literal.index, literal.sign)).or_default() += 1;
+                    let shortest_length = shortest_lengths.entry(literal.index).or_insert(length);
+                    *shortest_length = (*shortest_length).min(length);
+                }
+            }
+        }
+        let score = |variable_index: VariableSize| {
+            let positive = occurrence_counts.get(&(variable_index, true)).copied().unwrap_or(0);
+            let negative = occurrence_counts.get(&(variable_index, false)).copied().unwrap_or(0);
+            let shortest_length = shortest_lengths.get(&variable_index).copied().unwrap_or(usize::MAX);
+            (std::cmp::Reverse(shortest_length), positive + negative, positive >= negative)
+        };
+        let (branch_variable, branch_value) = component_variables
+            .iter()
+            .map(|variable_index| (*variable_index, score(*variable_index)))
+            .max_by_key(|(variable_index, (shortest_length, occurrence, _))| {
+                (self.is_projected[*variable_index], *shortest_length, *occurrence)
+            })
+            .map(|(variable_index, (_, _, value))| (variable_index, value))
+            .unwrap();
+        let is_projected = self.is_projected[branch_variable];
+
+        let base_decision_level = self.variables.current_decision_level();
+        let mut count = BigUint::zero();
+        for value in [branch_value, !branch_value] {
+            if !is_projected && !count.is_zero() {
+                break;
+            }
+            if let VariableState::Assigned { assigned_value, .. } = *self.variables.get(branch_variable) {
+                // 前の分岐の学習節によって割り当てられた
+                if assigned_value == value {
+                    count += self.count_variables(component_variables)?;
+                }
+                continue;
+            }
+            self.decision_count += 1;
+            self.variables.tentatively_assign(branch_variable, value, Reason::Decision);
+            let branch_count = match self.propagate() {
+                Ok(()) => self.count_variables(component_variables),
+                Err(conflict) => Err(conflict),
+            };
+            match branch_count {
+                Ok(branch_count) => {
+                    count += branch_count;
+                    // 積み直した割り当ての伝播で矛盾することがある
+                    self.backtrack(base_decision_level);
+                    self.propagate()?;
+                }
+                Err(Conflict) => self.resolve_conflict(base_decision_level)?,
+            }
+        }
+        self.cache.insert(key.clone(), count.clone());
+        self.cache_log.push(key);
+        Ok(count)
+    }
+}
+
+/// xor_variables の排他的論理和が parity に等しいという制約を 4 変数以下の XOR 制約に分割して xors に加える
+///
+/// 先頭の 3 変数の排他的論理和を新しい変数(番号は number_of_variables から順に割り当てる)で置き換える．
+/// 新しい変数の値は元の変数の値で決まるので，射影しなければモデル数は変わらない．
+fn cut_xor(
+    xor_variables: &[VariableSize],
+    parity: bool,
+    number_of_variables: &mut VariableSize,
+    xors: &mut Vec<(Vec<VariableSize>, bool)>,
+) {
+    let mut variables = xor_variables.to_vec();
+    while variables.len() > 4 {
+        let sum = *number_of_variables;
+        *number_of_variables += 1;
+        let mut chunk: Vec<VariableSize> = variables.drain(..3).collect();
+        chunk.push(sum);
+        xors.push((chunk, false));
+        variables.push(sum);
+    }
+    xors.push((variables, parity));
+}
+
+/// モデル数の計算の統計情報
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ModelCountingStatistics {
+    /// 分岐した回数
+    pub decision_count: usize,
+    /// 分岐の伝播で矛盾した回数
+    pub conflict_count: usize,
+    /// 成分のモデル数をキャッシュから得た回数
+    pub cache_hit_count: usize,
+    /// キャッシュした成分の数
+    pub cache_size: usize,
+}
+
+/// 問題のモデル数を求める
+///
+/// 問題に射影する変数("c p show")が指定されていれば，解をその変数に制限したときの異なる割り当ての数を求める．
+/// XOR 制約は 4 変数以下に分割してから CNF に展開して数える．結果とともに計算の統計情報を返す．
+#[inline(never)]
+pub fn count_models(problem: &SATProblem, options: &SATSolverOptions) -> (BigUint, ModelCountingStatistics) {
+    let mut number_of_variables = problem.number_of_variables;
+    let mut xors = Vec::new();
+    for (xor_variables, parity) in problem.xors.iter() {
+        cut_xor(xor_variables.as_slice(), *parity, &mut number_of_variables, &mut xors);
+    }
+    let mut counter = ModelCounter::new(problem, number_of_variables, options);
+    let mut is_consistent = true;
+    for clause in problem.clauses.iter() {
+        is_consistent = is_consistent && counter.add_clause(clause.as_slice());
+    }
+    for (xor_variables, parity) in xors.iter() {
+        // パリティが parity でない割り当てをそれぞれ 1 つの節で禁止する
+        for signs in 0..1u32 << xor_variables.len() {
+            if (signs.count_ones() & 1 == 0) != *parity {
+                continue;
+            }
+            let clause: Vec<Literal> = xor_variables
+                .iter()
+                .enumerate()
+                .map(|(i, variable_index)| Literal { sign: signs >> i & 1 == 0, index: *variable_index })
+                .collect();
+            is_consistent = is_consistent && counter.add_clause(&clause);
+        }
+    }
+    let variable_indices: Vec<VariableSize> = (0..number_of_variables).collect();
+    let count =
+        if is_consistent { counter.count_variables(&variable_indices).unwrap_or_default() } else { BigUint::zero() };
+    let statistics = ModelCountingStatistics {
+        decision_count: counter.decision_count,
+        conflict_count: counter.conflict_count,
+        cache_hit_count: counter.cache_hit_count,
+        cache_size: counter.cache.len(),
+    };
+    (count, statistics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::{brute_force, random_problem};
+    use super::*;
+
+    /// 射影した割り当ての数を全探索で求める
+    fn count_by_brute_force(problem: &SATProblem) -> BigUint {
+        let projection: Vec<VariableSize> = match &problem.projection {
+            Some(projection) => projection.iter().copied().collect(),
+            None => (0..problem.number_of_variables).collect(),
+        };
+        BigUint::from(brute_force(problem, &projection).len())
+    }
+
+    /// 3-SAT の節に長さ xor_length 以下の XOR 制約を 1 つ加えた問題
+    fn random_problem_with_xor(random: &mut Random, n: VariableSize, m: usize, xor_length: VariableSize) -> SATProblem {
+        let mut problem = random_problem(random, n, m);
+        let mut xor_variables = Array::default();
+        for variable_index in 0..n {
+            if xor_variables.len() < xor_length && random.next_bool() {
+                xor_variables.push(variable_index);
+            }
+        }
+        problem.xors.push((xor_variables, random.next_bool()));
+        problem
+    }
+
+    #[test]
+    fn count_models_matches_brute_force() {
+        let mut random = Random::new(1);
+        let options = SATSolverOptions::default();
+        for k in 0..40 {
+            let mut problem = random_problem_with_xor(&mut random, 12, 20 + k % 20, 9);
+            assert_eq!(count_models(&problem, &options).0, count_by_brute_force(&problem));
+            let mut projection = Array::default();
+            for variable_index in (0..problem.number_of_variables).filter(|_| random.next_bool()) {
+                projection.push(variable_index);
+            }
+            problem.projection = Some(projection);
+            assert_eq!(count_models(&problem, &options).0, count_by_brute_force(&problem));
+        }
+    }
+
+    #[test]
+    fn count_models_with_long_xor() {
+        let mut problem = SATProblem { number_of_variables: 100, ..SATProblem::default() };
+        let mut xor_variables = Array::default();
+        for variable_index in 0..100 {
+            xor_variables.push(variable_index);
+        }
+        problem.xors.push((xor_variables, true));
+        assert_eq!(count_models(&problem, &SATSolverOptions::default()).0, BigUint::one() << 99usize);
+    }
+}
@@ -0,0 +1,279 @@
+use crate::finite_collections::Array;
+
+use super::read_cnf::GroupedProblem;
+use super::solver::{SATSolver, SATSolverOptions, SATSolverResult};
+use super::types::{ConstraintSize, Literal, VariableSize};
+
+pub enum MUSResult {
+    /// 極小な充足不能部分集合をなすグループ(昇順)と，解いた回数・model rotation で MUS に加えたグループの数
+    Unsatisfiable {
+        groups: Array<ConstraintSize, ConstraintSize>,
+        solve_count: usize,
+        rotation_count: usize,
+    },
+    Satisfiable,
+    Unknown,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GroupState {
+    /// MUS に含まれるかまだわからない
+    Candidate,
+    /// 取り除くと充足可能になる(MUS に含まれる)
+    Necessary,
+    /// 取り除いても充足不能のまま
+    Removed,
+}
+
+/// グループ 0 の節をそのまま，それ以外のグループの節を新しい変数(グループを有効にする変数)の否定を加えて追加する
+///
+/// グループを有効にする変数を返す(グループ 0 の分は使わない)．グループの変数を仮定したときだけグループの節が働く．
+/// グループ 1, 2, ... の変数は問題の変数の直後に順に並ぶ．
+pub(super) fn add_grouped_problem(
+    solver: &mut SATSolver,
+    problem: &GroupedProblem,
+) -> Array<ConstraintSize, VariableSize> {
+    solver.expand_variables(problem.number_of_variables.saturating_sub(solver.number_of_variables()));
+    let first_selector = solver.number_of_variables();
+    solver.expand_variables(problem.number_of_groups - 1);
+    let mut selectors = Array::default();
+    selectors.push(0);
+    for group in 1..problem.number_of_groups {
+        selectors.push(first_selector + group - 1);
+    }
+    for (group, clause) in problem.clauses.iter() {
+        let mut selected_clause = clause.clone();
+        if *group != 0 {
+            selected_clause.push(Literal { sign: false, index: selectors[*group] });
+        }
+        solver.add_clause(&selected_clause);
+    }
+    selectors
+}
+
+struct MUSExtractor<'a> {
+    problem: &'a GroupedProblem,
+    solver: SATSolver,
+    // グループを有効にする変数(グループ 0 は使わない)
+    selectors: Array<ConstraintSize, VariableSize>,
+    states: Array<ConstraintSize, GroupState>,
+    // 変数を含む節
+    occurrences: Array<VariableSize, Array<ConstraintSize, ConstraintSize>>,
+    solve_count: usize,
+    rotation_count: usize,
+}
+
+impl<'a> MUSExtractor<'a> {
+    fn new(problem: &'a GroupedProblem, options: &SATSolverOptions) -> Self {
+        let mut solver = SATSolver::with_options(options.clone());
+        let selectors = add_grouped_problem(&mut solver, problem);
+        let mut occurrences: Array<VariableSize, Array<ConstraintSize, ConstraintSize>> = Array::default();
+        occurrences.resize(problem.number_of_variables, Array::default());
+        for (clause_index, (_, clause)) in problem.clauses.iter().enumerate() {
+            for literal in clause.iter() {
+                let clauses = &mut occurrences[literal.index];
+                if clauses.iter().last() != Some(&(clause_index as ConstraintSize)) {
+                    clauses.push(clause_index as ConstraintSize);
+                }
+            }
+        }
+        let mut states = Array::default();
+        states.resize(problem.number_of_groups, GroupState::Candidate);
+        MUSExtractor { problem, solver, selectors, states, occurrences, solve_count: 0, rotation_count: 0 }
+    }
+
+    fn is_active(&self, group: ConstraintSize) -> bool {
+        group == 0 || self.states[group] != GroupState::Removed
+    }
+
+    fn is_satisfied(&self, clause_index: ConstraintSize, solution: &Array<VariableSize, bool>) -> bool {
+        self.problem.clauses[clause_index].1.iter().any(|literal| solution[literal.index] == literal.sign)
+    }
+
+    /// グループを取り除く(そのグループの節はすべての解で無効になる)
+    fn remove(&mut self, group: ConstraintSize) {
+        self.states[group] = GroupState::Removed;
+        let mut clause = Array::default();
+        clause.push(Literal { sign: false, index: self.selectors[group] });
+        self.solver.add_clause(&clause);
+    }
+
+    /// excluded 以外の有効なグループを仮定して解き，充足不能であれば充足不能コアに含まれない候補のグループを取り除く
+    /// (clause-set refinement，excluded も取り除かれる)
+    fn solve_without(&mut self, excluded: ConstraintSize) -> SATSolverResult {
+        let assumptions: Vec<Literal> = (1..self.problem.number_of_groups)
+            .filter(|group| *group != excluded && self.states[*group] != GroupState::Removed)
+            .map(|group| Literal { sign: true, index: self.selectors[group] })
+            .collect();
+        self.solve_count += 1;
+        let result = self.solver.solve_with_assumptions(&assumptions);
+        if let SATSolverResult::Unsatisfiable = result {
+            let mut in_core = Array::default();
+            in_core.resize(self.problem.number_of_groups, false);
+            for literal in self.solver.failed_assumptions().iter() {
+                in_core[literal.index - self.selectors[1] + 1] = true;
+            }
+            for group in 1..self.problem.number_of_groups {
+                debug_assert!(self.states[group] != GroupState::Necessary || in_core[group]);
+                if self.states[group] == GroupState::Candidate && !in_core[group] {
+                    self.remove(group);
+                }
+            }
+        }
+        result
+    }
+
+    /// 取り除くと充足可能になるグループ group と，group 以外の有効なグループを充足する解から，
+    /// 解の変数を 1 つずつ反転して他に取り除くと充足可能になるグループを探す(model rotation)
+    fn rotate(&mut self, group: ConstraintSize, solution: Array<VariableSize, bool>) {
+        let mut stack = vec![(group, solution)];
+        while let Some((group, mut solution)) = stack.pop() {
+            let falsified: Vec<ConstraintSize> = (0..self.problem.clauses.len())
+                .filter(|clause_index| {
+                    self.problem.clauses[*clause_index].0 == group && !self.is_satisfied(*clause_index, &solution)
+                })
+                .collect();
+            let mut flipped_variables: Vec<VariableSize> = falsified
+                .iter()
+                .flat_map(|clause_index| self.problem.clauses[*clause_index].1.iter().map(|literal| literal.index))
+                .collect();
+            flipped_variables.sort_unstable();
+            flipped_variables.dedup();
+            for variable_index in flipped_variables {
+                solution[variable_index] = !solution[variable_index];
+                // 反転した変数を含まない偽の節はそのまま偽で，新たに偽になる節は反転した変数を含む
+                let mut new_group = None;
+                let mut is_unique = true;
+                let falsified_groups = falsified
+                    .iter()
+                    .copied()
+                    .filter(|clause_index| !self.is_satisfied(*clause_index, &solution))
+                    .chain(
+                        self.occurrences[variable_index]
+                            .iter()
+                            .copied()
+                            .filter(|clause_index| !self.is_satisfied(*clause_index, &solution)),
+                    )
+                    .map(|clause_index| self.problem.clauses[clause_index].0)
+                    .filter(|group| self.is_active(*group));
+                for falsified_group in falsified_groups {
+                    if falsified_group == 0 || new_group.is_some_and(|new_group| new_group != falsified_group) {
+                        is_unique = false;
+                        break;
+                    }
+                    new_group = Some(falsified_group);
+                }
+                if let (true, Some(new_group)) = (is_unique, new_group) {
+                    if self.states[new_group] == GroupState::Candidate {
+                        self.states[new_group] = GroupState::Necessary;
+                        self.rotation_count += 1;
+                        stack.push((new_group, solution.clone()));
+                    }
+                }
+                solution[variable_index] = !solution[variable_index];
+            }
+        }
+    }
+
+    /// 削除に基づいて MUS を求める
+    ///
+    /// 候補のグループを 1 つずつ除いて解き，充足不能であれば充足不能コアに含まれないグループとともに取り除き，
+    /// 充足可能であれば MUS に含めて model rotation を行う．
+    fn extract(&mut self) -> MUSResult {
+        match self.solve_without(0) {
+            SATSolverResult::Satisfiable { .. } => return MUSResult::Satisfiable,
+            SATSolverResult::Unsatisfiable => {}
+            SATSolverResult::Unknown => return MUSResult::Unknown,
+        }
+        for group in (1..self.problem.number_of_groups).rev() {
+            if self.states[group] != GroupState::Candidate {
+                continue;
+            }
+            match self.solve_without(group) {
+                SATSolverResult::Satisfiable { solution } => {
+                    self.states[group] = GroupState::Necessary;
+                    self.rotate(group, solution);
+                }
+                SATSolverResult::Unsatisfiable => {}
+                SATSolverResult::Unknown => return MUSResult::Unknown,
+            }
+        }
+        let mut groups = Array::default();
+        for group in 1..self.problem.number_of_groups {
+            if self.states[group] == GroupState::Necessary {
+                groups.push(group);
+            }
+        }
+        MUSResult::Unsatisfiable { groups, solve_count: self.solve_count, rotation_count: self.rotation_count }
+    }
+}
+
+/// 充足不能な問題から極小な充足不能部分集合(MUS)をなすグループを求める
+///
+/// グループ 0 の節(ハード節)は常に含める．ハード節だけで充足不能であれば空の集合を返す．
+#[inline(never)]
+pub fn extract_mus(problem: &GroupedProblem, options: &SATSolverOptions) -> MUSResult {
+    MUSExtractor::new(problem, options).extract()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::{is_true, random_clause, Random};
+    use super::*;
+
+    fn random_grouped_problem(random: &mut Random, n: VariableSize, m: usize, g: ConstraintSize) -> GroupedProblem {
+        let mut problem = GroupedProblem { number_of_variables: n, number_of_groups: g, ..GroupedProblem::default() };
+        for _ in 0..m {
+            let clause = random_clause(random, n);
+            problem.clauses.push((random.next_below(g), clause));
+        }
+        problem
+    }
+
+    /// is_enabled のグループの節がすべて充足可能かを全探索で調べる
+    fn is_satisfiable(problem: &GroupedProblem, is_enabled: &[bool]) -> bool {
+        (0..1u32 << problem.number_of_variables).any(|values| {
+            problem
+                .clauses
+                .iter()
+                .filter(|(group, _)| is_enabled[*group as usize])
+                .all(|(_, clause)| clause.iter().any(|literal| is_true(literal, values)))
+        })
+    }
+
+    #[test]
+    fn mus_is_minimal_unsatisfiable_subset() {
+        let mut random = Random::new(1);
+        let options = SATSolverOptions::default();
+        let mut unsatisfiable_count = 0;
+        for k in 0..60 {
+            let problem = random_grouped_problem(&mut random, 8, 40 + k % 20, 10);
+            let number_of_groups = problem.number_of_groups as usize;
+            match extract_mus(&problem, &options) {
+                MUSResult::Unsatisfiable { groups, solve_count, rotation_count } => {
+                    unsatisfiable_count += 1;
+                    // グループごとに高々 1 回解き，model rotation で加えたグループは MUS に含まれる
+                    assert!(solve_count <= number_of_groups);
+                    assert!(rotation_count <= groups.len() as usize);
+                    assert!(groups.iter().all(|group| 0 < *group && (*group as usize) < number_of_groups));
+                    assert!(groups.as_slice().windows(2).all(|pair| pair[0] < pair[1]));
+                    let mut is_enabled = vec![false; number_of_groups];
+                    is_enabled[0] = true;
+                    for group in groups.iter() {
+                        is_enabled[*group as usize] = true;
+                    }
+                    assert!(!is_satisfiable(&problem, &is_enabled));
+                    // どのグループを取り除いても充足可能になる
+                    for group in groups.iter() {
+                        is_enabled[*group as usize] = false;
+                        assert!(is_satisfiable(&problem, &is_enabled));
+                        is_enabled[*group as usize] = true;
+                    }
+                }
+                MUSResult::Satisfiable => assert!(is_satisfiable(&problem, &vec![true; number_of_groups])),
+                MUSResult::Unknown => panic!(),
+            }
+        }
+        assert!(unsatisfiable_count > 10);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::finite_collections::Array;
+
+use super::encoding::{add_pseudo_boolean_at_least, add_pseudo_boolean_at_most, PseudoBooleanEncoding};
+use super::read_opb::{PBProblem, PBRelation};
+use super::solver::{SATSolver, SATSolverOptions, SATSolverResult};
+use super::types::{Literal, VariableSize};
+
+/// 擬似ブール制約の扱い方
+#[derive(Clone, Copy, Debug)]
+pub enum PBConstraintHandling {
+    /// 係数がそろった制約は基数制約の伝播器で扱い，それ以外は BDD で符号化する
+    Native,
+    /// すべての制約を CNF に符号化する
+    Encoding(PseudoBooleanEncoding),
+}
+
+pub enum PBSolverResult {
+    /// 目的関数の最小値とそれを達成する解(目的関数がなければ充足可能であること)
+    Optimum {
+        value: Option<i64>,
+        solution: Array<VariableSize, bool>,
+    },
+    /// 最適性を示す前に打ち切られた
+    Satisfiable {
+        value: i64,
+        solution: Array<VariableSize, bool>,
+    },
+    Unsatisfiable,
+    Unknown,
+}
+
+/// Σ weight * literal <= bound を追加する
+fn add_at_most(solver: &mut SATSolver, terms: &[(i64, Literal)], bound: i64, handling: PBConstraintHandling) {
+    let encoding = match handling {
+        PBConstraintHandling::Encoding(encoding) => encoding,
+        PBConstraintHandling::Native => {
+            // 負の係数はリテラルを否定して正にする(bound が i64 の範囲を超える場合は符号化する)
+            let mut bound = Some(bound);
+            let mut literals = Vec::with_capacity(terms.len());
+            let mut weights = Vec::with_capacity(terms.len());
+            for (weight, literal) in terms.iter() {
+                if *weight > 0 {
+                    literals.push(*literal);
+                    weights.push(weight.unsigned_abs());
+                } else if *weight < 0 {
+                    bound = bound.and_then(|bound| bound.checked_sub(*weight));
+                    literals.push(!*literal);
+                    weights.push(weight.unsigned_abs());
+                }
+            }
+            let mut variable_indices: Vec<VariableSize> = literals.iter().map(|literal| literal.index).collect();
+            variable_indices.sort_unstable();
+            let is_distinct = variable_indices.windows(2).all(|pair| pair[0] != pair[1]);
+            if let Some(bound) = bound.filter(|bound| *bound >= 0) {
+                if is_distinct && weights.windows(2).all(|pair| pair[0] == pair[1]) {
+                    let k = weights.first().map_or(0, |weight| bound as u64 / weight).min(literals.len() as u64);
+                    solver.add_at_most(&literals, k as VariableSize);
+                    return;
+                }
+            }
+            PseudoBooleanEncoding::BDD
+        }
+    };
+    add_pseudo_boolean_at_most(solver, terms, bound, encoding);
+}
+
+/// 解における Σ weight * literal の値
+///
+/// 途中の和は i128 で求め，値が i64 の範囲を超える場合には panic する．
+fn evaluate(terms: &Array<VariableSize, (i64, Literal)>, solution: &Array<VariableSize, bool>) -> i64 {
+    let value: i128 = terms
+        .iter()
+        .filter(|(_, literal)| solution[literal.index] == literal.sign)
+        .map(|(weight, _)| *weight as i128)
+        .sum();
+    i64::try_from(value).unwrap_or_else(|_| panic!("objective value exceeds the range of i64: {}", value))
+}
+
+/// 擬似ブール最適化問題を解く
+///
+/// 解が見つかるたびに on_improvement に目的関数の値を通知し，目的関数が値-1 以下という制約を追加して同じソルバーで解き直す．
+/// 充足不能になった時点で最後の解が最適解である．
+#[inline(never)]
+pub fn solve_pb<F>(
+    problem: &PBProblem,
+    options: &SATSolverOptions,
+    handling: PBConstraintHandling,
+    mut on_improvement: F,
+) -> PBSolverResult
+where
+    F: FnMut(i64),
+{
+    let mut solver = SATSolver::with_options(options.clone());
+    solver.expand_variables(problem.number_of_all_variables);
+    // 積の変数 y と論理積 l1 & ... & ln が等しい
+    for (variable_index, literals) in problem.products.iter() {
+        let product = Literal { sign: true, index: *variable_index };
+        let mut definition = Array::default();
+        definition.push(product);
+        for literal in literals.iter() {
+            let mut clause = Array::default();
+            clause.push(!product);
+            clause.push(*literal);
+            solver.add_clause(&clause);
+            definition.push(!*literal);
+        }
+        solver.add_clause(&definition);
+    }
+    for constraint in problem.constraints.iter() {
+        let terms: Vec<(i64, Literal)> = constraint.terms.iter().copied().collect();
+        if constraint.relation != PBRelation::LessEqual {
+            // Σ w * l >= b を Σ (-w) * l <= -b として追加する(i64::MIN は符号を反転できないので符号化する)
+            let negated_terms: Option<Vec<(i64, Literal)>> =
+                terms.iter().map(|(weight, literal)| weight.checked_neg().map(|weight| (weight, *literal))).collect();
+            match (negated_terms, constraint.bound.checked_neg()) {
+                (Some(negated_terms), Some(negated_bound)) => {
+                    add_at_most(&mut solver, &negated_terms, negated_bound, handling)
+                }
+                _ => {
+                    let encoding = match handling {
+                        PBConstraintHandling::Encoding(encoding) => encoding,
+                        PBConstraintHandling::Native => PseudoBooleanEncoding::BDD,
+                    };
+                    add_pseudo_boolean_at_least(&mut solver, &terms, constraint.bound, encoding);
+                }
+            }
+        }
+        if constraint.relation != PBRelation::GreaterEqual {
+            add_at_most(&mut solver, &terms, constraint.bound, handling);
+        }
+    }
+
+    let mut best: Option<(i64, Array<VariableSize, bool>)> = None;
+    loop {
+        match solver.solve() {
+            SATSolverResult::Satisfiable { solution } => {
+                let Some(objective) = &problem.objective else {
+                    return PBSolverResult::Optimum { value: None, solution };
+                };
+                let value = evaluate(objective, &solution);
+                debug_assert!(best.as_ref().is_none_or(|(best_value, _)| value < *best_value));
+                on_improvement(value);
+                best = Some((value, solution));
+                let Some(bound) = value.checked_sub(1) else {
+                    // これ以上小さい値はない
+                    let (value, solution) = best.unwrap();
+                    return PBSolverResult::Optimum { value: Some(value), solution };
+                };
+                let terms: Vec<(i64, Literal)> = objective.iter().copied().collect();
+                add_at_most(&mut solver, &terms, bound, handling);
+            }
+            SATSolverResult::Unsatisfiable => {
+                return match best {
+                    Some((value, solution)) => PBSolverResult::Optimum { value: Some(value), solution },
+                    None => PBSolverResult::Unsatisfiable,
+                };
+            }
+            SATSolverResult::Unknown => {
+                return match best {
+                    Some((value, solution)) => PBSolverResult::Satisfiable { value, solution },
+                    None => PBSolverResult::Unknown,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::read_opb::read_opb;
+    use super::super::test_helpers::Random;
+    use super::*;
+
+    /// 乱数で生成した項(係数が負の項や積の項を含む)
+    fn random_terms(random: &mut Random, n: VariableSize, equal_coefficients: bool) -> String {
+        let len = 2 + random.next_below(4);
+        let magnitude = 1 + random.next_below(3) as i64;
+        let mut terms = String::new();
+        for _ in 0..len {
+            let coefficient = if equal_coefficients { magnitude } else { 1 + random.next_below(5) as i64 };
+            let coefficient = if random.next_bool() { coefficient } else { -coefficient };
+            terms += &format!("{:+} ", coefficient);
+            for _ in 0..if random.next_below(4) == 0 { 2 } else { 1 } {
+                terms += &format!("{}x{} ", if random.next_bool() { "" } else { "~" }, random.next_below(n) + 1);
+            }
+        }
+        terms
+    }
+
+    /// 乱数で生成した OPB 形式の問題
+    fn random_opb(random: &mut Random, n: VariableSize, m: usize) -> String {
+        let mut opb = format!("* #variable= {} #constraint= {}\n", n, m);
+        if random.next_below(4) != 0 {
+            opb += &format!("min: {};\n", random_terms(random, n, false));
+        }
+        for _ in 0..m {
+            let equal_coefficients = random.next_bool();
+            let terms = random_terms(random, n, equal_coefficients);
+            let relation = [">=", ">=", "=", "<=", "<="][random.next_below(5) as usize];
+            let bound = random.next_below(5) as i64 - 2;
+            opb += &format!("{}{} {} ;\n", terms, relation, bound);
+        }
+        opb
+    }
+
+    /// values の第 i ビットを変数 i の値とする割り当て(積の変数は論理積の値とする)
+    fn assignment(problem: &PBProblem, values: u32) -> Array<VariableSize, bool> {
+        let mut assignment = Array::default();
+        for variable_index in 0..problem.number_of_variables {
+            assignment.push(values >> variable_index & 1 != 0);
+        }
+        assignment.resize(problem.number_of_all_variables, false);
+        for (variable_index, literals) in problem.products.iter() {
+            assignment[*variable_index] = literals.iter().all(|literal| assignment[literal.index] == literal.sign);
+        }
+        assignment
+    }
+
+    /// 割り当てがすべての制約を充足するか
+    fn is_feasible(problem: &PBProblem, assignment: &Array<VariableSize, bool>) -> bool {
+        problem.constraints.iter().all(|constraint| {
+            let value = evaluate(&constraint.terms, assignment);
+            match constraint.relation {
+                PBRelation::GreaterEqual => value >= constraint.bound,
+                PBRelation::Equal => value == constraint.bound,
+                PBRelation::LessEqual => value <= constraint.bound,
+            }
+        })
+    }
+
+    /// 全探索で求めた目的関数の最小値(充足不能なら None，目的関数がなければ Some(0))
+    fn brute_force(problem: &PBProblem) -> Option<i64> {
+        (0..1u32 << problem.number_of_variables)
+            .map(|values| assignment(problem, values))
+            .filter(|assignment| is_feasible(problem, assignment))
+            .map(|assignment| problem.objective.as_ref().map_or(0, |objective| evaluate(objective, &assignment)))
+            .min()
+    }
+
+    #[test]
+    fn solve_pb_matches_brute_force() {
+        let mut random = Random::new(1);
+        let n = 8;
+        for k in 0..150 {
+            let problem = read_opb(random_opb(&mut random, n, 1 + k % 4).as_bytes());
+            let expected = brute_force(&problem);
+            for handling in [
+                PBConstraintHandling::Native,
+                PBConstraintHandling::Encoding(PseudoBooleanEncoding::BDD),
+                PBConstraintHandling::Encoding(PseudoBooleanEncoding::Adder),
+            ] {
+                match solve_pb(&problem, &SATSolverOptions::default(), handling, |_| {}) {
+                    PBSolverResult::Optimum { value, solution } => {
+                        // 解の積の変数は論理積と等しく，解はすべての制約を充足する
+                        let values = (0..n)
+                            .filter(|variable_index| solution[*variable_index])
+                            .fold(0, |values, variable_index| values | 1 << variable_index);
+                        let assignment = assignment(&problem, values);
+                        assert!((0..problem.number_of_all_variables).all(|index| solution[index] == assignment[index]));
+                        assert!(is_feasible(&problem, &assignment), "{:?}", handling);
+                        assert_eq!(value.unwrap_or(0), expected.unwrap(), "{:?}", handling);
+                        assert_eq!(value.is_some(), problem.objective.is_some());
+                    }
+                    PBSolverResult::Unsatisfiable => assert_eq!(expected, None, "{:?}", handling),
+                    _ => panic!(),
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "objective value exceeds the range of i64")]
+    fn evaluate_rejects_overflow() {
+        let mut terms = Array::default();
+        terms.push((i64::MAX, Literal { sign: true, index: 0 }));
+        terms.push((1, Literal { sign: true, index: 1 }));
+        let mut solution = Array::default();
+        solution.push(true);
+        solution.push(true);
+        evaluate(&terms, &solution);
+    }
+}
@@ -0,0 +1,300 @@
+use std::sync::atomic::{fence, AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+
+use crate::finite_collections::Array;
+
+use super::decision_heuristic::DecisionHeuristicKind;
+use super::read_cnf::SATProblem;
+use super::solver::{SATSolver, SATSolverOptions, SATSolverResult};
+use super::types::{Literal, VariableSize};
+use super::variables::PropagationOrder;
+
+/// 共有する節の長さの上限
+const MAX_SHARED_CLAUSE_LENGTH: usize = 8;
+
+struct SharedClauseSlot {
+    // 書き込み中は 2 * position + 1，書き込み完了後は 2 * position + 2
+    sequence: AtomicU64,
+    producer: AtomicU32,
+    lbd: AtomicU32,
+    length: AtomicU32,
+    literals: [AtomicU32; MAX_SHARED_CLAUSE_LENGTH],
+}
+
+/// スレッド間で学習節を交換するためのリングバッファ
+///
+/// 書き込み位置を fetch_add で確保し，各スロットは sequence による seqlock で保護する．
+/// 書き込み・読み出しのいずれもロックを取らず，競合した節は破棄する(節の共有は取りこぼしを許容する)．
+pub struct ClauseExchange {
+    slots: Box<[SharedClauseSlot]>,
+    write_position: AtomicU64,
+}
+
+impl ClauseExchange {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0);
+        ClauseExchange {
+            slots: (0..capacity)
+                .map(|_| SharedClauseSlot {
+                    sequence: AtomicU64::new(0),
+                    producer: AtomicU32::new(0),
+                    lbd: AtomicU32::new(0),
+                    length: AtomicU32::new(0),
+                    literals: std::array::from_fn(|_| AtomicU32::new(0)),
+                })
+                .collect(),
+            write_position: AtomicU64::new(0),
+        }
+    }
+
+    #[inline(always)]
+    fn encode(literal: &Literal) -> u32 {
+        (literal.index << 1) | literal.sign as u32
+    }
+
+    #[inline(always)]
+    fn decode(code: u32) -> Literal {
+        Literal { index: code >> 1, sign: code & 1 != 0 }
+    }
+
+    /// 節を書き込む(長すぎる節やスロットの競合で書き込めなかった場合には false を返す)
+    pub fn export(&self, producer: u32, literals: &Array<VariableSize, Literal>, lbd: VariableSize) -> bool {
+        if literals.len() as usize > MAX_SHARED_CLAUSE_LENGTH {
+            return false;
+        }
+        let position = self.write_position.fetch_add(1, Ordering::Relaxed);
+        let slot = &self.slots[(position % self.slots.len() as u64) as usize];
+        // より古い書き込みが完了しているスロットのみを確保する
+        let current_sequence = slot.sequence.load(Ordering::Relaxed);
+        if !current_sequence.is_multiple_of(2)
+            || current_sequence > 2 * position
+            || slot
+                .sequence
+                .compare_exchange(current_sequence, 2 * position + 1, Ordering::Relaxed, Ordering::Relaxed)
+                .is_err()
+        {
+            return false;
+        }
+        fence(Ordering::Release);
+        slot.producer.store(producer, Ordering::Relaxed);
+        slot.lbd.store(lbd, Ordering::Relaxed);
+        slot.length.store(literals.len(), Ordering::Relaxed);
+        for (k, literal) in literals.iter().enumerate() {
+            slot.literals[k].store(Self::encode(literal), Ordering::Relaxed);
+        }
+        slot.sequence.store(2 * position + 2, Ordering::Release);
+        true
+    }
+
+    /// cursor 以降に書き込まれた他のスレッドの節を (LBD, 節) として clauses に追加し，cursor を進める
+    ///
+    /// 読み出し時点で書き込み中の節や，読み出す前に上書きされた節は読み飛ばす．
+    pub fn import(
+        &self,
+        consumer: u32,
+        cursor: &mut u64,
+        clauses: &mut Array<VariableSize, (VariableSize, Array<VariableSize, Literal>)>,
+    ) {
+        let write_position = self.write_position.load(Ordering::Relaxed);
+        let capacity = self.slots.len() as u64;
+        let first_position = (*cursor).max(write_position.saturating_sub(capacity));
+        for position in first_position..write_position {
+            let slot = &self.slots[(position % capacity) as usize];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            if sequence != 2 * position + 2 {
+                continue;
+            }
+            let producer = slot.producer.load(Ordering::Relaxed);
+            let lbd = slot.lbd.load(Ordering::Relaxed);
+            let length = (slot.length.load(Ordering::Relaxed) as usize).min(MAX_SHARED_CLAUSE_LENGTH);
+            let mut literals = Array::default();
+            for k in 0..length {
+                literals.push(Self::decode(slot.literals[k].load(Ordering::Relaxed)));
+            }
+            fence(Ordering::Acquire);
+            if slot.sequence.load(Ordering::Relaxed) != sequence || producer == consumer {
+                continue;
+            }
+            clauses.push((lbd, literals));
+        }
+        *cursor = write_position;
+    }
+
+    /// cursor 以降に他のスレッドが書き込んだ節(import で読み出される節)があるか
+    ///
+    /// 自身が書き込んだ節は読み出されないので，cursor の直後に続く自身の節は cursor を進めて読み飛ばす．
+    pub fn has_new_clauses(&self, consumer: u32, cursor: &mut u64) -> bool {
+        let write_position = self.write_position.load(Ordering::Relaxed);
+        let capacity = self.slots.len() as u64;
+        let first_position = (*cursor).max(write_position.saturating_sub(capacity));
+        let mut is_own_prefix = true;
+        for position in first_position..write_position {
+            let slot = &self.slots[(position % capacity) as usize];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            if sequence != 2 * position + 2 {
+                // 書き込み中か上書きされた節
+                is_own_prefix = false;
+                continue;
+            }
+            let producer = slot.producer.load(Ordering::Relaxed);
+            fence(Ordering::Acquire);
+            if slot.sequence.load(Ordering::Relaxed) != sequence {
+                is_own_prefix = false;
+                continue;
+            }
+            if producer != consumer {
+                return true;
+            }
+            if is_own_prefix {
+                *cursor = position + 1;
+            }
+        }
+        false
+    }
+}
+
+/// SATSolver が ClauseExchange を介して学習節を共有するための状態
+pub struct ClauseSharing {
+    pub exchange: Arc<ClauseExchange>,
+    pub id: u32,
+    pub cursor: u64,
+    /// LBD がこの値以下の学習節(と単位節)を共有する
+    pub export_lbd_threshold: VariableSize,
+    pub exported_count: usize,
+    pub imported_count: usize,
+}
+
+impl ClauseSharing {
+    pub fn new(exchange: Arc<ClauseExchange>, id: u32, export_lbd_threshold: VariableSize) -> Self {
+        ClauseSharing { exchange, id, cursor: 0, export_lbd_threshold, exported_count: 0, imported_count: 0 }
+    }
+}
+
+/// k 番目のスレッドの設定(0 番目は base のまま)
+fn diversify(base: &SATSolverOptions, k: usize) -> SATSolverOptions {
+    let mut options = base.clone();
+    if k == 0 {
+        return options;
+    }
+    // スレッドごとに異なるシードで初期の選択順序と同点の順序を乱択化する
+    options.seed = base.seed.wrapping_add(k as u64);
+    options.shuffle_initial_activity = true;
+    options.random_tie_breaking = true;
+    options.decision_heuristic = [
+        DecisionHeuristicKind::Vmtf,
+        DecisionHeuristicKind::Lrb,
+        DecisionHeuristicKind::Chb,
+        DecisionHeuristicKind::Activity,
+    ][(k - 1) % 4];
+    if (k - 1) / 4 % 2 == 1 {
+        options.propagation_order = PropagationOrder::FIFO;
+        options.chronological_backtracking_threshold = VariableSize::MAX;
+    }
+    if (k - 1) / 8 % 2 == 1 {
+        options.reuse_trail = !options.reuse_trail;
+        options.local_search_flips = if base.local_search_flips == 0 { 100000 } else { 0 };
+    }
+    options
+}
+
+/// 設定の異なる number_of_threads 個の SATSolver で並列に解き，最初に得られた結果を返す
+///
+/// 各スレッドは単位節と LBD の小さい学習節を ClauseExchange で共有し，いずれかのスレッドが結果を得た時点で全スレッドを停止する．
+pub fn solve_portfolio(problem: &SATProblem, options: &SATSolverOptions, number_of_threads: usize) -> SATSolverResult {
+    assert!(number_of_threads >= 1);
+    let exchange = Arc::new(ClauseExchange::new(1 << 16));
+    let terminate = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|scope| {
+        for k in 0..number_of_threads {
+            let thread_options = diversify(options, k);
+            let exchange = exchange.clone();
+            let terminate = terminate.clone();
+            let sender = sender.clone();
+            scope.spawn(move || {
+                let mut solver = SATSolver::with_options(thread_options);
+                solver.add_problem(problem);
+                solver.set_clause_sharing(ClauseSharing::new(exchange, k as u32, 2));
+                solver.set_terminate_flag(terminate);
+                let result = solver.solve();
+                let (exported_count, imported_count) = solver.sharing_summary();
+                eprintln!("thread={} exported_count={} imported_count={}", k, exported_count, imported_count);
+                // 受信側が先に結果を受け取って終了していれば送信に失敗するが問題ない
+                let _ = sender.send((k, result));
+            });
+        }
+        drop(sender);
+        // 最初に得られた結果を採用して他のスレッドを停止
+        let mut answer = SATSolverResult::Unknown;
+        for (k, result) in receiver.iter() {
+            if !matches!(result, SATSolverResult::Unknown) {
+                eprintln!("answered_by_thread={}", k);
+                answer = result;
+                terminate.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+        answer
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::{is_solution, random_problem, Random};
+    use super::*;
+
+    #[test]
+    fn portfolio_agrees_with_single_solver() {
+        let mut random = Random::new(1);
+        let options = SATSolverOptions::default();
+        let mut satisfiable_count = 0;
+        for k in 0..20 {
+            // 節と変数の比が 4.26 前後の(充足可能性が半々の)問題
+            let n = 60 + 5 * (k % 4);
+            let problem = random_problem(&mut random, n, (n as f64 * (4.0 + 0.05 * (k % 10) as f64)) as usize);
+            let mut solver = SATSolver::with_options(options.clone());
+            solver.add_problem(&problem);
+            let expected = matches!(solver.solve(), SATSolverResult::Satisfiable { .. });
+            match solve_portfolio(&problem, &options, 4) {
+                SATSolverResult::Satisfiable { solution } => {
+                    assert!(expected);
+                    assert_eq!(solution.len(), n);
+                    assert!(is_solution(&problem, &solution));
+                    satisfiable_count += 1;
+                }
+                SATSolverResult::Unsatisfiable => assert!(!expected),
+                SATSolverResult::Unknown => panic!(),
+            }
+        }
+        assert!(0 < satisfiable_count && satisfiable_count < 20);
+    }
+
+    #[test]
+    fn own_clauses_are_not_new_clauses() {
+        let exchange = ClauseExchange::new(4);
+        let mut clause = Array::default();
+        clause.push(Literal { index: 0, sign: true });
+        clause.push(Literal { index: 1, sign: false });
+        let mut cursor = 0;
+        assert!(!exchange.has_new_clauses(0, &mut cursor));
+        // 自身の節だけであれば新しい節はなく，cursor はその節を読み飛ばす
+        assert!(exchange.export(0, &clause, 2));
+        assert!(exchange.export(0, &clause, 2));
+        assert!(!exchange.has_new_clauses(0, &mut cursor));
+        assert_eq!(cursor, 2);
+        // 他のスレッドの節の後の自身の節は読み飛ばさない
+        assert!(exchange.export(1, &clause, 3));
+        assert!(exchange.export(0, &clause, 2));
+        assert!(exchange.has_new_clauses(0, &mut cursor));
+        let mut other_cursor = 2;
+        assert!(exchange.has_new_clauses(1, &mut other_cursor));
+        assert_eq!(other_cursor, 3);
+        assert_eq!(cursor, 2);
+        let mut clauses = Array::default();
+        exchange.import(0, &mut cursor, &mut clauses);
+        assert_eq!(clauses.len(), 1);
+        assert_eq!(clauses[0].0, 3);
+        assert_eq!(cursor, 4);
+        assert!(!exchange.has_new_clauses(0, &mut cursor));
+    }
+}
@@ -0,0 +1,44 @@
+/// 疑似乱数生成器(xorshift64*)
+///
+/// 同じシードからは常に同じ系列を生成する．
+#[derive(Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        // シードを splitmix64 で撹拌(状態が 0 になると系列が 0 のみになるため 0 を避ける)
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        Random { state: if z == 0 { 0x9e3779b97f4a7c15 } else { z } }
+    }
+
+    #[inline(always)]
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    /// [0, 1) の一様乱数
+    #[inline(always)]
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// [0, n) の一様乱数
+    #[inline(always)]
+    pub fn next_below(&mut self, n: u32) -> u32 {
+        debug_assert!(n != 0);
+        (((self.next_u64() >> 32) * n as u64) >> 32) as u32
+    }
+
+    #[inline(always)]
+    pub fn next_bool(&mut self) -> bool {
+        (self.next_u64() >> 63) != 0
+    }
+}
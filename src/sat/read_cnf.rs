@@ -3,7 +3,42 @@ use crate::finite_collections::Array;
 
 #[derive(Default)]
 pub struct SATProblem {
+    // ヘッダで宣言された変数の数(節に現れる変数の数の方が多ければそちら)
+    pub number_of_variables: VariableSize,
     pub clauses: Array<ConstraintSize, Array<VariableSize, Literal>>,
+    // XOR 制約(変数, 排他的論理和の値)
+    pub xors: Array<ConstraintSize, (Array<VariableSize, VariableSize>, bool)>,
+    // "c p show" で指定された射影する変数(指定がなければ None)
+    pub projection: Option<Array<VariableSize, VariableSize>>,
+}
+
+impl SATProblem {
+    /// ヘッダ行("p cnf 変数の数 節の数")とコメント行("c p show 変数 ... 0")を読む
+    fn parse_header_or_comment(&mut self, line: &str) {
+        if let Some(show) = line.strip_prefix("c p show") {
+            let projection = self.projection.get_or_insert_with(Array::default);
+            for literal in parse_line(show).iter() {
+                projection.push(literal.index);
+            }
+        } else if let Some(header) = line.strip_prefix('p') {
+            if let Some(number_of_variables) = header.split_whitespace().nth(1).and_then(|field| field.parse().ok()) {
+                self.number_of_variables = number_of_variables;
+            }
+        }
+    }
+
+    /// 節と XOR 制約に現れる変数に合わせて変数の数を更新する
+    fn update_number_of_variables(&mut self) {
+        let used_number_of_variables = self
+            .clauses
+            .iter()
+            .flat_map(|clause| clause.iter().map(|literal| literal.index + 1))
+            .chain(self.xors.iter().flat_map(|(xor_variables, _)| xor_variables.iter().map(|index| index + 1)))
+            .chain(self.projection.iter().flat_map(|projection| projection.iter().map(|index| index + 1)))
+            .max()
+            .unwrap_or(0);
+        self.number_of_variables = self.number_of_variables.max(used_number_of_variables);
+    }
 }
 
 fn parse_line(line: &str) -> Array<VariableSize, Literal> {
@@ -22,6 +57,170 @@ fn parse_line(line: &str) -> Array<VariableSize, Literal> {
     clause
 }
 
+/// "x" で始まる XOR 節(リテラルの排他的論理和が真)を変数とパリティの組に変換する
+fn parse_xor_line(line: &str) -> (Array<VariableSize, VariableSize>, bool) {
+    let mut xor_variables = Array::default();
+    let mut parity = true;
+    for literal in parse_line(line).iter() {
+        xor_variables.push(literal.index);
+        parity ^= !literal.sign;
+    }
+    (xor_variables, parity)
+}
+
+/// iCNF 形式(節と "a" で始まるキューブの列)を読み込む
+pub fn read_icnf<R>(reader: R) -> (SATProblem, Array<ConstraintSize, Array<VariableSize, Literal>>)
+where
+    R: std::io::BufRead,
+{
+    let mut problem = SATProblem::default();
+    let mut cubes = Array::default();
+    for read_result in reader.lines() {
+        let line = read_result.unwrap();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('c') || line.starts_with('p') {
+            problem.parse_header_or_comment(&line);
+            continue;
+        }
+        if let Some(cube) = line.strip_prefix('a') {
+            cubes.push(parse_line(cube));
+        } else if let Some(xor) = line.strip_prefix('x') {
+            problem.xors.push(parse_xor_line(xor));
+        } else {
+            problem.clauses.push(parse_line(&line))
+        }
+    }
+    problem.update_number_of_variables();
+    // キューブにのみ現れる変数も含める
+    let used_number_of_variables = cubes
+        .iter()
+        .flat_map(|cube: &Array<VariableSize, Literal>| cube.iter().map(|literal| literal.index + 1))
+        .max()
+        .unwrap_or(0);
+    problem.number_of_variables = problem.number_of_variables.max(used_number_of_variables);
+    (problem, cubes)
+}
+
+/// 重み付き MaxSAT 問題
+#[derive(Default)]
+pub struct MaxSATProblem {
+    pub number_of_variables: VariableSize,
+    pub hard_clauses: Array<ConstraintSize, Array<VariableSize, Literal>>,
+    // ソフト節(重み, 節)
+    pub soft_clauses: Array<ConstraintSize, (u64, Array<VariableSize, Literal>)>,
+}
+
+/// WCNF 形式を読み込む
+///
+/// 従来の形式("p wcnf 変数の数 節の数 top" と重み付きの節，重みが top 以上の節はハード節)と，
+/// 2022 年以降の形式(ハード節は "h" で始まり，それ以外は重み付きのソフト節)のいずれも読める．
+/// コストを i64 の係数の擬似ブール制約で扱えるように，ソフト節の重みの和が i64::MAX を超える問題は受け付けない．
+pub fn read_wcnf<R>(reader: R) -> MaxSATProblem
+where
+    R: std::io::BufRead,
+{
+    let mut problem = MaxSATProblem::default();
+    let mut top = u64::MAX;
+    let mut soft_weight_sum: u64 = 0;
+    for read_result in reader.lines() {
+        let line = read_result.unwrap();
+        if line.is_empty() || line.starts_with('c') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('p') {
+            let fields: Vec<&str> = header.split_whitespace().collect();
+            assert!(fields.first() == Some(&"wcnf"), "invalid header: {}", line);
+            if let Some(number_of_variables) = fields.get(1) {
+                problem.number_of_variables = number_of_variables.parse().unwrap();
+            }
+            if let Some(value) = fields.get(3) {
+                top = value.parse().unwrap();
+            }
+            continue;
+        }
+        if let Some(clause) = line.strip_prefix('h') {
+            problem.hard_clauses.push(parse_line(clause));
+            continue;
+        }
+        let line = line.trim_start();
+        let (weight, clause) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let weight = weight.parse::<u64>().unwrap();
+        let clause = parse_line(clause);
+        if weight >= top {
+            problem.hard_clauses.push(clause);
+        } else if weight > 0 {
+            soft_weight_sum = soft_weight_sum
+                .checked_add(weight)
+                .filter(|sum| *sum <= i64::MAX as u64)
+                .unwrap_or_else(|| panic!("sum of soft clause weights exceeds {}: {}", i64::MAX, line));
+            problem.soft_clauses.push((weight, clause));
+        }
+    }
+    let used_number_of_variables = problem
+        .hard_clauses
+        .iter()
+        .chain(problem.soft_clauses.iter().map(|(_, clause)| clause))
+        .flat_map(|clause| clause.iter().map(|literal| literal.index + 1))
+        .max()
+        .unwrap_or(0);
+    problem.number_of_variables = problem.number_of_variables.max(used_number_of_variables);
+    problem
+}
+
+/// 節をグループに分けた問題(MUS の抽出に使う)
+#[derive(Default)]
+pub struct GroupedProblem {
+    pub number_of_variables: VariableSize,
+    // グループの数(グループ 0 はハード節で，ソフトなグループは 1 から number_of_groups - 1)
+    pub number_of_groups: ConstraintSize,
+    // (グループ, 節)
+    pub clauses: Array<ConstraintSize, (ConstraintSize, Array<VariableSize, Literal>)>,
+}
+
+/// GCNF 形式("p gcnf 変数の数 節の数 最後のグループ" と "{グループ}" で始まる節)を読み込む
+///
+/// CNF 形式("p cnf")であれば各節をそれぞれ 1 つのグループとする．
+pub fn read_gcnf<R>(reader: R) -> GroupedProblem
+where
+    R: std::io::BufRead,
+{
+    let mut problem = GroupedProblem { number_of_groups: 1, ..GroupedProblem::default() };
+    let mut is_grouped = false;
+    for read_result in reader.lines() {
+        let line = read_result.unwrap();
+        if line.is_empty() || line.starts_with('c') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('p') {
+            let fields: Vec<&str> = header.split_whitespace().collect();
+            assert!(matches!(fields.first(), Some(&"gcnf") | Some(&"cnf")), "invalid header: {}", line);
+            is_grouped = fields[0] == "gcnf";
+            if let Some(number_of_variables) = fields.get(1) {
+                problem.number_of_variables = number_of_variables.parse().unwrap();
+            }
+            continue;
+        }
+        let (group, clause) = if is_grouped {
+            let (group, clause) = line.trim_start().strip_prefix('{').and_then(|line| line.split_once('}')).unwrap();
+            (group.trim().parse::<ConstraintSize>().unwrap(), parse_line(clause))
+        } else {
+            (problem.clauses.len() + 1, parse_line(&line))
+        };
+        problem.number_of_groups = problem.number_of_groups.max(group + 1);
+        problem.clauses.push((group, clause));
+    }
+    let used_number_of_variables = problem
+        .clauses
+        .iter()
+        .flat_map(|(_, clause)| clause.iter().map(|literal| literal.index + 1))
+        .max()
+        .unwrap_or(0);
+    problem.number_of_variables = problem.number_of_variables.max(used_number_of_variables);
+    problem
+}
+
 pub fn read_cnf<R>(reader: R) -> SATProblem
 where
     R: std::io::BufRead,
@@ -29,10 +228,79 @@ where
     let mut problem = SATProblem::default();
     for read_result in reader.lines() {
         let line = read_result.unwrap();
-        if line.is_empty() || line.starts_with('c') || line.starts_with('p') {
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('c') || line.starts_with('p') {
+            problem.parse_header_or_comment(&line);
             continue;
         }
-        problem.clauses.push(parse_line(&line))
+        if let Some(xor) = line.strip_prefix('x') {
+            problem.xors.push(parse_xor_line(xor));
+        } else {
+            problem.clauses.push(parse_line(&line))
+        }
     }
+    problem.update_number_of_variables();
     problem
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn literals(clause: &Array<VariableSize, Literal>) -> Vec<i64> {
+        clause
+            .iter()
+            .map(|literal| if literal.sign { literal.index as i64 + 1 } else { -(literal.index as i64 + 1) })
+            .collect()
+    }
+
+    #[test]
+    fn read_icnf_separates_clauses_and_cubes() {
+        let input = "c comment\np inccnf\n1 -2 0\n\n2 3 0\na -1 0\na 1 4 0\n";
+        let (problem, cubes) = read_icnf(input.as_bytes());
+        assert_eq!(problem.number_of_variables, 4);
+        assert_eq!(problem.clauses.iter().map(literals).collect::<Vec<_>>(), vec![vec![1, -2], vec![2, 3]]);
+        assert_eq!(cubes.iter().map(literals).collect::<Vec<_>>(), vec![vec![-1], vec![1, 4]]);
+    }
+
+    #[test]
+    fn read_wcnf_with_top_weight() {
+        let input = "c comment\np wcnf 4 4 10\n10 1 -2 0\n3 2 0\n0 3 0\n1 -4 0\n";
+        let problem = read_wcnf(input.as_bytes());
+        assert_eq!(problem.number_of_variables, 4);
+        assert_eq!(problem.hard_clauses.iter().map(literals).collect::<Vec<_>>(), vec![vec![1, -2]]);
+        // 重み 0 のソフト節は読み飛ばす
+        assert_eq!(
+            problem.soft_clauses.iter().map(|(weight, clause)| (*weight, literals(clause))).collect::<Vec<_>>(),
+            vec![(3, vec![2]), (1, vec![-4])]
+        );
+    }
+
+    #[test]
+    fn read_wcnf_with_hard_marker() {
+        let input = "h 1 2 0\n5 -1 0\nh -2 5 0\n2 0\n";
+        let problem = read_wcnf(input.as_bytes());
+        assert_eq!(problem.number_of_variables, 5);
+        assert_eq!(problem.hard_clauses.iter().map(literals).collect::<Vec<_>>(), vec![vec![1, 2], vec![-2, 5]]);
+        assert_eq!(
+            problem.soft_clauses.iter().map(|(weight, clause)| (*weight, literals(clause))).collect::<Vec<_>>(),
+            vec![(5, vec![-1]), (2, vec![])]
+        );
+    }
+
+    #[test]
+    fn read_wcnf_with_large_weights() {
+        let input = format!("h 1 2 0\n{} -1 0\n{} -2 0\n", i64::MAX - 1, 1);
+        let problem = read_wcnf(input.as_bytes());
+        assert_eq!(problem.soft_clauses.iter().map(|(weight, _)| *weight).sum::<u64>(), i64::MAX as u64);
+    }
+
+    #[test]
+    #[should_panic(expected = "sum of soft clause weights exceeds")]
+    fn read_wcnf_rejects_overflowing_weights() {
+        let input = format!("h 1 2 0\n{} -1 0\n{} -2 0\n", i64::MAX, 1);
+        read_wcnf(input.as_bytes());
+    }
+}
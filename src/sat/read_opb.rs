@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+use super::types::{ConstraintSize, Literal, VariableSize};
+use crate::finite_collections::Array;
+
+/// 擬似ブール制約の関係
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PBRelation {
+    GreaterEqual,
+    Equal,
+    LessEqual,
+}
+
+/// Σ coefficient * literal (relation) bound
+pub struct PBConstraint {
+    pub terms: Array<VariableSize, (i64, Literal)>,
+    pub relation: PBRelation,
+    pub bound: i64,
+}
+
+#[derive(Default)]
+pub struct PBProblem {
+    // 入力に現れる変数の数(積の項のために追加した変数は含まない)
+    pub number_of_variables: VariableSize,
+    // 積の項のために追加した変数も含めた変数の数
+    pub number_of_all_variables: VariableSize,
+    // 最小化する目的関数
+    pub objective: Option<Array<VariableSize, (i64, Literal)>>,
+    pub constraints: Array<ConstraintSize, PBConstraint>,
+    // 積の項を表す変数と，その変数と等しい論理積のリテラル
+    pub products: Array<ConstraintSize, (VariableSize, Array<VariableSize, Literal>)>,
+}
+
+fn parse_literal(token: &str) -> Option<Literal> {
+    let (sign, name) = match token.strip_prefix('~') {
+        Some(name) => (false, name),
+        None => (true, token),
+    };
+    let number = name.strip_prefix('x')?.parse::<VariableSize>().ok()?;
+    assert!(number >= 1, "invalid variable: {}", token);
+    Some(Literal { sign, index: number - 1 })
+}
+
+/// 係数と(積の)リテラルの列を読む
+fn parse_terms<'a, I>(tokens: &mut std::iter::Peekable<I>) -> Vec<(i64, Vec<Literal>)>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut terms = Vec::new();
+    while let Some(token) = tokens.peek() {
+        let Ok(coefficient) = token.parse::<i64>() else {
+            break;
+        };
+        tokens.next();
+        let mut literals = Vec::new();
+        while let Some(literal) = tokens.peek().and_then(|token| parse_literal(token)) {
+            literals.push(literal);
+            tokens.next();
+        }
+        assert!(!literals.is_empty(), "missing variable after coefficient {}", coefficient);
+        terms.push((coefficient, literals));
+    }
+    terms
+}
+
+/// 積の項を変数に置き換える(同じ積には同じ変数を使う)
+fn linearize(
+    terms: Vec<(i64, Vec<Literal>)>,
+    problem: &mut PBProblem,
+    product_variables: &mut HashMap<Vec<(VariableSize, bool)>, VariableSize>,
+) -> Array<VariableSize, (i64, Literal)> {
+    let mut linear_terms = Array::default();
+    for (coefficient, mut literals) in terms.into_iter() {
+        literals.sort_by_key(|literal| (literal.index, literal.sign));
+        literals.dedup();
+        if literals.len() == 1 {
+            linear_terms.push((coefficient, literals[0]));
+            continue;
+        }
+        let key = literals.iter().map(|literal| (literal.index, literal.sign)).collect();
+        let variable_index = *product_variables.entry(key).or_insert_with(|| {
+            let variable_index = problem.number_of_all_variables;
+            problem.number_of_all_variables += 1;
+            let mut product = Array::default();
+            for literal in literals.iter() {
+                product.push(*literal);
+            }
+            problem.products.push((variable_index, product));
+            variable_index
+        });
+        linear_terms.push((coefficient, Literal { sign: true, index: variable_index }));
+    }
+    linear_terms
+}
+
+/// OPB 形式(PB competition の入力形式)を読み込む
+///
+/// 目的関数は "min:" で始まる行で，制約は ">=", "=", "<=" のいずれかで係数付きのリテラルの和と定数を比較する．
+/// 非線形の項(リテラルの積)は論理積を表す変数に置き換える．
+pub fn read_opb<R>(reader: R) -> PBProblem
+where
+    R: std::io::BufRead,
+{
+    let mut content = String::new();
+    let mut declared_number_of_variables = 0;
+    for read_result in reader.lines() {
+        let line = read_result.unwrap();
+        if let Some(comment) = line.strip_prefix('*') {
+            // "* #variable= 5 #constraint= 4" から変数の数を読む
+            let mut fields = comment.split_whitespace();
+            while let Some(field) = fields.next() {
+                if field == "#variable=" {
+                    declared_number_of_variables = fields.next().and_then(|n| n.parse().ok()).unwrap_or(0);
+                }
+            }
+            continue;
+        }
+        content.push_str(&line);
+        content.push('\n');
+    }
+
+    // 変数の数が分かってから積の変数を割り当てるため，先にすべての文を読む
+    let mut objective = None;
+    let mut constraints = Vec::new();
+    for statement in content.split(';') {
+        let mut tokens = statement.split_whitespace().peekable();
+        match tokens.peek() {
+            None => continue,
+            Some(&"min:") => {
+                tokens.next();
+                objective = Some(parse_terms(&mut tokens));
+            }
+            Some(_) => {
+                let terms = parse_terms(&mut tokens);
+                let relation = match tokens.next() {
+                    Some(">=") => PBRelation::GreaterEqual,
+                    Some("=") => PBRelation::Equal,
+                    Some("<=") => PBRelation::LessEqual,
+                    token => panic!("invalid relation: {:?}", token),
+                };
+                let bound = tokens.next().and_then(|token| token.parse::<i64>().ok()).expect("invalid bound");
+                constraints.push((terms, relation, bound));
+            }
+        }
+        assert!(tokens.next().is_none(), "unexpected token in statement: {}", statement.trim());
+    }
+
+    let mut problem = PBProblem::default();
+    let used_number_of_variables = objective
+        .iter()
+        .chain(constraints.iter().map(|(terms, _, _)| terms))
+        .flat_map(|terms| terms.iter().flat_map(|(_, literals)| literals.iter().map(|literal| literal.index + 1)))
+        .max()
+        .unwrap_or(0);
+    problem.number_of_variables = used_number_of_variables.max(declared_number_of_variables);
+    problem.number_of_all_variables = problem.number_of_variables;
+    let mut product_variables = HashMap::new();
+    if let Some(terms) = objective {
+        problem.objective = Some(linearize(terms, &mut problem, &mut product_variables));
+    }
+    for (terms, relation, bound) in constraints.into_iter() {
+        let terms = linearize(terms, &mut problem, &mut product_variables);
+        problem.constraints.push(PBConstraint { terms, relation, bound });
+    }
+    problem
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(terms: &Array<VariableSize, (i64, Literal)>) -> Vec<(i64, bool, VariableSize)> {
+        terms.iter().map(|(weight, literal)| (*weight, literal.sign, literal.index)).collect()
+    }
+
+    #[test]
+    fn read_opb_linear_and_product_terms() {
+        let input = "* #variable= 5 #constraint= 3\n\
+                     min: +2 x1 -3 ~x2 ;\n\
+                     1 x1 +1 x2 x3\n  >= 1 ;\n\
+                     -1 x3 x2 = -1 ;\n\
+                     4 ~x1 <= 3 ;\n";
+        let problem = read_opb(input.as_bytes());
+        assert_eq!(problem.number_of_variables, 5);
+        // x2 x3 と x3 x2 は同じ積の変数(番号 6)に置き換えられる
+        assert_eq!(problem.number_of_all_variables, 6);
+        assert_eq!(problem.products.len(), 1);
+        assert_eq!(problem.products[0].0, 5);
+        assert_eq!(
+            problem.products[0].1.iter().map(|literal| (literal.sign, literal.index)).collect::<Vec<_>>(),
+            vec![(true, 1), (true, 2)]
+        );
+        assert_eq!(terms(problem.objective.as_ref().unwrap()), vec![(2, true, 0), (-3, false, 1)]);
+        assert_eq!(problem.constraints.len(), 3);
+        assert_eq!(terms(&problem.constraints[0].terms), vec![(1, true, 0), (1, true, 5)]);
+        assert_eq!(problem.constraints[0].relation, PBRelation::GreaterEqual);
+        assert_eq!(problem.constraints[0].bound, 1);
+        assert_eq!(terms(&problem.constraints[1].terms), vec![(-1, true, 5)]);
+        assert_eq!(problem.constraints[1].relation, PBRelation::Equal);
+        assert_eq!(problem.constraints[1].bound, -1);
+        assert_eq!(terms(&problem.constraints[2].terms), vec![(4, false, 0)]);
+        assert_eq!(problem.constraints[2].relation, PBRelation::LessEqual);
+        assert_eq!(problem.constraints[2].bound, 3);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::finite_collections::{Array, FiniteMap};
 
-use super::clause_theory::ClauseTheory;
+use super::theory::Theories;
 use super::types::{Literal, Reason, VariableSize};
 use super::variables::{VariableState, Variables};
 
@@ -14,12 +14,7 @@ pub struct Simplify {
 
 impl Simplify {
     #[inline(never)]
-    pub fn simplify(
-        &mut self,
-        clause: &mut Array<VariableSize, Literal>,
-        variables: &Variables,
-        theory: &mut ClauseTheory,
-    ) {
+    pub fn simplify(&mut self, clause: &mut Array<VariableSize, Literal>, variables: &Variables, theories: &Theories) {
         if clause.len() <= 2 {
             return;
         }
@@ -36,9 +31,9 @@ impl Simplify {
         });
         //
         self.decision_level_to_min_assignment_level.clear();
-        if variables.dimension() > self.decision_level_to_min_assignment_level.capacity() {
+        if variables.dimension() + 1 > self.decision_level_to_min_assignment_level.capacity() {
             self.decision_level_to_min_assignment_level
-                .reserve(variables.dimension() - self.decision_level_to_min_assignment_level.capacity());
+                .reserve(variables.dimension() + 1 - self.decision_level_to_min_assignment_level.capacity());
         }
         self.variable_index_to_redundancy.clear();
         if variables.dimension() > self.variable_index_to_redundancy.capacity() {
@@ -67,7 +62,7 @@ impl Simplify {
                 debug_assert!(*assigned_value != literal.sign);
                 self.variable_index_to_redundancy.remove(literal.index);
                 self.literal_stack.clear();
-                if self.is_redundant(literal.index, variables, theory) {
+                if self.is_redundant(literal.index, variables, theories) {
                     clause.swap_remove(k);
                 } else {
                     debug_assert!(self.variable_index_to_redundancy.contains_key(literal.index));
@@ -77,7 +72,7 @@ impl Simplify {
         }
     }
 
-    fn is_redundant(&mut self, variable_index: VariableSize, variables: &Variables, theory: &mut ClauseTheory) -> bool {
+    fn is_redundant(&mut self, variable_index: VariableSize, variables: &Variables, theories: &Theories) -> bool {
         if let Some(is_redundant) = self.variable_index_to_redundancy.get(variable_index) {
             // 当該変数がキャッシュに含まれていればキャッシュの内容を返却
             return *is_redundant;
@@ -97,17 +92,16 @@ impl Simplify {
             } else if let Reason::Decision { .. } = reason {
                 // 当該変数が決定変数ならば false
                 is_redundant = false;
-            } else if let Reason::Propagation { .. } = reason {
+            } else if let Reason::Propagation { .. } | Reason::Theory { .. } | Reason::External { .. } = reason {
                 // 当該変数の割当を説明する節を取得
-                self.literal_buffer.clear();
-                theory.explain(variable_index, *assigned_value, *reason, &mut self.literal_buffer);
+                theories.explain(variable_index, *assigned_value, *reason, variables, &mut self.literal_buffer);
                 // 現在のスタックサイズを取得
                 let n = self.literal_stack.len();
                 // 当該変数以外の変数(当該変数への割当の原因になっている変数)をスタックに積む
                 self.literal_stack.extend(self.literal_buffer.iter().filter(|l| l.index != variable_index));
                 // 割当の原因になっている全変数について再帰して判定
                 for k in n..self.literal_stack.len() {
-                    is_redundant &= self.is_redundant(self.literal_stack[k].index, variables, theory);
+                    is_redundant &= self.is_redundant(self.literal_stack[k].index, variables, theories);
                     if !is_redundant {
                         break;
                     }
@@ -1,55 +1,306 @@
 use std::hint::unreachable_unchecked;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use crate::finite_collections::Array;
 
 use super::analyze::Analyze;
+use super::calculate_lbd::CalculateLBD;
+use super::cardinality_theory::CardinalityTheory;
 use super::clause_theory::ClauseTheory;
+use super::decision_heuristic::{create_decision_heuristic, DecisionHeuristicKind};
+use super::local_search::LocalSearch;
+use super::portfolio::ClauseSharing;
+use super::random::Random;
+use super::read_cnf::SATProblem;
+use super::theory::Theories;
 use super::types::{Literal, Reason, VariableSize};
-use super::variables::{VariableState, Variables};
+use super::user_propagator::{UserPropagator, UserTheory};
+use super::variables::{PropagationOrder, PropagationStatistics, VariableRandomization, VariableState, Variables};
+use super::xor_theory::XORTheory;
 
 enum SearchResult {
     Satisfiable,
+    SatisfiableByLocalSearch,
+    Interrupted,
     Unsatisfiable,
     // Undefined,
 }
 
 enum PropagationResult {
     Consistent,
-    Conflict { variable_index: VariableSize, reasons: [Reason; 2] },
+    Conflict {
+        variable_index: VariableSize,
+        reasons: [Reason; 2],
+    },
+    /// 理論が返した，すべてのリテラルに偽が割り当てられている節
+    ExternalConflict {
+        clause: Array<VariableSize, Literal>,
+    },
 }
 
 pub enum SATSolverResult {
-    Satisfiable { solution: Array<VariableSize, bool> },
+    Satisfiable {
+        solution: Array<VariableSize, bool>,
+    },
     Unsatisfiable,
+    /// 停止フラグによって中断された
+    Unknown,
 }
 
+/// 同時に発生している複数の矛盾からの学習方法
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConflictLearning {
+    /// 最初に選択された矛盾のみを解析する
+    Single,
+    /// 複数の矛盾を解析し，LBD が最小の学習節(同じ LBD なら最短の学習節)のみを追加する
+    BestLBD,
+    /// 複数の矛盾を解析し，最短の学習節(同じ長さなら LBD が最小の学習節)のみを追加する
+    BestLength,
+    /// 複数の矛盾を解析し，得られた学習節をすべて追加する
+    All,
+}
+
+#[derive(Clone)]
+pub struct SATSolverOptions {
+    /// 決定変数の選択方法
+    pub decision_heuristic: DecisionHeuristicKind,
+    /// 仮割り当てされた変数を伝播する順序
+    pub propagation_order: PropagationOrder,
+    /// バックジャンプで取り消される決定レベルの数がこの値を超える場合には，時系列バックトラック(1 レベルだけのバックトラック)を行う
+    pub chronological_backtracking_threshold: VariableSize,
+    /// リスタート時に，次に選択される決定変数よりアクティビティの高い決定変数による割り当てを再利用する
+    pub reuse_trail: bool,
+    /// LBD がこの値以下の学習節は削除しない(Core 階層)
+    pub core_lbd_threshold: VariableSize,
+    /// LBD がこの値以下の学習節は使用されている間は削除しない(Mid 階層)
+    pub mid_lbd_threshold: VariableSize,
+    /// Mid 階層の学習節がこの回数の矛盾の間使用されなければ Local 階層に降格する
+    pub mid_tier_unused_limit: usize,
+    /// 学習節の削除を行う間隔(矛盾の回数)
+    pub reduction_interval: usize,
+    /// 学習節の削除を行うたびに削除の間隔をこの値だけ長くする
+    pub reduction_interval_increment: usize,
+    /// 同時に発生している複数の矛盾からの学習方法
+    pub conflict_learning: ConflictLearning,
+    /// 1 回の矛盾で解析する矛盾している変数の最大数(ConflictLearning::Single 以外の場合)
+    pub max_analyzed_conflicts: VariableSize,
+    /// 探索の開始時と位相の再設定(rephase)時に行う局所探索の反転回数の上限(0 なら局所探索を行わない)
+    ///
+    /// 既定では充足不能な問題でも探索時間の増加が小さい回数に抑える．
+    /// 充足可能なランダム・クラフト系の問題では 100000 程度まで増やすと大きく速くなる．
+    pub local_search_flips: usize,
+    /// 位相の再設定を行う間隔(矛盾の回数)．再設定を行うたびにこの値だけ間隔を長くする
+    pub rephase_interval: usize,
+    /// 疑似乱数のシード(同じシードであれば探索は完全に再現される)
+    pub seed: u64,
+    /// 決定変数をランダムに選択する確率
+    pub random_decision_frequency: f64,
+    /// 決定変数の初期の選択順序(アクティビティ)をランダムにする
+    pub shuffle_initial_activity: bool,
+    /// 変数の初期の位相をランダムにする
+    pub randomize_initial_phase: bool,
+    /// 仮割り当てされた変数・矛盾している変数の優先度が同じ場合の順序をランダムにする
+    pub random_tie_breaking: bool,
+}
+
+impl Default for SATSolverOptions {
+    fn default() -> Self {
+        SATSolverOptions {
+            decision_heuristic: DecisionHeuristicKind::Activity,
+            propagation_order: PropagationOrder::Score,
+            chronological_backtracking_threshold: 100,
+            reuse_trail: true,
+            core_lbd_threshold: 2,
+            mid_lbd_threshold: 6,
+            mid_tier_unused_limit: 30000,
+            reduction_interval: 5000,
+            reduction_interval_increment: 100,
+            conflict_learning: ConflictLearning::Single,
+            max_analyzed_conflicts: 8,
+            local_search_flips: 10000,
+            rephase_interval: 10000,
+            seed: 0,
+            random_decision_frequency: 0.0,
+            shuffle_initial_activity: false,
+            randomize_initial_phase: false,
+            random_tie_breaking: false,
+        }
+    }
+}
+
+/// 学習節を通知するコールバック
+type LearnCallback = Box<dyn FnMut(&Array<VariableSize, Literal>)>;
+
 pub struct SATSolver {
+    options: SATSolverOptions,
     variables: Variables,
-    clause_theory: ClauseTheory,
+    theories: Theories,
     analyze: Analyze,
+    calculate_lbd: CalculateLBD,
+    conflicts: Array<VariableSize, (VariableSize, [Reason; 2])>,
+    learnt_clauses: Array<VariableSize, (VariableSize, Array<VariableSize, Literal>)>,
+    local_search: LocalSearch,
+    random: Random,
+    clause_sharing: Option<ClauseSharing>,
+    imported_clauses: Array<VariableSize, (VariableSize, Array<VariableSize, Literal>)>,
+    terminate: Option<Arc<AtomicBool>>,
+    terminate_callback: Option<Box<dyn FnMut() -> bool>>,
+    // 学習節を通知する長さの上限と通知先
+    learn_callback: Option<(VariableSize, LearnCallback)>,
+    assumptions: Array<VariableSize, Literal>,
+    // assumptions[..assumption_cursor] は真が割り当てられている(バックジャンプ時には 0 に戻す)
+    assumption_cursor: VariableSize,
+    failed_assumptions: Array<VariableSize, Literal>,
+    // 決定レベル 0 で偽になる節が追加された
+    is_unsatisfiable: bool,
+    // 末尾の変数のうち解の列挙や remove_last_variables のために確保している変数の数(呼び出し側には見せない)
+    reserved_variable_count: VariableSize,
+    next_rephase_conflict_count: usize,
+    rephase_count: usize,
     conflict_count: usize,
     restart_count: usize,
+    chronological_backtrack_count: usize,
+    // バックジャンプ時に積み直して再度伝播した変数の数
+    repropagated_variable_count: usize,
 }
 
 impl SATSolver {
     #[inline(never)]
     pub fn new() -> Self {
+        Self::with_options(SATSolverOptions::default())
+    }
+
+    #[inline(never)]
+    pub fn with_options(options: SATSolverOptions) -> Self {
         SATSolver {
-            variables: Variables::new(50.0),
-            clause_theory: ClauseTheory::new(100000, 100, 1000),
+            variables: Variables::new(
+                create_decision_heuristic(options.decision_heuristic),
+                options.propagation_order,
+                VariableRandomization {
+                    initial_phase: options.randomize_initial_phase,
+                    initial_order: options.shuffle_initial_activity,
+                    tie_breaking: options.random_tie_breaking,
+                },
+            ),
+            theories: Theories::new(ClauseTheory::new(&options)),
             analyze: Analyze::default(),
+            calculate_lbd: CalculateLBD::default(),
+            conflicts: Array::default(),
+            learnt_clauses: Array::default(),
+            local_search: LocalSearch::new(options.seed, 2.5),
+            random: Random::new(options.seed.wrapping_add(1)),
+            clause_sharing: None,
+            imported_clauses: Array::default(),
+            terminate: None,
+            terminate_callback: None,
+            learn_callback: None,
+            assumptions: Array::default(),
+            assumption_cursor: 0,
+            failed_assumptions: Array::default(),
+            is_unsatisfiable: false,
+            reserved_variable_count: 0,
+            next_rephase_conflict_count: 0usize,
+            rephase_count: 0usize,
             conflict_count: 0usize,
             restart_count: 0usize,
+            chronological_backtrack_count: 0usize,
+            repropagated_variable_count: 0usize,
+            options,
         }
     }
 
+    /// 変数を additional 個追加する
+    ///
+    /// 確保している変数があれば，それを先に割り当てる(いずれも制約のない変数に戻してある)．
     #[inline(never)]
     pub fn expand_variables(&mut self, additional: VariableSize) {
+        let reused = additional.min(self.reserved_variable_count);
+        self.reserved_variable_count -= reused;
+        self.expand_dimension(additional - reused);
+    }
+
+    /// 末尾の count 個の変数を含む制約を(学習した制約も含めて)すべて取り除き，それらの変数を確保し直す
+    ///
+    /// 取り除いた変数は呼び出し側には見せず，expand_variables で再び割り当てる．
+    /// 取り除く変数は決定レベル 0 で割り当てられてはならない(否定を仮定して制約を有効にするだけの変数など)．
+    #[inline(never)]
+    pub fn remove_last_variables(&mut self, count: VariableSize) {
+        if self.variables.current_decision_level() > 0 {
+            self.backjump(0);
+        }
+        let number_of_variables = self.number_of_variables();
+        let variable_indices: Vec<VariableSize> = (number_of_variables - count..number_of_variables).collect();
+        self.theories.remove_constraints_with_variables(&variable_indices, &self.variables);
+        debug_assert!(variable_indices.iter().all(|variable_index| !self.variables.get(*variable_index).is_assigned()));
+        self.reserved_variable_count += count;
+    }
+
+    fn expand_dimension(&mut self, additional: VariableSize) {
         if additional == 0 {
             return;
         }
-        self.variables.redimension(self.variables.dimension() + additional);
-        self.clause_theory.expand(additional);
+        self.variables.redimension(self.variables.dimension() + additional, &mut self.random);
+        self.theories.expand(additional);
+    }
+
+    /// 他のソルバーと学習節を共有する
+    pub fn set_clause_sharing(&mut self, clause_sharing: ClauseSharing) {
+        self.clause_sharing = Some(clause_sharing);
+    }
+
+    /// flag が true になったら探索を中断して SATSolverResult::Unknown を返す
+    pub fn set_terminate_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.terminate = Some(flag);
+    }
+
+    /// 探索中に繰り返し callback を呼び，true が返されたら探索を中断して SATSolverResult::Unknown を返す(None なら解除)
+    pub fn set_terminate_callback(&mut self, callback: Option<Box<dyn FnMut() -> bool>>) {
+        self.terminate_callback = callback;
+    }
+
+    /// 長さが max_length 以下の学習節が得られるたびに callback を呼ぶ(None なら解除)
+    pub fn set_learn_callback(&mut self, max_length: VariableSize, callback: Option<LearnCallback>) {
+        self.learn_callback = callback.map(|callback| (max_length, callback));
+    }
+
+    /// 外部の伝播器を探索に参加させる
+    ///
+    /// 決定レベル 0 に戻り，決定レベル 0 で割り当てられている変数を通知してから登録する．
+    pub fn set_user_propagator(&mut self, mut user_propagator: Box<dyn UserPropagator>) {
+        if self.variables.current_decision_level() > 0 {
+            self.backjump(0);
+        }
+        for position in 0..self.variables.number_of_assigned_variables() {
+            let variable_index = self.variables.assigned_variable(position);
+            let VariableState::Assigned { assigned_value, .. } = *self.variables.get(variable_index) else {
+                unreachable!();
+            };
+            user_propagator.notify_assignment(Literal { index: variable_index, sign: assigned_value }, 0);
+        }
+        self.theories.user_theory = Some(UserTheory::new(user_propagator));
+    }
+
+    /// 外部の伝播器を探索から外して返す(決定レベル 0 に戻ってから外す)
+    pub fn take_user_propagator(&mut self) -> Option<Box<dyn UserPropagator>> {
+        if self.variables.current_decision_level() > 0 {
+            self.backjump(0);
+        }
+        self.theories.user_theory.take().map(UserTheory::into_user_propagator)
+    }
+
+    #[inline(never)]
+    pub fn number_of_variables(&self) -> VariableSize {
+        self.variables.dimension() - self.reserved_variable_count
+    }
+
+    /// 変数に割り当てられている値(未割り当てであれば None)
+    #[inline(always)]
+    pub fn value(&self, variable_index: VariableSize) -> Option<bool> {
+        match self.variables.get(variable_index) {
+            VariableState::Assigned { assigned_value, .. } => Some(*assigned_value),
+            _ => None,
+        }
     }
 
     #[inline(never)]
@@ -57,41 +308,367 @@ impl SATSolver {
         // println!("@add_clause");
         // 必要に応じて変数の次元を拡張
         let required_variable_dimension = literals.iter().map(|l| l.index + 1).max().unwrap_or(0);
-        if required_variable_dimension > self.variables.dimension() {
-            self.expand_variables(required_variable_dimension - self.variables.dimension());
+        if required_variable_dimension > self.number_of_variables() {
+            self.expand_variables(required_variable_dimension - self.number_of_variables());
+        }
+        // 前回の求解の割り当てが残っていれば決定レベル 0 に戻す
+        if self.variables.current_decision_level() > 0 {
+            self.backjump(0);
+        }
+
+        let mut literals = literals.clone();
+        if !self.remove_fixed_literals(&mut literals) {
+            return;
+        }
+        if literals.is_empty() {
+            self.is_unsatisfiable = true;
+            return;
+        }
+        self.theories.clause_theory.add_clause(literals, false, &mut self.variables);
+    }
+
+    /// XOR 制約 (variable_indices の変数の排他的論理和が parity に等しい) を追加する
+    #[inline(never)]
+    pub fn add_xor(&mut self, variable_indices: &[VariableSize], parity: bool) {
+        let required_variable_dimension = variable_indices.iter().map(|i| i + 1).max().unwrap_or(0);
+        if required_variable_dimension > self.number_of_variables() {
+            self.expand_variables(required_variable_dimension - self.number_of_variables());
+        }
+        if self.variables.current_decision_level() > 0 {
+            self.backjump(0);
+        }
+        self.theories.get_or_add(XORTheory::new).add_xor(variable_indices, parity);
+    }
+
+    /// 基数制約 sum(literals) <= k を追加する(literals の変数は互いに異なること)
+    #[inline(never)]
+    pub fn add_at_most(&mut self, literals: &[Literal], k: VariableSize) {
+        debug_assert!({
+            let mut variable_indices: Vec<VariableSize> = literals.iter().map(|literal| literal.index).collect();
+            variable_indices.sort_unstable();
+            variable_indices.windows(2).all(|pair| pair[0] != pair[1])
+        });
+        let required_variable_dimension = literals.iter().map(|l| l.index + 1).max().unwrap_or(0);
+        if required_variable_dimension > self.number_of_variables() {
+            self.expand_variables(required_variable_dimension - self.number_of_variables());
+        }
+        if self.variables.current_decision_level() > 0 {
+            self.backjump(0);
         }
+        // 決定レベル 0 で割り当て済みのリテラルは取り除く(真のリテラルの分だけ k を減らす)
+        let mut unfixed_literals = Array::default();
+        let mut bound = k as i64;
+        for literal in literals.iter() {
+            if self.variables.get(literal.index).is_value_fixed(literal.sign) {
+                bound -= 1;
+            } else if !self.variables.get(literal.index).is_value_fixed(!literal.sign) {
+                unfixed_literals.push(*literal);
+            }
+        }
+        if bound < 0 {
+            self.is_unsatisfiable = true;
+        } else if bound >= unfixed_literals.len() as i64 {
+            // 常に充足される
+        } else if bound == 0 {
+            for literal in unfixed_literals.iter() {
+                let mut clause = Array::default();
+                clause.push(!*literal);
+                self.add_clause(&clause);
+            }
+        } else if bound == unfixed_literals.len() as i64 - 1 {
+            let mut clause = Array::default();
+            for literal in unfixed_literals.iter() {
+                clause.push(!*literal);
+            }
+            self.add_clause(&clause);
+        } else {
+            self.theories.get_or_add(CardinalityTheory::new).add_at_most(unfixed_literals, bound as VariableSize);
+        }
+    }
 
-        self.clause_theory.add_clause(literals.clone(), false, &mut self.variables);
+    /// 問題の節と XOR 制約をすべて追加する
+    #[inline(never)]
+    pub fn add_problem(&mut self, problem: &SATProblem) {
+        if problem.number_of_variables > self.number_of_variables() {
+            self.expand_variables(problem.number_of_variables - self.number_of_variables());
+        }
+        for clause in problem.clauses.iter() {
+            self.add_clause(clause);
+        }
+        for (variable_indices, parity) in problem.xors.iter() {
+            self.add_xor(variable_indices.as_slice(), *parity);
+        }
     }
 
     #[inline(never)]
     pub fn solve(&mut self) -> SATSolverResult {
+        self.solve_with_assumptions(&[])
+    }
+
+    /// assumptions のリテラルをすべて真とする解を求める
+    ///
+    /// 充足不可能な場合には，その原因となった仮定を failed_assumptions で取得できる(空であれば仮定によらず充足不可能)．
+    /// 追加した節と学習節は次の呼び出しでもそのまま使用される．
+    #[inline(never)]
+    pub fn solve_with_assumptions(&mut self, assumptions: &[Literal]) -> SATSolverResult {
+        self.failed_assumptions.clear();
+        self.assumptions.clear();
+        for literal in assumptions.iter() {
+            self.assumptions.push(*literal);
+        }
+        let required_variable_dimension = assumptions.iter().map(|l| l.index + 1).max().unwrap_or(0);
+        if required_variable_dimension > self.number_of_variables() {
+            self.expand_variables(required_variable_dimension - self.number_of_variables());
+        }
+        if self.variables.current_decision_level() > 0 {
+            self.backjump(0);
+        }
+        self.assumption_cursor = 0;
+        if !self.rebuild_theories() {
+            return SATSolverResult::Unsatisfiable;
+        }
         let search_result = self.search();
         match search_result {
             SearchResult::Satisfiable => {
                 let mut solution = Array::default();
-                for variable_index in 0..self.variables.dimension() {
+                for variable_index in 0..self.number_of_variables() {
                     if let VariableState::Assigned { assigned_value, .. } = self.variables.get(variable_index) {
                         solution.push(*assigned_value);
                     } else {
                         unreachable!();
                     }
                 }
-                return SATSolverResult::Satisfiable { solution: solution };
+                SATSolverResult::Satisfiable { solution }
+            }
+            SearchResult::SatisfiableByLocalSearch => {
+                let mut solution = self.local_search.best_assignment().clone();
+                solution.resize(self.number_of_variables(), false);
+                SATSolverResult::Satisfiable { solution }
             }
             SearchResult::Unsatisfiable => {
-                return SATSolverResult::Unsatisfiable;
-            } // SearchResult::Undefined => {
-              //     unreachable!();
-              // }
+                // 仮定によらず充足不可能であれば以降の呼び出しでも充足不可能
+                if self.failed_assumptions.is_empty() {
+                    self.is_unsatisfiable = true;
+                }
+                SATSolverResult::Unsatisfiable
+            }
+            SearchResult::Interrupted => SATSolverResult::Unknown,
+            // SearchResult::Undefined => {
+            //     unreachable!();
+            // }
+        }
+    }
+
+    /// 直前の solve_with_assumptions で充足不可能となった原因の仮定
+    #[inline(always)]
+    pub fn failed_assumptions(&self) -> &Array<VariableSize, Literal> {
+        &self.failed_assumptions
+    }
+
+    /// projection の変数への射影が異なる解を順に求め，見つかるたびに callback を呼ぶ
+    ///
+    /// 解は最大 limit 個まで求め(None なら制限なし)，見つけた解の数を返す．
+    /// 求めた解を禁止する節は選択用の 2 つの変数 s, t を加えた節 (¬s ∨ ¬t ∨ ...) として追加して s, t を仮定して解く．
+    /// s, t は負のリテラルとしてしか現れないので，これらの節から導かれる学習節も ¬s と ¬t をともに含み，
+    /// s, t が決定レベル 0 で割り当てられることはない．終了時に s, t を含む節をすべて削除して制約のない変数に戻し，
+    /// 次の列挙で再利用する(呼び出し側には見せず，expand_variables で変数を追加するときに先に割り当てる)．
+    #[inline(never)]
+    pub fn enumerate_models<F>(&mut self, projection: &[VariableSize], limit: Option<usize>, callback: F) -> usize
+    where
+        F: FnMut(&Array<VariableSize, bool>),
+    {
+        self.enumerate_models_with_assumptions(&[], projection, limit, callback)
+    }
+
+    /// assumptions のリテラルをすべて真とする解に限って enumerate_models と同様に解を列挙する
+    ///
+    /// 解の射影を含意する決定変数が仮定と projection の変数だけであれば，禁止する節はそれらの決定の否定にする
+    /// (決定が同じ解は射影も同じなので，射影の異なる解を取り除くことはない)．
+    /// そうでなければ projection のすべての変数の値の否定にする．
+    #[inline(never)]
+    pub fn enumerate_models_with_assumptions<F>(
+        &mut self,
+        assumptions: &[Literal],
+        projection: &[VariableSize],
+        limit: Option<usize>,
+        mut callback: F,
+    ) -> usize
+    where
+        F: FnMut(&Array<VariableSize, bool>),
+    {
+        let required_variable_dimension = projection.iter().map(|variable_index| variable_index + 1).max().unwrap_or(0);
+        if required_variable_dimension > self.number_of_variables() {
+            self.expand_variables(required_variable_dimension - self.number_of_variables());
+        }
+        // 末尾に確保している変数を選択用の変数にする(足りなければ追加する)
+        if self.reserved_variable_count < 2 {
+            self.expand_dimension(2 - self.reserved_variable_count);
+            self.reserved_variable_count = 2;
+        }
+        let number_of_variables = self.number_of_variables();
+        let reserved_variable_count = self.reserved_variable_count;
+        self.reserved_variable_count = 0;
+        let dimension = self.variables.dimension() - 2;
+        let selectors = [Literal { sign: true, index: dimension }, Literal { sign: true, index: dimension + 1 }];
+        let mut selectors_and_assumptions = selectors.to_vec();
+        selectors_and_assumptions.extend_from_slice(assumptions);
+        let mut is_projected: Array<VariableSize, bool> = Array::default();
+        is_projected.resize(dimension, false);
+        for variable_index in projection.iter() {
+            is_projected[*variable_index] = true;
+        }
+        let mut projected_literals = Vec::with_capacity(projection.len());
+        let mut decisions = Array::default();
+        let mut model_count = 0;
+        while limit.is_none_or(|limit| model_count < limit) {
+            let SATSolverResult::Satisfiable { mut solution } = self.solve_with_assumptions(&selectors_and_assumptions)
+            else {
+                break;
+            };
+            model_count += 1;
+            // 選択用の変数や確保している変数は呼び出し側には見せない
+            solution.resize(number_of_variables, false);
+            callback(&solution);
+            projected_literals.clear();
+            projected_literals.extend(
+                projection
+                    .iter()
+                    .map(|variable_index| Literal { sign: solution[*variable_index], index: *variable_index }),
+            );
+            // 局所探索で見つけた解では割り当ての理由を辿れない
+            let is_decision_blocking = self.variables.number_of_assigned_variables() == self.variables.dimension() && {
+                self.analyze.collect_decisions(&projected_literals, &self.variables, &self.theories, &mut decisions);
+                decisions
+                    .iter()
+                    .all(|literal| selectors_and_assumptions.contains(literal) || is_projected[literal.index])
+            };
+            let mut blocking_clause = Array::default();
+            for selector in selectors.iter() {
+                blocking_clause.push(!*selector);
+            }
+            if is_decision_blocking {
+                for literal in decisions.iter().filter(|literal| !selectors_and_assumptions.contains(literal)) {
+                    blocking_clause.push(!*literal);
+                }
+            } else {
+                for literal in projected_literals.iter() {
+                    blocking_clause.push(!*literal);
+                }
+            }
+            self.add_clause(&blocking_clause);
+        }
+        if self.variables.current_decision_level() > 0 {
+            self.backjump(0);
+        }
+        self.theories.clause_theory.remove_clauses_with_variables(&[dimension, dimension + 1], &self.variables);
+        debug_assert!(selectors.iter().all(|selector| !self.variables.get(selector.index).is_assigned()));
+        self.reserved_variable_count = reserved_variable_count;
+        model_count
+    }
+
+    /// variable_indices の変数のリテラルのうち，すべての解で真になるもの(バックボーン)を求める
+    ///
+    /// 最初の解のリテラルを候補とし，候補の否定を仮定して解く．充足不能であればバックボーンとして単位節を追加し，
+    /// 解が見つかればその解で偽になる候補を取り除く．同じソルバーで解き続けるので学習節は呼び出し間で再利用される．
+    /// バックボーンとともに解いた回数を返し，充足不能な場合や中断された場合には None を返す．
+    #[inline(never)]
+    pub fn backbone(&mut self, variable_indices: &[VariableSize]) -> Option<(Array<VariableSize, Literal>, usize)> {
+        let required_variable_dimension =
+            variable_indices.iter().map(|variable_index| variable_index + 1).max().unwrap_or(0);
+        if required_variable_dimension > self.number_of_variables() {
+            self.expand_variables(required_variable_dimension - self.number_of_variables());
+        }
+        let SATSolverResult::Satisfiable { solution } = self.solve() else {
+            return None;
+        };
+        let mut candidates: Vec<Literal> = variable_indices
+            .iter()
+            .map(|variable_index| Literal { sign: solution[*variable_index], index: *variable_index })
+            .collect();
+        let mut backbone = Array::default();
+        let mut solve_count = 1;
+        while let Some(candidate) = candidates.pop() {
+            solve_count += 1;
+            match self.solve_with_assumptions(&[!candidate]) {
+                SATSolverResult::Satisfiable { solution } => {
+                    candidates.retain(|literal| solution[literal.index] == literal.sign);
+                }
+                SATSolverResult::Unsatisfiable => {
+                    backbone.push(candidate);
+                    let mut clause = Array::default();
+                    clause.push(candidate);
+                    self.add_clause(&clause);
+                }
+                SATSolverResult::Unknown => {
+                    return None;
+                }
+            }
+        }
+        Some((backbone, solve_count))
+    }
+
+    /// 決定レベル 0 から cube のリテラルを順に決定変数として割り当てて伝播する
+    ///
+    /// 矛盾が生じた場合には false を返す．先読みによるキューブの生成に使用する．
+    #[inline(never)]
+    pub fn propagate_cube(&mut self, cube: &[Literal]) -> bool {
+        if self.variables.current_decision_level() > 0 {
+            self.backjump(0);
+        }
+        if !self.rebuild_theories() {
+            return false;
+        }
+        if !matches!(self.propagate(), PropagationResult::Consistent) {
+            return false;
+        }
+        for literal in cube.iter() {
+            match self.value(literal.index) {
+                Some(value) if value == literal.sign => continue,
+                Some(_) => return false,
+                None => {}
+            }
+            self.variables.tentatively_assign(literal.index, literal.sign, Reason::Decision);
+            if !matches!(self.propagate(), PropagationResult::Consistent) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// 現在の割り当てに literal を決定変数として加えて伝播し，新たに割り当てられた変数の数を返す
+    ///
+    /// 矛盾が生じた場合には None を返す．いずれの場合も割り当ては呼び出し前の状態に戻す．
+    #[inline(never)]
+    pub fn probe(&mut self, literal: Literal) -> Option<VariableSize> {
+        debug_assert!(self.value(literal.index).is_none());
+        let decision_level = self.variables.current_decision_level();
+        let number_of_assigned_variables = self.variables.number_of_assigned_variables();
+        self.variables.tentatively_assign(literal.index, literal.sign, Reason::Decision);
+        let propagation_result = self.propagate();
+        let number_of_propagated_variables =
+            self.variables.number_of_assigned_variables() - number_of_assigned_variables;
+        self.backjump(decision_level);
+        match propagation_result {
+            PropagationResult::Consistent => Some(number_of_propagated_variables),
+            PropagationResult::Conflict { .. } | PropagationResult::ExternalConflict { .. } => None,
         }
     }
 
     #[inline(never)]
     fn search(&mut self) -> SearchResult {
         loop {
+            if let Some(terminate) = &self.terminate {
+                if terminate.load(Ordering::Relaxed) {
+                    return SearchResult::Interrupted;
+                }
+            }
+            if let Some(terminate_callback) = &mut self.terminate_callback {
+                if terminate_callback() {
+                    return SearchResult::Interrupted;
+                }
+            }
             let propagation_result = self.propagate();
-            if let PropagationResult::Conflict { variable_index, reasons } = propagation_result {
+            if !matches!(propagation_result, PropagationResult::Consistent) {
                 // 矛盾を検知した場合
                 self.conflict_count += 1;
                 // 決定レベル 0 での矛盾であれば充足不可能
@@ -99,32 +676,77 @@ impl SATSolver {
                     return SearchResult::Unsatisfiable;
                 }
                 // analyze
-                let (backjump_decision_level, learnt_clause) =
-                    self.analyze.analyze(variable_index, reasons, &mut self.variables, &mut self.clause_theory);
                 // 長さ 0 の学習節が得られたら充足不可能
-                if learnt_clause.len() == 0 {
+                if !self.analyze_conflicts(propagation_result) {
                     return SearchResult::Unsatisfiable;
                 }
-                // 伝播可能な決定レベルまでバックジャンプ
-                self.backjump(backjump_decision_level);
-                // 学習節を追加
-                self.clause_theory.add_clause(learnt_clause, true, &mut self.variables);
+                // 学習節を他のソルバーと共有
+                if self.clause_sharing.is_some() {
+                    self.export_learnt_clauses();
+                }
+                if let Some((max_length, learn_callback)) = &mut self.learn_callback {
+                    for (_, learnt_clause) in self.learnt_clauses.iter() {
+                        if learnt_clause.len() <= *max_length {
+                            learn_callback(learnt_clause);
+                        }
+                    }
+                }
+                // 学習節ごとに伝播可能な決定レベルまでバックジャンプして学習節を追加
+                // NOTE: 学習節の LBD を計算できるよう，バックジャンプ先の決定レベルの降順に追加する
+                self.learnt_clauses.sort_by_cached_key(|(backjump_decision_level, ..)| *backjump_decision_level);
+                while let Some((backjump_decision_level, learnt_clause)) = self.learnt_clauses.pop() {
+                    if self.variables.current_decision_level() > backjump_decision_level {
+                        self.backjump(backjump_decision_level);
+                    }
+                    self.theories.clause_theory.add_clause(learnt_clause, true, &mut self.variables);
+                }
                 // 時刻を 1 つ進める(内部でアクティビティの指数平滑化を行っているため)
                 self.variables.advance_time();
-                self.clause_theory.advance_time();
+                self.theories.advance_time();
+            } else if self.assumption_cursor < self.assumptions.len() {
+                // 仮定を順に決定変数として割り当て，偽が割り当てられている仮定があれば充足不可能
+                if !self.decide_assumption() {
+                    return SearchResult::Unsatisfiable;
+                }
             } else if self.variables.number_of_assigned_variables() == self.variables.dimension() {
                 // 未割り当ての変数がなくなれば充足可能
                 return SearchResult::Satisfiable;
-            } else if self.clause_theory.is_request_restart() {
+            } else if self.options.local_search_flips != 0 && self.conflict_count >= self.next_rephase_conflict_count {
+                // 条件を満たしたら局所探索によって位相を再設定(局所探索で解が見つかれば充足可能)
+                if self.rephase() {
+                    return SearchResult::SatisfiableByLocalSearch;
+                }
+            } else if self.theories.is_request_reduction() {
+                // 条件を満たしたら学習節などを削除
+                self.theories.reduce(&self.variables);
+            } else if self.theories.is_request_restart() {
                 // 条件を満たしたらリスタート
-                if self.variables.current_decision_level() != 0 {
-                    self.backjump(0);
+                // 共有された学習節があれば決定レベル 0 に戻って追加
+                let has_shared_clauses = match &mut self.clause_sharing {
+                    Some(clause_sharing) => {
+                        clause_sharing.exchange.has_new_clauses(clause_sharing.id, &mut clause_sharing.cursor)
+                    }
+                    None => false,
+                };
+                let restart_decision_level = if self.options.reuse_trail && !has_shared_clauses {
+                    self.variables.reusable_decision_level()
+                } else {
+                    0
+                };
+                if self.variables.current_decision_level() > restart_decision_level {
+                    self.backjump(restart_decision_level);
                 }
-                self.clause_theory.restart(&self.variables);
+                if has_shared_clauses && !self.import_shared_clauses() {
+                    return SearchResult::Unsatisfiable;
+                }
+                self.theories.restart();
                 eprintln!(
-                    "restart_count={} conflict_count={} fixed={}",
+                    "restart_count={} conflict_count={} chronological_backtrack_count={} repropagated_variable_count={} reused_decision_level={} assigned={}",
                     self.restart_count,
                     self.conflict_count,
+                    self.chronological_backtrack_count,
+                    self.repropagated_variable_count,
+                    restart_decision_level.min(self.variables.current_decision_level()),
                     self.variables.number_of_assigned_variables(),
                 );
                 self.restart_count += 1;
@@ -135,22 +757,298 @@ impl SATSolver {
         }
     }
 
+    /// 追加された制約を各理論に取り込ませる(決定レベル 0 で呼ぶ)
+    ///
+    /// 充足不可能であることが分かっていれば false を返す．
+    #[inline(never)]
+    fn rebuild_theories(&mut self) -> bool {
+        debug_assert!(self.variables.current_decision_level() == 0);
+        if !self.is_unsatisfiable && !self.theories.rebuild(&mut self.variables) {
+            self.is_unsatisfiable = true;
+        }
+        !self.is_unsatisfiable
+    }
+
     #[inline(never)]
     pub fn summary(&self) -> (usize, usize, usize, usize, usize, usize) {
         // TODO: 各種サマリを返せるようにしたい & 計算途中にコールバック関数でも返せるようにしたい
-        let s = self.clause_theory.summary();
+        let s = self.theories.clause_theory.summary();
         (s.0, s.1, s.2, s.3, self.conflict_count, self.restart_count)
     }
 
+    /// XOR 制約の行数，XOR 制約による伝播の回数，行の消去の回数を返す
+    pub fn xor_summary(&self) -> (usize, usize, usize) {
+        self.theories.get::<XORTheory>().map_or((0, 0, 0), XORTheory::summary)
+    }
+
+    /// 基数制約の数と基数制約による伝播の回数を返す
+    pub fn cardinality_summary(&self) -> (usize, usize) {
+        self.theories.get::<CardinalityTheory>().map_or((0, 0), CardinalityTheory::summary)
+    }
+
+    /// 共有した学習節の数と共有された学習節の数を返す
+    pub fn sharing_summary(&self) -> (usize, usize) {
+        match &self.clause_sharing {
+            Some(clause_sharing) => (clause_sharing.exported_count, clause_sharing.imported_count),
+            None => (0, 0),
+        }
+    }
+
+    /// 伝播順序とその統計情報を返す
+    #[inline(never)]
+    pub fn propagation_summary(&self) -> (PropagationOrder, PropagationStatistics) {
+        (self.variables.propagation_order(), self.variables.propagation_statistics())
+    }
+
+    /// learnt_clauses のうち単位節と LBD の小さい学習節を共有する(バックジャンプ前に呼ぶ)
+    #[inline(never)]
+    fn export_learnt_clauses(&mut self) {
+        let Some(clause_sharing) = &mut self.clause_sharing else {
+            return;
+        };
+        for (_, learnt_clause) in self.learnt_clauses.iter() {
+            let lbd = self.calculate_lbd.calculate(learnt_clause, &self.variables);
+            if (learnt_clause.len() == 1 || lbd <= clause_sharing.export_lbd_threshold)
+                && clause_sharing.exchange.export(clause_sharing.id, learnt_clause, lbd)
+            {
+                clause_sharing.exported_count += 1;
+            }
+        }
+    }
+
+    /// 他のソルバーから共有された学習節を追加する(決定レベル 0 で呼ぶ)
+    ///
+    /// 決定レベル 0 で充足されている節は追加せず，偽のリテラルは取り除いて追加する．
+    /// 空の節が得られた場合には false を返す．
+    #[inline(never)]
+    fn import_shared_clauses(&mut self) -> bool {
+        debug_assert!(self.variables.current_decision_level() == 0);
+        let Some(clause_sharing) = &mut self.clause_sharing else {
+            return true;
+        };
+        self.imported_clauses.clear();
+        clause_sharing.exchange.import(clause_sharing.id, &mut clause_sharing.cursor, &mut self.imported_clauses);
+        clause_sharing.imported_count += self.imported_clauses.len() as usize;
+        while let Some((lbd, mut literals)) = self.imported_clauses.pop() {
+            debug_assert!(literals.iter().all(|literal| literal.index < self.variables.dimension()));
+            if !self.remove_fixed_literals(&mut literals) {
+                continue;
+            }
+            if literals.is_empty() {
+                return false;
+            }
+            self.theories.clause_theory.add_imported_clause(literals, lbd, &mut self.variables);
+        }
+        true
+    }
+
+    /// 決定レベル 0 に戻って局所探索を行い，最良の割り当てを位相として保存する
+    ///
+    /// 局所探索で非学習節とすべての理論の制約を充足する割り当てが見つかった場合には true を返す．
+    #[inline(never)]
+    fn rephase(&mut self) -> bool {
+        if self.variables.current_decision_level() > 0 {
+            self.backjump(0);
+        }
+        let result =
+            self.local_search.search(&self.theories.clause_theory, &self.variables, self.options.local_search_flips);
+        eprintln!(
+            "rephase_count={} conflict_count={} local_search_unsatisfied={:?} local_search_flip_count={}",
+            self.rephase_count,
+            self.conflict_count,
+            result,
+            self.local_search.flip_count(),
+        );
+        self.rephase_count += 1;
+        self.next_rephase_conflict_count = self.conflict_count + self.options.rephase_interval * self.rephase_count;
+        match result {
+            Some(0)
+                if self
+                    .assumptions
+                    .iter()
+                    .all(|literal| self.local_search.best_assignment()[literal.index] == literal.sign)
+                    && self.theories.is_satisfied_by(self.local_search.best_assignment()) =>
+            {
+                true
+            }
+            Some(_) => {
+                for variable_index in 0..self.variables.dimension() {
+                    let value = self.local_search.best_assignment()[variable_index];
+                    self.variables.save_phase(variable_index, value);
+                }
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// 矛盾を解析して学習節とそのバックジャンプ先の決定レベルを learnt_clauses に格納する
+    ///
+    /// ConflictLearning::Single 以外の場合には，同時に発生している他の矛盾も最大 max_analyzed_conflicts 個まで解析する．
+    /// 外部の伝播器が返した矛盾の節は単独で解析する．長さ 0 の学習節が得られた場合には false を返す．
+    #[inline(never)]
+    fn analyze_conflicts(&mut self, conflict: PropagationResult) -> bool {
+        let (variable_index, reasons) = match conflict {
+            PropagationResult::Conflict { variable_index, reasons } => (variable_index, reasons),
+            PropagationResult::ExternalConflict { clause } => {
+                self.learnt_clauses.clear();
+                let (conflict_decision_level, backjump_decision_level, learnt_clause) =
+                    self.analyze.analyze_clause(&clause, &mut self.variables, &self.theories);
+                if learnt_clause.is_empty() {
+                    return false;
+                }
+                let backjump_decision_level =
+                    self.limit_backjump_decision_level(conflict_decision_level, backjump_decision_level);
+                self.learnt_clauses.push((backjump_decision_level, learnt_clause));
+                return true;
+            }
+            PropagationResult::Consistent => unreachable!(),
+        };
+        // 解析する矛盾を収集
+        self.conflicts.clear();
+        self.conflicts.push((variable_index, reasons));
+        if self.options.conflict_learning != ConflictLearning::Single {
+            for (other_variable_index, variable_state) in self.variables.conflicting_variables() {
+                if self.conflicts.len() >= self.options.max_analyzed_conflicts {
+                    break;
+                }
+                if other_variable_index == variable_index {
+                    continue;
+                }
+                let VariableState::Conflicting { reasons: other_reasons, .. } = variable_state else {
+                    unsafe {
+                        unreachable_unchecked();
+                    }
+                };
+                self.conflicts.push((other_variable_index, *other_reasons));
+            }
+            // 追加で解析する矛盾の節のアクティビティを増大
+            for k in 1..self.conflicts.len() {
+                for reason in self.conflicts[k].1.iter() {
+                    self.theories.increase_activity(*reason);
+                }
+            }
+        }
+        // 各矛盾を解析
+        self.learnt_clauses.clear();
+        let mut best_key = (VariableSize::MAX, VariableSize::MAX);
+        for k in 0..self.conflicts.len() {
+            let (variable_index, reasons) = self.conflicts[k];
+            let (conflict_decision_level, backjump_decision_level, learnt_clause) =
+                self.analyze.analyze(variable_index, reasons, &mut self.variables, &self.theories);
+            if learnt_clause.is_empty() {
+                return false;
+            }
+            let backjump_decision_level =
+                self.limit_backjump_decision_level(conflict_decision_level, backjump_decision_level);
+            // 最良の学習節のみを残す場合には (LBD, 長さ) または (長さ, LBD) で比較
+            let key = match self.options.conflict_learning {
+                ConflictLearning::Single | ConflictLearning::All => {
+                    self.learnt_clauses.push((backjump_decision_level, learnt_clause));
+                    continue;
+                }
+                ConflictLearning::BestLBD => {
+                    (self.calculate_lbd.calculate(&learnt_clause, &self.variables), learnt_clause.len())
+                }
+                ConflictLearning::BestLength => {
+                    (learnt_clause.len(), self.calculate_lbd.calculate(&learnt_clause, &self.variables))
+                }
+            };
+            if key < best_key {
+                best_key = key;
+                self.learnt_clauses.clear();
+                self.learnt_clauses.push((backjump_decision_level, learnt_clause));
+            }
+        }
+        debug_assert!(!self.learnt_clauses.is_empty());
+        true
+    }
+
+    /// バックジャンプ幅が大きすぎる場合には矛盾の決定レベルの 1 つ下までの時系列バックトラックにとどめる
+    #[inline(always)]
+    fn limit_backjump_decision_level(
+        &mut self,
+        conflict_decision_level: VariableSize,
+        backjump_decision_level: VariableSize,
+    ) -> VariableSize {
+        if conflict_decision_level - backjump_decision_level > self.options.chronological_backtracking_threshold {
+            self.chronological_backtrack_count += 1;
+            conflict_decision_level - 1
+        } else {
+            backjump_decision_level
+        }
+    }
+
+    /// 真が割り当てられていない最初の仮定を決定変数として割り当てる
+    ///
+    /// 偽が割り当てられている仮定があれば，その原因となった仮定を failed_assumptions に格納して false を返す．
+    #[inline(never)]
+    fn decide_assumption(&mut self) -> bool {
+        while self.assumption_cursor < self.assumptions.len() {
+            let literal = self.assumptions[self.assumption_cursor];
+            match self.value(literal.index) {
+                Some(value) if value == literal.sign => {
+                    self.assumption_cursor += 1;
+                }
+                Some(_) => {
+                    self.analyze.analyze_final(literal, &self.variables, &self.theories, &mut self.failed_assumptions);
+                    return false;
+                }
+                None => {
+                    self.variables.tentatively_assign(literal.index, literal.sign, Reason::Decision);
+                    return true;
+                }
+            }
+        }
+        true
+    }
+
+    /// 決定レベル 0 で真のリテラルを含む節であれば false を返し，そうでなければ決定レベル 0 で偽のリテラルを取り除く
+    #[inline(never)]
+    fn remove_fixed_literals(&self, literals: &mut Array<VariableSize, Literal>) -> bool {
+        if literals.iter().any(|literal| self.variables.get(literal.index).is_value_fixed(literal.sign)) {
+            return false;
+        }
+        let mut k = 0;
+        while k < literals.len() {
+            if self.variables.get(literals[k].index).is_value_fixed(!literals[k].sign) {
+                literals.swap_remove(k);
+            } else {
+                k += 1;
+            }
+        }
+        true
+    }
+
     #[inline(never)]
     fn decide(&mut self) {
         // println!("@decide");
         assert!(self.variables.number_of_unassigned_variables() != 0);
         assert!(self.variables.number_of_tentative_assigned_variables() == 0);
 
+        // 理論が未割り当ての変数のリテラルを返せばそれを決定変数とする
+        if let Some(literal) = self.theories.decide() {
+            if let VariableState::Unassigned { .. } = self.variables.get(literal.index) {
+                self.variables.tentatively_assign(literal.index, literal.sign, Reason::Decision);
+                return;
+            }
+        }
+
+        // 一定の確率で未割り当ての変数をランダムに選択
+        if self.options.random_decision_frequency > 0.0
+            && self.random.next_f64() < self.options.random_decision_frequency
+        {
+            let variable_index = self.random.next_below(self.variables.dimension());
+            if let VariableState::Unassigned { last_assigned_value } = *self.variables.get(variable_index) {
+                self.variables.tentatively_assign(variable_index, last_assigned_value, Reason::Decision);
+                assert!(self.variables.number_of_tentative_assigned_variables() == 1);
+                return;
+            }
+        }
+
         let (variable_index, variable_state) = self.variables.first_unassigned_variable().unwrap();
-        let VariableState::Unassigned { last_assigned_value } = variable_state else { unreachable!() };
-        self.variables.tentatively_assign(variable_index, *last_assigned_value, Reason::Decision);
+        let VariableState::Unassigned { last_assigned_value } = *variable_state else { unreachable!() };
+        self.variables.tentatively_assign(variable_index, last_assigned_value, Reason::Decision);
 
         assert!(self.variables.number_of_tentative_assigned_variables() == 1);
     }
@@ -161,50 +1059,777 @@ impl SATSolver {
         // println!("{} to {}", self.variable_manager.current_decision_level(), backjump_decision_level);
         assert!(backjump_decision_level < self.variables.current_decision_level());
 
-        self.variables.cancel_tentative_assignment();
-        while self.variables.current_decision_level() > backjump_decision_level {
-            self.variables.unassign();
-        }
+        let reassigned_position = self.variables.backtrack(backjump_decision_level);
+        self.assumption_cursor = 0;
+        // 割り当てを維持したまま積み直された変数の割り当てを再度伝播(時系列バックトラック時の伝播漏れを防ぐ)
+        // NOTE: 積み直されるのは現在の決定レベルより小さい決定レベルで伝播された割り当てだけなので，時系列バックトラックを行わなければほとんど生じない
+        self.repropagated_variable_count +=
+            (self.variables.number_of_assigned_variables() - reassigned_position) as usize;
+        self.theories.backtrack(backjump_decision_level, reassigned_position, &mut self.variables);
     }
 
     #[inline(never)]
     fn propagate(&mut self) -> PropagationResult {
         // println!("@propagate");
-        while self.variables.number_of_tentative_assigned_variables() != 0
-            || self.variables.number_of_conflicting_variables() != 0
-        {
-            if self.variables.number_of_conflicting_variables() != 0 {
-                // 矛盾が発生していれば矛盾している変数を 1 つ選んで返す
-                let (variable_index, variable_state) = self.variables.first_conflicting_variable().unwrap();
-                let VariableState::Conflicting { reasons, .. } = variable_state else {
+        loop {
+            while self.variables.number_of_tentative_assigned_variables() != 0
+                || self.variables.number_of_conflicting_variables() != 0
+            {
+                if self.variables.number_of_conflicting_variables() != 0 {
+                    // 矛盾が発生していれば矛盾している変数を 1 つ選んで返す
+                    let (variable_index, variable_state) = self.variables.first_conflicting_variable().unwrap();
+                    let VariableState::Conflicting { reasons, .. } = variable_state else {
+                        unsafe {
+                            unreachable_unchecked();
+                        }
+                    };
+                    for reason in reasons.iter() {
+                        self.theories.increase_activity(*reason);
+                    }
+                    return PropagationResult::Conflict { variable_index, reasons: *reasons };
+                }
+                // 伝播によって仮割り当てされた変数のうち最も優先度の高いものを取り出す
+                let (variable_index, variable_state) = self.variables.first_tentatively_assigned_variable().unwrap();
+                let VariableState::TentativelyAssigned { reason, .. } = variable_state else {
                     unsafe {
                         unreachable_unchecked();
                     }
                 };
-                for reason in reasons.iter() {
-                    if let Reason::Propagation { clause_index, .. } = *reason {
-                        self.clause_theory.increase_activity(clause_index);
-                    }
+                // 理由のアクティビティを増大
+                self.theories.increase_activity(*reason);
+                // 本割り当て
+                self.variables.assign(variable_index);
+                self.theories.propagate(variable_index, &mut self.variables);
+            }
+            // 伝播が尽きたら理論に追加の伝播を問い合わせる
+            if let Err(clause) = self.theories.propagate_at_fixpoint(&mut self.variables) {
+                return PropagationResult::ExternalConflict { clause };
+            }
+            // すべての変数が割り当てられたら理論に解を検査させる
+            if self.variables.number_of_tentative_assigned_variables() == 0
+                && self.variables.number_of_assigned_variables() == self.variables.dimension()
+            {
+                if let Err(clause) = self.theories.check_model(&self.variables) {
+                    return PropagationResult::ExternalConflict { clause };
+                }
+            }
+            if self.variables.number_of_tentative_assigned_variables() == 0 {
+                break;
+            }
+        }
+
+        PropagationResult::Consistent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::{HashMap, HashSet};
+    use std::rc::Rc;
+
+    use super::super::test_helpers::{brute_force, is_solution, random_problem};
+    use super::super::theory::Theory;
+    use super::super::types::ConstraintSize;
+    use super::super::user_propagator::UserPropagation;
+    use super::*;
+
+    #[test]
+    fn enumerate_models_matches_brute_force() {
+        let mut random = Random::new(1);
+        let n = 10;
+        for _ in 0..30 {
+            let problem = random_problem(&mut random, n, 30);
+            let mut solver = SATSolver::new();
+            solver.add_problem(&problem);
+            // 同じソルバーで射影を変えて繰り返し列挙する
+            for projection in [vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9], vec![0, 2, 4, 6], vec![9, 3], vec![]] {
+                let expected = brute_force(&problem, &projection);
+                let mut models = HashSet::new();
+                let count = solver.enumerate_models(&projection, None, |solution| {
+                    assert_eq!(solution.len(), n);
+                    assert!(is_solution(&problem, solution));
+                    assert!(models.insert(projection.iter().map(|variable_index| solution[*variable_index]).collect()));
+                });
+                assert_eq!(count, models.len());
+                assert_eq!(models, expected);
+                assert_eq!(solver.number_of_variables(), n);
+            }
+            // 列挙の後も元の問題を解ける
+            let satisfiable = !brute_force(&problem, &[]).is_empty();
+            assert_eq!(matches!(solver.solve(), SATSolverResult::Satisfiable { .. }), satisfiable);
+        }
+    }
+
+    #[test]
+    fn variables_added_after_enumeration_are_unconstrained() {
+        let mut solver = SATSolver::new();
+        let mut clause = Array::default();
+        clause.push(Literal { sign: true, index: 0 });
+        clause.push(Literal { sign: true, index: 1 });
+        solver.add_clause(&clause);
+        assert_eq!(solver.enumerate_models(&[0, 1], None, |_| {}), 3);
+        assert_eq!(solver.number_of_variables(), 2);
+        solver.expand_variables(3);
+        assert_eq!(solver.number_of_variables(), 5);
+        assert_eq!(solver.enumerate_models(&[0, 1, 2, 3, 4], None, |_| {}), 3 * 8);
+        let SATSolverResult::Satisfiable { solution } = solver.solve() else {
+            panic!();
+        };
+        assert_eq!(solution.len(), 5);
+    }
+
+    #[test]
+    fn backbone_is_intersection_of_models() {
+        let mut random = Random::new(3);
+        let n = 10;
+        for k in 0..40 {
+            let problem = random_problem(&mut random, n, 30 + k % 20);
+            let variable_indices: Vec<VariableSize> = (0..n).filter(|_| random.next_below(4) != 0).collect();
+            let models = brute_force(&problem, &variable_indices);
+            let mut solver = SATSolver::new();
+            solver.add_problem(&problem);
+            let Some((backbone, solve_count)) = solver.backbone(&variable_indices) else {
+                assert!(models.is_empty());
+                continue;
+            };
+            // 候補ごとに高々 1 回解く
+            assert!(solve_count <= variable_indices.len() + 1);
+            // すべての解で同じ値をとる変数のリテラル
+            let expected: HashSet<(VariableSize, bool)> = variable_indices
+                .iter()
+                .enumerate()
+                .filter_map(|(position, variable_index)| {
+                    let value = models.iter().next().unwrap()[position];
+                    models.iter().all(|model| model[position] == value).then_some((*variable_index, value))
+                })
+                .collect();
+            let actual: HashSet<(VariableSize, bool)> =
+                backbone.iter().map(|literal| (literal.index, literal.sign)).collect();
+            assert_eq!(actual.len(), backbone.len() as usize);
+            assert_eq!(actual, expected);
+            // バックボーンの単位節を加えても解は変わらない
+            assert_eq!(solver.enumerate_models(&variable_indices, None, |_| {}), models.len());
+        }
+    }
+
+    #[test]
+    fn learning_from_multiple_conflicts_matches_single_conflict() {
+        let mut random = Random::new(4);
+        for conflict_learning in [ConflictLearning::BestLBD, ConflictLearning::BestLength, ConflictLearning::All] {
+            for max_analyzed_conflicts in [2, 8] {
+                let options =
+                    SATSolverOptions { conflict_learning, max_analyzed_conflicts, ..SATSolverOptions::default() };
+                // 小さな問題では解の列挙が全探索と一致する
+                let n = 10;
+                for _ in 0..10 {
+                    let problem = random_problem(&mut random, n, 30);
+                    let mut solver = SATSolver::with_options(options.clone());
+                    solver.add_problem(&problem);
+                    let projection: Vec<VariableSize> = (0..n).collect();
+                    let expected = brute_force(&problem, &projection);
+                    assert_eq!(solver.enumerate_models(&projection, None, |_| {}), expected.len());
+                }
+                // 節と変数の比が 4.26 前後の問題では充足可能性が ConflictLearning::Single と一致する
+                let n = 80;
+                for _ in 0..5 {
+                    let problem = random_problem(&mut random, n, 341);
+                    let results: Vec<bool> = [SATSolverOptions::default(), options.clone()]
+                        .into_iter()
+                        .map(|options| {
+                            let mut solver = SATSolver::with_options(options);
+                            solver.add_problem(&problem);
+                            match solver.solve() {
+                                SATSolverResult::Satisfiable { solution } => {
+                                    assert!(is_solution(&problem, &solution));
+                                    true
+                                }
+                                SATSolverResult::Unsatisfiable => false,
+                                SATSolverResult::Unknown => panic!(),
+                            }
+                        })
+                        .collect();
+                    assert_eq!(results[0], results[1]);
                 }
-                return PropagationResult::Conflict { variable_index: variable_index, reasons: *reasons };
             }
-            // 伝播によって仮割り当てされた変数のうち最も優先度の高いものを取り出す
-            let (variable_index, variable_state) = self.variables.first_tentatively_assigned_variable().unwrap();
-            let VariableState::TentativelyAssigned { reason, .. } = variable_state else {
-                unsafe {
-                    unreachable_unchecked();
+        }
+    }
+
+    #[test]
+    fn same_seed_reproduces_search() {
+        let mut random = Random::new(5);
+        let n = 100;
+        let problem = random_problem(&mut random, n, 420);
+        let run = |seed: u64| {
+            // NOTE: 局所探索で解が見つかると CDCL の探索を行わないため，局所探索は行わない
+            let options = SATSolverOptions {
+                local_search_flips: 0,
+                seed,
+                random_decision_frequency: 0.05,
+                shuffle_initial_activity: true,
+                randomize_initial_phase: true,
+                random_tie_breaking: true,
+                ..SATSolverOptions::default()
+            };
+            let mut solver = SATSolver::with_options(options);
+            solver.add_problem(&problem);
+            let solution = match solver.solve() {
+                SATSolverResult::Satisfiable { solution } => {
+                    assert!(is_solution(&problem, &solution));
+                    Some(solution.iter().copied().collect::<Vec<_>>())
                 }
+                SATSolverResult::Unsatisfiable => None,
+                SATSolverResult::Unknown => panic!(),
             };
-            // 節のアクティビティを増大
-            if let Reason::Propagation { clause_index, .. } = reason {
-                self.clause_theory.increase_activity(*clause_index);
+            (solver.summary(), solution)
+        };
+        let runs: Vec<_> = (0..4).map(run).collect();
+        // 同じシードであれば探索は完全に再現される
+        for (seed, result) in runs.iter().enumerate() {
+            assert!(run(seed as u64) == *result);
+        }
+        // 充足可能性はシードによらないが，探索はシードによって異なる
+        assert!(runs.iter().all(|result| result.1.is_some() == runs[0].1.is_some()));
+        assert!(runs.iter().any(|result| result.0 != runs[0].0));
+    }
+
+    /// 伝播が尽きた状態の割り当てのスタックの不変条件を確かめる
+    ///
+    /// 時系列バックトラックの後もスタック上の位置と割り当てレベルが一致し，伝播された変数の理由節の他のリテラルは
+    /// それより下で偽が割り当てられていて，problem の節に伝播漏れがない．
+    fn assert_trail_invariants(solver: &SATSolver, problem: &SATProblem) {
+        let variables = &solver.variables;
+        let mut decision_count = 0;
+        for position in 0..variables.number_of_assigned_variables() {
+            let variable_index = variables.assigned_variable(position);
+            let VariableState::Assigned { assigned_value, decision_level, assignment_level, reason } =
+                *variables.get(variable_index)
+            else {
+                panic!("an unassigned variable is on the trail");
+            };
+            assert_eq!(assignment_level, position + 1);
+            assert!(decision_level <= variables.current_decision_level());
+            if let Reason::Decision = reason {
+                // 決定変数は決定レベルの順に積まれている
+                decision_count += 1;
+                assert_eq!(decision_level, decision_count);
+                continue;
+            }
+            let mut clause = Array::default();
+            solver.theories.explain(variable_index, assigned_value, reason, variables, &mut clause);
+            let mut max_decision_level = 0;
+            for literal in clause.iter().filter(|literal| literal.index != variable_index) {
+                let VariableState::Assigned {
+                    assigned_value: other_value,
+                    decision_level: other_decision_level,
+                    assignment_level: other_assignment_level,
+                    ..
+                } = *variables.get(literal.index)
+                else {
+                    panic!("the reason has an unassigned literal");
+                };
+                assert_eq!(other_value, !literal.sign);
+                assert!(other_assignment_level < assignment_level);
+                max_decision_level = max_decision_level.max(other_decision_level);
             }
-            // 本割り当て
-            self.variables.assign(variable_index);
-            // 割り当てを通知
-            self.clause_theory.propagate(variable_index, &mut self.variables);
+            assert_eq!(decision_level, max_decision_level);
         }
+        assert_eq!(decision_count, variables.current_decision_level());
+        for clause in problem.clauses.iter() {
+            let is_satisfied =
+                clause.iter().any(|literal| variables.get(literal.index).is_value_assigned(literal.sign));
+            let unassigned_count = clause.iter().filter(|literal| !variables.get(literal.index).is_assigned()).count();
+            assert!(is_satisfied || unassigned_count >= 2);
+        }
+    }
 
-        PropagationResult::Consistent
+    #[test]
+    fn chronological_backtracking_matches_brute_force() {
+        let mut random = Random::new(6);
+        for chronological_backtracking_threshold in [0, VariableSize::MAX] {
+            let options = SATSolverOptions { chronological_backtracking_threshold, ..SATSolverOptions::default() };
+            let mut chronological_backtrack_count = 0;
+            let n = 12;
+            for k in 0..40 {
+                let problem = random_problem(&mut random, n, 30 + k % 30);
+                let projection: Vec<VariableSize> = (0..n).collect();
+                let expected = brute_force(&problem, &projection);
+                let mut solver = SATSolver::with_options(options.clone());
+                solver.add_problem(&problem);
+                let mut models = HashSet::new();
+                solver.enumerate_models(&projection, None, |solution| {
+                    assert!(is_solution(&problem, solution));
+                    assert!(models.insert(solution.iter().copied().collect::<Vec<_>>()));
+                });
+                assert_eq!(models, expected);
+                chronological_backtrack_count += solver.chronological_backtrack_count;
+            }
+            // 大きな問題では充足可能性が時系列バックトラックを行わない場合と一致する
+            let n = 80;
+            for _ in 0..5 {
+                let problem = random_problem(&mut random, n, 341);
+                let results: Vec<bool> = [SATSolverOptions::default(), options.clone()]
+                    .into_iter()
+                    .map(|options| {
+                        let mut solver = SATSolver::with_options(options);
+                        solver.add_problem(&problem);
+                        let result = match solver.solve() {
+                            SATSolverResult::Satisfiable { solution } => {
+                                assert!(is_solution(&problem, &solution));
+                                true
+                            }
+                            SATSolverResult::Unsatisfiable => false,
+                            SATSolverResult::Unknown => panic!(),
+                        };
+                        chronological_backtrack_count += solver.chronological_backtrack_count;
+                        result
+                    })
+                    .collect();
+                assert_eq!(results[0], results[1]);
+            }
+            assert_eq!(chronological_backtrack_count != 0, chronological_backtracking_threshold == 0);
+        }
+    }
+
+    #[test]
+    fn reusing_trail_does_not_change_results() {
+        let mut random = Random::new(8);
+        let n = 12;
+        for k in 0..40 {
+            let problem = random_problem(&mut random, n, 30 + k % 30);
+            let projection: Vec<VariableSize> = (0..n).collect();
+            let expected = brute_force(&problem, &projection);
+            for reuse_trail in [true, false] {
+                let mut solver =
+                    SATSolver::with_options(SATSolverOptions { reuse_trail, ..SATSolverOptions::default() });
+                solver.add_problem(&problem);
+                let mut models = HashSet::new();
+                solver.enumerate_models(&projection, None, |solution| {
+                    assert!(models.insert(solution.iter().copied().collect::<Vec<_>>()));
+                });
+                assert_eq!(models, expected);
+            }
+        }
+        // リスタートを繰り返す大きな問題でも充足可能性は一致する
+        let n = 150;
+        for _ in 0..6 {
+            let problem = random_problem(&mut random, n, 639);
+            let results: Vec<bool> = [true, false]
+                .into_iter()
+                .map(|reuse_trail| {
+                    let mut solver =
+                        SATSolver::with_options(SATSolverOptions { reuse_trail, ..SATSolverOptions::default() });
+                    solver.add_problem(&problem);
+                    match solver.solve() {
+                        SATSolverResult::Satisfiable { solution } => {
+                            assert!(is_solution(&problem, &solution));
+                            true
+                        }
+                        SATSolverResult::Unsatisfiable => false,
+                        SATSolverResult::Unknown => panic!(),
+                    }
+                })
+                .collect();
+            assert_eq!(results[0], results[1]);
+        }
+    }
+
+    #[test]
+    fn every_decision_heuristic_matches_brute_force() {
+        let kinds = [
+            DecisionHeuristicKind::Activity,
+            DecisionHeuristicKind::Vmtf,
+            DecisionHeuristicKind::Lrb,
+            DecisionHeuristicKind::Chb,
+        ];
+        let mut random = Random::new(9);
+        let n = 12;
+        for k in 0..30 {
+            let problem = random_problem(&mut random, n, 30 + k % 30);
+            let projection: Vec<VariableSize> = (0..n).collect();
+            let expected = brute_force(&problem, &projection);
+            for decision_heuristic in kinds {
+                let mut solver =
+                    SATSolver::with_options(SATSolverOptions { decision_heuristic, ..SATSolverOptions::default() });
+                solver.add_problem(&problem);
+                let mut models = HashSet::new();
+                solver.enumerate_models(&projection, None, |solution| {
+                    assert!(models.insert(solution.iter().copied().collect::<Vec<_>>()));
+                });
+                assert_eq!(models, expected, "{:?}", decision_heuristic);
+            }
+        }
+        // リスタートを繰り返す大きな問題でも充足可能性は一致する
+        let n = 150;
+        for _ in 0..6 {
+            let problem = random_problem(&mut random, n, 639);
+            let results: Vec<bool> = kinds
+                .into_iter()
+                .map(|decision_heuristic| {
+                    let mut solver =
+                        SATSolver::with_options(SATSolverOptions { decision_heuristic, ..SATSolverOptions::default() });
+                    solver.add_problem(&problem);
+                    match solver.solve() {
+                        SATSolverResult::Satisfiable { solution } => {
+                            assert!(is_solution(&problem, &solution), "{:?}", decision_heuristic);
+                            true
+                        }
+                        SATSolverResult::Unsatisfiable => false,
+                        SATSolverResult::Unknown => panic!(),
+                    }
+                })
+                .collect();
+            assert!(results.iter().all(|result| *result == results[0]), "{:?}", results);
+        }
+    }
+
+    #[test]
+    fn every_propagation_order_matches_brute_force() {
+        let orders = [PropagationOrder::FIFO, PropagationOrder::Score, PropagationOrder::LBD, PropagationOrder::Length];
+        let mut random = Random::new(10);
+        let n = 12;
+        for k in 0..30 {
+            let problem = random_problem(&mut random, n, 30 + k % 30);
+            let projection: Vec<VariableSize> = (0..n).collect();
+            let expected = brute_force(&problem, &projection);
+            for propagation_order in orders {
+                let mut solver =
+                    SATSolver::with_options(SATSolverOptions { propagation_order, ..SATSolverOptions::default() });
+                solver.add_problem(&problem);
+                let mut models = HashSet::new();
+                solver.enumerate_models(&projection, None, |solution| {
+                    assert!(models.insert(solution.iter().copied().collect::<Vec<_>>()));
+                });
+                assert_eq!(models, expected, "{:?}", propagation_order);
+            }
+        }
+        // リスタートを繰り返す大きな問題でも充足可能性は一致する
+        let n = 150;
+        for _ in 0..6 {
+            let problem = random_problem(&mut random, n, 639);
+            let results: Vec<bool> = orders
+                .into_iter()
+                .map(|propagation_order| {
+                    let mut solver =
+                        SATSolver::with_options(SATSolverOptions { propagation_order, ..SATSolverOptions::default() });
+                    solver.add_problem(&problem);
+                    match solver.solve() {
+                        SATSolverResult::Satisfiable { solution } => {
+                            assert!(is_solution(&problem, &solution), "{:?}", propagation_order);
+                            true
+                        }
+                        SATSolverResult::Unsatisfiable => false,
+                        SATSolverResult::Unknown => panic!(),
+                    }
+                })
+                .collect();
+            assert!(results.iter().all(|result| *result == results[0]), "{:?}", results);
+        }
+    }
+
+    #[test]
+    fn trail_invariants_hold_after_chronological_backtracking() {
+        let mut random = Random::new(7);
+        let options = SATSolverOptions { chronological_backtracking_threshold: 0, ..SATSolverOptions::default() };
+        let mut repropagated_variable_count = 0;
+        let n = 60;
+        for _ in 0..10 {
+            let problem = random_problem(&mut random, n, 250);
+            let mut solver = SATSolver::with_options(options.clone());
+            solver.add_problem(&problem);
+            assert!(solver.rebuild_theories());
+            // search と同じ手順で矛盾を解析してバックジャンプし，伝播が尽きるたびに不変条件を確かめる
+            let is_satisfiable = loop {
+                let propagation_result = solver.propagate();
+                if let PropagationResult::Consistent = propagation_result {
+                    assert_trail_invariants(&solver, &problem);
+                    if solver.variables.number_of_assigned_variables() == solver.variables.dimension() {
+                        break true;
+                    }
+                    solver.decide();
+                    continue;
+                }
+                if solver.variables.current_decision_level() == 0 || !solver.analyze_conflicts(propagation_result) {
+                    break false;
+                }
+                solver.learnt_clauses.sort_by_cached_key(|(backjump_decision_level, ..)| *backjump_decision_level);
+                while let Some((backjump_decision_level, learnt_clause)) = solver.learnt_clauses.pop() {
+                    if solver.variables.current_decision_level() > backjump_decision_level {
+                        solver.backjump(backjump_decision_level);
+                    }
+                    solver.theories.clause_theory.add_clause(learnt_clause, true, &mut solver.variables);
+                }
+                solver.variables.advance_time();
+                solver.theories.advance_time();
+            };
+            repropagated_variable_count += solver.repropagated_variable_count;
+            let mut other_solver = SATSolver::new();
+            other_solver.add_problem(&problem);
+            assert_eq!(matches!(other_solver.solve(), SATSolverResult::Satisfiable { .. }), is_satisfiable);
+        }
+        // 決定レベルの小さい割り当てが積み直されて再度伝播された
+        assert!(repropagated_variable_count != 0);
+    }
+
+    /// 理論を包んで explain と increase_activity の呼び出しを数え，自身の theory_index の理由でだけ呼ばれることを確かめる理論
+    struct RoutingProbe<T: Theory> {
+        theory: T,
+        theory_index: ConstraintSize,
+        explain_count: Cell<usize>,
+        increase_activity_count: usize,
+    }
+
+    impl<T: Theory> RoutingProbe<T> {
+        fn new(theory: T, theory_index: ConstraintSize) -> Self {
+            RoutingProbe { theory, theory_index, explain_count: Cell::new(0), increase_activity_count: 0 }
+        }
+
+        fn assert_routed(&self, reason: Reason) {
+            let Reason::Theory { theory_index, .. } = reason else {
+                panic!("a reason not of Reason::Theory is routed to a theory");
+            };
+            assert_eq!(theory_index, self.theory_index);
+        }
+    }
+
+    impl<T: Theory> Theory for RoutingProbe<T> {
+        fn expand(&mut self, additional: VariableSize) {
+            self.theory.expand(additional);
+        }
+
+        fn propagate(&mut self, variable_index: VariableSize, variables: &mut Variables) {
+            self.theory.propagate(variable_index, variables);
+        }
+
+        fn propagate_at_fixpoint(&mut self, variables: &mut Variables) -> Result<(), Array<VariableSize, Literal>> {
+            self.theory.propagate_at_fixpoint(variables)
+        }
+
+        fn explain(
+            &self,
+            variable_index: VariableSize,
+            value: bool,
+            reason: Reason,
+            variables: &Variables,
+            clause: &mut Array<VariableSize, Literal>,
+        ) {
+            self.assert_routed(reason);
+            self.explain_count.set(self.explain_count.get() + 1);
+            self.theory.explain(variable_index, value, reason, variables, clause);
+        }
+
+        fn backtrack(
+            &mut self,
+            decision_level: VariableSize,
+            reassigned_position: VariableSize,
+            variables: &mut Variables,
+        ) {
+            self.theory.backtrack(decision_level, reassigned_position, variables);
+        }
+
+        fn increase_activity(&mut self, reason: Reason) {
+            self.assert_routed(reason);
+            self.increase_activity_count += 1;
+            self.theory.increase_activity(reason);
+        }
+
+        fn advance_time(&mut self) {
+            self.theory.advance_time();
+        }
+
+        fn is_request_reduction(&self) -> bool {
+            self.theory.is_request_reduction()
+        }
+
+        fn reduce(&mut self, variables: &Variables) {
+            self.theory.reduce(variables);
+        }
+
+        fn remove_constraints_with_variables(&mut self, variable_indices: &[VariableSize], variables: &Variables) {
+            self.theory.remove_constraints_with_variables(variable_indices, variables);
+        }
+
+        fn rebuild(&mut self, variables: &mut Variables) -> bool {
+            self.theory.rebuild(variables)
+        }
+
+        fn is_request_restart(&self) -> bool {
+            self.theory.is_request_restart()
+        }
+
+        fn restart(&mut self) {
+            self.theory.restart();
+        }
+
+        fn decide(&mut self) -> Option<Literal> {
+            self.theory.decide()
+        }
+
+        fn check_model(&mut self, variables: &Variables) -> Result<(), Array<VariableSize, Literal>> {
+            self.theory.check_model(variables)
+        }
+
+        fn is_satisfied_by(&mut self, assignment: &Array<VariableSize, bool>) -> bool {
+            self.theory.is_satisfied_by(assignment)
+        }
+    }
+
+    /// 節 literals を伝播だけで課す伝播器(explain が自身の伝播したリテラルについてだけ呼ばれることを確かめる)
+    struct ClausePropagator {
+        literals: Vec<Literal>,
+        // 割り当てられている変数の値と決定レベル
+        assignments: HashMap<VariableSize, (bool, VariableSize)>,
+        explain_count: Rc<Cell<usize>>,
+    }
+
+    impl UserPropagator for ClausePropagator {
+        fn notify_assignment(&mut self, literal: Literal, decision_level: VariableSize) {
+            if self.literals.iter().any(|other| other.index == literal.index) {
+                assert!(self.assignments.insert(literal.index, (literal.sign, decision_level)).is_none());
+            }
+        }
+
+        fn notify_backtrack(&mut self, decision_level: VariableSize) {
+            self.assignments.retain(|_, (_, assigned_decision_level)| *assigned_decision_level <= decision_level);
+        }
+
+        fn propagate(&mut self) -> Option<UserPropagation> {
+            let value =
+                |literal: &Literal| self.assignments.get(&literal.index).map(|(value, _)| *value == literal.sign);
+            if self.literals.iter().any(|literal| value(literal) == Some(true)) {
+                return None;
+            }
+            let unassigned: Vec<Literal> =
+                self.literals.iter().copied().filter(|literal| value(literal).is_none()).collect();
+            match unassigned.as_slice() {
+                [] => Some(UserPropagation::Conflict(to_array(&self.literals))),
+                [literal] => Some(UserPropagation::Propagate(*literal)),
+                _ => None,
+            }
+        }
+
+        fn explain(&self, literal: Literal, clause: &mut Array<VariableSize, Literal>) {
+            assert!(self.literals.contains(&literal));
+            for other in self.literals.iter().filter(|other| other.index != literal.index) {
+                assert_eq!(self.assignments.get(&other.index).map(|(value, _)| *value), Some(!other.sign));
+            }
+            self.explain_count.set(self.explain_count.get() + 1);
+            *clause = to_array(&self.literals);
+        }
+    }
+
+    fn to_array<T: Copy>(values: &[T]) -> Array<VariableSize, T> {
+        let mut array = Array::default();
+        for value in values.iter() {
+            array.push(*value);
+        }
+        array
+    }
+
+    /// 変数の部分集合を符号を乱択して選んだリテラル列(長さは 3 以上)
+    fn random_literals(random: &mut Random, n: VariableSize) -> Vec<Literal> {
+        loop {
+            let mut literals = Vec::new();
+            for variable_index in 0..n {
+                if random.next_bool() {
+                    literals.push(Literal { index: variable_index, sign: random.next_bool() });
+                }
+            }
+            if literals.len() >= 3 {
+                return literals;
+            }
+        }
+    }
+
+    #[test]
+    fn theories_route_by_theory_index_and_match_brute_force() {
+        let mut random = Random::new(1);
+        let n = 10;
+        let all_variables: Vec<VariableSize> = (0..n).collect();
+        let mut xor_counts = (0, 0);
+        let mut cardinality_counts = (0, 0);
+        let user_explain_count = Rc::new(Cell::new(0));
+        for k in 0..40 {
+            let problem = random_problem(&mut random, n, 5 + k % 15);
+            let xors: Vec<(Vec<VariableSize>, bool)> = (0..2)
+                .map(|_| {
+                    let literals = random_literals(&mut random, n);
+                    (literals.iter().map(|literal| literal.index).collect(), random.next_bool())
+                })
+                .collect();
+            let at_mosts: Vec<(Vec<Literal>, VariableSize)> = (0..2)
+                .map(|_| {
+                    let literals = random_literals(&mut random, n);
+                    let bound = 1 + random.next_below(literals.len() as VariableSize - 2);
+                    (literals, bound)
+                })
+                .collect();
+            let user_clause = random_literals(&mut random, n);
+            for chronological_backtracking_threshold in [VariableSize::MAX, 0] {
+                let mut solver = SATSolver::with_options(SATSolverOptions {
+                    chronological_backtracking_threshold,
+                    local_search_flips: 0,
+                    ..SATSolverOptions::default()
+                });
+                solver.add_problem(&problem);
+                // XOR 制約と基数制約の理論を包んで登録し，節の理論と外部の伝播器の間に並べる
+                let xor_theory = solver
+                    .theories
+                    .get_or_add(|theory_index| RoutingProbe::new(XORTheory::new(theory_index), theory_index));
+                for (variable_indices, parity) in xors.iter() {
+                    xor_theory.theory.add_xor(variable_indices, *parity);
+                }
+                let cardinality_theory = solver
+                    .theories
+                    .get_or_add(|theory_index| RoutingProbe::new(CardinalityTheory::new(theory_index), theory_index));
+                for (literals, bound) in at_mosts.iter() {
+                    cardinality_theory.theory.add_at_most(to_array(literals), *bound);
+                }
+                solver.set_user_propagator(Box::new(ClausePropagator {
+                    literals: user_clause.clone(),
+                    assignments: HashMap::new(),
+                    explain_count: user_explain_count.clone(),
+                }));
+                let mut expected_problem = SATProblem { number_of_variables: n, ..SATProblem::default() };
+                for clause in problem.clauses.iter() {
+                    expected_problem.clauses.push(clause.clone());
+                }
+                expected_problem.clauses.push(to_array(&user_clause));
+                for (variable_indices, parity) in xors.iter() {
+                    expected_problem.xors.push((to_array(variable_indices), *parity));
+                }
+                let expected: HashSet<Vec<bool>> = brute_force(&expected_problem, &all_variables)
+                    .into_iter()
+                    .filter(|values| {
+                        at_mosts.iter().all(|(literals, bound)| {
+                            literals.iter().filter(|literal| values[literal.index as usize] == literal.sign).count()
+                                <= *bound as usize
+                        })
+                    })
+                    .collect();
+                match solver.solve() {
+                    SATSolverResult::Satisfiable { solution } => {
+                        assert!(expected.contains(&solution.iter().copied().collect::<Vec<bool>>()));
+                    }
+                    SATSolverResult::Unsatisfiable => assert!(expected.is_empty()),
+                    SATSolverResult::Unknown => unreachable!(),
+                }
+                let mut models = HashSet::new();
+                solver.enumerate_models(&all_variables, None, |solution| {
+                    assert!(models.insert(solution.iter().copied().collect::<Vec<bool>>()));
+                });
+                assert_eq!(models, expected);
+                let xor_theory = solver.theories.get::<RoutingProbe<XORTheory>>().unwrap();
+                xor_counts.0 += xor_theory.explain_count.get();
+                xor_counts.1 += xor_theory.increase_activity_count;
+                let cardinality_theory = solver.theories.get::<RoutingProbe<CardinalityTheory>>().unwrap();
+                cardinality_counts.0 += cardinality_theory.explain_count.get();
+                cardinality_counts.1 += cardinality_theory.increase_activity_count;
+            }
+        }
+        // どの理論の理由も説明とアクティビティの増大に使われた
+        assert!(xor_counts.0 != 0 && xor_counts.1 != 0);
+        assert!(cardinality_counts.0 != 0 && cardinality_counts.1 != 0);
+        assert!(user_explain_count.get() != 0);
     }
 }
@@ -0,0 +1,59 @@
+use std::collections::HashSet;
+
+use crate::finite_collections::Array;
+
+use super::read_cnf::SATProblem;
+use super::types::{Literal, VariableSize};
+
+pub use super::random::Random;
+
+/// 乱数で生成した長さ 3 の節(節の中の変数は互いに異なる)
+pub fn random_clause(random: &mut Random, n: VariableSize) -> Array<VariableSize, Literal> {
+    let mut clause: Array<VariableSize, Literal> = Array::default();
+    while clause.len() < 3 {
+        let variable_index = random.next_below(n);
+        if clause.iter().all(|literal| literal.index != variable_index) {
+            clause.push(Literal { sign: random.next_bool(), index: variable_index });
+        }
+    }
+    clause
+}
+
+/// 乱数で生成した変数 n 個・節 m 個の 3-SAT の問題
+pub fn random_problem(random: &mut Random, n: VariableSize, m: usize) -> SATProblem {
+    let mut problem = SATProblem { number_of_variables: n, ..SATProblem::default() };
+    for _ in 0..m {
+        problem.clauses.push(random_clause(random, n));
+    }
+    problem
+}
+
+/// values の第 i ビットを変数 i の値とする割り当てで literal が真か
+pub fn is_true(literal: &Literal, values: u32) -> bool {
+    (values >> literal.index & 1 != 0) == literal.sign
+}
+
+/// values の第 i ビットを変数 i の値とする割り当てが節と XOR 制約をすべて充足するか
+pub fn is_model(problem: &SATProblem, values: u32) -> bool {
+    problem.clauses.iter().all(|clause| clause.iter().any(|literal| is_true(literal, values)))
+        && problem.xors.iter().all(|(xor_variables, parity)| {
+            xor_variables.iter().filter(|variable_index| values >> **variable_index & 1 != 0).count() % 2
+                == *parity as usize
+        })
+}
+
+/// solution が節と XOR 制約をすべて充足するか
+pub fn is_solution(problem: &SATProblem, solution: &Array<VariableSize, bool>) -> bool {
+    problem.clauses.iter().all(|clause| clause.iter().any(|literal| solution[literal.index] == literal.sign))
+        && problem.xors.iter().all(|(xor_variables, parity)| {
+            xor_variables.iter().filter(|variable_index| solution[**variable_index]).count() % 2 == *parity as usize
+        })
+}
+
+/// projection への射影が異なる解を全探索で求める
+pub fn brute_force(problem: &SATProblem, projection: &[VariableSize]) -> HashSet<Vec<bool>> {
+    (0..1u32 << problem.number_of_variables)
+        .filter(|values| is_model(problem, *values))
+        .map(|values| projection.iter().map(|variable_index| values >> variable_index & 1 != 0).collect())
+        .collect()
+}
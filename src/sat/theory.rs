@@ -0,0 +1,308 @@
+use std::any::Any;
+
+use crate::finite_collections::Array;
+
+use super::clause_theory::ClauseTheory;
+use super::types::{ConstraintSize, Literal, Reason, VariableSize};
+use super::user_propagator::UserTheory;
+use super::variables::Variables;
+
+/// Theories に登録した理論を具体的な型として取り出すための変換(すべての 'static な型に実装される)
+pub trait AsAny: Any {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// 割り当てを伝播する理論
+///
+/// SATSolver は本割り当てした変数を各理論に通知し，理論は伝播するリテラルを理由とともに Variables に仮割り当てする．
+/// 理由節は Analyze から要求されたときに explain で求める．
+pub trait Theory: AsAny {
+    /// 変数の次元を拡張する
+    fn expand(&mut self, additional: VariableSize);
+
+    /// 本割り当てされた変数 variable_index による伝播を仮割り当てする
+    fn propagate(&mut self, variable_index: VariableSize, variables: &mut Variables);
+
+    /// 仮割り当てがなくなるまで伝播したときに呼ばれ，追加の伝播を仮割り当てする
+    ///
+    /// 矛盾していれば，すべてのリテラルに偽が割り当てられている節を Err で返す．
+    fn propagate_at_fixpoint(&mut self, _variables: &mut Variables) -> Result<(), Array<VariableSize, Literal>> {
+        Ok(())
+    }
+
+    /// この理論による割り当ての理由となる節を clause に格納する
+    fn explain(
+        &self,
+        variable_index: VariableSize,
+        value: bool,
+        reason: Reason,
+        variables: &Variables,
+        clause: &mut Array<VariableSize, Literal>,
+    );
+
+    /// 決定レベル decision_level までバックトラックした
+    ///
+    /// 時系列バックトラックで割り当てを維持したまま積み直された変数(reassigned_position 以降)を再度伝播する．
+    fn backtrack(
+        &mut self,
+        _decision_level: VariableSize,
+        reassigned_position: VariableSize,
+        variables: &mut Variables,
+    ) {
+        for position in reassigned_position..variables.number_of_assigned_variables() {
+            let variable_index = variables.assigned_variable(position);
+            self.propagate(variable_index, variables);
+        }
+    }
+
+    /// この理論による割り当てが伝播または矛盾の解析に使われた
+    fn increase_activity(&mut self, _reason: Reason) {}
+
+    /// 矛盾の解析が終わるたびに時刻を 1 つ進める
+    fn advance_time(&mut self) {}
+
+    /// 制約の整理(学習した制約の削除など)を要求するか
+    fn is_request_reduction(&self) -> bool {
+        false
+    }
+
+    /// 制約を整理する(決定レベル 0 とは限らない)
+    fn reduce(&mut self, _variables: &Variables) {}
+
+    /// variable_indices のいずれかの変数を含む制約(学習した制約も含む)を取り除く(決定レベル 0 で呼ぶ)
+    ///
+    /// 既定では何もしない(制約を取り除けない理論の制約には variable_indices の変数を含めないこと)．
+    fn remove_constraints_with_variables(&mut self, _variable_indices: &[VariableSize], _variables: &Variables) {}
+
+    /// 探索を始める前に決定レベル 0 で呼ばれ，前回の探索から追加された制約を取り込む
+    ///
+    /// 充足不可能であることが分かれば false を返す．
+    fn rebuild(&mut self, _variables: &mut Variables) -> bool {
+        true
+    }
+
+    /// リスタートを要求するか
+    fn is_request_restart(&self) -> bool {
+        false
+    }
+
+    /// リスタートした
+    fn restart(&mut self) {}
+
+    /// 決定変数として割り当てるリテラルを返す(None なら SATSolver が選択する)
+    fn decide(&mut self) -> Option<Literal> {
+        None
+    }
+
+    /// すべての変数が割り当てられて矛盾がないときに呼ばれ，割り当てを解として受け入れるかを検査する
+    ///
+    /// 受け入れなければ，すべてのリテラルに偽が割り当てられている節を Err で返す．
+    fn check_model(&mut self, _variables: &Variables) -> Result<(), Array<VariableSize, Literal>> {
+        Ok(())
+    }
+
+    /// 割り当てがこの理論の制約をすべて充足するか
+    fn is_satisfied_by(&mut self, assignment: &Array<VariableSize, bool>) -> bool;
+}
+
+/// SATSolver が持つ理論
+///
+/// 節の理論(理由は Reason::Propagation)と外部の伝播器(理由は Reason::External)以外の理論は，
+/// 登録した順に theories に並べ，その位置を Reason::Theory の theory_index とする．
+/// 制約の追加など理論に固有の操作は get_mut などで取り出した理論に対して行い，
+/// 探索中の伝播，説明，バックトラック，整理，リスタートは各理論に振り分ける．
+pub struct Theories {
+    pub clause_theory: ClauseTheory,
+    theories: Array<ConstraintSize, Box<dyn Theory>>,
+    pub user_theory: Option<UserTheory>,
+    dimension: VariableSize,
+}
+
+impl Theories {
+    pub fn new(clause_theory: ClauseTheory) -> Self {
+        Theories { clause_theory, theories: Array::default(), user_theory: None, dimension: 0 }
+    }
+
+    /// create(theory_index) で作った理論を登録して theory_index を返す(理論の変数の次元は登録時に揃える)
+    pub fn add<T: Theory>(&mut self, create: impl FnOnce(ConstraintSize) -> T) -> ConstraintSize {
+        let theory_index = self.theories.len();
+        let mut theory = create(theory_index);
+        theory.expand(self.dimension);
+        self.theories.push(Box::new(theory));
+        theory_index
+    }
+
+    /// 登録した型 T の理論(最初に登録したもの)
+    pub fn get<T: Theory>(&self) -> Option<&T> {
+        self.theories.iter().find_map(|theory| (**theory).as_any().downcast_ref::<T>())
+    }
+
+    /// 登録した型 T の理論(なければ create で作って登録する)
+    pub fn get_or_add<T: Theory>(&mut self, create: impl FnOnce(ConstraintSize) -> T) -> &mut T {
+        let theory_index = match self.theories.iter().position(|theory| (**theory).as_any().is::<T>()) {
+            Some(position) => position as ConstraintSize,
+            None => self.add(create),
+        };
+        (*self.theories[theory_index]).as_any_mut().downcast_mut::<T>().unwrap()
+    }
+
+    /// 伝播する順に並べた理論
+    #[inline(always)]
+    fn theories_mut(&mut self) -> impl Iterator<Item = &mut dyn Theory> {
+        std::iter::once(&mut self.clause_theory as &mut dyn Theory)
+            .chain(self.theories.iter_mut().map(|theory| &mut **theory))
+            .chain(self.user_theory.iter_mut().map(|theory| theory as &mut dyn Theory))
+    }
+
+    fn theories(&self) -> impl Iterator<Item = &dyn Theory> {
+        std::iter::once(&self.clause_theory as &dyn Theory)
+            .chain(self.theories.iter().map(|theory| &**theory))
+            .chain(self.user_theory.iter().map(|theory| theory as &dyn Theory))
+    }
+
+    /// 割り当ての理由を生成した理論
+    #[inline(always)]
+    fn theory_of(&self, reason: Reason) -> &dyn Theory {
+        match reason {
+            Reason::Propagation { .. } => &self.clause_theory,
+            Reason::Theory { theory_index, .. } => &*self.theories[theory_index],
+            Reason::External { .. } => self.user_theory.as_ref().unwrap(),
+            Reason::Decision => unreachable!(),
+        }
+    }
+
+    #[inline(always)]
+    fn theory_mut_of(&mut self, reason: Reason) -> &mut dyn Theory {
+        match reason {
+            Reason::Propagation { .. } => &mut self.clause_theory,
+            Reason::Theory { theory_index, .. } => &mut *self.theories[theory_index],
+            Reason::External { .. } => self.user_theory.as_mut().unwrap(),
+            Reason::Decision => unreachable!(),
+        }
+    }
+
+    pub fn expand(&mut self, additional: VariableSize) {
+        self.dimension += additional;
+        for theory in self.theories_mut() {
+            theory.expand(additional);
+        }
+    }
+    #[inline(always)]
+    pub fn propagate(&mut self, variable_index: VariableSize, variables: &mut Variables) {
+        for theory in self.theories_mut() {
+            theory.propagate(variable_index, variables);
+        }
+    }
+
+    /// 伝播が尽きたときに各理論に追加の伝播を問い合わせる(いずれかの理論が仮割り当てを行えばそこで打ち切る)
+    pub fn propagate_at_fixpoint(&mut self, variables: &mut Variables) -> Result<(), Array<VariableSize, Literal>> {
+        for theory in self.theories_mut() {
+            theory.propagate_at_fixpoint(variables)?;
+            if variables.number_of_tentative_assigned_variables() != 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// 割り当ての理由となる節を求める(理由を生成した理論が生成する)
+    #[inline(always)]
+    pub fn explain(
+        &self,
+        variable_index: VariableSize,
+        value: bool,
+        reason: Reason,
+        variables: &Variables,
+        clause: &mut Array<VariableSize, Literal>,
+    ) {
+        self.theory_of(reason).explain(variable_index, value, reason, variables, clause);
+    }
+
+    pub fn backtrack(
+        &mut self,
+        decision_level: VariableSize,
+        reassigned_position: VariableSize,
+        variables: &mut Variables,
+    ) {
+        for theory in self.theories_mut() {
+            theory.backtrack(decision_level, reassigned_position, variables);
+        }
+    }
+
+    #[inline(always)]
+    pub fn increase_activity(&mut self, reason: Reason) {
+        match reason {
+            Reason::Decision => {}
+            Reason::Propagation { .. } => self.clause_theory.increase_activity(reason),
+            Reason::Theory { .. } | Reason::External { .. } => self.theory_mut_of(reason).increase_activity(reason),
+        }
+    }
+
+    pub fn advance_time(&mut self) {
+        for theory in self.theories_mut() {
+            theory.advance_time();
+        }
+    }
+
+    pub fn is_request_reduction(&self) -> bool {
+        self.theories().any(|theory| theory.is_request_reduction())
+    }
+
+    /// 整理を要求している理論の制約を整理する
+    pub fn reduce(&mut self, variables: &Variables) {
+        for theory in self.theories_mut() {
+            if theory.is_request_reduction() {
+                theory.reduce(variables);
+            }
+        }
+    }
+
+    /// すべての理論から variable_indices のいずれかの変数を含む制約を取り除く(決定レベル 0 で呼ぶ)
+    pub fn remove_constraints_with_variables(&mut self, variable_indices: &[VariableSize], variables: &Variables) {
+        for theory in self.theories_mut() {
+            theory.remove_constraints_with_variables(variable_indices, variables);
+        }
+    }
+
+    /// すべての理論に追加された制約を取り込ませる(決定レベル 0 で呼ぶ)
+    pub fn rebuild(&mut self, variables: &mut Variables) -> bool {
+        self.theories_mut().all(|theory| theory.rebuild(variables))
+    }
+
+    pub fn is_request_restart(&self) -> bool {
+        self.theories().any(|theory| theory.is_request_restart())
+    }
+
+    pub fn restart(&mut self) {
+        for theory in self.theories_mut() {
+            theory.restart();
+        }
+    }
+
+    pub fn decide(&mut self) -> Option<Literal> {
+        self.theories_mut().find_map(|theory| theory.decide())
+    }
+
+    /// すべての理論に解を検査させる(最初に受け入れなかった理論の節を返す)
+    pub fn check_model(&mut self, variables: &Variables) -> Result<(), Array<VariableSize, Literal>> {
+        for theory in self.theories_mut() {
+            theory.check_model(variables)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_satisfied_by(&mut self, assignment: &Array<VariableSize, bool>) -> bool {
+        self.theories_mut().all(|theory| theory.is_satisfied_by(assignment))
+    }
+}
@@ -10,6 +10,16 @@ pub struct Literal {
     pub index: VariableSize,
 }
 
+impl std::ops::Not for Literal {
+    type Output = Literal;
+
+    /// 否定のリテラル
+    #[inline(always)]
+    fn not(self) -> Literal {
+        Literal { sign: !self.sign, index: self.index }
+    }
+}
+
 /// 割り当て理由
 #[derive(Clone, Copy)]
 pub enum Reason {
@@ -20,6 +30,23 @@ pub enum Reason {
         lbd: VariableSize,           // TODO 1byte に
         clause_length: VariableSize, // TODO 1byte に
         assignment_level_at_propagated: VariableSize,
+        decision_level: VariableSize, // 伝播されたリテラルが属する決定レベル(節中の他のリテラルの決定レベルの最大値)
+    },
+    /// 節以外の理論による伝播(理由節は theory_index の理論が explanation_index をもとに必要に応じて生成する)
+    ///
+    /// lbd と clause_length は理由節の LBD と長さ(伝播時点で分からなければその上界)．
+    Theory {
+        theory_index: ConstraintSize,
+        explanation_index: ConstraintSize,
+        lbd: VariableSize,
+        clause_length: VariableSize,
+        decision_level: VariableSize,
+    },
+    /// 外部の伝播器(UserPropagator)による伝播(理由節は Analyze から要求されたときに外部の伝播器から得る)
+    External {
+        lbd: VariableSize,
+        clause_length: VariableSize,
+        decision_level: VariableSize,
     },
 }
 
@@ -0,0 +1,328 @@
+use crate::finite_collections::{Array, Set};
+
+use super::theory::Theory;
+use super::types::{Literal, Reason, VariableSize};
+use super::variables::{VariableState, Variables};
+
+/// 外部の伝播器が SATSolver に伝える伝播
+pub enum UserPropagation {
+    /// literal を真にする(理由節は Analyze から要求されたときに UserPropagator::explain で求める)
+    Propagate(Literal),
+    /// 現在の割り当てですべてのリテラルが偽になっている節
+    Conflict(Array<VariableSize, Literal>),
+}
+
+/// 探索に参加する外部の伝播器(IPASIR-UP に相当)
+///
+/// SATSolver の伝播中に割り当て・決定・バックトラックを通知され，伝播，矛盾，決定変数の候補を返す．
+/// 時系列バックトラックでは決定レベルの順に割り当てられるとは限らないので，割り当ては決定レベルとともに通知する．
+/// バックトラック後も決定レベルが decision_level 以下の割り当ては維持され，再度通知されることはない．
+pub trait UserPropagator {
+    /// 決定変数が割り当てられて決定レベルが decision_level になった(決定変数の割り当てはこの後に通知する)
+    fn notify_new_decision_level(&mut self, _decision_level: VariableSize) {}
+
+    /// 決定レベル decision_level で literal に真が割り当てられた
+    fn notify_assignment(&mut self, literal: Literal, decision_level: VariableSize);
+
+    /// 決定レベル decision_level までバックトラックした(それより大きい決定レベルの割り当てはすべて取り消された)
+    fn notify_backtrack(&mut self, decision_level: VariableSize);
+
+    /// SATSolver の伝播が尽きるたびに呼ばれ，伝播か矛盾を 1 つ返す(なければ None)
+    ///
+    /// Propagate で返したリテラルは，理由節の他のリテラルの決定レベルの最大値で割り当てられる
+    /// (決定レベルを求めるために伝播した時点で explain が呼ばれる)．
+    /// 既に偽が割り当てられているリテラルを返した場合には，その理由節を矛盾として扱う．
+    fn propagate(&mut self) -> Option<UserPropagation>;
+
+    /// propagate で真にした literal の理由節(literal と，偽が割り当てられているリテラルからなる節)を clause に格納する
+    ///
+    /// literal が割り当てられている間は何度呼ばれても同じ節を返すこと．
+    fn explain(&self, literal: Literal, clause: &mut Array<VariableSize, Literal>);
+
+    /// 決定変数として割り当てるリテラルを返す(None なら SATSolver が選択する)
+    fn decide(&mut self) -> Option<Literal> {
+        None
+    }
+
+    /// SATSolver が解 solution を見つけたときに呼ばれ，解を受け入れなければ solution で偽になる節を返す
+    ///
+    /// 返した節は矛盾として扱われ，探索が続けられる．局所探索で見つけた割り当ての検査にも使われる．
+    fn check_model(&mut self, _solution: &Array<VariableSize, bool>) -> Option<Array<VariableSize, Literal>> {
+        None
+    }
+}
+
+/// UserPropagator を SATSolver の理論として扱う
+pub struct UserTheory {
+    user_propagator: Box<dyn UserPropagator>,
+    explanation: Array<VariableSize, Literal>,
+    solution: Array<VariableSize, bool>,
+    decision_level_set: Set<VariableSize>,
+}
+
+impl UserTheory {
+    pub fn new(user_propagator: Box<dyn UserPropagator>) -> Self {
+        UserTheory {
+            user_propagator,
+            explanation: Array::default(),
+            solution: Array::default(),
+            decision_level_set: Set::default(),
+        }
+    }
+
+    pub fn into_user_propagator(self) -> Box<dyn UserPropagator> {
+        self.user_propagator
+    }
+}
+
+impl Theory for UserTheory {
+    fn expand(&mut self, _additional: VariableSize) {}
+
+    /// 割り当てを通知する(決定変数であれば先に決定レベルの増加を通知する)
+    fn propagate(&mut self, variable_index: VariableSize, variables: &mut Variables) {
+        let VariableState::Assigned { assigned_value, decision_level, reason, .. } = *variables.get(variable_index)
+        else {
+            unreachable!();
+        };
+        if let Reason::Decision = reason {
+            self.user_propagator.notify_new_decision_level(decision_level);
+        }
+        self.user_propagator.notify_assignment(Literal { index: variable_index, sign: assigned_value }, decision_level);
+    }
+
+    /// 外部の伝播器に伝播を問い合わせ，返されたリテラルを理由節の他のリテラルの決定レベルの最大値で仮割り当てする
+    ///
+    /// 理由節は伝播時点で一度求めて決定レベル，LBD，長さを計算し，Analyze から要求されたときに改めて求める．
+    fn propagate_at_fixpoint(&mut self, variables: &mut Variables) -> Result<(), Array<VariableSize, Literal>> {
+        loop {
+            match self.user_propagator.propagate() {
+                Some(UserPropagation::Propagate(literal)) => {
+                    debug_assert!(literal.index < variables.dimension());
+                    match *variables.get(literal.index) {
+                        VariableState::Assigned { assigned_value, .. } if assigned_value == literal.sign => {}
+                        VariableState::Assigned { .. } => {
+                            // 偽が割り当てられているリテラルであれば理由節が矛盾
+                            let mut clause = Array::default();
+                            self.user_propagator.explain(literal, &mut clause);
+                            return Err(clause);
+                        }
+                        _ => {
+                            self.user_propagator.explain(literal, &mut self.explanation);
+                            debug_assert!(self.explanation.iter().any(|l| *l == literal));
+                            if self.decision_level_set.capacity() < variables.dimension() + 1 {
+                                self.decision_level_set
+                                    .reserve(variables.dimension() + 1 - self.decision_level_set.capacity());
+                            }
+                            let mut lbd = 0;
+                            let mut propagation_decision_level = 0;
+                            for other in self.explanation.iter().filter(|l| l.index != literal.index) {
+                                let VariableState::Assigned { assigned_value, decision_level, .. } =
+                                    *variables.get(other.index)
+                                else {
+                                    unreachable!("the explanation has an unassigned literal");
+                                };
+                                debug_assert!(assigned_value != other.sign);
+                                propagation_decision_level = propagation_decision_level.max(decision_level);
+                                if decision_level != 0 && !self.decision_level_set.contains_key(decision_level) {
+                                    self.decision_level_set.insert(decision_level);
+                                    lbd += 1;
+                                }
+                            }
+                            self.decision_level_set.clear();
+                            variables.tentatively_assign(
+                                literal.index,
+                                literal.sign,
+                                Reason::External {
+                                    lbd,
+                                    clause_length: self.explanation.len(),
+                                    decision_level: propagation_decision_level,
+                                },
+                            );
+                            return Ok(());
+                        }
+                    }
+                }
+                Some(UserPropagation::Conflict(clause)) => return Err(clause),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    fn explain(
+        &self,
+        variable_index: VariableSize,
+        value: bool,
+        _reason: Reason,
+        variables: &Variables,
+        clause: &mut Array<VariableSize, Literal>,
+    ) {
+        let literal = Literal { index: variable_index, sign: value };
+        clause.clear();
+        self.user_propagator.explain(literal, clause);
+        debug_assert!(clause.iter().any(|l| *l == literal));
+        debug_assert!(clause.iter().all(|l| *l == literal || variables.get(l.index).is_value_assigned(!l.sign)));
+        // 理由節のリテラルは伝播されたリテラルより先に割り当てられていなければならない
+        debug_assert!({
+            let assignment_level_of = |index| match variables.get(index) {
+                VariableState::Assigned { assignment_level, .. } => *assignment_level,
+                _ => VariableSize::MAX,
+            };
+            let assignment_level = assignment_level_of(variable_index);
+            clause.iter().all(|l| l.index == variable_index || assignment_level_of(l.index) < assignment_level)
+        });
+    }
+
+    /// バックトラックを通知する(割り当てを維持した変数は再度通知しない)
+    fn backtrack(
+        &mut self,
+        decision_level: VariableSize,
+        _reassigned_position: VariableSize,
+        _variables: &mut Variables,
+    ) {
+        self.user_propagator.notify_backtrack(decision_level);
+    }
+
+    fn decide(&mut self) -> Option<Literal> {
+        self.user_propagator.decide()
+    }
+
+    /// 解を外部の伝播器に検査させる
+    fn check_model(&mut self, variables: &Variables) -> Result<(), Array<VariableSize, Literal>> {
+        self.solution.clear();
+        for variable_index in 0..variables.dimension() {
+            let VariableState::Assigned { assigned_value, .. } = *variables.get(variable_index) else {
+                unreachable!();
+            };
+            self.solution.push(assigned_value);
+        }
+        match self.user_propagator.check_model(&self.solution) {
+            Some(clause) => Err(clause),
+            None => Ok(()),
+        }
+    }
+
+    /// 割り当てを外部の伝播器に検査させる
+    fn is_satisfied_by(&mut self, assignment: &Array<VariableSize, bool>) -> bool {
+        self.user_propagator.check_model(assignment).is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::super::solver::{SATSolver, SATSolverOptions, SATSolverResult};
+    use super::super::test_helpers::{brute_force, random_problem, Random};
+    use super::*;
+
+    /// variable_indices の変数の排他的論理和が parity に等しいという制約を課す伝播器
+    ///
+    /// is_eager が false であれば伝播せず，解の検査だけで制約を課す．
+    struct ParityPropagator {
+        variable_indices: Vec<VariableSize>,
+        parity: bool,
+        is_eager: bool,
+        // 割り当てられている変数の値と決定レベル
+        assignments: HashMap<VariableSize, (bool, VariableSize)>,
+    }
+
+    impl ParityPropagator {
+        /// 割り当てられている変数の値をすべて否定したリテラルの節
+        fn blocking_clause(&self, values: impl Fn(VariableSize) -> bool) -> Array<VariableSize, Literal> {
+            let mut clause = Array::default();
+            for variable_index in self.variable_indices.iter() {
+                clause.push(Literal { index: *variable_index, sign: !values(*variable_index) });
+            }
+            clause
+        }
+    }
+
+    impl UserPropagator for ParityPropagator {
+        fn notify_assignment(&mut self, literal: Literal, decision_level: VariableSize) {
+            if self.variable_indices.contains(&literal.index) {
+                assert!(self.assignments.insert(literal.index, (literal.sign, decision_level)).is_none());
+            }
+        }
+
+        fn notify_backtrack(&mut self, decision_level: VariableSize) {
+            self.assignments.retain(|_, (_, assigned_decision_level)| *assigned_decision_level <= decision_level);
+        }
+
+        fn propagate(&mut self) -> Option<UserPropagation> {
+            if !self.is_eager {
+                return None;
+            }
+            let unassigned: Vec<VariableSize> = self
+                .variable_indices
+                .iter()
+                .copied()
+                .filter(|variable_index| !self.assignments.contains_key(variable_index))
+                .collect();
+            let parity = self.assignments.values().fold(self.parity, |parity, (value, _)| parity ^ value);
+            match unassigned.as_slice() {
+                [] if parity => Some(UserPropagation::Conflict(self.blocking_clause(|i| self.assignments[&i].0))),
+                [variable_index] => Some(UserPropagation::Propagate(Literal { index: *variable_index, sign: parity })),
+                _ => None,
+            }
+        }
+
+        fn explain(&self, literal: Literal, clause: &mut Array<VariableSize, Literal>) {
+            clause.clear();
+            clause.push(literal);
+            for variable_index in self.variable_indices.iter().filter(|i| **i != literal.index) {
+                clause.push(Literal { index: *variable_index, sign: !self.assignments[variable_index].0 });
+            }
+        }
+
+        fn check_model(&mut self, solution: &Array<VariableSize, bool>) -> Option<Array<VariableSize, Literal>> {
+            let parity = self.variable_indices.iter().fold(self.parity, |parity, i| parity ^ solution[*i]);
+            parity.then(|| self.blocking_clause(|i| solution[i]))
+        }
+    }
+
+    #[test]
+    fn parity_propagator_matches_brute_force() {
+        let mut random = Random::new(1);
+        let n = 10;
+        for k in 0..40 {
+            let mut problem = random_problem(&mut random, n, 20 + k % 20);
+            let variable_indices: Vec<VariableSize> = (0..n).filter(|_| random.next_bool()).collect();
+            let parity = random.next_bool();
+            // 伝播器が課す制約を XOR 制約として加えた問題の解
+            let mut xor_variables = Array::default();
+            for variable_index in variable_indices.iter() {
+                xor_variables.push(*variable_index);
+            }
+            problem.xors.push((xor_variables, parity));
+            let all_variables: Vec<VariableSize> = (0..n).collect();
+            let expected = brute_force(&problem, &all_variables);
+            problem.xors.clear();
+            // 時系列バックトラックでは現在の決定レベルより小さい決定レベルで伝播される
+            for (is_eager, chronological_backtracking_threshold) in [(true, VariableSize::MAX), (true, 0), (false, 0)] {
+                let mut solver = SATSolver::with_options(SATSolverOptions {
+                    chronological_backtracking_threshold,
+                    ..SATSolverOptions::default()
+                });
+                solver.add_problem(&problem);
+                solver.set_user_propagator(Box::new(ParityPropagator {
+                    variable_indices: variable_indices.clone(),
+                    parity,
+                    is_eager,
+                    assignments: HashMap::new(),
+                }));
+                match solver.solve() {
+                    SATSolverResult::Satisfiable { solution } => {
+                        assert!(expected.contains(&solution.iter().copied().collect::<Vec<bool>>()));
+                    }
+                    SATSolverResult::Unsatisfiable => assert!(expected.is_empty()),
+                    SATSolverResult::Unknown => unreachable!(),
+                }
+                let mut models = HashSet::new();
+                solver.enumerate_models(&all_variables, None, |solution| {
+                    assert!(models.insert(solution.iter().copied().collect::<Vec<bool>>()));
+                });
+                assert_eq!(models, expected);
+            }
+        }
+    }
+}
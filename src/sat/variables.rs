@@ -1,8 +1,11 @@
+use std::cell::Cell;
 use std::hint::unreachable_unchecked;
 
 use crate::finite_collections::indirect_heap;
 use crate::finite_collections::Array;
 
+use super::decision_heuristic::DecisionHeuristic;
+use super::random::Random;
 use super::types::{Reason, VariableSize};
 
 //#[repr(align(64))] // TODO 後で検証（単に 64 byte にするとどうなるのか・size を 32byte に切り詰めて align を 32byte にするとどうなるのか）
@@ -31,13 +34,205 @@ impl VariableState {
             false
         }
     }
+
+    /// 決定レベル 0 で value が割り当てられているか
+    pub fn is_value_fixed(&self, value: bool) -> bool {
+        if let VariableState::Assigned { assigned_value, decision_level: 0, .. } = self {
+            *assigned_value == value
+        } else {
+            false
+        }
+    }
 }
 
-type ConflictingVariableScore = (f64, VariableSize, VariableSize);
+// NOTE: 末尾の要素は同点の場合の順序を決める値(乱択化しない場合は 0)
+type ConflictingVariableScore = (f64, VariableSize, VariableSize, VariableSize);
+
+type TentativelyAssigedVariableScore = (f64, VariableSize, VariableSize, VariableSize);
+
+/// 仮割り当てされた変数を伝播する順序(矛盾している変数を選択する順序)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PropagationOrder {
+    /// 仮割り当てされた順(ヒープを使用しない)
+    FIFO,
+    /// 変数の優先度の降順 → 理由節の LBD の昇順 → 理由節の長さの昇順
+    Score,
+    /// 理由節の LBD の昇順 → 理由節の長さの昇順
+    LBD,
+    /// 理由節の長さの昇順 → 理由節の LBD の昇順
+    Length,
+}
 
-type TentativelyAssigedVariableScore = (f64, VariableSize, VariableSize);
+/// 仮割り当てされた変数の待ち行列
+///
+/// PropagationOrder::FIFO の場合には配列による待ち行列，それ以外の場合には score による二分ヒープで管理する．
+struct TentativelyAssignedVariableQueue {
+    propagation_order: PropagationOrder,
+    heap: Array<VariableSize, (VariableSize, TentativelyAssigedVariableScore)>,
+    queue: Array<VariableSize, VariableSize>,
+    head: VariableSize,
+    len: VariableSize,
+    comparison_count: Cell<usize>,
+}
 
-type UnassignedVariableScore = f64;
+impl TentativelyAssignedVariableQueue {
+    const NULL_INDEX: VariableSize = VariableSize::MAX;
+
+    fn new(propagation_order: PropagationOrder) -> Self {
+        TentativelyAssignedVariableQueue {
+            propagation_order,
+            heap: Array::default(),
+            queue: Array::default(),
+            head: 0,
+            len: 0,
+            comparison_count: Cell::new(0),
+        }
+    }
+
+    #[inline(always)]
+    fn len(&self) -> VariableSize {
+        self.len
+    }
+
+    #[inline(always)]
+    fn first(&self) -> Option<VariableSize> {
+        if self.len == 0 {
+            None
+        } else if self.propagation_order == PropagationOrder::FIFO {
+            debug_assert!(self.queue[self.head] != Self::NULL_INDEX);
+            Some(self.queue[self.head])
+        } else {
+            Some(self.heap[0].0)
+        }
+    }
+
+    #[inline(always)]
+    fn score(
+        &self,
+        positions: &Array<VariableSize, VariableSize>,
+        index: VariableSize,
+    ) -> TentativelyAssigedVariableScore {
+        if self.propagation_order == PropagationOrder::FIFO {
+            (0.0, 0, 0, 0)
+        } else {
+            self.heap[positions[index]].1
+        }
+    }
+
+    #[inline(always)]
+    fn push(
+        &mut self,
+        positions: &mut Array<VariableSize, VariableSize>,
+        index: VariableSize,
+        score: TentativelyAssigedVariableScore,
+    ) {
+        if self.propagation_order == PropagationOrder::FIFO {
+            debug_assert!(positions[index] == Variables::NULL_POSITION);
+            positions[index] = self.queue.len();
+            self.queue.push(index);
+        } else {
+            Variables::push_heap_item(positions, &mut self.heap, index, score, &self.comparison_count);
+        }
+        self.len += 1;
+    }
+
+    #[inline(always)]
+    fn remove(&mut self, positions: &mut Array<VariableSize, VariableSize>, index: VariableSize) {
+        if self.propagation_order == PropagationOrder::FIFO {
+            // 空きにしておき，先頭であれば空きでない位置まで先頭を進める
+            let position = positions[index];
+            debug_assert!(self.queue[position] == index);
+            positions[index] = Variables::NULL_POSITION;
+            self.queue[position] = Self::NULL_INDEX;
+            self.len -= 1;
+            if self.len == 0 {
+                self.queue.clear();
+                self.head = 0;
+            } else if self.queue.len() > 2 * self.len {
+                // 空きが要素数を超えたら詰め直す(詰め直しの計算量は削除の回数で償却される)
+                self.compact(positions);
+            } else if position == self.head {
+                while self.queue[self.head] == Self::NULL_INDEX {
+                    self.head += 1;
+                }
+            }
+        } else {
+            Variables::pop_heap_item(positions, &mut self.heap, index, &self.comparison_count);
+            self.len -= 1;
+        }
+    }
+
+    /// 空きを除いて待ち行列を先頭から詰め直す
+    fn compact(&mut self, positions: &mut Array<VariableSize, VariableSize>) {
+        let mut len = 0;
+        for k in self.head..self.queue.len() {
+            let index = self.queue[k];
+            if index != Self::NULL_INDEX {
+                self.queue[len] = index;
+                positions[index] = len;
+                len += 1;
+            }
+        }
+        debug_assert!(len == self.len);
+        self.queue.resize(len, Self::NULL_INDEX);
+        self.head = 0;
+    }
+
+    #[inline(always)]
+    fn change_score(
+        &mut self,
+        positions: &mut Array<VariableSize, VariableSize>,
+        index: VariableSize,
+        score: TentativelyAssigedVariableScore,
+    ) {
+        if self.propagation_order != PropagationOrder::FIFO {
+            Variables::change_heap_value(positions, &mut self.heap, index, score, &self.comparison_count);
+        }
+    }
+
+    fn collect(&self, indices: &mut Array<VariableSize, VariableSize>) {
+        indices.clear();
+        if self.propagation_order == PropagationOrder::FIFO {
+            for k in self.head..self.queue.len() {
+                if self.queue[k] != Self::NULL_INDEX {
+                    indices.push(self.queue[k]);
+                }
+            }
+        } else {
+            for (index, ..) in self.heap.iter() {
+                indices.push(*index);
+            }
+        }
+    }
+}
+
+/// 伝播順序ごとの統計情報
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PropagationStatistics {
+    /// 仮割り当ての回数
+    pub tentative_assignment_count: usize,
+    /// 仮割り当ての理由を置き換えた回数
+    pub reason_replacement_count: usize,
+    /// 矛盾を検出した回数
+    pub conflict_detection_count: usize,
+    /// 仮割り当てされた変数の待ち行列の最大長
+    pub max_queue_length: VariableSize,
+    /// 仮割り当てされた変数のヒープで行った比較の回数(FIFO では 0)
+    pub queue_comparison_count: usize,
+    /// 矛盾している変数のヒープで行った比較の回数
+    pub conflict_comparison_count: usize,
+}
+
+/// 変数の追加時に行う乱択化
+#[derive(Clone, Copy, Default)]
+pub struct VariableRandomization {
+    /// 初期の位相をランダムにする
+    pub initial_phase: bool,
+    /// 初期の決定変数の選択順序をランダムにする
+    pub initial_order: bool,
+    /// 仮割り当てされた変数・矛盾している変数の同点の順序をランダムにする
+    pub tie_breaking: bool,
+}
 
 /// 変数の割り当て状態を管理する
 pub struct Variables {
@@ -45,32 +240,44 @@ pub struct Variables {
     variable_states: Array<VariableSize, VariableState>,
     positions: Array<VariableSize, VariableSize>,
     assigned_variables: Array<VariableSize, VariableSize>,
+    decision_variables: Array<VariableSize, VariableSize>,
     conflicting_variables: Array<VariableSize, (VariableSize, ConflictingVariableScore)>,
-    tentatively_assigned_variables: Array<VariableSize, (VariableSize, TentativelyAssigedVariableScore)>,
-    unassigned_variables: Array<VariableSize, (VariableSize, UnassignedVariableScore)>,
-    activity_time_constant: f64,
-    activity_increase_value: f64,
-    activities: Array<VariableSize, f64>,
+    conflict_comparison_count: Cell<usize>,
+    tentatively_assigned_variables: TentativelyAssignedVariableQueue,
+    number_of_unassigned_variables: VariableSize,
+    variable_buffer: Array<VariableSize, VariableSize>,
+    decision_heuristic: Box<dyn DecisionHeuristic>,
+    propagation_order: PropagationOrder,
+    propagation_statistics: PropagationStatistics,
+    randomization: VariableRandomization,
+    tie_breakers: Array<VariableSize, VariableSize>,
 }
 
 impl Variables {
     const NULL_POSITION: VariableSize = VariableSize::MAX;
 
     #[inline(never)]
-    pub fn new(activity_time_constant: f64) -> Self {
-        assert!(activity_time_constant.is_finite());
-        assert!(activity_time_constant > 0.0);
+    pub fn new(
+        decision_heuristic: Box<dyn DecisionHeuristic>,
+        propagation_order: PropagationOrder,
+        randomization: VariableRandomization,
+    ) -> Self {
         Variables {
             decision_level: 0,
             variable_states: Array::default(),
             positions: Array::default(),
             assigned_variables: Array::default(),
+            decision_variables: Array::default(),
             conflicting_variables: Array::default(),
-            tentatively_assigned_variables: Array::default(),
-            unassigned_variables: Array::default(),
-            activity_time_constant: activity_time_constant,
-            activity_increase_value: 1.0,
-            activities: Array::default(),
+            conflict_comparison_count: Cell::new(0),
+            tentatively_assigned_variables: TentativelyAssignedVariableQueue::new(propagation_order),
+            number_of_unassigned_variables: 0,
+            variable_buffer: Array::default(),
+            decision_heuristic,
+            propagation_order,
+            propagation_statistics: PropagationStatistics::default(),
+            randomization,
+            tie_breakers: Array::default(),
         }
     }
 
@@ -96,7 +303,20 @@ impl Variables {
 
     #[inline(always)]
     pub fn number_of_unassigned_variables(&self) -> VariableSize {
-        self.unassigned_variables.len()
+        self.number_of_unassigned_variables
+    }
+
+    #[inline(always)]
+    pub fn propagation_order(&self) -> PropagationOrder {
+        self.propagation_order
+    }
+
+    pub fn propagation_statistics(&self) -> PropagationStatistics {
+        PropagationStatistics {
+            queue_comparison_count: self.tentatively_assigned_variables.comparison_count.get(),
+            conflict_comparison_count: self.conflict_comparison_count.get(),
+            ..self.propagation_statistics
+        }
     }
 
     #[inline(always)]
@@ -109,41 +329,60 @@ impl Variables {
         self.assigned_variables.len()
     }
 
+    #[inline(always)]
+    pub fn assigned_variable(&self, position: VariableSize) -> VariableSize {
+        self.assigned_variables[position]
+    }
+
     #[inline(never)]
-    pub fn redimension(&mut self, new_dimension: VariableSize) {
+    pub fn redimension(&mut self, new_dimension: VariableSize, random: &mut Random) {
         assert!(new_dimension >= self.variable_states.len());
+        let first = self.variable_states.len();
+        let additional = new_dimension - first;
         while new_dimension > self.variable_states.len() {
-            let index = self.variable_states.len();
-            let initial_value = false;
-            let initial_activity = 0.0;
-            let priority = Self::calculate_unassigned_variable_score(initial_activity);
+            let initial_value = if self.randomization.initial_phase { random.next_bool() } else { false };
             // 未割り当て変数として初期化
             self.variable_states.push(VariableState::Unassigned { last_assigned_value: initial_value });
-            self.activities.push(initial_activity);
             self.positions.push(Self::NULL_POSITION);
-            // 未割り当て変数のキューに追加
-            Self::push_heap_item(&mut self.positions, &mut self.unassigned_variables, index, priority);
+            self.tie_breakers.push(if self.randomization.tie_breaking {
+                random.next_below(VariableSize::MAX)
+            } else {
+                0
+            });
             // 整合性チェック
-            debug_assert!(self.variable_states.len() == self.activities.len());
             debug_assert!(self.variable_states.len() == self.positions.len());
         }
+        self.number_of_unassigned_variables += additional;
+        // 追加された変数を決定変数の候補に追加
+        self.decision_heuristic.expand(additional);
+        if self.randomization.initial_order {
+            self.decision_heuristic.shuffle(first, random);
+        }
     }
 
-    pub fn first_unassigned_variable(&self) -> Option<(VariableSize, &VariableState)> {
-        match self.unassigned_variables.first() {
-            Some((index, ..)) => {
-                debug_assert!(matches!(self.variable_states[*index], VariableState::Unassigned { .. }));
-                Some((*index, &self.variable_states[*index]))
-            }
+    /// 次に決定変数として選択される未割り当ての変数を返す
+    pub fn first_unassigned_variable(&mut self) -> Option<(VariableSize, &VariableState)> {
+        let variable_states = &self.variable_states;
+        match self
+            .decision_heuristic
+            .select(&|index| matches!(variable_states[index], VariableState::Unassigned { .. }))
+        {
+            Some(index) => Some((index, &self.variable_states[index])),
             None => None,
         }
     }
 
+    /// 次に決定変数として選択される未割り当ての変数を返す(決定変数の選択方法の内部状態を変更しない)
+    pub fn peek_unassigned_variable(&self) -> Option<VariableSize> {
+        let variable_states = &self.variable_states;
+        self.decision_heuristic.peek(&|index| matches!(variable_states[index], VariableState::Unassigned { .. }))
+    }
+
     pub fn first_tentatively_assigned_variable(&self) -> Option<(VariableSize, &VariableState)> {
         match self.tentatively_assigned_variables.first() {
-            Some((index, ..)) => {
-                debug_assert!(matches!(self.variable_states[*index], VariableState::TentativelyAssigned { .. }));
-                Some((*index, &self.variable_states[*index]))
+            Some(index) => {
+                debug_assert!(matches!(self.variable_states[index], VariableState::TentativelyAssigned { .. }));
+                Some((index, &self.variable_states[index]))
             }
             None => None,
         }
@@ -159,27 +398,58 @@ impl Variables {
         }
     }
 
+    /// 矛盾している変数を列挙する(先頭は first_conflicting_variable と同じ変数)
+    pub fn conflicting_variables(&self) -> impl Iterator<Item = (VariableSize, &VariableState)> {
+        self.conflicting_variables.iter().map(|(index, ..)| {
+            debug_assert!(matches!(self.variable_states[*index], VariableState::Conflicting { .. }));
+            (*index, &self.variable_states[*index])
+        })
+    }
+
     #[inline(always)]
     pub fn get(&self, index: VariableSize) -> &VariableState {
         &self.variable_states[index]
     }
 
+    /// 変数の位相(次に決定変数として選択されたときに割り当てる値)を設定する
+    ///
+    /// 割り当て済みの変数の位相は割り当てが取り消されたときに割り当てられていた値で上書きされる．
+    #[inline(always)]
+    pub fn save_phase(&mut self, index: VariableSize, value: bool) {
+        match &mut self.variable_states[index] {
+            VariableState::Unassigned { last_assigned_value }
+            | VariableState::TentativelyAssigned { last_assigned_value, .. }
+            | VariableState::Conflicting { last_assigned_value, .. } => {
+                *last_assigned_value = value;
+            }
+            VariableState::Assigned { .. } => {}
+        }
+    }
+
     #[inline(always)]
     pub fn tentatively_assign(&mut self, index: VariableSize, value: bool, reason: Reason) {
+        self.propagation_statistics.tentative_assignment_count += 1;
         let variable_state = &mut self.variable_states[index];
         // 現在の割当状態に応じて場合分け
         match variable_state {
             VariableState::Unassigned { last_assigned_value } => {
                 // 未割り当ての場合
                 // 仮割当状態に遷移
-                let score = Self::calculate_tentatively_assigned_variable_score(&reason, self.activities[index]);
+                let score = Self::calculate_tentatively_assigned_variable_score(
+                    self.propagation_order,
+                    &reason,
+                    self.decision_heuristic.priority(index),
+                    self.tie_breakers[index],
+                );
                 *variable_state = VariableState::TentativelyAssigned {
                     last_assigned_value: *last_assigned_value,
                     tentatively_assigned_value: value,
                     reason: reason,
                 };
-                Self::pop_heap_item(&mut self.positions, &mut self.unassigned_variables, index);
-                Self::push_heap_item(&mut self.positions, &mut self.tentatively_assigned_variables, index, score);
+                self.number_of_unassigned_variables -= 1;
+                self.tentatively_assigned_variables.push(&mut self.positions, index, score);
+                self.propagation_statistics.max_queue_length =
+                    self.propagation_statistics.max_queue_length.max(self.tentatively_assigned_variables.len());
             }
             VariableState::TentativelyAssigned {
                 last_assigned_value,
@@ -189,40 +459,79 @@ impl Variables {
             } => {
                 if value == *tentatively_assigned_value {
                     // 同じ値に仮割当されてる場合
-                    let new_score =
-                        Self::calculate_tentatively_assigned_variable_score(&reason, self.activities[index]);
-                    let current_score = self.tentatively_assigned_variables[self.positions[index]].1;
-                    if new_score < current_score {
-                        // priority を改善するなら上書き
+                    let new_score = Self::calculate_tentatively_assigned_variable_score(
+                        self.propagation_order,
+                        &reason,
+                        self.decision_heuristic.priority(index),
+                        self.tie_breakers[index],
+                    );
+                    let current_score = self.tentatively_assigned_variables.score(&self.positions, index);
+                    let new_decision_level = Self::decision_level_of(&reason, self.decision_level);
+                    let current_decision_level = Self::decision_level_of(original_reason, self.decision_level);
+                    if new_decision_level < current_decision_level
+                        || (new_decision_level == current_decision_level && new_score < current_score)
+                    {
+                        // より低い決定レベルで伝播されるか，priority を改善するなら上書き
                         *original_reason = reason;
-                        Self::change_heap_value(
-                            &mut self.positions,
-                            &mut self.tentatively_assigned_variables,
-                            index,
-                            new_score,
-                        )
+                        self.tentatively_assigned_variables.change_score(&mut self.positions, index, new_score);
+                        self.propagation_statistics.reason_replacement_count += 1;
                     }
                 } else {
                     // 異なる値に仮割当されてる場合
                     // Conflicting 状態に遷移
                     let reasons = if value == false { [reason, *original_reason] } else { [*original_reason, reason] };
-                    let score = Self::calculate_conflicting_variable_score(&reasons, self.activities[index]);
+                    let score = Self::calculate_conflicting_variable_score(
+                        self.propagation_order,
+                        &reasons,
+                        self.decision_heuristic.priority(index),
+                        self.tie_breakers[index],
+                        self.propagation_statistics.conflict_detection_count,
+                    );
                     *variable_state =
                         VariableState::Conflicting { last_assigned_value: *last_assigned_value, reasons: reasons };
-                    Self::pop_heap_item(&mut self.positions, &mut self.tentatively_assigned_variables, index);
-                    Self::push_heap_item(&mut self.positions, &mut self.conflicting_variables, index, score);
+                    self.tentatively_assigned_variables.remove(&mut self.positions, index);
+                    Self::push_heap_item(
+                        &mut self.positions,
+                        &mut self.conflicting_variables,
+                        index,
+                        score,
+                        &self.conflict_comparison_count,
+                    );
+                    self.propagation_statistics.conflict_detection_count += 1;
                 }
             }
             VariableState::Conflicting { reasons: current_reasons, .. } => {
                 // 既に矛盾している場合
                 let new_reasons =
                     if value == false { [reason, current_reasons[1]] } else { [current_reasons[0], reason] };
-                let new_score = Self::calculate_conflicting_variable_score(&new_reasons, self.activities[index]);
                 let current_score = self.conflicting_variables[self.positions[index]].1;
-                if new_score < current_score {
-                    // score を改善するなら上書き
+                let new_score = if self.propagation_order == PropagationOrder::FIFO {
+                    // FIFO の場合は検出された順序を維持する
+                    current_score
+                } else {
+                    Self::calculate_conflicting_variable_score(
+                        self.propagation_order,
+                        &new_reasons,
+                        self.decision_heuristic.priority(index),
+                        self.tie_breakers[index],
+                        0,
+                    )
+                };
+                let new_decision_level = Self::conflict_decision_level_of(&new_reasons, self.decision_level);
+                let current_decision_level = Self::conflict_decision_level_of(current_reasons, self.decision_level);
+                if new_decision_level < current_decision_level
+                    || (new_decision_level == current_decision_level && new_score < current_score)
+                {
+                    // 矛盾の決定レベルを下げるか，score を改善するなら上書き
                     *current_reasons = new_reasons;
-                    Self::change_heap_value(&mut self.positions, &mut self.conflicting_variables, index, new_score);
+                    Self::change_heap_value(
+                        &mut self.positions,
+                        &mut self.conflicting_variables,
+                        index,
+                        new_score,
+                        &self.conflict_comparison_count,
+                    );
+                    self.propagation_statistics.reason_replacement_count += 1;
                 }
             }
             VariableState::Assigned { .. } => {
@@ -236,21 +545,34 @@ impl Variables {
         let variable_state = &mut self.variable_states[index];
         match variable_state {
             VariableState::TentativelyAssigned { tentatively_assigned_value, reason, .. } => {
-                if let Reason::Decision = reason {
-                    self.decision_level += 1;
-                }
+                // 決定変数であれば新たな決定レベルに，伝播であれば伝播元の節の決定レベルに割り当てる
+                // NOTE: 時系列バックトラックを行うと，割り当てのスタック上で決定レベルが昇順に並ぶとは限らない
+                let decision_level = match reason {
+                    Reason::Decision => {
+                        self.decision_level += 1;
+                        self.decision_variables.push(index);
+                        self.decision_level
+                    }
+                    Reason::Propagation { decision_level, .. }
+                    | Reason::Theory { decision_level, .. }
+                    | Reason::External { decision_level, .. } => {
+                        debug_assert!(*decision_level <= self.decision_level);
+                        *decision_level
+                    }
+                };
                 let stack_position = self.assigned_variables.len();
                 *variable_state = VariableState::Assigned {
                     assigned_value: *tentatively_assigned_value,
-                    decision_level: self.decision_level,
+                    decision_level,
                     assignment_level: stack_position + 1,
                     reason: *reason,
                 };
-                Self::pop_heap_item(&mut self.positions, &mut self.tentatively_assigned_variables, index);
+                self.tentatively_assigned_variables.remove(&mut self.positions, index);
                 //
                 debug_assert!(self.positions[index] == Self::NULL_POSITION);
                 self.positions[index] = stack_position;
                 self.assigned_variables.push(index);
+                self.decision_heuristic.assign(index);
             }
             VariableState::Assigned { .. } => {
                 unreachable!();
@@ -264,122 +586,249 @@ impl Variables {
         }
     }
 
+    /// リスタート時に再利用できる決定レベルを求める
+    ///
+    /// 次に選択される決定変数よりも優先度の低い決定変数が現れる直前の決定レベルを返す．
+    /// そのような決定変数が存在しなければ現在の決定レベルを返す．
+    #[inline(never)]
+    pub fn reusable_decision_level(&self) -> VariableSize {
+        let Some(next_variable_index) = self.peek_unassigned_variable() else {
+            return self.decision_level;
+        };
+        let next_priority = self.decision_heuristic.priority(next_variable_index);
+        for (k, variable_index) in self.decision_variables.iter().enumerate() {
+            if self.decision_heuristic.priority(*variable_index) < next_priority {
+                return k as VariableSize;
+            }
+        }
+        self.decision_level
+    }
+
+    /// 決定レベル decision_level までバックトラックする
+    ///
+    /// decision_level 以下の決定レベルで割り当てられている変数は割り当てを維持したまま割り当てのスタックに積み直す．
+    /// 積み直した変数の割り当てを再度伝播させる必要があるため，積み直した変数のスタック上の開始位置を返す．
+    #[inline(never)]
+    pub fn backtrack(&mut self, decision_level: VariableSize) -> VariableSize {
+        assert!(decision_level < self.decision_level);
+        // バックトラック先の決定レベルより大きい決定レベルで伝播された仮割り当てを取り消す
+        self.cancel_tentative_assignment(decision_level);
+        // 決定レベルが decision_level になるまで割り当てを取り消す
+        self.variable_buffer.clear();
+        while self.decision_level > decision_level {
+            let index = unsafe { self.assigned_variables.pop().unwrap_unchecked() };
+            debug_assert!(self.positions[index] == self.assigned_variables.len());
+            self.positions[index] = Self::NULL_POSITION;
+            let variable_state = &mut self.variable_states[index];
+            debug_assert!(matches!(variable_state, VariableState::Assigned { .. }));
+            let VariableState::Assigned { assigned_value, decision_level: assigned_decision_level, reason, .. } =
+                variable_state
+            else {
+                unsafe {
+                    unreachable_unchecked();
+                }
+            };
+            debug_assert!(*assigned_decision_level <= self.decision_level);
+            if let Reason::Decision = reason {
+                debug_assert!(self.decision_variables.last() == Some(&index));
+                self.decision_variables.pop();
+                self.decision_level -= 1;
+            }
+            if *assigned_decision_level <= decision_level {
+                // バックトラック先の決定レベル以下で割り当てられている変数は退避
+                self.variable_buffer.push(index);
+            } else {
+                *variable_state = VariableState::Unassigned { last_assigned_value: *assigned_value };
+                self.number_of_unassigned_variables += 1;
+                self.decision_heuristic.unassign(index);
+            }
+        }
+        // 退避した変数を元の順序で積み直す
+        let reassigned_position = self.assigned_variables.len();
+        while let Some(index) = self.variable_buffer.pop() {
+            let stack_position = self.assigned_variables.len();
+            let VariableState::Assigned { assignment_level, .. } = &mut self.variable_states[index] else {
+                unsafe {
+                    unreachable_unchecked();
+                }
+            };
+            *assignment_level = stack_position + 1;
+            self.positions[index] = stack_position;
+            self.assigned_variables.push(index);
+        }
+        reassigned_position
+    }
+
     #[inline(never)]
-    pub fn cancel_tentative_assignment(&mut self) {
-        while !self.conflicting_variables.is_empty() {
-            let index = unsafe { self.conflicting_variables.first().unwrap_unchecked().0 };
+    fn cancel_tentative_assignment(&mut self, decision_level: VariableSize) {
+        // 矛盾している変数
+        self.variable_buffer.clear();
+        for (index, ..) in self.conflicting_variables.iter() {
+            self.variable_buffer.push(*index);
+        }
+        for k in 0..self.variable_buffer.len() {
+            let index = self.variable_buffer[k];
             let variable_state = &mut self.variable_states[index];
             debug_assert!(matches!(variable_state, VariableState::Conflicting { .. }));
-            let VariableState::Conflicting { last_assigned_value, .. } = variable_state else {
+            let VariableState::Conflicting { last_assigned_value, reasons } = variable_state else {
                 unsafe { unreachable_unchecked() }
             };
-            let priority = Self::calculate_unassigned_variable_score(self.activities[index]);
-            *variable_state = VariableState::Unassigned { last_assigned_value: *last_assigned_value };
-            Self::pop_heap_item(&mut self.positions, &mut self.conflicting_variables, index);
-            Self::push_heap_item(&mut self.positions, &mut self.unassigned_variables, index, priority);
+            let reasons = *reasons;
+            let is_alive =
+                reasons.map(|reason| Self::decision_level_of(&reason, self.decision_level) <= decision_level);
+            if is_alive[0] && is_alive[1] {
+                // 両方の伝播がバックトラック後も有効であれば矛盾状態のまま
+                continue;
+            }
+            Self::pop_heap_item(
+                &mut self.positions,
+                &mut self.conflicting_variables,
+                index,
+                &self.conflict_comparison_count,
+            );
+            if is_alive[0] || is_alive[1] {
+                // 一方の伝播のみが有効であればその伝播による仮割り当て状態に遷移
+                let value = is_alive[1];
+                let reason = reasons[value as usize];
+                let score = Self::calculate_tentatively_assigned_variable_score(
+                    self.propagation_order,
+                    &reason,
+                    self.decision_heuristic.priority(index),
+                    self.tie_breakers[index],
+                );
+                *variable_state = VariableState::TentativelyAssigned {
+                    last_assigned_value: *last_assigned_value,
+                    tentatively_assigned_value: value,
+                    reason,
+                };
+                self.tentatively_assigned_variables.push(&mut self.positions, index, score);
+            } else {
+                *variable_state = VariableState::Unassigned { last_assigned_value: *last_assigned_value };
+                self.number_of_unassigned_variables += 1;
+                self.decision_heuristic.unassign(index);
+            }
         }
-        while !self.tentatively_assigned_variables.is_empty() {
-            let index = unsafe { self.tentatively_assigned_variables.first().unwrap_unchecked().0 };
+        // 仮割り当てされている変数
+        self.tentatively_assigned_variables.collect(&mut self.variable_buffer);
+        for k in 0..self.variable_buffer.len() {
+            let index = self.variable_buffer[k];
             let variable_state = &mut self.variable_states[index];
             debug_assert!(matches!(variable_state, VariableState::TentativelyAssigned { .. }));
-            let VariableState::TentativelyAssigned { last_assigned_value, .. } = variable_state else {
+            let VariableState::TentativelyAssigned { last_assigned_value, reason, .. } = variable_state else {
                 unsafe { unreachable_unchecked() }
             };
-            let score = Self::calculate_unassigned_variable_score(self.activities[index]);
+            if Self::decision_level_of(reason, self.decision_level) <= decision_level {
+                // バックトラック後も有効な伝播であれば仮割り当て状態のまま
+                continue;
+            }
             *variable_state = VariableState::Unassigned { last_assigned_value: *last_assigned_value };
-            Self::pop_heap_item(&mut self.positions, &mut self.tentatively_assigned_variables, index);
-            Self::push_heap_item(&mut self.positions, &mut self.unassigned_variables, index, score);
+            self.tentatively_assigned_variables.remove(&mut self.positions, index);
+            self.number_of_unassigned_variables += 1;
+            self.decision_heuristic.unassign(index);
         }
+        self.variable_buffer.clear();
     }
 
     #[inline(always)]
-    pub fn unassign(&mut self) -> VariableSize {
-        assert!(!self.assigned_variables.is_empty());
-        let index = unsafe { self.assigned_variables.pop().unwrap_unchecked() };
-        debug_assert!(self.positions[index] == self.assigned_variables.len());
-        self.positions[index] = Self::NULL_POSITION;
-        let variable_state = &mut self.variable_states[index];
-        debug_assert!(matches!(variable_state, VariableState::Assigned { .. }));
-        let VariableState::Assigned { assigned_value, decision_level, assignment_level, reason } = variable_state
-        else {
-            unsafe {
-                unreachable_unchecked();
-            }
-        };
-        debug_assert!(*decision_level == self.decision_level);
-        debug_assert!(*assignment_level == self.assigned_variables.len() + 1);
-        if let Reason::Decision = reason {
-            self.decision_level -= 1;
-        }
-        *variable_state = VariableState::Unassigned { last_assigned_value: *assigned_value };
-        Self::push_heap_item(
-            &mut self.positions,
-            &mut self.unassigned_variables,
-            index,
-            Self::calculate_unassigned_variable_score(self.activities[index]),
-        );
-        index
+    pub fn increase_activity(&mut self, index: VariableSize) {
+        self.decision_heuristic.bump(index);
     }
 
     #[inline(always)]
-    pub fn increase_activity(&mut self, index: VariableSize) {
-        self.activities[index] += self.activity_increase_value;
-        if self.activities[index] > 1e4 {
-            for activity in self.activities.iter_mut() {
-                *activity /= self.activity_increase_value;
-            }
-            self.activity_increase_value = 1.0;
-            // NOTE: 変更に際して注意が必要( activity を定数倍しても unassigned_variables をソートし直す必要がないという仮定を使っている)
-            for (index, priority) in self.unassigned_variables.iter_mut() {
-                *priority = Self::calculate_unassigned_variable_score(self.activities[*index]);
-            }
+    pub fn advance_time(&mut self) {
+        self.decision_heuristic.advance_time();
+    }
+
+    /// 仮割り当ての理由から，割り当てられる決定レベルを求める
+    #[inline(always)]
+    fn decision_level_of(reason: &Reason, current_decision_level: VariableSize) -> VariableSize {
+        match reason {
+            Reason::Decision => current_decision_level + 1,
+            Reason::Propagation { decision_level, .. }
+            | Reason::Theory { decision_level, .. }
+            | Reason::External { decision_level, .. } => *decision_level,
         }
     }
 
     #[inline(always)]
-    pub fn advance_time(&mut self) {
-        self.activity_increase_value /= 1.0 - 1.0 / self.activity_time_constant;
+    fn conflict_decision_level_of(reasons: &[Reason; 2], current_decision_level: VariableSize) -> VariableSize {
+        Self::decision_level_of(&reasons[0], current_decision_level)
+            .max(Self::decision_level_of(&reasons[1], current_decision_level))
     }
 
     #[inline(always)]
-    fn calculate_conflicting_variable_score(reasons: &[Reason; 2], activity: f64) -> ConflictingVariableScore {
-        debug_assert!(matches!(reasons[0], Reason::Propagation { .. }));
-        debug_assert!(matches!(reasons[1], Reason::Propagation { .. }));
-        let Reason::Propagation { lbd: lbd0, clause_length: clause_length0, .. } = reasons[0] else {
+    fn calculate_conflicting_variable_score(
+        propagation_order: PropagationOrder,
+        reasons: &[Reason; 2],
+        priority: f64,
+        tie_breaker: VariableSize,
+        sequence_number: usize,
+    ) -> ConflictingVariableScore {
+        debug_assert!(!matches!(reasons[0], Reason::Decision));
+        debug_assert!(!matches!(reasons[1], Reason::Decision));
+        let (Reason::Propagation { lbd: lbd0, clause_length: clause_length0, .. }
+        | Reason::Theory { lbd: lbd0, clause_length: clause_length0, .. }
+        | Reason::External { lbd: lbd0, clause_length: clause_length0, .. }) = reasons[0]
+        else {
             unsafe {
                 unreachable_unchecked();
             }
         };
-        let Reason::Propagation { lbd: lbd1, clause_length: clause_length1, .. } = reasons[1] else {
+        let (Reason::Propagation { lbd: lbd1, clause_length: clause_length1, .. }
+        | Reason::Theory { lbd: lbd1, clause_length: clause_length1, .. }
+        | Reason::External { lbd: lbd1, clause_length: clause_length1, .. }) = reasons[1]
+        else {
             unsafe {
                 unreachable_unchecked();
             }
         };
-        (-activity, lbd0 + lbd1, clause_length0 + clause_length1)
+        let (lbd, clause_length) = (lbd0 + lbd1, clause_length0 + clause_length1);
+        match propagation_order {
+            PropagationOrder::FIFO => (sequence_number as f64, 0, 0, 0),
+            PropagationOrder::Score => (-priority, lbd, clause_length, tie_breaker),
+            PropagationOrder::LBD => (lbd as f64, clause_length, tie_breaker, 0),
+            PropagationOrder::Length => (clause_length as f64, lbd, tie_breaker, 0),
+        }
     }
 
     #[inline(always)]
     fn calculate_tentatively_assigned_variable_score(
+        propagation_order: PropagationOrder,
         reason: &Reason,
-        activity: f64,
+        priority: f64,
+        tie_breaker: VariableSize,
     ) -> TentativelyAssigedVariableScore {
-        match reason {
-            Reason::Decision => (-f64::INFINITY, 0, 0),
-            Reason::Propagation { lbd, clause_length, .. } => (-activity, *lbd, *clause_length),
+        match (propagation_order, reason) {
+            (_, Reason::Decision) => (-f64::INFINITY, 0, 0, 0),
+            (PropagationOrder::FIFO, _) => (0.0, 0, 0, 0),
+            (
+                PropagationOrder::Score,
+                Reason::Propagation { lbd, clause_length, .. }
+                | Reason::Theory { lbd, clause_length, .. }
+                | Reason::External { lbd, clause_length, .. },
+            ) => (-priority, *lbd, *clause_length, tie_breaker),
+            (
+                PropagationOrder::LBD,
+                Reason::Propagation { lbd, clause_length, .. }
+                | Reason::Theory { lbd, clause_length, .. }
+                | Reason::External { lbd, clause_length, .. },
+            ) => (*lbd as f64, *clause_length, tie_breaker, 0),
+            (
+                PropagationOrder::Length,
+                Reason::Propagation { lbd, clause_length, .. }
+                | Reason::Theory { lbd, clause_length, .. }
+                | Reason::External { lbd, clause_length, .. },
+            ) => (*clause_length as f64, *lbd, tie_breaker, 0),
         }
     }
 
-    #[inline(always)]
-    fn calculate_unassigned_variable_score(activity: f64) -> UnassignedVariableScore {
-        -activity
-    }
-
     #[inline(always)]
     fn push_heap_item<T>(
         positions: &mut Array<VariableSize, VariableSize>,
         heap: &mut Array<VariableSize, (VariableSize, T)>,
         index: VariableSize,
         value: T,
+        comparison_count: &Cell<usize>,
     ) where
         T: std::cmp::PartialOrd,
     {
@@ -387,7 +836,7 @@ impl Variables {
         let position = heap.len();
         positions[index] = position;
         heap.push((index, value));
-        Self::up_heap(positions, heap, position);
+        Self::up_heap(positions, heap, position, comparison_count);
     }
 
     #[inline(always)]
@@ -396,19 +845,21 @@ impl Variables {
         heap: &mut Array<VariableSize, (VariableSize, T)>,
         index: VariableSize,
         value: T,
+        comparison_count: &Cell<usize>,
     ) where
         T: std::cmp::PartialOrd,
     {
         let position = positions[index];
         debug_assert!(heap[position].0 == index);
         heap[position].1 = value;
-        Self::update_heap(positions, heap, position);
+        Self::update_heap(positions, heap, position, comparison_count);
     }
 
     fn pop_heap_item<T>(
         positions: &mut Array<VariableSize, VariableSize>,
         heap: &mut Array<VariableSize, (VariableSize, T)>,
         index: VariableSize,
+        comparison_count: &Cell<usize>,
     ) where
         T: std::cmp::PartialOrd,
     {
@@ -420,7 +871,7 @@ impl Variables {
         } else {
             heap.swap_remove(position).0;
             positions[heap[position].0] = position;
-            Self::update_heap(positions, heap, position);
+            Self::update_heap(positions, heap, position, comparison_count);
         }
     }
 
@@ -429,10 +880,14 @@ impl Variables {
         positions: &mut Array<VariableSize, VariableSize>,
         heap: &mut Array<VariableSize, (VariableSize, T)>,
         position: VariableSize,
+        comparison_count: &Cell<usize>,
     ) where
         T: std::cmp::PartialOrd,
     {
-        indirect_heap::update_heap(heap, positions, position, &|lhs, rhs| lhs.1 < rhs.1);
+        indirect_heap::update_heap(heap, positions, position, &|lhs, rhs| {
+            comparison_count.set(comparison_count.get() + 1);
+            lhs.1 < rhs.1
+        });
     }
 
     #[inline(always)]
@@ -440,10 +895,14 @@ impl Variables {
         positions: &mut Array<VariableSize, VariableSize>,
         heap: &mut Array<VariableSize, (VariableSize, T)>,
         position: VariableSize,
+        comparison_count: &Cell<usize>,
     ) where
         T: std::cmp::PartialOrd,
     {
-        indirect_heap::down_heap(heap, positions, position, &|lhs, rhs| lhs.1 < rhs.1);
+        indirect_heap::down_heap(heap, positions, position, &|lhs, rhs| {
+            comparison_count.set(comparison_count.get() + 1);
+            lhs.1 < rhs.1
+        });
     }
 
     #[inline(always)]
@@ -451,9 +910,109 @@ impl Variables {
         positions: &mut Array<VariableSize, VariableSize>,
         heap: &mut Array<VariableSize, (VariableSize, T)>,
         position: VariableSize,
+        comparison_count: &Cell<usize>,
     ) where
         T: std::cmp::PartialOrd,
     {
-        indirect_heap::up_heap(heap, positions, position, &|lhs, rhs| lhs.1 < rhs.1);
+        indirect_heap::up_heap(heap, positions, position, &|lhs, rhs| {
+            comparison_count.set(comparison_count.get() + 1);
+            lhs.1 < rhs.1
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decision_heuristic::{create_decision_heuristic, DecisionHeuristicKind};
+    use super::*;
+
+    #[test]
+    fn fifo_queue_stays_compact() {
+        let mut random = Random::new(2);
+        let n = 64;
+        let mut positions: Array<VariableSize, VariableSize> = Array::default();
+        positions.resize(n, Variables::NULL_POSITION);
+        let mut queue = TentativelyAssignedVariableQueue::new(PropagationOrder::FIFO);
+        // 追加順に並べた待ち行列の要素
+        let mut expected: Vec<VariableSize> = Vec::new();
+        let mut indices: Array<VariableSize, VariableSize> = Array::default();
+        for _ in 0..5000 {
+            let index = random.next_below(n);
+            if positions[index] == Variables::NULL_POSITION {
+                if random.next_below(3) != 0 {
+                    queue.push(&mut positions, index, (0.0, 0, 0, 0));
+                    expected.push(index);
+                }
+            } else {
+                queue.remove(&mut positions, index);
+                expected.retain(|other| *other != index);
+            }
+            // 先頭以外が削除されても，空きは要素数を超えない
+            assert_eq!(queue.len() as usize, expected.len());
+            assert!(queue.queue.len() <= 2 * queue.len());
+            assert_eq!(queue.first(), expected.first().copied());
+            queue.collect(&mut indices);
+            assert_eq!(indices.as_slice(), expected.as_slice());
+        }
+    }
+
+    #[test]
+    fn reused_decisions_rank_above_next_decision() {
+        let mut random = Random::new(1);
+        for kind in [
+            DecisionHeuristicKind::Activity,
+            DecisionHeuristicKind::Vmtf,
+            DecisionHeuristicKind::Lrb,
+            DecisionHeuristicKind::Chb,
+        ] {
+            let mut variables = Variables::new(
+                create_decision_heuristic(kind),
+                PropagationOrder::Score,
+                VariableRandomization::default(),
+            );
+            let n = 30;
+            variables.redimension(n, &mut random);
+            let mut partially_reused_count = 0;
+            for _ in 0..400 {
+                if let Some((variable_index, _)) = variables.first_unassigned_variable() {
+                    variables.tentatively_assign(variable_index, random.next_bool(), Reason::Decision);
+                    variables.assign(variable_index);
+                } else {
+                    variables.backtrack(0);
+                    continue;
+                }
+                // 矛盾の解析に関与したものとして変数の優先度を変え，バックジャンプする
+                for _ in 0..3 {
+                    variables.increase_activity(random.next_below(n));
+                }
+                variables.advance_time();
+                if random.next_below(3) == 0 {
+                    variables.backtrack(random.next_below(variables.current_decision_level()));
+                }
+                if random.next_below(4) != 0 {
+                    continue;
+                }
+                // リスタート
+                let current_decision_level = variables.current_decision_level();
+                let reused_decision_level = variables.reusable_decision_level();
+                assert!(reused_decision_level <= current_decision_level);
+                let priority = |variable_index: VariableSize| variables.decision_heuristic.priority(variable_index);
+                let Some(next_variable_index) = variables.peek_unassigned_variable() else {
+                    assert_eq!(reused_decision_level, current_decision_level);
+                    continue;
+                };
+                let next_priority = priority(next_variable_index);
+                // 再利用する決定変数は次の決定変数より優先度が低くなく，その直後の決定変数は優先度が低い
+                for k in 0..reused_decision_level {
+                    assert!(priority(variables.decision_variables[k]) >= next_priority);
+                }
+                if reused_decision_level < current_decision_level {
+                    assert!(priority(variables.decision_variables[reused_decision_level]) < next_priority);
+                    partially_reused_count += (reused_decision_level != 0) as usize;
+                    variables.backtrack(reused_decision_level);
+                }
+            }
+            assert!(partially_reused_count != 0, "{:?}", kind);
+        }
     }
 }
@@ -0,0 +1,463 @@
+use crate::finite_collections::{Array, Set};
+
+use super::theory::Theory;
+use super::types::{ConstraintSize, Literal, Reason, VariableSize};
+use super::variables::{VariableState, Variables};
+
+const NULL_COLUMN: VariableSize = VariableSize::MAX;
+
+/// Gauss-Jordan 消去によって得られた行(XOR 制約)
+///
+/// 基底変数の列は他の行には含まれない．基底変数と非基底変数の 1 つ(watched_column)を監視する．
+struct XORRow {
+    bits: Array<VariableSize, u64>,
+    parity: bool,
+    basic_column: VariableSize,
+    watched_column: VariableSize,
+}
+
+/// 伝播した時点の行の写し
+struct XORExplanation {
+    variable_index: VariableSize,
+    value: bool,
+    bits: Array<VariableSize, u64>,
+}
+
+#[inline(always)]
+fn contains_column(bits: &Array<VariableSize, u64>, column: VariableSize) -> bool {
+    (bits[column / 64] >> (column % 64)) & 1 != 0
+}
+
+#[inline(always)]
+fn xor_bits(bits: &mut Array<VariableSize, u64>, other: &Array<VariableSize, u64>) {
+    for k in 0..other.len() {
+        bits[k] ^= other[k];
+    }
+}
+
+/// 行に含まれる列を昇順に列挙する
+#[inline(always)]
+fn columns_of(bits: &Array<VariableSize, u64>) -> impl Iterator<Item = VariableSize> + '_ {
+    bits.iter().enumerate().flat_map(|(k, word)| {
+        let mut word = *word;
+        std::iter::from_fn(move || {
+            if word == 0 {
+                return None;
+            }
+            let bit = word.trailing_zeros();
+            word &= word - 1;
+            Some(k as VariableSize * 64 + bit)
+        })
+    })
+}
+
+/// XOR 制約を Gauss-Jordan 消去によって伝播する
+///
+/// 行列を常に簡約階段形に保ち，基底変数が割り当てられたら行内の未割り当ての変数を新たな基底変数として
+/// 他の行からその列を消去する(バックトラック時に行列を元に戻す必要はない)．
+/// 非基底変数がすべて割り当てられた行は基底変数に伝播し，理由節は Analyze から要求されたときに伝播時点の行の写しから生成する．
+#[derive(Default)]
+pub struct XORTheory {
+    theory_index: ConstraintSize,
+    // 追加された XOR 制約(変数, パリティ)
+    xors: Array<ConstraintSize, (Array<VariableSize, VariableSize>, bool)>,
+    is_dirty: bool,
+    variable_columns: Array<VariableSize, VariableSize>,
+    column_variables: Array<VariableSize, VariableSize>,
+    number_of_words: VariableSize,
+    rows: Array<ConstraintSize, XORRow>,
+    // 列を監視している行(監視をやめた行は走査時に取り除く)
+    watching_rows: Array<VariableSize, Array<ConstraintSize, ConstraintSize>>,
+    // 消去によって内容が変わった行
+    pending_rows: Array<ConstraintSize, ConstraintSize>,
+    explanations: Array<ConstraintSize, XORExplanation>,
+    next_explanation: ConstraintSize,
+    row_buffer: Array<VariableSize, u64>,
+    decision_level_set: Set<VariableSize>,
+    propagation_count: usize,
+    elimination_count: usize,
+}
+
+impl XORTheory {
+    /// 説明の領域を再利用するために調べる領域の数
+    const EXPLANATION_SEARCH_LIMIT: usize = 4;
+
+    /// Reason::Theory の theory_index を theory_index とする
+    pub fn new(theory_index: ConstraintSize) -> Self {
+        XORTheory { theory_index, ..Default::default() }
+    }
+
+    /// XOR 制約 (variable_indices の変数の排他的論理和が parity に等しい) を追加する
+    ///
+    /// 行列は次の rebuild で作り直す．
+    pub fn add_xor(&mut self, variable_indices: &[VariableSize], parity: bool) {
+        let mut xor_variables = Array::default();
+        for variable_index in variable_indices.iter() {
+            if self.variable_columns[*variable_index] == NULL_COLUMN {
+                let column = self.column_variables.len();
+                self.variable_columns[*variable_index] = column;
+                self.column_variables.push(*variable_index);
+                self.watching_rows.push(Array::default());
+                self.number_of_words = column / 64 + 1;
+            }
+            xor_variables.push(*variable_index);
+        }
+        self.xors.push((xor_variables, parity));
+        self.is_dirty = true;
+    }
+
+    pub fn summary(&self) -> (usize, usize, usize) {
+        (self.rows.len() as usize, self.propagation_count, self.elimination_count)
+    }
+
+    /// 行 row_index 以外の行から column の列を消去し，内容が変わった行を pending_rows に積む
+    fn eliminate(&mut self, row_index: ConstraintSize, column: VariableSize) {
+        self.row_buffer.clone_from(&self.rows[row_index].bits);
+        let parity = self.rows[row_index].parity;
+        for other_row_index in 0..self.rows.len() {
+            if other_row_index != row_index && contains_column(&self.rows[other_row_index].bits, column) {
+                xor_bits(&mut self.rows[other_row_index].bits, &self.row_buffer);
+                self.rows[other_row_index].parity ^= parity;
+                self.elimination_count += 1;
+                self.pending_rows.push(other_row_index);
+            }
+        }
+    }
+
+    /// pending_rows の行の基底変数と監視する変数を付け直す
+    ///
+    /// 基底変数が割り当て済みであれば未割り当ての変数を新たな基底変数とし，
+    /// 未割り当ての非基底変数がなければ最後に割り当てられた非基底変数を監視して基底変数に伝播する．
+    #[inline(never)]
+    fn settle_pending_rows(&mut self, variables: &mut Variables) {
+        while let Some(row_index) = self.pending_rows.pop() {
+            let basic_column = self.rows[row_index].basic_column;
+            if variables.get(self.column_variables[basic_column]).is_assigned() {
+                let Some(new_basic_column) =
+                    self.find_unassigned_column(row_index, basic_column, NULL_COLUMN, variables)
+                else {
+                    // すべての変数が割り当て済み
+                    debug_assert!(self.is_row_satisfied(row_index, variables));
+                    continue;
+                };
+                self.rows[row_index].basic_column = new_basic_column;
+                self.watching_rows[new_basic_column].push(row_index);
+                self.eliminate(row_index, new_basic_column);
+            }
+            let XORRow { basic_column, watched_column, .. } = self.rows[row_index];
+            if watched_column != NULL_COLUMN
+                && watched_column != basic_column
+                && contains_column(&self.rows[row_index].bits, watched_column)
+                && !variables.get(self.column_variables[watched_column]).is_assigned()
+            {
+                continue;
+            }
+            match self.find_unassigned_column(row_index, basic_column, NULL_COLUMN, variables) {
+                Some(new_watched_column) => {
+                    self.rows[row_index].watched_column = new_watched_column;
+                    self.watching_rows[new_watched_column].push(row_index);
+                }
+                None => {
+                    let latest_column = self.latest_assigned_column(row_index, variables);
+                    if latest_column != watched_column {
+                        self.rows[row_index].watched_column = latest_column;
+                        if latest_column != NULL_COLUMN {
+                            self.watching_rows[latest_column].push(row_index);
+                        }
+                    }
+                    self.propagate_row(row_index, variables);
+                }
+            }
+        }
+    }
+
+    /// 非基底変数がすべて割り当てられている行から基底変数に伝播する
+    #[inline(never)]
+    fn propagate_row(&mut self, row_index: ConstraintSize, variables: &mut Variables) {
+        let row = &self.rows[row_index];
+        let basic_variable_index = self.column_variables[row.basic_column];
+        debug_assert!(!variables.get(basic_variable_index).is_assigned());
+        if self.decision_level_set.capacity() < variables.dimension() + 1 {
+            self.decision_level_set.reserve(variables.dimension() + 1 - self.decision_level_set.capacity());
+        }
+        // 基底変数の値は非基底変数の値とパリティの排他的論理和
+        let mut value = row.parity;
+        let mut lbd = 0;
+        let mut clause_length = 1;
+        let mut propagation_decision_level = 0;
+        for column in columns_of(&row.bits) {
+            if column == row.basic_column {
+                continue;
+            }
+            let VariableState::Assigned { assigned_value, decision_level, .. } =
+                *variables.get(self.column_variables[column])
+            else {
+                unreachable!();
+            };
+            value ^= assigned_value;
+            clause_length += 1;
+            propagation_decision_level = propagation_decision_level.max(decision_level);
+            if decision_level != 0 && !self.decision_level_set.contains_key(decision_level) {
+                self.decision_level_set.insert(decision_level);
+                lbd += 1;
+            }
+        }
+        self.decision_level_set.clear();
+        self.propagation_count += 1;
+        // 伝播時点の行を保存
+        let explanation_index = self.allocate_explanation(variables);
+        let explanation = &mut self.explanations[explanation_index];
+        explanation.variable_index = basic_variable_index;
+        explanation.value = value;
+        explanation.bits.clone_from(&self.rows[row_index].bits);
+        variables.tentatively_assign(
+            basic_variable_index,
+            value,
+            Reason::Theory {
+                theory_index: self.theory_index,
+                explanation_index,
+                lbd,
+                clause_length,
+                decision_level: propagation_decision_level,
+            },
+        );
+    }
+
+    /// どの割り当ての理由にもなっていない説明の領域を返す(見つからなければ追加する)
+    fn allocate_explanation(&mut self, variables: &Variables) -> ConstraintSize {
+        for _ in 0..Self::EXPLANATION_SEARCH_LIMIT {
+            if self.next_explanation >= self.explanations.len() {
+                self.next_explanation = 0;
+                if self.explanations.is_empty() {
+                    break;
+                }
+            }
+            let explanation_index = self.next_explanation;
+            self.next_explanation += 1;
+            if !self.is_explanation_used(explanation_index, variables) {
+                return explanation_index;
+            }
+        }
+        self.explanations.push(XORExplanation { variable_index: 0, value: false, bits: Array::default() });
+        self.explanations.len() - 1
+    }
+
+    fn is_explanation_used(&self, explanation_index: ConstraintSize, variables: &Variables) -> bool {
+        let explanation = &self.explanations[explanation_index];
+        let is_explained_by = |reason: &Reason| matches!(reason, Reason::Theory { theory_index, explanation_index: i, .. } if *theory_index == self.theory_index && *i == explanation_index);
+        match variables.get(explanation.variable_index) {
+            VariableState::Assigned { assigned_value, reason, .. } => {
+                *assigned_value == explanation.value && is_explained_by(reason)
+            }
+            VariableState::TentativelyAssigned { tentatively_assigned_value, reason, .. } => {
+                *tentatively_assigned_value == explanation.value && is_explained_by(reason)
+            }
+            VariableState::Conflicting { reasons, .. } => is_explained_by(&reasons[explanation.value as usize]),
+            VariableState::Unassigned { .. } => false,
+        }
+    }
+
+    /// 行に含まれる未割り当ての変数のうち excluded_column0, excluded_column1 以外のものを 1 つ返す
+    #[inline(always)]
+    fn find_unassigned_column(
+        &self,
+        row_index: ConstraintSize,
+        excluded_column0: VariableSize,
+        excluded_column1: VariableSize,
+        variables: &Variables,
+    ) -> Option<VariableSize> {
+        columns_of(&self.rows[row_index].bits).find(|column| {
+            *column != excluded_column0
+                && *column != excluded_column1
+                && !variables.get(self.column_variables[*column]).is_assigned()
+        })
+    }
+
+    /// 行に含まれる割り当て済みの非基底変数のうち最後に割り当てられたもの
+    fn latest_assigned_column(&self, row_index: ConstraintSize, variables: &Variables) -> VariableSize {
+        let row = &self.rows[row_index];
+        let mut latest = (0, NULL_COLUMN);
+        for column in columns_of(&row.bits) {
+            if column == row.basic_column {
+                continue;
+            }
+            if let VariableState::Assigned { assignment_level, .. } = variables.get(self.column_variables[column]) {
+                if latest.1 == NULL_COLUMN || *assignment_level > latest.0 {
+                    latest = (*assignment_level, column);
+                }
+            }
+        }
+        latest.1
+    }
+
+    fn is_row_satisfied(&self, row_index: ConstraintSize, variables: &Variables) -> bool {
+        let row = &self.rows[row_index];
+        columns_of(&row.bits)
+            .fold(false, |sum, column| sum ^ variables.get(self.column_variables[column]).is_value_assigned(true))
+            == row.parity
+    }
+}
+
+impl Theory for XORTheory {
+    fn expand(&mut self, additional: VariableSize) {
+        self.variable_columns.resize(self.variable_columns.len() + additional, NULL_COLUMN);
+    }
+
+    #[inline(never)]
+    fn propagate(&mut self, assigned_variable_index: VariableSize, variables: &mut Variables) {
+        let column = self.variable_columns[assigned_variable_index];
+        if column == NULL_COLUMN {
+            return;
+        }
+        let mut k: ConstraintSize = 0;
+        while k < self.watching_rows[column].len() {
+            let row_index = self.watching_rows[column][k];
+            let XORRow { basic_column, watched_column, .. } = self.rows[row_index];
+            if column == basic_column {
+                // 基底変数が割り当てられた場合には，未割り当ての変数を新たな基底変数として他の行から消去
+                if let Some(new_basic_column) = self.find_unassigned_column(row_index, column, NULL_COLUMN, variables) {
+                    self.rows[row_index].basic_column = new_basic_column;
+                    self.watching_rows[new_basic_column].push(row_index);
+                    self.eliminate(row_index, new_basic_column);
+                    self.pending_rows.push(row_index);
+                    self.settle_pending_rows(variables);
+                }
+            } else if column == watched_column {
+                // 監視している非基底変数が割り当てられた場合には，未割り当ての非基底変数に監視を移す
+                match self.find_unassigned_column(row_index, basic_column, column, variables) {
+                    Some(new_watched_column) => {
+                        self.rows[row_index].watched_column = new_watched_column;
+                        self.watching_rows[new_watched_column].push(row_index);
+                    }
+                    None => {
+                        // 未割り当ての非基底変数がなければ基底変数に伝播
+                        if !variables.get(self.column_variables[basic_column]).is_assigned() {
+                            self.propagate_row(row_index, variables);
+                        }
+                    }
+                }
+            }
+            // 監視をやめた行を取り除く
+            let row = &self.rows[row_index];
+            if row.basic_column != column && row.watched_column != column {
+                self.watching_rows[column].swap_remove(k);
+            } else {
+                k += 1;
+            }
+        }
+    }
+
+    /// variable_indices のいずれかの変数を含む XOR 制約を取り除く(行列は次の rebuild で作り直す)
+    ///
+    /// 取り除く XOR 制約は決定レベル 0 の割り当ての理由に使われていないこと．
+    fn remove_constraints_with_variables(&mut self, variable_indices: &[VariableSize], variables: &Variables) {
+        debug_assert!(variables.current_decision_level() == 0);
+        let mut k: ConstraintSize = 0;
+        while k < self.xors.len() {
+            if self.xors[k].0.iter().any(|variable_index| variable_indices.contains(variable_index)) {
+                self.xors.swap_remove(k);
+                self.is_dirty = true;
+            } else {
+                k += 1;
+            }
+        }
+    }
+
+    /// XOR 制約が追加されていれば Gauss-Jordan 消去によって行列を作り直す(決定レベル 0 で呼ぶ)
+    ///
+    /// 決定レベル 0 で割り当てられている変数は取り除く．矛盾(0 = 1)が得られた場合には false を返す．
+    #[inline(never)]
+    fn rebuild(&mut self, variables: &mut Variables) -> bool {
+        debug_assert!(variables.current_decision_level() == 0);
+        if !self.is_dirty {
+            return true;
+        }
+        self.is_dirty = false;
+        self.rows.clear();
+        for watching_rows in self.watching_rows.iter_mut() {
+            watching_rows.clear();
+        }
+        for k in 0..self.xors.len() {
+            // 行を作成(同じ変数が 2 回現れれば打ち消し合う)
+            self.row_buffer.clear();
+            self.row_buffer.resize(self.number_of_words, 0);
+            let mut parity = self.xors[k].1;
+            for variable_index in self.xors[k].0.iter() {
+                if let VariableState::Assigned { assigned_value, .. } = variables.get(*variable_index) {
+                    parity ^= *assigned_value;
+                } else {
+                    let column = self.variable_columns[*variable_index];
+                    self.row_buffer[column / 64] ^= 1 << (column % 64);
+                }
+            }
+            // 既存の行の基底変数の列を消去
+            for row in self.rows.iter() {
+                if contains_column(&self.row_buffer, row.basic_column) {
+                    xor_bits(&mut self.row_buffer, &row.bits);
+                    parity ^= row.parity;
+                }
+            }
+            let Some(pivot) = columns_of(&self.row_buffer).next() else {
+                // 既存の行の線形結合であれば不要(パリティが異なれば矛盾)
+                if parity {
+                    return false;
+                }
+                continue;
+            };
+            // 既存の行から新たな基底変数の列を消去
+            for row in self.rows.iter_mut() {
+                if contains_column(&row.bits, pivot) {
+                    xor_bits(&mut row.bits, &self.row_buffer);
+                    row.parity ^= parity;
+                }
+            }
+            self.rows.push(XORRow {
+                bits: self.row_buffer.clone(),
+                parity,
+                basic_column: pivot,
+                watched_column: NULL_COLUMN,
+            });
+        }
+        // 監視する変数を設定(非基底変数を持たない行は基底変数に伝播)
+        for row_index in 0..self.rows.len() {
+            self.watching_rows[self.rows[row_index].basic_column].push(row_index);
+            self.pending_rows.push(row_index);
+        }
+        self.settle_pending_rows(variables);
+        true
+    }
+
+    fn explain(
+        &self,
+        variable_index: VariableSize,
+        value: bool,
+        reason: Reason,
+        variables: &Variables,
+        clause: &mut Array<VariableSize, Literal>,
+    ) {
+        let Reason::Theory { explanation_index, .. } = reason else {
+            unreachable!();
+        };
+        let explanation = &self.explanations[explanation_index];
+        assert!(explanation.variable_index == variable_index && explanation.value == value);
+        clause.clear();
+        clause.push(Literal { index: variable_index, sign: value });
+        for column in columns_of(&explanation.bits) {
+            let other_variable_index = self.column_variables[column];
+            if other_variable_index == variable_index {
+                continue;
+            }
+            let VariableState::Assigned { assigned_value, .. } = variables.get(other_variable_index) else {
+                unreachable!();
+            };
+            clause.push(Literal { index: other_variable_index, sign: !*assigned_value });
+        }
+    }
+
+    /// 割り当てが追加されたすべての XOR 制約を充足するか
+    fn is_satisfied_by(&mut self, assignment: &Array<VariableSize, bool>) -> bool {
+        self.xors.iter().all(|(xor_variables, parity)| {
+            xor_variables.iter().fold(false, |sum, variable_index| sum ^ assignment[*variable_index]) == *parity
+        })
+    }
+}